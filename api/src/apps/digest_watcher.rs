@@ -0,0 +1,226 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2021 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+
+use crate::apps::{Apps, AppsError};
+use crate::config::{Config, ImageWatcher};
+use crate::models::service::ContainerType;
+use crate::models::{AppName, Image};
+use crate::registry::Registry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::time::interval;
+
+/// Periodically resolves the digests of the image tags that running services use and redeploys
+/// the services whose tag refers to a new image (see [`ImageWatcher`]).
+pub struct DigestWatcher {
+    config: Config,
+    /// The last resolved digest of each watched service.
+    digests: HashMap<(AppName, String), String>,
+}
+
+impl DigestWatcher {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            digests: HashMap::new(),
+        }
+    }
+
+    /// Starts watching the images if the [`ImageWatcher`] is configured.
+    pub fn spawn(mut self, apps: Arc<Apps>) {
+        let Some(watcher) = self.config.image_watcher().cloned() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let mut interval = interval(watcher.interval());
+            loop {
+                interval.tick().await;
+                if let Err(err) = self.check_digests(&apps, &watcher).await {
+                    error!("Cannot check the image digests of the apps: {}", err);
+                }
+            }
+        });
+    }
+
+    async fn check_digests(
+        &mut self,
+        apps: &Apps,
+        watcher: &ImageWatcher,
+    ) -> Result<(), AppsError> {
+        debug!("Resolving the image digests of the watched services.");
+        let apps_with_services = apps.get_apps().await?;
+
+        // Digests only change for tags, e.g. `latest`, but not for images referenced by digest.
+        let watched_services = apps_with_services
+            .iter_all()
+            .filter(|(app_name, _)| watcher.watches(app_name))
+            .flat_map(|(app_name, services)| {
+                services
+                    .iter()
+                    .filter(|service| {
+                        service.container_type() == &ContainerType::Instance
+                            || service.container_type() == &ContainerType::Replica
+                    })
+                    .filter(|service| matches!(service.config().image(), Image::Named { .. }))
+                    .map(move |service| {
+                        (
+                            (app_name.clone(), service.service_name().clone()),
+                            service.config().image().clone(),
+                            service.image_digest().map(String::from),
+                        )
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        self.digests.retain(|key, _| {
+            watched_services
+                .iter()
+                .any(|(watched, _, _)| watched == key)
+        });
+
+        let images = watched_services
+            .iter()
+            .map(|(_, image, _)| image.clone())
+            .collect::<HashSet<_>>();
+        let mut digests = HashMap::with_capacity(images.len());
+        let registry = Registry::new(&self.config);
+        for image in images {
+            // Resolving each image on its own ensures that one unavailable image does not prevent
+            // the redeployment of the others.
+            match registry
+                .resolve_image_infos(&HashSet::from([image.clone()]))
+                .await
+            {
+                Ok(mut image_infos) => {
+                    if let Some(image_info) = image_infos.remove(&image) {
+                        digests.insert(
+                            image,
+                            (
+                                image_info.digest().clone(),
+                                image_info.config_digest().clone(),
+                            ),
+                        );
+                    }
+                }
+                Err(err) => warn!("Cannot resolve the digest of {}: {}", image, err),
+            }
+        }
+
+        let mut redeploys = 0;
+        for (key, image, running_digest) in watched_services {
+            let Some((digest, config_digest)) = digests.get(&image) else {
+                continue;
+            };
+
+            // Without a known digest, the digest of the running image is the baseline so that
+            // images that have been pushed while PREvant was not watching are picked up.
+            let known_digest = self.digests.get(&key).or(running_digest.as_ref());
+            if !refers_to_new_image(known_digest, digest, config_digest) {
+                if !self.digests.contains_key(&key) {
+                    trace!("Watching {} of {} with digest {}", key.1, key.0, digest);
+                }
+                self.digests.insert(key, digest.clone());
+                continue;
+            }
+
+            let (app_name, service_name) = &key;
+            if redeploys >= watcher.max_redeploys() {
+                debug!("Postponing the redeployment of {service_name} of {app_name}");
+                continue;
+            }
+            redeploys += 1;
+
+            info!("{image} of {service_name} of {app_name} refers to the new digest {digest}");
+            match apps.redeploy_service(app_name, service_name).await {
+                Ok(_) => {
+                    self.digests.insert(key, digest.clone());
+                }
+                Err(err) => warn!("Cannot redeploy {service_name} of {app_name}: {err}"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether the resolved digests of a tag refer to another image than the known digest.
+/// The known digest can be the digest of the image manifest or, for Docker, the digest of the
+/// image config. If no digest is known, the tag is considered unchanged.
+fn refers_to_new_image(
+    known_digest: Option<&String>,
+    manifest_digest: &str,
+    config_digest: &str,
+) -> bool {
+    match known_digest {
+        Some(known_digest) => known_digest != manifest_digest && known_digest != config_digest,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_not_detect_change_without_known_digest() {
+        assert!(!refers_to_new_image(
+            None,
+            "sha256:manifest",
+            "sha256:config"
+        ));
+    }
+
+    #[test]
+    fn should_not_detect_change_of_same_manifest_digest() {
+        let known_digest = String::from("sha256:manifest");
+        assert!(!refers_to_new_image(
+            Some(&known_digest),
+            "sha256:manifest",
+            "sha256:config"
+        ));
+    }
+
+    #[test]
+    fn should_not_detect_change_of_same_config_digest() {
+        let known_digest = String::from("sha256:config");
+        assert!(!refers_to_new_image(
+            Some(&known_digest),
+            "sha256:manifest",
+            "sha256:config"
+        ));
+    }
+
+    #[test]
+    fn should_detect_change_of_digest() {
+        let known_digest = String::from("sha256:old-manifest");
+        assert!(refers_to_new_image(
+            Some(&known_digest),
+            "sha256:manifest",
+            "sha256:config"
+        ));
+    }
+}
@@ -25,6 +25,8 @@
  */
 
 use crate::apps::{Apps, AppsError};
+use crate::config::Routing;
+use crate::metrics::{self, HostMetaCrawlResult};
 use crate::models::service::{Service, ServiceBuilder, ServiceStatus};
 use crate::models::{AppName, RequestInfo, WebHostMeta};
 use chrono::{DateTime, Utc};
@@ -40,9 +42,11 @@ use yansi::Paint;
 
 pub struct HostMetaCache {
     reader_factory: ReadHandleFactory<Key, Arc<Value>>,
+    routing: Routing,
 }
 pub struct HostMetaCrawler {
     writer: WriteHandle<Key, Arc<Value>>,
+    routing: Routing,
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -57,14 +61,15 @@ struct Value {
     web_host_meta: WebHostMeta,
 }
 
-pub fn new() -> (HostMetaCache, HostMetaCrawler) {
+pub fn new(routing: Routing) -> (HostMetaCache, HostMetaCrawler) {
     let (reader, writer) = evmap::new();
 
     (
         HostMetaCache {
             reader_factory: reader.factory(),
+            routing: routing.clone(),
         },
-        HostMetaCrawler { writer },
+        HostMetaCrawler { writer, routing },
     )
 }
 
@@ -85,14 +90,17 @@ impl HostMetaCache {
                     service_id: service.id().to_string(),
                 };
 
-                let mut b =
-                    ServiceBuilder::from(service).base_url(request_info.get_base_url().clone());
+                let base_url = request_info.get_base_url();
+                let service_url = self
+                    .routing
+                    .service_url(base_url, &key.app_name, service.service_name())
+                    .unwrap_or_else(|| base_url.clone());
+
+                let mut b = ServiceBuilder::from(service)
+                    .base_url(base_url.clone())
+                    .routing(self.routing.clone());
                 if let Some(value) = reader.get_one(&key) {
-                    b = b.web_host_meta(
-                        value
-                            .web_host_meta
-                            .with_base_url(request_info.get_base_url()),
-                    );
+                    b = b.web_host_meta(value.web_host_meta.with_base_url(&service_url));
                 }
 
                 assigned_apps.insert(key.app_name, b.build().unwrap());
@@ -157,8 +165,12 @@ impl HostMetaCrawler {
         );
         let now = Utc::now();
         let duration_prevant_startup = Utc::now().signed_duration_since(since_timestamp);
-        let resolved_host_meta_infos =
-            Self::resolve_host_meta(services_without_host_meta, duration_prevant_startup).await;
+        let resolved_host_meta_infos = Self::resolve_host_meta(
+            services_without_host_meta,
+            &self.routing,
+            duration_prevant_startup,
+        )
+        .await;
         for (key, _service, web_host_meta) in resolved_host_meta_infos {
             if !web_host_meta.is_valid() {
                 continue;
@@ -218,6 +230,7 @@ impl HostMetaCrawler {
 
     async fn resolve_host_meta(
         services_without_host_meta: Vec<(Key, Service)>,
+        routing: &Routing,
         duration_prevant_startup: chrono::Duration,
     ) -> Vec<(Key, Service, WebHostMeta)> {
         let number_of_services = services_without_host_meta.len();
@@ -229,8 +242,16 @@ impl HostMetaCrawler {
 
         for (key, service) in services_without_host_meta {
             let tx = tx.clone();
+            let forwarded_prefix =
+                routing.forwarded_prefix(service.app_name(), service.service_name());
             tokio::spawn(async move {
-                let r = Self::resolve_web_host_meta(key, service, duration_prevant_startup).await;
+                let r = Self::resolve_web_host_meta(
+                    key,
+                    service,
+                    forwarded_prefix,
+                    duration_prevant_startup,
+                )
+                .await;
                 if let Err(err) = tx.send(r).await {
                     error!("Cannot send host meta result: {}", err);
                 }
@@ -249,6 +270,7 @@ impl HostMetaCrawler {
     async fn resolve_web_host_meta(
         key: Key,
         service: Service,
+        forwarded_prefix: Option<String>,
         duration_prevant_startup: chrono::Duration,
     ) -> (Key, Service, WebHostMeta) {
         let url = match service.endpoint_url() {
@@ -256,7 +278,7 @@ impl HostMetaCrawler {
             Some(endpoint_url) => endpoint_url.join(".well-known/host-meta.json").unwrap(),
         };
 
-        let get_request = reqwest::Client::builder()
+        let mut request = reqwest::Client::builder()
             .connect_timeout(Duration::from_millis(500))
             .timeout(Duration::from_millis(750))
             .user_agent(format!("PREvant/{}", clap::crate_version!()))
@@ -264,18 +286,20 @@ impl HostMetaCrawler {
             .unwrap()
             .get(&url.to_string())
             .header("Forwarded", "host=www.prevant.example.com;proto=http")
-            .header(
-                "X-Forwarded-Prefix",
-                format!("/{}/{}", service.app_name(), service.service_name()),
-            )
-            .header("Accept", "application/json")
-            .send()
-            .await;
+            .header("Accept", "application/json");
+        if let Some(forwarded_prefix) = forwarded_prefix {
+            request = request.header("X-Forwarded-Prefix", forwarded_prefix);
+        }
+        let get_request = request.send().await;
 
         let meta = match get_request {
             Ok(response) => match response.json::<WebHostMeta>().await {
-                Ok(meta) => meta,
+                Ok(meta) => {
+                    metrics::observe_host_meta_crawl(HostMetaCrawlResult::Resolved);
+                    meta
+                }
                 Err(err) => {
+                    metrics::observe_host_meta_crawl(HostMetaCrawlResult::Unparsable);
                     error!(
                         "Cannot parse host meta for service {} of {}: {}",
                         Paint::magenta(service.service_name()),
@@ -286,6 +310,7 @@ impl HostMetaCrawler {
                 }
             },
             Err(err) => {
+                metrics::observe_host_meta_crawl(HostMetaCrawlResult::Unreachable);
                 debug!(
                     "Cannot acquire host meta for service {} of {}: {}",
                     Paint::magenta(service.service_name()),
@@ -0,0 +1,532 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+
+use crate::apps::Apps;
+use crate::config::{Config, LogStore};
+use crate::models::AppName;
+use chrono::{DateTime, FixedOffset};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio::time::interval;
+
+const LOG_FILE_EXTENSION: &str = "log";
+/// The number of bytes at the end of a log file that are read first to find its last log lines.
+const TAIL_SIZE: u64 = 64 * 1024;
+
+/// Stores the log lines of services in one file per service (see [`LogStore`]). Each line of a
+/// file contains the timestamp and the log line as JSON array.
+pub struct LogArchive {
+    log_store: LogStore,
+    /// The last archived log lines of each service, which prevent that log lines are archived
+    /// twice.
+    archive_ends: Mutex<HashMap<(AppName, String), Option<ArchiveEnd>>>,
+}
+
+/// The log lines with the latest timestamp of an archived service. Log lines with that timestamp
+/// can still arrive and they are only archived if they differ from these lines.
+#[derive(Clone, Debug, PartialEq)]
+struct ArchiveEnd {
+    timestamp: DateTime<FixedOffset>,
+    log_lines: Vec<String>,
+}
+
+impl LogArchive {
+    pub fn new(log_store: LogStore) -> Self {
+        Self {
+            log_store,
+            archive_ends: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn app_directory(&self, app_name: &AppName) -> Result<PathBuf, LogArchiveError> {
+        Ok(self.log_store.path().join(file_name(app_name)?))
+    }
+
+    fn log_file(&self, app_name: &AppName, service_name: &str) -> Result<PathBuf, LogArchiveError> {
+        Ok(self
+            .app_directory(app_name)?
+            .join(format!("{}.{LOG_FILE_EXTENSION}", file_name(service_name)?)))
+    }
+
+    /// Returns the timestamp of the last archived log line of the service.
+    pub async fn last_timestamp(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<DateTime<FixedOffset>>, LogArchiveError> {
+        let mut archive_ends = self.archive_ends.lock().await;
+        Ok(self
+            .archive_end_of(&mut archive_ends, app_name, service_name)
+            .await?
+            .map(|archive_end| archive_end.timestamp))
+    }
+
+    async fn archive_end_of(
+        &self,
+        archive_ends: &mut HashMap<(AppName, String), Option<ArchiveEnd>>,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<ArchiveEnd>, LogArchiveError> {
+        let key = (app_name.clone(), service_name.to_string());
+        if let Some(archive_end) = archive_ends.get(&key) {
+            return Ok(archive_end.clone());
+        }
+
+        let archive_end = read_archive_end(&self.log_file(app_name, service_name)?).await?;
+        archive_ends.insert(key, archive_end.clone());
+        Ok(archive_end)
+    }
+
+    /// Appends the log lines of the service that have not been archived yet. Log lines with the
+    /// timestamp of the last archived log lines are compared line by line because services can
+    /// write multiple lines at the same time.
+    pub async fn append(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        log_lines: &[(DateTime<FixedOffset>, String)],
+    ) -> Result<(), LogArchiveError> {
+        let mut archive_ends = self.archive_ends.lock().await;
+        let mut archive_end = self
+            .archive_end_of(&mut archive_ends, app_name, service_name)
+            .await?;
+        // The archived log lines that the given log lines can still repeat.
+        let mut archived_lines = archive_end
+            .as_ref()
+            .map(|archive_end| archive_end.log_lines.clone())
+            .unwrap_or_default();
+
+        let mut content = String::new();
+        for log_line in log_lines {
+            match &mut archive_end {
+                Some(end) if log_line.0 < end.timestamp => continue,
+                Some(end) if log_line.0 == end.timestamp => {
+                    if let Some(index) = archived_lines.iter().position(|line| line == &log_line.1)
+                    {
+                        archived_lines.remove(index);
+                        continue;
+                    }
+                    end.log_lines.push(log_line.1.clone());
+                }
+                _ => {
+                    archive_end = Some(ArchiveEnd {
+                        timestamp: log_line.0,
+                        log_lines: vec![log_line.1.clone()],
+                    });
+                    archived_lines.clear();
+                }
+            }
+            content.push_str(&serde_json::to_string(log_line)?);
+            content.push('\n');
+        }
+        if content.is_empty() {
+            return Ok(());
+        }
+
+        let last_timestamp = archive_end
+            .as_ref()
+            .map(|archive_end| archive_end.timestamp);
+        trace!("Archiving the logs of {service_name} of {app_name} until {last_timestamp:?}");
+        tokio::fs::create_dir_all(self.app_directory(app_name)?).await?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_file(app_name, service_name)?)
+            .await?;
+        file.write_all(content.as_bytes()).await?;
+        file.flush().await?;
+
+        archive_ends.insert((app_name.clone(), service_name.to_string()), archive_end);
+        Ok(())
+    }
+
+    /// Reads the archived log lines of the service since the given timestamp.
+    pub async fn read(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        since: &Option<DateTime<FixedOffset>>,
+    ) -> Result<Vec<(DateTime<FixedOffset>, String)>, LogArchiveError> {
+        let file = match tokio::fs::File::open(self.log_file(app_name, service_name)?).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut log_lines = Vec::new();
+        let mut lines = BufReader::new(file).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                continue;
+            }
+            let log_line: (DateTime<FixedOffset>, String) = serde_json::from_str(&line)?;
+            if since.map_or(true, |since| log_line.0 >= since) {
+                log_lines.push(log_line);
+            }
+        }
+        Ok(log_lines)
+    }
+
+    /// Returns the names of the services of the app that have archived logs.
+    pub async fn service_names(&self, app_name: &AppName) -> Result<Vec<String>, LogArchiveError> {
+        let mut entries = match tokio::fs::read_dir(self.app_directory(app_name)?).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut service_names = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension() != Some(OsStr::new(LOG_FILE_EXTENSION)) {
+                continue;
+            }
+            if let Some(service_name) = path.file_stem() {
+                service_names.push(service_name.to_string_lossy().into_owned());
+            }
+        }
+        service_names.sort();
+        Ok(service_names)
+    }
+
+    /// Removes the archived logs of the services that have not been appended to within the
+    /// retention.
+    pub async fn purge(&self) -> Result<(), LogArchiveError> {
+        let mut archive_ends = self.archive_ends.lock().await;
+
+        let mut app_directories = match tokio::fs::read_dir(self.log_store.path()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        while let Some(app_directory) = app_directories.next_entry().await? {
+            if !app_directory.file_type().await?.is_dir() {
+                continue;
+            }
+
+            let mut remaining_files = 0;
+            let mut files = tokio::fs::read_dir(app_directory.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let modified = file.metadata().await?.modified()?;
+                if modified.elapsed().unwrap_or_default() >= self.log_store.retention() {
+                    debug!("Removing the archived logs {}", file.path().display());
+                    tokio::fs::remove_file(file.path()).await?;
+                } else {
+                    remaining_files += 1;
+                }
+            }
+
+            if remaining_files == 0 {
+                tokio::fs::remove_dir(app_directory.path()).await?;
+            }
+        }
+
+        // Removed files must be read again to determine their last log lines.
+        archive_ends.clear();
+        Ok(())
+    }
+}
+
+/// Periodically archives the logs of previous containers, e.g. of containers that crashed, and
+/// removes the archived logs that exceed the retention (see [`LogStore`]).
+pub struct LogRetainer {
+    log_store: Option<LogStore>,
+}
+
+impl LogRetainer {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            log_store: config.log_store().cloned(),
+        }
+    }
+
+    /// Starts the retention of logs if the [`LogStore`] is configured.
+    pub fn spawn(self, apps: Arc<Apps>) {
+        let Some(log_store) = self.log_store else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let mut interval = interval(log_store.interval());
+            loop {
+                interval.tick().await;
+                if let Err(err) = apps.archive_previous_logs().await {
+                    error!("Cannot archive the logs of previous containers: {}", err);
+                }
+            }
+        });
+    }
+}
+
+/// Reads the log lines with the latest timestamp from the end of the log file. Only the tail of
+/// the file is read, which grows until it contains a log line with an earlier timestamp.
+async fn read_archive_end(log_file: &Path) -> Result<Option<ArchiveEnd>, LogArchiveError> {
+    let mut file = match tokio::fs::File::open(log_file).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let length = file.metadata().await?.len();
+
+    let mut tail_size = TAIL_SIZE;
+    loop {
+        let start = length.saturating_sub(tail_size);
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).await?;
+
+        // The first line of the tail is incomplete unless the tail is the whole file.
+        let mut lines = tail.split(|byte| *byte == b'\n');
+        if start > 0 {
+            lines.next();
+        }
+        let log_lines = lines
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice::<(DateTime<FixedOffset>, String)>)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(timestamp) = log_lines.last().map(|(timestamp, _)| *timestamp) {
+            if start == 0 || log_lines.iter().any(|(t, _)| *t < timestamp) {
+                return Ok(Some(ArchiveEnd {
+                    timestamp,
+                    log_lines: log_lines
+                        .into_iter()
+                        .filter(|(t, _)| *t == timestamp)
+                        .map(|(_, log_line)| log_line)
+                        .collect(),
+                }));
+            }
+        }
+        if start == 0 {
+            return Ok(None);
+        }
+        tail_size *= 2;
+    }
+}
+
+/// Ensures that names of apps and services cannot refer to files outside of the log store.
+fn file_name(name: &str) -> Result<&str, LogArchiveError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(LogArchiveError::InvalidName {
+            name: name.to_string(),
+        });
+    }
+    Ok(name)
+}
+
+#[derive(Debug, Fail)]
+pub enum LogArchiveError {
+    #[fail(display = "Cannot archive logs under the name {}", name)]
+    InvalidName { name: String },
+    #[fail(display = "Cannot access the archived logs: {}", err)]
+    Storage { err: String },
+    #[fail(display = "Invalid archived log line: {}", err)]
+    InvalidLogLine { err: String },
+}
+
+impl From<std::io::Error> for LogArchiveError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Storage {
+            err: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for LogArchiveError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::InvalidLogLine {
+            err: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn log_archive(directory: &tempfile::TempDir, retention_hours: u64) -> LogArchive {
+        let config = crate::config_from_str!(&format!(
+            r#"
+            [logStore]
+            path = {:?}
+            retentionHours = {retention_hours}
+            "#,
+            directory.path()
+        ));
+        LogArchive::new(config.log_store().unwrap().clone())
+    }
+
+    fn log_line(timestamp: &str, line: &str) -> (DateTime<FixedOffset>, String) {
+        (
+            DateTime::parse_from_rfc3339(timestamp).unwrap(),
+            String::from(line),
+        )
+    }
+
+    #[tokio::test]
+    async fn should_append_log_lines_once() -> Result<(), LogArchiveError> {
+        let directory = tempfile::tempdir()?;
+        let archive = log_archive(&directory, 24);
+        let app_name = AppName::master();
+
+        archive
+            .append(
+                &app_name,
+                "db",
+                &[
+                    log_line("2019-07-18T07:25:00Z", "starting\n"),
+                    log_line("2019-07-18T07:30:00Z", "ready\n"),
+                ],
+            )
+            .await?;
+        archive
+            .append(
+                &app_name,
+                "db",
+                &[
+                    log_line("2019-07-18T07:30:00Z", "ready\n"),
+                    log_line("2019-07-18T07:35:00Z", "shutting down\n"),
+                ],
+            )
+            .await?;
+
+        assert_eq!(
+            archive
+                .read(
+                    &app_name,
+                    "db",
+                    &Some(DateTime::parse_from_rfc3339("2019-07-18T07:30:00Z").unwrap())
+                )
+                .await?,
+            vec![
+                log_line("2019-07-18T07:30:00Z", "ready\n"),
+                log_line("2019-07-18T07:35:00Z", "shutting down\n"),
+            ]
+        );
+        assert_eq!(archive.service_names(&app_name).await?, vec!["db"]);
+
+        // A new archive must not archive the log lines again.
+        let archive = log_archive(&directory, 24);
+        archive
+            .append(
+                &app_name,
+                "db",
+                &[log_line("2019-07-18T07:35:00Z", "shutting down\n")],
+            )
+            .await?;
+        assert_eq!(archive.read(&app_name, "db", &None).await?.len(), 3);
+        assert!(archive
+            .read(&AppName::from_str("..").unwrap(), "db", &None)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_purge_log_lines_exceeding_retention() -> Result<(), LogArchiveError> {
+        let directory = tempfile::tempdir()?;
+        let app_name = AppName::master();
+
+        let archive = log_archive(&directory, 24);
+        archive
+            .append(
+                &app_name,
+                "db",
+                &[log_line("2019-07-18T07:25:00Z", "starting\n")],
+            )
+            .await?;
+        archive.purge().await?;
+        assert_eq!(archive.service_names(&app_name).await?, vec!["db"]);
+
+        let archive = log_archive(&directory, 0);
+        archive.purge().await?;
+        assert!(archive.service_names(&app_name).await?.is_empty());
+        assert!(archive.read(&app_name, "db", &None).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_append_log_lines_with_same_timestamp_once() -> Result<(), LogArchiveError> {
+        let directory = tempfile::tempdir()?;
+        let archive = log_archive(&directory, 24);
+        let app_name = AppName::master();
+
+        // The long log line makes the archive read more than the first tail of the file.
+        let long_line = format!("{}\n", "x".repeat(TAIL_SIZE as usize));
+        archive
+            .append(
+                &app_name,
+                "db",
+                &[
+                    log_line("2019-07-18T07:25:00Z", &long_line),
+                    log_line("2019-07-18T07:30:00Z", "ready\n"),
+                    log_line("2019-07-18T07:30:00Z", &long_line),
+                ],
+            )
+            .await?;
+
+        let archive = log_archive(&directory, 24);
+        archive
+            .append(
+                &app_name,
+                "db",
+                &[
+                    log_line("2019-07-18T07:25:00Z", &long_line),
+                    log_line("2019-07-18T07:30:00Z", "ready\n"),
+                    log_line("2019-07-18T07:30:00Z", &long_line),
+                    log_line("2019-07-18T07:30:00Z", "accepting connections\n"),
+                    log_line("2019-07-18T07:35:00Z", "shutting down\n"),
+                ],
+            )
+            .await?;
+
+        assert_eq!(
+            archive.read(&app_name, "db", &None).await?,
+            vec![
+                log_line("2019-07-18T07:25:00Z", &long_line),
+                log_line("2019-07-18T07:30:00Z", "ready\n"),
+                log_line("2019-07-18T07:30:00Z", &long_line),
+                log_line("2019-07-18T07:30:00Z", "accepting connections\n"),
+                log_line("2019-07-18T07:35:00Z", "shutting down\n"),
+            ]
+        );
+        assert_eq!(
+            archive.last_timestamp(&app_name, "db").await?,
+            Some(DateTime::parse_from_rfc3339("2019-07-18T07:35:00Z").unwrap())
+        );
+
+        Ok(())
+    }
+}
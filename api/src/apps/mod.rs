@@ -23,35 +23,51 @@
  * THE SOFTWARE.
  * =========================LICENSE_END==================================
  */
+mod digest_watcher;
 mod host_meta_cache;
+mod log_archive;
 mod routes;
 
 pub use crate::apps::AppsService as Apps;
 pub use crate::apps::AppsServiceError as AppsError;
-use crate::config::{Config, ConfigError};
+use crate::config::{Config, ConfigError, DeploymentQueueing, RoutingError};
 use crate::deployment::deployment_unit::DeploymentUnitBuilder;
-use crate::infrastructure::Infrastructure;
+use crate::deployment::DeploymentUnit;
+use crate::infrastructure::{DeploymentTargetError, Infrastructure, UnsupportedLogStreams};
+use crate::metrics::{self, DeploymentStage, Operation};
 use crate::models::service::{ContainerType, Service, ServiceStatus};
-use crate::models::{AppName, AppStatusChangeId, LogChunk, ServiceConfig};
+use crate::models::{
+    AppName, AppStatusChangeId, ExecOutput, ExecSession, LogChunk, LogFilter, LogStreams,
+    ServiceConfig,
+};
 use crate::registry::Registry;
 use crate::registry::RegistryError;
+use async_stream::stream;
 use chrono::{DateTime, FixedOffset};
-use futures::stream::BoxStream;
+pub use digest_watcher::DigestWatcher;
+use futures::future;
+use futures::stream::{self, BoxStream};
 use futures::StreamExt;
 use handlebars::RenderError;
 pub use host_meta_cache::new as host_meta_crawling;
 pub use host_meta_cache::HostMetaCache;
+pub use log_archive::LogRetainer;
+use log_archive::{LogArchive, LogArchiveError};
 use multimap::MultiMap;
-pub use routes::{apps_routes, delete_app_sync};
+pub use routes::{apps_routes, delete_app_sync, OidcSessionValidator};
 use std::collections::{HashMap, HashSet};
 use std::convert::From;
+use std::future::Future;
 use std::sync::{Arc, Condvar, Mutex};
 use std::time::Duration;
+use tokio::sync::oneshot;
 
 pub struct AppsService {
     config: Config,
     infrastructure: Box<dyn Infrastructure>,
     app_guards: Mutex<HashMap<AppName, Arc<AppGuard>>>,
+    deployment_queues: Mutex<HashMap<AppName, Arc<DeploymentQueue>>>,
+    log_archive: Option<LogArchive>,
 }
 
 type GuardedResult = Result<Vec<Service>, AppsServiceError>;
@@ -127,15 +143,145 @@ impl AppGuard {
     }
 }
 
+/// Serializes the deployments and deletions of an app if concurrent deployment requests are
+/// queued instead of being rejected (see [`DeploymentQueueing`]).
+struct DeploymentQueue {
+    /// Tokio's mutex grants the lock in FIFO order.
+    lock: tokio::sync::Mutex<()>,
+    /// The deployment requests waiting for the lock in arrival order.
+    waiting: Mutex<Vec<QueuedRequest>>,
+    /// The superseded requests in arrival order that the next processed request with the same
+    /// options deploys together with its own one.
+    superseded: Mutex<Vec<SupersededRequest>>,
+}
+
+/// The options of a deployment request besides the service configurations. Only requests with
+/// equal options can be coalesced.
+#[derive(Clone, PartialEq)]
+struct DeploymentOptions {
+    replicate_from: Option<AppName>,
+    deployment_target: Option<String>,
+}
+
+struct QueuedRequest {
+    status_id: AppStatusChangeId,
+    options: DeploymentOptions,
+}
+
+struct SupersededRequest {
+    status_id: AppStatusChangeId,
+    options: DeploymentOptions,
+    service_configs: Vec<ServiceConfig>,
+    result: oneshot::Sender<GuardedResult>,
+}
+
+/// Removes a request from the waiting requests of the queue when the request acquired the lock
+/// or when the request has been dropped while waiting for it.
+struct WaitingRequest<'a> {
+    queue: &'a DeploymentQueue,
+    status_id: AppStatusChangeId,
+}
+
+impl Drop for WaitingRequest<'_> {
+    fn drop(&mut self) {
+        self.queue
+            .waiting
+            .lock()
+            .unwrap()
+            .retain(|request| request.status_id != self.status_id);
+    }
+}
+
+impl DeploymentQueue {
+    fn new() -> Self {
+        Self {
+            lock: tokio::sync::Mutex::new(()),
+            waiting: Mutex::new(Vec::new()),
+            superseded: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn position(&self, status_id: &AppStatusChangeId) -> Option<usize> {
+        self.waiting
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|request| &request.status_id == status_id)
+            .map(|index| index + 1)
+    }
+
+    fn enqueue(
+        &self,
+        status_id: AppStatusChangeId,
+        options: DeploymentOptions,
+    ) -> WaitingRequest<'_> {
+        self.waiting
+            .lock()
+            .unwrap()
+            .push(QueuedRequest { status_id, options });
+        WaitingRequest {
+            queue: self,
+            status_id,
+        }
+    }
+
+    /// Returns true if the next waiting request can supersede a request with the given options.
+    fn is_superseded(&self, options: &DeploymentOptions) -> bool {
+        self.waiting
+            .lock()
+            .unwrap()
+            .first()
+            .map_or(false, |request| &request.options == options)
+    }
+
+    fn is_pending(&self, status_id: &AppStatusChangeId) -> bool {
+        self.superseded
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|request| &request.status_id == status_id)
+    }
+
+    /// Removes the superseded requests with the given options from the queue.
+    fn take_superseded(&self, options: &DeploymentOptions) -> Vec<SupersededRequest> {
+        let mut superseded = self.superseded.lock().unwrap();
+        let (taken, remaining): (Vec<_>, Vec<_>) = superseded
+            .drain(..)
+            .partition(|request| &request.options == options);
+        *superseded = remaining;
+        taken
+    }
+}
+
+/// Merges the configurations of the superseded requests into the configurations of the request
+/// that supersedes them. Newer configurations of a service replace older ones.
+fn merge_superseded_configs(
+    superseded: &[SupersededRequest],
+    service_configs: &[ServiceConfig],
+) -> Vec<ServiceConfig> {
+    let mut configs = Vec::<ServiceConfig>::new();
+    for config in superseded
+        .iter()
+        .flat_map(|request| request.service_configs.iter())
+        .chain(service_configs.iter())
+    {
+        configs.retain(|c| c.service_name() != config.service_name());
+        configs.push(config.clone());
+    }
+    configs
+}
+
 impl AppsService {
     pub fn new(
         config: Config,
         infrastructure: Box<dyn Infrastructure>,
     ) -> Result<AppsService, AppsServiceError> {
         Ok(AppsService {
+            log_archive: config.log_store().cloned().map(LogArchive::new),
             config,
             infrastructure,
             app_guards: Mutex::new(HashMap::new()),
+            deployment_queues: Mutex::new(HashMap::new()),
         })
     }
 
@@ -198,10 +344,43 @@ impl AppsService {
             .collect::<Vec<ServiceConfig>>())
     }
 
+    fn deployment_queue(&self, app_name: &AppName) -> Arc<DeploymentQueue> {
+        self.deployment_queues
+            .lock()
+            .unwrap()
+            .entry(app_name.clone())
+            .or_insert_with(|| Arc::new(DeploymentQueue::new()))
+            .clone()
+    }
+
+    fn release_deployment_queue(&self, app_name: &AppName, queue: Arc<DeploymentQueue>) {
+        let mut queues = self.deployment_queues.lock().unwrap();
+        // Only the map and this function refer to the queue, i.e. no request is waiting.
+        if Arc::strong_count(&queue) == 2 {
+            queues.remove(app_name);
+        }
+    }
+
+    /// Returns the position of the deployment request in the queue of the app, starting with 1
+    /// for the request that is processed next.
+    pub fn queue_position(
+        &self,
+        app_name: &AppName,
+        status_id: &AppStatusChangeId,
+    ) -> Option<usize> {
+        let queues = self.deployment_queues.lock().unwrap();
+        queues.get(app_name)?.position(status_id)
+    }
+
     pub async fn wait_for_status_change(
         &self,
+        app_name: &AppName,
         status_id: &AppStatusChangeId,
     ) -> Result<Vec<Service>, AppsServiceError> {
+        while self.queue_position(app_name, status_id).is_some() {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+
         let mut services = Vec::new();
         while let Some(s) = self
             .infrastructure
@@ -223,13 +402,49 @@ impl AppsService {
     ///
     /// # Arguments
     /// * `replicate_from` - The application name that is used as a template.
+    /// * `deployment_target` - The deployment target that a new app is placed on instead of the
+    ///   one chosen by the placement rules.
     pub async fn create_or_update(
         &self,
         app_name: &AppName,
         status_id: &AppStatusChangeId,
         replicate_from: Option<AppName>,
         service_configs: &[ServiceConfig],
+        deployment_target: Option<String>,
+    ) -> Result<Vec<Service>, AppsServiceError> {
+        let result = self
+            .create_or_update_guarded(
+                app_name,
+                status_id,
+                replicate_from,
+                service_configs,
+                deployment_target,
+            )
+            .await;
+        metrics::observe_operation(Operation::Deployment, &result);
+        result
+    }
+
+    async fn create_or_update_guarded(
+        &self,
+        app_name: &AppName,
+        status_id: &AppStatusChangeId,
+        replicate_from: Option<AppName>,
+        service_configs: &[ServiceConfig],
+        deployment_target: Option<String>,
     ) -> Result<Vec<Service>, AppsServiceError> {
+        if self.config.deployment_queueing() != &DeploymentQueueing::Reject {
+            return self
+                .create_or_update_queued(
+                    app_name,
+                    status_id,
+                    replicate_from,
+                    service_configs,
+                    deployment_target,
+                )
+                .await;
+        }
+
         let guard = self.create_or_get_app_guard(app_name.clone(), AppGuardKind::Deployment)?;
 
         if !guard.is_first() {
@@ -240,18 +455,130 @@ impl AppsService {
 
         guard.notify_with_result(
             self,
-            self.create_or_update_impl(app_name, status_id, replicate_from, service_configs)
-                .await,
+            self.create_or_update_impl(
+                app_name,
+                status_id,
+                replicate_from,
+                service_configs,
+                deployment_target,
+            )
+            .await,
         )
     }
 
+    async fn create_or_update_queued(
+        &self,
+        app_name: &AppName,
+        status_id: &AppStatusChangeId,
+        replicate_from: Option<AppName>,
+        service_configs: &[ServiceConfig],
+        deployment_target: Option<String>,
+    ) -> Result<Vec<Service>, AppsServiceError> {
+        let options = DeploymentOptions {
+            replicate_from,
+            deployment_target,
+        };
+        let queue = self.deployment_queue(app_name);
+
+        let waiting = queue.enqueue(*status_id, options.clone());
+        let permit = queue.lock.lock().await;
+        drop(waiting);
+
+        let superseded = self.config.deployment_queueing() == &DeploymentQueueing::Coalesce
+            && queue.is_superseded(&options);
+
+        let result = if superseded {
+            debug!("Deployment {status_id} of {app_name} has been superseded by a newer one");
+
+            // The newer deployment deploys the services of this request as well and sends its
+            // result to this request.
+            let (sender, mut receiver) = oneshot::channel();
+            queue.superseded.lock().unwrap().push(SupersededRequest {
+                status_id: *status_id,
+                options: options.clone(),
+                service_configs: service_configs.to_vec(),
+                result: sender,
+            });
+            drop(permit);
+
+            let cancelled = || AppsServiceError::DeploymentCancelled {
+                app_name: app_name.clone(),
+            };
+            tokio::select! {
+                biased;
+
+                result = &mut receiver => result.unwrap_or_else(|_| Err(cancelled())),
+                permit = queue.lock.lock() => {
+                    if queue.is_pending(status_id) {
+                        // The newer request has been dropped before it took over this one.
+                        let result = self
+                            .deploy_queued(&queue, app_name, status_id, &options, &[])
+                            .await;
+                        drop(permit);
+                        result
+                    } else {
+                        // The request that took over this one sends the result before it
+                        // releases the lock.
+                        drop(permit);
+                        receiver.await.unwrap_or_else(|_| Err(cancelled()))
+                    }
+                }
+            }
+        } else {
+            let result = self
+                .deploy_queued(&queue, app_name, status_id, &options, service_configs)
+                .await;
+            drop(permit);
+            result
+        };
+
+        self.release_deployment_queue(app_name, queue);
+        result
+    }
+
+    /// Deploys the service configurations together with the ones of the superseded requests
+    /// with the same options and passes the result on to them. The caller must hold the lock of
+    /// the queue.
+    async fn deploy_queued(
+        &self,
+        queue: &DeploymentQueue,
+        app_name: &AppName,
+        status_id: &AppStatusChangeId,
+        options: &DeploymentOptions,
+        service_configs: &[ServiceConfig],
+    ) -> GuardedResult {
+        let superseded = queue.take_superseded(options);
+        let service_configs = merge_superseded_configs(&superseded, service_configs);
+
+        let result = self
+            .create_or_update_impl(
+                app_name,
+                status_id,
+                options.replicate_from.clone(),
+                &service_configs,
+                options.deployment_target.clone(),
+            )
+            .await;
+
+        for request in superseded {
+            // The superseded request might have been dropped in the meantime.
+            let _ = request.result.send(result.clone());
+        }
+        result
+    }
+
     async fn create_or_update_impl(
         &self,
         app_name: &AppName,
         status_id: &AppStatusChangeId,
         replicate_from: Option<AppName>,
         service_configs: &[ServiceConfig],
+        deployment_target: Option<String>,
     ) -> Result<Vec<Service>, AppsServiceError> {
+        let infrastructure = self
+            .placement(app_name, deployment_target.as_deref())
+            .await?;
+
         let mut configs = service_configs.to_vec();
 
         let replicate_from_app_name = replicate_from.unwrap_or_else(AppName::master);
@@ -275,23 +602,33 @@ impl AppsService {
             })
             .collect::<Vec<_>>();
 
+        let timer = metrics::time_deployment_stage(DeploymentStage::Configuration);
         let deployment_unit_builder = DeploymentUnitBuilder::init(app_name.clone(), configs)
+            .with_kubernetes_client(infrastructure.kubernetes_client().await?)
             .extend_with_config(&self.config)
+            .await?
             .extend_with_templating_only_service_configs(configs_for_templating);
+        timer.observe_duration();
 
+        let timer = metrics::time_deployment_stage(DeploymentStage::ImageResolution);
         let images = deployment_unit_builder.images();
         let image_infos = Registry::new(&self.config)
             .resolve_image_infos(&images)
             .await?;
+        timer.observe_duration();
 
+        let timer = metrics::time_deployment_stage(DeploymentStage::Templating);
         let deployment_unit_builder = deployment_unit_builder
             .extend_with_image_infos(image_infos)
-            .apply_templating()?
-            .apply_hooks(&self.config)
-            .await?;
+            .apply_templating()?;
+        timer.observe_duration();
+
+        let timer = metrics::time_deployment_stage(DeploymentStage::Hooks);
+        let deployment_unit_builder = deployment_unit_builder.apply_hooks(&self.config).await?;
+        timer.observe_duration();
 
         let deployment_unit = if let Ok(Some(base_traefik_ingress_route)) =
-            self.infrastructure.base_traefik_ingress_route().await
+            infrastructure.base_traefik_ingress_route(app_name).await
         {
             trace!(
                 "The base URL for {app_name} is: {:?}",
@@ -306,23 +643,63 @@ impl AppsService {
             deployment_unit_builder.build()
         };
 
-        let services = self
-            .infrastructure
+        self.config.routing().check_host_names(
+            app_name,
+            deployment_unit
+                .services()
+                .iter()
+                .map(|service| service.service_name().as_str()),
+        )?;
+
+        self.archive_logs_of_replaced_services(app_name, &deployment_unit)
+            .await;
+        let timer = metrics::time_deployment_stage(DeploymentStage::Deployment);
+        let services = infrastructure
             .deploy_services(
                 &status_id.to_string(),
                 &deployment_unit,
                 &self.config.container_config(),
             )
             .await?;
+        timer.observe_duration();
 
         Ok(services)
     }
 
+    /// Returns the infrastructure that deploys the app, which is only different from
+    /// `self.infrastructure` if PREvant manages multiple deployment targets.
+    async fn placement(
+        &self,
+        app_name: &AppName,
+        deployment_target: Option<&str>,
+    ) -> Result<&dyn Infrastructure, AppsServiceError> {
+        let placement = self
+            .infrastructure
+            .placement(app_name, deployment_target)
+            .await
+            .map_err(|err| match err.downcast::<DeploymentTargetError>() {
+                Ok(error) => AppsServiceError::InvalidDeploymentTarget { error },
+                Err(err) => AppsServiceError::from(err),
+            })?;
+
+        Ok(placement.unwrap_or(&*self.infrastructure))
+    }
+
     /// Deletes all services for the given `app_name`.
     pub async fn delete_app(
         &self,
         app_name: &AppName,
         status_id: &AppStatusChangeId,
+    ) -> Result<Vec<Service>, AppsServiceError> {
+        let result = self.delete_app_guarded(app_name, status_id).await;
+        metrics::observe_operation(Operation::Deletion, &result);
+        result
+    }
+
+    async fn delete_app_guarded(
+        &self,
+        app_name: &AppName,
+        status_id: &AppStatusChangeId,
     ) -> Result<Vec<Service>, AppsServiceError> {
         let guard = self.create_or_get_app_guard(app_name.clone(), AppGuardKind::Deletion)?;
 
@@ -338,10 +715,28 @@ impl AppsService {
         app_name: &AppName,
         status_id: &AppStatusChangeId,
     ) -> Result<Vec<Service>, AppsServiceError> {
+        let queue = match self.config.deployment_queueing() {
+            DeploymentQueueing::Reject => None,
+            DeploymentQueueing::Queue | DeploymentQueueing::Coalesce => {
+                Some(self.deployment_queue(app_name))
+            }
+        };
+
+        let permit = match &queue {
+            Some(queue) => Some(queue.lock.lock().await),
+            None => None,
+        };
+        self.archive_logs(app_name, None).await;
         let services = self
             .infrastructure
             .stop_services(&status_id.to_string(), app_name)
-            .await?;
+            .await;
+        drop(permit);
+        if let Some(queue) = queue {
+            self.release_deployment_queue(app_name, queue);
+        }
+
+        let services = services?;
         if services.is_empty() {
             Err(AppsServiceError::AppNotFound {
                 app_name: app_name.clone(),
@@ -351,345 +746,1537 @@ impl AppsService {
         }
     }
 
-    pub async fn stream_logs<'a>(
-        &'a self,
-        app_name: &'a AppName,
-        service_name: &'a str,
-        since: &'a Option<DateTime<FixedOffset>>,
-        limit: &'a Option<usize>,
-    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
-        self.infrastructure
-            .get_logs(app_name, service_name, since, limit, true)
-            .await
-    }
-
-    pub async fn get_logs<'a>(
-        &'a self,
-        app_name: &'a AppName,
-        service_name: &'a str,
-        since: &'a Option<DateTime<FixedOffset>>,
-        limit: &'a Option<usize>,
-    ) -> Result<Option<LogChunk>, AppsServiceError> {
-        let mut log_lines = Vec::new();
-        let mut log_stream = self
-            .infrastructure
-            .get_logs(app_name, service_name, since, limit, false)
+    /// Removes a single instance or replica from the app and redeploys the companions of the app
+    /// so that their templates do not refer to the removed service anymore. Returns the removed
+    /// service.
+    ///
+    /// # Arguments
+    /// * `replicate_from` - The application whose service replaces a removed instance as replica.
+    ///   Without it, the service is not replaced.
+    /// * `remove_volumes` - Whether the volumes of the removed service are deleted as well.
+    pub async fn delete_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        status_id: &AppStatusChangeId,
+        replicate_from: Option<AppName>,
+        remove_volumes: bool,
+    ) -> Result<Vec<Service>, AppsServiceError> {
+        let result = self
+            .guard_service_change(
+                app_name,
+                self.delete_service_impl(
+                    app_name,
+                    service_name,
+                    status_id,
+                    replicate_from,
+                    remove_volumes,
+                ),
+            )
             .await;
+        metrics::observe_operation(Operation::ServiceDeletion, &result);
+        result
+    }
 
-        while let Some(result) = log_stream.next().await {
-            if let Ok(log_line) = result {
-                log_lines.push(log_line);
+    /// Runs a change of single services of the app that must not interfere with deployments of
+    /// the app. Like deployments, it is rejected or waits for the running deployment.
+    async fn guard_service_change<F>(&self, app_name: &AppName, change: F) -> GuardedResult
+    where
+        F: Future<Output = GuardedResult>,
+    {
+        match self.config.deployment_queueing() {
+            DeploymentQueueing::Reject => {
+                let guard =
+                    self.create_or_get_app_guard(app_name.clone(), AppGuardKind::Deployment)?;
+
+                if !guard.is_first() {
+                    return Err(AppsServiceError::AppIsInDeployment {
+                        app_name: app_name.clone(),
+                    });
+                }
+
+                guard.notify_with_result(self, change.await)
+            }
+            DeploymentQueueing::Queue | DeploymentQueueing::Coalesce => {
+                let queue = self.deployment_queue(app_name);
+                let permit = queue.lock.lock().await;
+                let result = change.await;
+                drop(permit);
+                self.release_deployment_queue(app_name, queue);
+                result
             }
         }
-
-        Ok(Some(LogChunk::from(log_lines)))
     }
 
-    pub async fn change_status(
+    async fn delete_service_impl(
         &self,
         app_name: &AppName,
         service_name: &str,
-        status: ServiceStatus,
-    ) -> Result<Option<Service>, AppsServiceError> {
-        Ok(self
-            .infrastructure
-            .change_status(app_name, service_name, status)
-            .await?)
-    }
-}
+        status_id: &AppStatusChangeId,
+        replicate_from: Option<AppName>,
+        remove_volumes: bool,
+    ) -> Result<Vec<Service>, AppsServiceError> {
+        let service_not_found = || AppsServiceError::ServiceNotFound {
+            app_name: app_name.clone(),
+            service_name: service_name.to_string(),
+        };
 
-/// Defines error cases for the `AppService`
-#[derive(Debug, Clone, Fail)]
-pub enum AppsServiceError {
-    /// Will be used when no app with a given name is found
-    #[fail(display = "Cannot find app {}.", app_name)]
-    AppNotFound { app_name: AppName },
-    #[fail(
-        display = "The app {} is currently within deployment by another request.",
-        app_name
-    )]
-    AppIsInDeployment { app_name: AppName },
-    #[fail(
-        display = "The app {} is currently within deletion in by another request.",
-        app_name
-    )]
-    AppIsInDeletion { app_name: AppName },
-    /// Will be used when the service cannot interact correctly with the infrastructure.
-    #[fail(display = "Cannot interact with infrastructure: {}", error)]
-    InfrastructureError { error: Arc<failure::Error> },
-    /// Will be used if the service configuration cannot be loaded.
-    #[fail(display = "Invalid configuration: {}", error)]
-    InvalidServerConfiguration { error: Arc<ConfigError> },
-    #[fail(display = "Invalid configuration (invalid template): {}", error)]
-    InvalidTemplateFormat { error: Arc<RenderError> },
-    #[fail(display = "Unable to resolve information about image: {}", error)]
-    UnableToResolveImage { error: RegistryError },
-    #[fail(display = "Invalid deployment hook.")]
-    InvalidDeploymentHook,
-}
+        let removed_config = self
+            .infrastructure
+            .get_configs_of_app(app_name)
+            .await?
+            .into_iter()
+            .find(|config| config.service_name() == service_name)
+            .ok_or_else(service_not_found)?;
 
-impl From<ConfigError> for AppsServiceError {
-    fn from(error: ConfigError) -> Self {
-        AppsServiceError::InvalidServerConfiguration {
-            error: Arc::new(error),
+        self.archive_logs(app_name, Some(service_name)).await;
+        let removed_service = self
+            .infrastructure
+            .stop_service(app_name, service_name, remove_volumes)
+            .await?
+            .ok_or_else(service_not_found)?;
+
+        let mut replacements = Vec::new();
+        if let Some(replicate_from) = replicate_from.filter(|app| app != app_name) {
+            if removed_config.container_type() == &ContainerType::Instance {
+                replacements.extend(
+                    self.infrastructure
+                        .get_configs_of_app(&replicate_from)
+                        .await?
+                        .into_iter()
+                        .filter(|config| config.service_name() == service_name)
+                        .map(|mut config| {
+                            config.set_container_type(ContainerType::Replica);
+                            config
+                        }),
+                );
+            }
         }
-    }
-}
 
-impl From<failure::Error> for AppsServiceError {
-    fn from(error: failure::Error) -> Self {
-        AppsServiceError::InfrastructureError {
-            error: Arc::new(error),
+        let remaining_configs = self.infrastructure.get_configs_of_app(app_name).await?;
+        if replacements.is_empty() && remaining_configs.is_empty() {
+            debug!("Only companions remain in {app_name}, deleting the whole app");
+            self.infrastructure
+                .stop_services(&status_id.to_string(), app_name)
+                .await?;
+        } else {
+            // Like in any other deployment, the replicas are deployed again so that the templates
+            // of the companions still refer to them.
+            let replicas = remaining_configs
+                .into_iter()
+                .filter(|config| config.container_type() == &ContainerType::Replica)
+                .filter(|config| {
+                    !replacements
+                        .iter()
+                        .any(|c| c.service_name() == config.service_name())
+                })
+                .collect::<Vec<_>>();
+            let mut configs = replacements;
+            configs.extend(replicas);
+
+            // Replicating from the app itself prevents that the removed service comes back as
+            // replica of master.
+            self.create_or_update_impl(app_name, status_id, Some(app_name.clone()), &configs, None)
+                .await?;
         }
+
+        Ok(vec![removed_service])
     }
-}
 
-impl From<RenderError> for AppsServiceError {
-    fn from(error: RenderError) -> Self {
-        AppsServiceError::InvalidTemplateFormat {
-            error: Arc::new(error),
-        }
+    /// Restarts the containers of a service without changing its deployment.
+    pub async fn restart_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<Service>, AppsServiceError> {
+        let result = self
+            .guard_service_change(app_name, async {
+                self.archive_logs(app_name, Some(service_name)).await;
+                Ok(self
+                    .infrastructure
+                    .restart_service(app_name, service_name)
+                    .await?
+                    .into_iter()
+                    .collect())
+            })
+            .await;
+        metrics::observe_operation(Operation::Restart, &result);
+        Ok(result?.into_iter().next())
     }
-}
 
-impl From<RegistryError> for AppsServiceError {
-    fn from(error: RegistryError) -> Self {
-        AppsServiceError::UnableToResolveImage { error }
+    /// Redeploys a single instance or replica with the image that its tag currently refers to,
+    /// e.g. to pick up a newly pushed `latest` image. Returns the redeployed service.
+    pub async fn redeploy_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Vec<Service>, AppsServiceError> {
+        let result = self
+            .guard_service_change(app_name, self.redeploy_service_impl(app_name, service_name))
+            .await;
+        metrics::observe_operation(Operation::Redeployment, &result);
+        result
     }
-}
 
-#[cfg(test)]
-mod tests {
+    async fn redeploy_service_impl(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Vec<Service>, AppsServiceError> {
+        let service_not_found = || AppsServiceError::ServiceNotFound {
+            app_name: app_name.clone(),
+            service_name: service_name.to_string(),
+        };
 
-    use super::*;
-    use crate::infrastructure::{Dummy, TraefikIngressRoute, TraefikRouterRule};
-    use crate::models::{EnvironmentVariable, ServiceBuilder};
-    use crate::sc;
-    use chrono::Utc;
-    use secstr::SecUtf8;
-    use std::hash::Hash;
-    use std::io::Write;
-    use std::path::PathBuf;
-    use std::str::FromStr;
-    use tempfile::NamedTempFile;
-    use tokio::runtime;
+        let config = self
+            .infrastructure
+            .get_configs_of_app(app_name)
+            .await?
+            .into_iter()
+            .find(|config| config.service_name() == service_name)
+            .ok_or_else(service_not_found)?;
 
-    macro_rules! config_from_str {
-        ( $config_str:expr ) => {
-            toml::from_str::<Config>($config_str).unwrap()
-        };
+        let image = config.image().clone();
+        let image_infos = Registry::new(&self.config)
+            .resolve_image_infos(&HashSet::from([image.clone()]))
+            .await?;
+        let digest = image_infos.get(&image).map(|info| info.digest().as_str());
+        debug!("Redeploying {service_name} of {app_name} with {image} ({digest:?})");
+
+        self.archive_logs(app_name, Some(service_name)).await;
+        let service = self
+            .infrastructure
+            .redeploy_service(app_name, service_name, &image, digest)
+            .await?
+            .ok_or_else(service_not_found)?;
+
+        Ok(vec![service])
     }
 
-    macro_rules! assert_contains_service {
-        ( $services:expr, $service_name:expr, $container_type:expr ) => {
-            assert!(
-                $services
-                    .iter()
-                    .find(|s| s.service_name() == $service_name
-                        && s.container_type() == &$container_type)
-                    .is_some(),
-                "services should contain {:?} with type {:?}",
-                $service_name,
-                $container_type
+    /// Runs a command in the container of a service until it exits.
+    pub async fn exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<ExecOutput, AppsServiceError> {
+        self.infrastructure
+            .exec(app_name, service_name, command)
+            .await?
+            .ok_or_else(|| AppsServiceError::ServiceNotFound {
+                app_name: app_name.clone(),
+                service_name: service_name.to_string(),
+            })
+    }
+
+    /// Starts a command with a TTY in the container of a service, e.g. a shell.
+    pub async fn attach_exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<ExecSession, AppsServiceError> {
+        self.infrastructure
+            .attach_exec(app_name, service_name, command)
+            .await?
+            .ok_or_else(|| AppsServiceError::ServiceNotFound {
+                app_name: app_name.clone(),
+                service_name: service_name.to_string(),
+            })
+    }
+
+    /// Streams a tar archive of a file or directory in the container of a service.
+    pub async fn download_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+    ) -> Result<BoxStream<'static, Result<Vec<u8>, failure::Error>>, AppsServiceError> {
+        let mut archive = self
+            .infrastructure
+            .download_files(app_name, service_name, path)
+            .await?
+            .ok_or_else(|| AppsServiceError::ServiceNotFound {
+                app_name: app_name.clone(),
+                service_name: service_name.to_string(),
+            })?;
+
+        // Runtimes might report a missing path not until the archive is read. Reading the first
+        // chunk ensures that such an error fails the request instead of truncating the archive.
+        match archive.next().await {
+            Some(Err(err)) => Err(err.into()),
+            first => Ok(stream::iter(first).chain(archive).boxed()),
+        }
+    }
+
+    /// Extracts a tar archive into a directory in the container of a service.
+    pub async fn upload_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> Result<(), AppsServiceError> {
+        if !self
+            .infrastructure
+            .upload_files(app_name, service_name, path, archive)
+            .await?
+        {
+            return Err(AppsServiceError::ServiceNotFound {
+                app_name: app_name.clone(),
+                service_name: service_name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn stream_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        service_name: &'a str,
+        since: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        filter: &'a LogFilter,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+        let logs = self
+            .infrastructure
+            .get_logs(
+                app_name,
+                service_name,
+                since,
+                limit_of_infrastructure(limit, filter),
+                filter.streams(),
+                true,
+            )
+            .await;
+        filter_logs(logs, filter, limit)
+    }
+
+    pub async fn get_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        service_name: &'a str,
+        since: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        filter: &'a LogFilter,
+    ) -> Result<Option<LogChunk>, AppsServiceError> {
+        let log_lines = self
+            .collect_logs(app_name, service_name, since, limit, filter)
+            .await?;
+        Ok(Some(LogChunk::from(log_lines)))
+    }
+
+    /// Streams the log lines of all services of the app in the order in which they arrive. Each
+    /// line is prefixed with the name of the service that wrote it.
+    pub async fn stream_app_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        since: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        filter: &'a LogFilter,
+    ) -> Result<
+        BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>>,
+        AppsServiceError,
+    > {
+        let logs =
+            self.service_names_of_app(app_name)
+                .await?
+                .into_iter()
+                .map(move |service_name| {
+                    stream! {
+                        let mut logs = self
+                            .stream_logs(app_name, &service_name, since, limit, filter)
+                            .await;
+                        while let Some(result) = logs.next().await {
+                            yield result.map(|(timestamp, log_line)| {
+                                (timestamp, format!("{service_name} | {log_line}"))
+                            });
+                        }
+                    }
+                    .boxed()
+                });
+
+        Ok(stream::select_all(logs)
+            .take(limit.unwrap_or(usize::MAX))
+            .boxed())
+    }
+
+    /// Merges the log lines of all services of the app ordered by their timestamps. Each line is
+    /// prefixed with the name of the service that wrote it.
+    pub async fn get_app_logs(
+        &self,
+        app_name: &AppName,
+        since: &Option<DateTime<FixedOffset>>,
+        limit: &Option<usize>,
+        filter: &LogFilter,
+    ) -> Result<LogChunk, AppsServiceError> {
+        let mut log_lines = Vec::new();
+        for service_name in self.service_names_of_app(app_name).await? {
+            let service_log_lines = self
+                .collect_logs(app_name, &service_name, since, limit, filter)
+                .await?;
+            log_lines.extend(
+                service_log_lines.into_iter().map(|(timestamp, log_line)| {
+                    (timestamp, format!("{service_name} | {log_line}"))
+                }),
             );
+        }
+
+        log_lines.sort_by_key(|(timestamp, _)| *timestamp);
+        log_lines.truncate(limit.unwrap_or(usize::MAX));
+
+        Ok(LogChunk::from(log_lines))
+    }
+
+    async fn collect_logs(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        since: &Option<DateTime<FixedOffset>>,
+        limit: &Option<usize>,
+        filter: &LogFilter,
+    ) -> Result<Vec<(DateTime<FixedOffset>, String)>, AppsServiceError> {
+        let logs = self
+            .infrastructure
+            .get_logs(
+                app_name,
+                service_name,
+                since,
+                limit_of_infrastructure(limit, filter),
+                filter.streams(),
+                false,
+            )
+            .await;
+
+        let mut live_log_lines = Vec::new();
+        let mut log_stream = filter_logs(logs, filter, limit);
+        while let Some(result) = log_stream.next().await {
+            match result {
+                Ok(log_line) => live_log_lines.push(log_line),
+                Err(err) => {
+                    return Err(match err.downcast::<UnsupportedLogStreams>() {
+                        Ok(UnsupportedLogStreams { streams }) => {
+                            AppsServiceError::UnsupportedLogStreams { streams }
+                        }
+                        Err(err) => AppsServiceError::from(err),
+                    })
+                }
+            }
+        }
+
+        // The archive might contain the log lines of the running container as well, which are
+        // already part of the live log lines.
+        let mut log_lines = self
+            .archived_logs(app_name, service_name, since, filter)
+            .await;
+        if let Some((first_live_timestamp, _)) = live_log_lines.first() {
+            log_lines.retain(|(timestamp, _)| timestamp < first_live_timestamp);
+        }
+        log_lines.extend(live_log_lines);
+        log_lines.truncate(limit.unwrap_or(usize::MAX));
+        Ok(log_lines)
+    }
+
+    /// Returns the archived log lines of the service that match the filter. The archive does not
+    /// know the output streams of the log lines and, therefore, it only provides log lines if
+    /// both streams are selected.
+    async fn archived_logs(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        since: &Option<DateTime<FixedOffset>>,
+        filter: &LogFilter,
+    ) -> Vec<(DateTime<FixedOffset>, String)> {
+        let Some(log_archive) = &self.log_archive else {
+            return Vec::new();
+        };
+        if filter.streams() != LogStreams::Both {
+            return Vec::new();
+        }
+
+        match log_archive.read(app_name, service_name, since).await {
+            Ok(log_lines) => log_lines
+                .into_iter()
+                .filter(|(timestamp, log_line)| {
+                    !filter.is_past_until(timestamp) && filter.matches(log_line)
+                })
+                .collect(),
+            Err(err) => {
+                warn!("Cannot read the archived logs of {service_name} of {app_name}: {err}");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Archives the log lines of the services of the app, or only of the given service, before
+    /// their containers are removed or replaced. Failures are logged because they must not
+    /// prevent the change of the services.
+    async fn archive_logs(&self, app_name: &AppName, service_name: Option<&str>) {
+        let Some(log_archive) = &self.log_archive else {
+            return;
+        };
+
+        let service_names = match service_name {
+            Some(service_name) => vec![service_name.to_string()],
+            None => match self.running_service_names(app_name).await {
+                Ok(service_names) => service_names,
+                Err(err) => {
+                    warn!("Cannot archive the logs of {app_name}: {err}");
+                    return;
+                }
+            },
+        };
+
+        self.archive_logs_of_services(log_archive, app_name, service_names)
+            .await;
+    }
+
+    /// Archives the log lines of the running services that the deployment unit replaces. The
+    /// other services of the app keep running and their logs are archived by the
+    /// [`LogRetainer`] or when they are replaced later on.
+    async fn archive_logs_of_replaced_services(
+        &self,
+        app_name: &AppName,
+        deployment_unit: &DeploymentUnit,
+    ) {
+        let Some(log_archive) = &self.log_archive else {
+            return;
+        };
+
+        let deployed_service_names = deployment_unit
+            .services()
+            .iter()
+            .map(|service| service.service_name())
+            .collect::<HashSet<_>>();
+        let service_names = match self.running_service_names(app_name).await {
+            Ok(service_names) => service_names
+                .into_iter()
+                .filter(|service_name| deployed_service_names.contains(service_name))
+                .collect(),
+            Err(err) => {
+                warn!("Cannot archive the logs of {app_name}: {err}");
+                return;
+            }
+        };
+
+        self.archive_logs_of_services(log_archive, app_name, service_names)
+            .await;
+    }
+
+    async fn archive_logs_of_services(
+        &self,
+        log_archive: &LogArchive,
+        app_name: &AppName,
+        service_names: Vec<String>,
+    ) {
+        future::join_all(service_names.iter().map(|service_name| async move {
+            let result = async {
+                let since = log_archive.last_timestamp(app_name, service_name).await?;
+                let log_lines = self
+                    .infrastructure
+                    .get_logs(
+                        app_name,
+                        service_name,
+                        &since,
+                        &None,
+                        LogStreams::Both,
+                        false,
+                    )
+                    .await
+                    .filter_map(|result| future::ready(result.ok()))
+                    .collect::<Vec<_>>()
+                    .await;
+                log_archive.append(app_name, service_name, &log_lines).await
+            };
+
+            if let Err(err) = result.await {
+                warn!("Cannot archive the logs of {service_name} of {app_name}: {err}");
+            }
+        }))
+        .await;
+    }
+
+    /// Archives the log lines of the previous containers of all services, e.g. of containers
+    /// that crashed, and removes the archived logs that exceed the retention.
+    pub async fn archive_previous_logs(&self) -> Result<(), AppsServiceError> {
+        let Some(log_archive) = &self.log_archive else {
+            return Ok(());
         };
+
+        let apps = self.infrastructure.get_services().await?;
+        for (app_name, services) in apps.iter_all() {
+            for service in services {
+                let service_name = service.service_name();
+                let log_lines = self
+                    .infrastructure
+                    .get_previous_logs(app_name, service_name)
+                    .await
+                    .filter_map(|result| future::ready(result.ok()))
+                    .collect::<Vec<_>>()
+                    .await;
+
+                if let Err(err) = log_archive.append(app_name, service_name, &log_lines).await {
+                    warn!(
+                        "Cannot archive the previous logs of {service_name} of {app_name}: {err}"
+                    );
+                }
+            }
+        }
+
+        log_archive.purge().await?;
+        Ok(())
+    }
+
+    async fn running_service_names(
+        &self,
+        app_name: &AppName,
+    ) -> Result<Vec<String>, AppsServiceError> {
+        Ok(self
+            .infrastructure
+            .get_services()
+            .await?
+            .get_vec(app_name)
+            .map_or_else(Vec::new, |services| {
+                services
+                    .iter()
+                    .map(|service| service.service_name().clone())
+                    .collect()
+            }))
+    }
+
+    /// Returns the names of the running services of the app and of the services with archived
+    /// logs.
+    async fn service_names_of_app(
+        &self,
+        app_name: &AppName,
+    ) -> Result<Vec<String>, AppsServiceError> {
+        let mut service_names = self.running_service_names(app_name).await?;
+        if let Some(log_archive) = &self.log_archive {
+            service_names.extend(log_archive.service_names(app_name).await?);
+            service_names.sort();
+            service_names.dedup();
+        }
+
+        if service_names.is_empty() {
+            return Err(AppsServiceError::AppNotFound {
+                app_name: app_name.clone(),
+            });
+        }
+        Ok(service_names)
+    }
+
+    pub async fn change_status(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        status: ServiceStatus,
+    ) -> Result<Option<Service>, AppsServiceError> {
+        Ok(self
+            .infrastructure
+            .change_status(app_name, service_name, status)
+            .await?)
+    }
+}
+
+/// Lines that do not match the pattern of the filter must not count towards the limit, so the
+/// limit can only be passed to the infrastructure if the filter has no pattern.
+fn limit_of_infrastructure<'a>(limit: &'a Option<usize>, filter: &LogFilter) -> &'a Option<usize> {
+    if filter.has_pattern() {
+        &None
+    } else {
+        limit
+    }
+}
+
+fn filter_logs<'a>(
+    logs: BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>>,
+    filter: &'a LogFilter,
+    limit: &Option<usize>,
+) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+    logs.take_while(move |result| {
+        future::ready(!matches!(result, Ok((timestamp, _)) if filter.is_past_until(timestamp)))
+    })
+    .filter(move |result| {
+        future::ready(match result {
+            Ok((_, log_line)) => filter.matches(log_line),
+            Err(_) => true,
+        })
+    })
+    .take(limit.unwrap_or(usize::MAX))
+    .boxed()
+}
+
+/// Defines error cases for the `AppService`
+#[derive(Debug, Clone, Fail)]
+pub enum AppsServiceError {
+    /// Will be used when no app with a given name is found
+    #[fail(display = "Cannot find app {}.", app_name)]
+    AppNotFound { app_name: AppName },
+    /// Will be used when an app does not run an instance or replica with a given name
+    #[fail(display = "Cannot find service {} of app {}.", service_name, app_name)]
+    ServiceNotFound {
+        app_name: AppName,
+        service_name: String,
+    },
+    #[fail(
+        display = "The app {} is currently within deployment by another request.",
+        app_name
+    )]
+    AppIsInDeployment { app_name: AppName },
+    #[fail(
+        display = "The app {} is currently within deletion in by another request.",
+        app_name
+    )]
+    AppIsInDeletion { app_name: AppName },
+    #[fail(
+        display = "The deployment of the app {} that covered this request has been cancelled.",
+        app_name
+    )]
+    DeploymentCancelled { app_name: AppName },
+    /// Will be used when the service cannot interact correctly with the infrastructure.
+    #[fail(display = "Cannot interact with infrastructure: {}", error)]
+    InfrastructureError { error: Arc<failure::Error> },
+    /// Will be used if the service configuration cannot be loaded.
+    #[fail(display = "Invalid configuration: {}", error)]
+    InvalidServerConfiguration { error: Arc<ConfigError> },
+    #[fail(display = "Invalid configuration (invalid template): {}", error)]
+    InvalidTemplateFormat { error: Arc<RenderError> },
+    #[fail(display = "Unable to resolve information about image: {}", error)]
+    UnableToResolveImage { error: RegistryError },
+    #[fail(display = "Invalid deployment hook.")]
+    InvalidDeploymentHook,
+    #[fail(
+        display = "The dependencies of the services {} contain a cycle.",
+        services
+    )]
+    CyclicServiceDependencies { services: String },
+    #[fail(display = "{}", error)]
+    InvalidDeploymentTarget { error: DeploymentTargetError },
+    #[fail(
+        display = "The runtime cannot provide the log lines of {} only.",
+        streams
+    )]
+    UnsupportedLogStreams { streams: LogStreams },
+    /// Will be used when the archived logs cannot be read or written.
+    #[fail(display = "{}", error)]
+    InaccessibleLogArchive { error: Arc<LogArchiveError> },
+    #[fail(display = "{}", error)]
+    InvalidHostName { error: RoutingError },
+}
+
+impl From<ConfigError> for AppsServiceError {
+    fn from(error: ConfigError) -> Self {
+        AppsServiceError::InvalidServerConfiguration {
+            error: Arc::new(error),
+        }
     }
+}
+
+impl From<failure::Error> for AppsServiceError {
+    fn from(error: failure::Error) -> Self {
+        AppsServiceError::InfrastructureError {
+            error: Arc::new(error),
+        }
+    }
+}
+
+impl From<RenderError> for AppsServiceError {
+    fn from(error: RenderError) -> Self {
+        AppsServiceError::InvalidTemplateFormat {
+            error: Arc::new(error),
+        }
+    }
+}
+
+impl From<RoutingError> for AppsServiceError {
+    fn from(error: RoutingError) -> Self {
+        AppsServiceError::InvalidHostName { error }
+    }
+}
+
+impl From<RegistryError> for AppsServiceError {
+    fn from(error: RegistryError) -> Self {
+        AppsServiceError::UnableToResolveImage { error }
+    }
+}
+
+impl From<LogArchiveError> for AppsServiceError {
+    fn from(error: LogArchiveError) -> Self {
+        AppsServiceError::InaccessibleLogArchive {
+            error: Arc::new(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::infrastructure::{Dummy, TraefikIngressRoute, TraefikRouterRule};
+    use crate::models::{EnvironmentVariable, ServiceBuilder};
+    use crate::sc;
+    use chrono::Utc;
+    use secstr::SecUtf8;
+    use std::hash::Hash;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use tempfile::NamedTempFile;
+    use tokio::runtime;
+
+    macro_rules! config_from_str {
+        ( $config_str:expr ) => {
+            toml::from_str::<Config>($config_str).unwrap()
+        };
+    }
+
+    macro_rules! assert_contains_service {
+        ( $services:expr, $service_name:expr, $container_type:expr ) => {
+            assert!(
+                $services
+                    .iter()
+                    .find(|s| s.service_name() == $service_name
+                        && s.container_type() == &$container_type)
+                    .is_some(),
+                "services should contain {:?} with type {:?}",
+                $service_name,
+                $container_type
+            );
+        };
+    }
+
+    fn config_with_deployment_hook(script: &str) -> (NamedTempFile, Config) {
+        let mut hook_file = NamedTempFile::new().unwrap();
+
+        hook_file.write_all(script.as_bytes()).unwrap();
+
+        let config = crate::config_from_str!(&format!(
+            r#"
+            [hooks]
+            deployment = {:?}
+            "#,
+            hook_file.path()
+        ));
+
+        (hook_file, config)
+    }
+
+    #[tokio::test]
+    async fn should_create_app_for_master() -> Result<(), AppsServiceError> {
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+
+        let deployed_apps = apps.get_apps().await?;
+        assert_eq!(deployed_apps.len(), 1);
+        let services = deployed_apps.get_vec(&AppName::master()).unwrap();
+        assert_eq!(services.len(), 1);
+        assert_contains_service!(services, "service-a", ContainerType::Instance);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_replication_from_master() -> Result<(), AppsServiceError> {
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a"), sc!("service-b")],
+            None,
+        )
+        .await?;
+
+        apps.create_or_update(
+            &AppName::from_str("branch").unwrap(),
+            &AppStatusChangeId::new(),
+            Some(AppName::master()),
+            &vec![sc!("service-b")],
+            None,
+        )
+        .await?;
+
+        let deployed_apps = apps.get_apps().await?;
+
+        let services = deployed_apps
+            .get_vec(&AppName::from_str("branch").unwrap())
+            .unwrap();
+        assert_eq!(services.len(), 2);
+        assert_contains_service!(services, "service-b", ContainerType::Instance);
+        assert_contains_service!(services, "service-a", ContainerType::Replica);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_override_replicas_from_master() -> Result<(), AppsServiceError> {
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a"), sc!("service-b")],
+            None,
+        )
+        .await?;
+
+        apps.create_or_update(
+            &AppName::from_str("branch").unwrap(),
+            &AppStatusChangeId::new(),
+            Some(AppName::master()),
+            &vec![sc!("service-b")],
+            None,
+        )
+        .await?;
+
+        apps.create_or_update(
+            &AppName::from_str("branch").unwrap(),
+            &AppStatusChangeId::new(),
+            Some(AppName::master()),
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+
+        let deployed_apps = apps.get_apps().await?;
+
+        let services = deployed_apps
+            .get_vec(&AppName::from_str("branch").unwrap())
+            .unwrap();
+        assert_eq!(services.len(), 2);
+        assert_contains_service!(services, "service-a", ContainerType::Instance);
+        assert_contains_service!(services, "service-b", ContainerType::Instance);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_create_app_for_master_with_secrets() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [services.mariadb]
+            [[services.mariadb.secrets]]
+            name = "user"
+            data = "SGVsbG8="
+            appSelector = "master"
+        "#
+        );
+
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("mariadb")],
+            None,
+        )
+        .await?;
+
+        let configs = apps
+            .infrastructure
+            .get_configs_of_app(&AppName::master())
+            .await?;
+        assert_eq!(configs.len(), 1);
+
+        let files = configs.get(0).unwrap().files().unwrap();
+        assert_eq!(
+            files.get(&PathBuf::from("/run/secrets/user")).unwrap(),
+            &SecUtf8::from("Hello")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_create_app_for_master_without_secrets_because_of_none_matching_app_selector(
+    ) -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [services.mariadb]
+            [[services.mariadb.secrets]]
+            name = "user"
+            data = "SGVsbG8="
+            appSelector = "master"
+        "#
+        );
+
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        apps.create_or_update(
+            &AppName::from_str("master-1.x").unwrap(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("mariadb")],
+            None,
+        )
+        .await?;
+
+        let configs = apps
+            .infrastructure
+            .get_configs_of_app(&AppName::from_str("master-1.x").unwrap())
+            .await?;
+        assert_eq!(configs.len(), 1);
+
+        let files = configs.get(0).unwrap().files();
+        assert_eq!(files, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_collect_log_chunk_from_infrastructure() -> Result<(), AppsServiceError> {
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        let app_name = AppName::master();
+
+        apps.create_or_update(
+            &app_name,
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a"), sc!("service-b")],
+            None,
+        )
+        .await?;
+
+        let log_chunk = apps
+            .get_logs(
+                &app_name,
+                &String::from("service-a"),
+                &None,
+                &Some(100),
+                &LogFilter::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            log_chunk.since(),
+            &DateTime::parse_from_rfc3339("2019-07-18T07:25:00.000000000Z").unwrap()
+        );
+
+        assert_eq!(
+            log_chunk.until(),
+            &DateTime::parse_from_rfc3339("2019-07-18T07:35:00.000000000Z").unwrap()
+        );
+
+        assert_eq!(
+            log_chunk.log_lines(),
+            r#"Log msg 1 of service-a of app master
+Log msg 2 of service-a of app master
+Log msg 3 of service-a of app master
+"#
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_merge_filtered_logs_of_all_services() -> Result<(), AppsServiceError> {
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        let app_name = AppName::master();
+        apps.create_or_update(
+            &app_name,
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a"), sc!("service-b")],
+            None,
+        )
+        .await?;
+
+        let filter = LogFilter::new(
+            Some(DateTime::parse_from_rfc3339("2019-07-18T07:30:00.000000000Z").unwrap()),
+            Some(regex::Regex::new("msg [23]").unwrap()),
+            Default::default(),
+        );
+        let log_chunk = apps
+            .get_app_logs(&app_name, &None, &Some(100), &filter)
+            .await?;
+
+        let mut log_lines = log_chunk.log_lines().lines().collect::<Vec<_>>();
+        log_lines.sort();
+        assert_eq!(
+            log_lines,
+            vec![
+                "service-a | Log msg 2 of service-a of app master",
+                "service-b | Log msg 2 of service-b of app master",
+            ]
+        );
+
+        let stderr_only = LogFilter::new(None, None, crate::models::LogStreams::Stderr);
+        let log_chunk = apps
+            .get_app_logs(&app_name, &None, &None, &stderr_only)
+            .await?;
+        assert_eq!(log_chunk.log_lines(), "");
+
+        assert!(matches!(
+            apps.get_app_logs(&AppName::from_str("other").unwrap(), &None, &None, &filter)
+                .await,
+            Err(AppsServiceError::AppNotFound { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_provide_archived_logs_of_deleted_app() -> Result<(), AppsServiceError> {
+        let log_directory = tempfile::tempdir().unwrap();
+        let config = crate::config_from_str!(&format!(
+            r#"
+            [logStore]
+            path = {:?}
+            "#,
+            log_directory.path()
+        ));
+        let apps = AppsService::new(config, Box::new(Dummy::new()))?;
+
+        let app_name = AppName::from_str("review").unwrap();
+        apps.create_or_update(
+            &app_name,
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+        apps.delete_app(&app_name, &AppStatusChangeId::new())
+            .await?;
+
+        assert!(log_directory
+            .path()
+            .join("review")
+            .join("service-a.log")
+            .exists());
+
+        let log_chunk = apps
+            .get_app_logs(&app_name, &None, &None, &LogFilter::default())
+            .await?;
+        assert_eq!(
+            log_chunk.log_lines(),
+            r#"service-a | Log msg 1 of service-a of app review
+service-a | Log msg 2 of service-a of app review
+service-a | Log msg 3 of service-a of app review
+"#
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_only_archive_logs_of_replaced_services() -> Result<(), AppsServiceError> {
+        let log_directory = tempfile::tempdir().unwrap();
+        let config = crate::config_from_str!(&format!(
+            r#"
+            [logStore]
+            path = {:?}
+            "#,
+            log_directory.path()
+        ));
+        let apps = AppsService::new(config, Box::new(Dummy::new()))?;
+
+        let app_name = AppName::from_str("review").unwrap();
+        apps.create_or_update(
+            &app_name,
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a"), sc!("service-b")],
+            None,
+        )
+        .await?;
+        apps.create_or_update(
+            &app_name,
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+
+        let app_directory = log_directory.path().join("review");
+        assert!(app_directory.join("service-a.log").exists());
+        assert!(!app_directory.join("service-b.log").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_stream_logs_from_infrastructure() -> Result<(), AppsServiceError> {
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        let app_name = AppName::from_str("master").unwrap();
+        let services = vec![sc!("service-a"), sc!("service-b")];
+        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &services, None)
+            .await?;
+        for service in services {
+            let mut log_stream = apps
+                .stream_logs(
+                    &app_name,
+                    service.service_name(),
+                    &None,
+                    &None,
+                    &LogFilter::default(),
+                )
+                .await;
 
-    fn config_with_deployment_hook(script: &str) -> (NamedTempFile, Config) {
-        let mut hook_file = NamedTempFile::new().unwrap();
+            assert_eq!(
+                log_stream.next().await.unwrap().unwrap(),
+                (
+                    DateTime::parse_from_rfc3339("2019-07-18T07:25:00.000000000Z").unwrap(),
+                    format!(
+                        "Log msg 1 of {} of app {app_name}\n",
+                        service.service_name()
+                    )
+                )
+            );
 
-        hook_file.write_all(script.as_bytes()).unwrap();
+            assert_eq!(
+                log_stream.next().await.unwrap().unwrap(),
+                (
+                    DateTime::parse_from_rfc3339("2019-07-18T07:30:00.000000000Z").unwrap(),
+                    format!(
+                        "Log msg 2 of {} of app {app_name}\n",
+                        service.service_name()
+                    )
+                )
+            );
 
-        let config = crate::config_from_str!(&format!(
-            r#"
-            [hooks]
-            deployment = {:?}
-            "#,
-            hook_file.path()
-        ));
+            assert_eq!(
+                log_stream.next().await.unwrap().unwrap(),
+                (
+                    DateTime::parse_from_rfc3339("2019-07-18T07:35:00.000000000Z").unwrap(),
+                    format!(
+                        "Log msg 3 of {} of app {app_name}\n",
+                        service.service_name()
+                    )
+                )
+            );
+        }
 
-        (hook_file, config)
+        Ok(())
     }
 
     #[tokio::test]
-    async fn should_create_app_for_master() -> Result<(), AppsServiceError> {
-        let config = Config::default();
+    async fn should_deploy_companions() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [companions.openid]
+            serviceName = 'openid'
+            type = 'application'
+            image = 'keycloak/keycloak:23.0'
+
+            [companions.db]
+            serviceName = 'db'
+            type = 'service'
+            image = 'postgres:16.1'
+        "#
+        );
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
 
+        let app_name = AppName::master();
         apps.create_or_update(
-            &AppName::master(),
+            &app_name,
             &AppStatusChangeId::new(),
             None,
             &vec![sc!("service-a")],
+            None,
         )
         .await?;
-
         let deployed_apps = apps.get_apps().await?;
-        assert_eq!(deployed_apps.len(), 1);
-        let services = deployed_apps.get_vec(&AppName::master()).unwrap();
-        assert_eq!(services.len(), 1);
+
+        let services = deployed_apps.get_vec(&app_name).unwrap();
+        assert_eq!(services.len(), 3);
+        assert_contains_service!(services, "openid", ContainerType::ApplicationCompanion);
+        assert_contains_service!(services, "db", ContainerType::ServiceCompanion);
         assert_contains_service!(services, "service-a", ContainerType::Instance);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_replication_from_master() -> Result<(), AppsServiceError> {
-        let config = Config::default();
+    async fn should_filter_companions_if_services_to_deploy_contain_same_service_name(
+    ) -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [companions.openid]
+            serviceName = 'openid'
+            type = 'application'
+            image = 'keycloak/keycloak:23.0'
+
+            [companions.db]
+            serviceName = 'db'
+            type = 'service'
+            image = 'postgres:16.1'
+        "#
+        );
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
 
-        apps.create_or_update(
-            &AppName::master(),
-            &AppStatusChangeId::new(),
-            None,
-            &vec![sc!("service-a"), sc!("service-b")],
-        )
-        .await?;
+        let app_name = AppName::master();
+        let configs = vec![sc!("openid"), sc!("db")];
+        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &configs, None)
+            .await?;
+        let deployed_apps = apps.get_apps().await?;
 
-        apps.create_or_update(
-            &AppName::from_str("branch").unwrap(),
-            &AppStatusChangeId::new(),
-            Some(AppName::master()),
-            &vec![sc!("service-b")],
-        )
-        .await?;
+        let services = deployed_apps.get_vec(&app_name).unwrap();
+        assert_eq!(services.len(), 2);
+        assert_contains_service!(services, "openid", ContainerType::Instance);
+        assert_contains_service!(services, "db", ContainerType::Instance);
+
+        let openid_configs: Vec<ServiceConfig> = apps
+            .infrastructure
+            .get_configs_of_app(&app_name)
+            .await?
+            .into_iter()
+            .filter(|config| config.service_name() == "openid")
+            .collect();
+        assert_eq!(openid_configs.len(), 1);
+        assert_eq!(openid_configs[0].image(), configs[0].image());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_merge_with_companion_config_if_services_to_deploy_contain_same_service_name(
+    ) -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [companions.openid]
+            serviceName = 'openid'
+            type = 'application'
+            image = 'keycloak/keycloak:23.0'
+            env = [ "VAR_1=abcd", "VAR_2=1234" ]
+
+            [companions.openid.labels]
+            'traefik.frontend.rule' = 'PathPrefix:/example.com/openid/;'
+            'traefik.frontend.priority' = '20000'
+        "#
+        );
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+
+        let app_name = AppName::master();
+
+        let configs = vec![crate::sc!(
+            "openid",
+            labels = (),
+            env = ("VAR_1" => "efg"),
+            files = ()
+        )];
+
+        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &configs, None)
+            .await?;
 
         let deployed_apps = apps.get_apps().await?;
 
-        let services = deployed_apps
-            .get_vec(&AppName::from_str("branch").unwrap())
-            .unwrap();
-        assert_eq!(services.len(), 2);
-        assert_contains_service!(services, "service-b", ContainerType::Instance);
-        assert_contains_service!(services, "service-a", ContainerType::Replica);
+        let services = deployed_apps.get_vec(&app_name).unwrap();
+        assert_eq!(services.len(), 1);
+        assert_contains_service!(services, "openid", ContainerType::Instance);
+
+        let openid_configs: Vec<ServiceConfig> = apps
+            .infrastructure
+            .get_configs_of_app(&app_name)
+            .await?
+            .into_iter()
+            .filter(|config| config.service_name() == "openid")
+            .collect();
+        assert_eq!(openid_configs.len(), 1);
+
+        use secstr::SecUtf8;
+        let openid_env = openid_configs[0].env().unwrap();
+        assert_eq!(
+            openid_env.variable("VAR_1"),
+            Some(&EnvironmentVariable::new(
+                String::from("VAR_1"),
+                SecUtf8::from("efg")
+            ))
+        );
+        assert_eq!(
+            openid_env.variable("VAR_2"),
+            Some(&EnvironmentVariable::new(
+                String::from("VAR_2"),
+                SecUtf8::from("1234")
+            ))
+        );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_override_replicas_from_master() -> Result<(), AppsServiceError> {
-        let config = Config::default();
+    async fn should_include_running_instance_in_templating() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [companions.openid]
+            serviceName = 'openid'
+            type = 'application'
+            image = 'keycloak/keycloak:23.0'
+            env = [ """SERVICES={{~#each services~}}{{name}},{{~/each~}}""" ]
+        "#
+        );
+
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
+        let app_name = AppName::master();
 
         apps.create_or_update(
-            &AppName::master(),
+            &app_name,
             &AppStatusChangeId::new(),
             None,
-            &vec![sc!("service-a"), sc!("service-b")],
+            &vec![crate::sc!("service-a")],
+            None,
         )
         .await?;
-
         apps.create_or_update(
-            &AppName::from_str("branch").unwrap(),
+            &app_name,
             &AppStatusChangeId::new(),
-            Some(AppName::master()),
-            &vec![sc!("service-b")],
+            None,
+            &vec![crate::sc!("service-b")],
+            None,
         )
         .await?;
-
         apps.create_or_update(
-            &AppName::from_str("branch").unwrap(),
+            &app_name,
             &AppStatusChangeId::new(),
-            Some(AppName::master()),
-            &vec![sc!("service-a")],
+            None,
+            &vec![crate::sc!("service-c")],
+            None,
         )
         .await?;
 
-        let deployed_apps = apps.get_apps().await?;
-
-        let services = deployed_apps
-            .get_vec(&AppName::from_str("branch").unwrap())
+        let services = apps.infrastructure.get_services().await?;
+        let openid_config = services
+            .get_vec(&AppName::master())
+            .unwrap()
+            .iter()
+            .find(|service| service.service_name() == "openid")
+            .map(|service| service.config())
             .unwrap();
-        assert_eq!(services.len(), 2);
-        assert_contains_service!(services, "service-a", ContainerType::Instance);
-        assert_contains_service!(services, "service-b", ContainerType::Instance);
+        let openid_env = openid_config.env().unwrap().get(0).unwrap();
+
+        assert_eq!(
+            openid_env.value().unsecure(),
+            "service-a,service-b,service-c,"
+        );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_create_app_for_master_with_secrets() -> Result<(), AppsServiceError> {
-        let config = config_from_str!(
-            r#"
-            [services.mariadb]
-            [[services.mariadb.secrets]]
-            name = "user"
-            data = "SGVsbG8="
-            appSelector = "master"
-        "#
-        );
-
+    async fn should_delete_apps() -> Result<(), AppsServiceError> {
+        let config = Config::default();
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
 
+        let app_name = AppName::master();
         apps.create_or_update(
-            &AppName::master(),
+            &app_name,
             &AppStatusChangeId::new(),
             None,
-            &vec![sc!("mariadb")],
+            &vec![sc!("service-a")],
+            None,
         )
         .await?;
-
-        let configs = apps
-            .infrastructure
-            .get_configs_of_app(&AppName::master())
+        let deleted_services = apps
+            .delete_app(&app_name, &AppStatusChangeId::new())
             .await?;
-        assert_eq!(configs.len(), 1);
 
-        let files = configs.get(0).unwrap().files().unwrap();
         assert_eq!(
-            files.get(&PathBuf::from("/run/secrets/user")).unwrap(),
-            &SecUtf8::from("Hello")
+            deleted_services,
+            vec![ServiceBuilder::new()
+                .id("service-a".to_string())
+                .app_name("master".to_string())
+                .config(crate::sc!("service-a"))
+                .started_at(
+                    DateTime::parse_from_rfc3339("2019-07-18T07:25:00.000000000Z")
+                        .unwrap()
+                        .with_timezone(&Utc),
+                )
+                .build()
+                .unwrap()],
         );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_create_app_for_master_without_secrets_because_of_none_matching_app_selector(
+    async fn should_delete_apps_from_parallel_threads_returning_the_same_result(
     ) -> Result<(), AppsServiceError> {
-        let config = config_from_str!(
-            r#"
-            [services.mariadb]
-            [[services.mariadb.secrets]]
-            name = "user"
-            data = "SGVsbG8="
-            appSelector = "master"
-        "#
-        );
-
-        let infrastructure = Box::new(Dummy::new());
-        let apps = AppsService::new(config, infrastructure)?;
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::with_delay(std::time::Duration::from_millis(500)));
+        let apps = Arc::new(AppsService::new(config, infrastructure)?);
 
+        let app_name = AppName::master();
         apps.create_or_update(
-            &AppName::from_str("master-1.x").unwrap(),
+            &app_name,
             &AppStatusChangeId::new(),
             None,
-            &vec![sc!("mariadb")],
+            &vec![sc!("service-a")],
+            None,
         )
         .await?;
 
-        let configs = apps
-            .infrastructure
-            .get_configs_of_app(&AppName::from_str("master-1.x").unwrap())
-            .await?;
-        assert_eq!(configs.len(), 1);
+        let apps_clone = apps.clone();
+        let handle1 = std::thread::spawn(move || {
+            let rt = runtime::Builder::new_current_thread()
+                .enable_time()
+                .build()
+                .unwrap();
+            rt.block_on(apps_clone.delete_app(&app_name, &AppStatusChangeId::new()))
+        });
+        let app_name = AppName::master();
+        let handle2 = std::thread::spawn(move || {
+            let rt = runtime::Builder::new_current_thread()
+                .enable_time()
+                .build()
+                .unwrap();
+            rt.block_on(apps.delete_app(&app_name, &AppStatusChangeId::new()))
+        });
 
-        let files = configs.get(0).unwrap().files();
-        assert_eq!(files, None);
+        assert_eq!(handle1.join().unwrap()?, handle2.join().unwrap()?,);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_collect_log_chunk_from_infrastructure() -> Result<(), AppsServiceError> {
-        let config = Config::default();
+    async fn should_delete_service_and_rerender_companions() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [companions.openid]
+            serviceName = 'openid'
+            type = 'application'
+            image = 'keycloak/keycloak:23.0'
+            env = [ """SERVICES={{~#each services~}}{{name}},{{~/each~}}""" ]
+        "#
+        );
+
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
-
         let app_name = AppName::master();
 
         apps.create_or_update(
@@ -697,246 +2284,240 @@ mod tests {
             &AppStatusChangeId::new(),
             None,
             &vec![sc!("service-a"), sc!("service-b")],
+            None,
         )
         .await?;
 
-        let log_chunk = apps
-            .get_logs(&app_name, &String::from("service-a"), &None, &Some(100))
-            .await
-            .unwrap()
-            .unwrap();
-
-        assert_eq!(
-            log_chunk.since(),
-            &DateTime::parse_from_rfc3339("2019-07-18T07:25:00.000000000Z").unwrap()
-        );
+        let deleted_services = apps
+            .delete_service(
+                &app_name,
+                "service-b",
+                &AppStatusChangeId::new(),
+                None,
+                false,
+            )
+            .await?;
+        assert_eq!(deleted_services.len(), 1);
+        assert_eq!(deleted_services[0].service_name(), "service-b");
 
-        assert_eq!(
-            log_chunk.until(),
-            &DateTime::parse_from_rfc3339("2019-07-18T07:35:00.000000000Z").unwrap()
-        );
+        let deployed_apps = apps.get_apps().await?;
+        let services = deployed_apps.get_vec(&app_name).unwrap();
+        assert_eq!(services.len(), 2);
+        assert_contains_service!(services, "service-a", ContainerType::Instance);
 
-        assert_eq!(
-            log_chunk.log_lines(),
-            r#"Log msg 1 of service-a of app master
-Log msg 2 of service-a of app master
-Log msg 3 of service-a of app master
-"#
-        );
+        let openid_config = services
+            .iter()
+            .find(|service| service.service_name() == "openid")
+            .map(|service| service.config())
+            .unwrap();
+        let openid_env = openid_config.env().unwrap().get(0).unwrap();
+        assert_eq!(openid_env.value().unsecure(), "service-a,");
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_stream_logs_from_infrastructure() -> Result<(), AppsServiceError> {
+    async fn should_replace_deleted_service_with_replica() -> Result<(), AppsServiceError> {
         let config = Config::default();
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
+        let branch = AppName::from_str("branch").unwrap();
 
-        let app_name = AppName::from_str("master").unwrap();
-        let services = vec![sc!("service-a"), sc!("service-b")];
-        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &services)
-            .await?;
-        for service in services {
-            let mut log_stream = apps
-                .stream_logs(&app_name, service.service_name(), &None, &None)
-                .await;
-
-            assert_eq!(
-                log_stream.next().await.unwrap().unwrap(),
-                (
-                    DateTime::parse_from_rfc3339("2019-07-18T07:25:00.000000000Z").unwrap(),
-                    format!(
-                        "Log msg 1 of {} of app {app_name}\n",
-                        service.service_name()
-                    )
-                )
-            );
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a"), sc!("service-b")],
+            None,
+        )
+        .await?;
+        apps.create_or_update(
+            &branch,
+            &AppStatusChangeId::new(),
+            Some(AppName::master()),
+            &vec![sc!("service-b")],
+            None,
+        )
+        .await?;
 
-            assert_eq!(
-                log_stream.next().await.unwrap().unwrap(),
-                (
-                    DateTime::parse_from_rfc3339("2019-07-18T07:30:00.000000000Z").unwrap(),
-                    format!(
-                        "Log msg 2 of {} of app {app_name}\n",
-                        service.service_name()
-                    )
-                )
-            );
+        apps.delete_service(
+            &branch,
+            "service-b",
+            &AppStatusChangeId::new(),
+            Some(AppName::master()),
+            false,
+        )
+        .await?;
 
-            assert_eq!(
-                log_stream.next().await.unwrap().unwrap(),
-                (
-                    DateTime::parse_from_rfc3339("2019-07-18T07:35:00.000000000Z").unwrap(),
-                    format!(
-                        "Log msg 3 of {} of app {app_name}\n",
-                        service.service_name()
-                    )
-                )
-            );
-        }
+        let deployed_apps = apps.get_apps().await?;
+        let services = deployed_apps.get_vec(&branch).unwrap();
+        assert_eq!(services.len(), 2);
+        assert_contains_service!(services, "service-a", ContainerType::Replica);
+        assert_contains_service!(services, "service-b", ContainerType::Replica);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_deploy_companions() -> Result<(), AppsServiceError> {
-        let config = config_from_str!(
-            r#"
-            [companions.openid]
-            serviceName = 'openid'
-            type = 'application'
-            image = 'keycloak/keycloak:23.0'
-
-            [companions.db]
-            serviceName = 'db'
-            type = 'service'
-            image = 'postgres:16.1'
-        "#
-        );
+    async fn should_not_delete_unknown_service() -> Result<(), AppsServiceError> {
+        let config = Config::default();
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
 
-        let app_name = AppName::master();
         apps.create_or_update(
-            &app_name,
+            &AppName::master(),
             &AppStatusChangeId::new(),
             None,
             &vec![sc!("service-a")],
+            None,
         )
         .await?;
-        let deployed_apps = apps.get_apps().await?;
 
-        let services = deployed_apps.get_vec(&app_name).unwrap();
-        assert_eq!(services.len(), 3);
-        assert_contains_service!(services, "openid", ContainerType::ApplicationCompanion);
-        assert_contains_service!(services, "db", ContainerType::ServiceCompanion);
-        assert_contains_service!(services, "service-a", ContainerType::Instance);
+        let result = apps
+            .delete_service(
+                &AppName::master(),
+                "service-b",
+                &AppStatusChangeId::new(),
+                None,
+                false,
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(AppsServiceError::ServiceNotFound { .. })
+        ));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_filter_companions_if_services_to_deploy_contain_same_service_name(
-    ) -> Result<(), AppsServiceError> {
-        let config = config_from_str!(
-            r#"
-            [companions.openid]
-            serviceName = 'openid'
-            type = 'application'
-            image = 'keycloak/keycloak:23.0'
-
-            [companions.db]
-            serviceName = 'db'
-            type = 'service'
-            image = 'postgres:16.1'
-        "#
-        );
+    async fn should_redeploy_paused_service() -> Result<(), AppsServiceError> {
+        let config = Config::default();
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
-
         let app_name = AppName::master();
-        let configs = vec![sc!("openid"), sc!("db")];
-        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &configs)
-            .await?;
-        let deployed_apps = apps.get_apps().await?;
 
-        let services = deployed_apps.get_vec(&app_name).unwrap();
-        assert_eq!(services.len(), 2);
-        assert_contains_service!(services, "openid", ContainerType::Instance);
-        assert_contains_service!(services, "db", ContainerType::Instance);
+        apps.create_or_update(
+            &app_name,
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+        apps.change_status(&app_name, "service-a", ServiceStatus::Paused)
+            .await?;
 
-        let openid_configs: Vec<ServiceConfig> = apps
-            .infrastructure
-            .get_configs_of_app(&app_name)
-            .await?
-            .into_iter()
-            .filter(|config| config.service_name() == "openid")
-            .collect();
-        assert_eq!(openid_configs.len(), 1);
-        assert_eq!(openid_configs[0].image(), configs[0].image());
+        let services = apps.redeploy_service(&app_name, "service-a").await?;
+
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].status(), &ServiceStatus::Running);
+        assert!(matches!(
+            apps.redeploy_service(&app_name, "service-b").await,
+            Err(AppsServiceError::ServiceNotFound { .. })
+        ));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_merge_with_companion_config_if_services_to_deploy_contain_same_service_name(
-    ) -> Result<(), AppsServiceError> {
-        let config = config_from_str!(
-            r#"
-            [companions.openid]
-            serviceName = 'openid'
-            type = 'application'
-            image = 'keycloak/keycloak:23.0'
-            env = [ "VAR_1=abcd", "VAR_2=1234" ]
-
-            [companions.openid.labels]
-            'traefik.frontend.rule' = 'PathPrefix:/example.com/openid/;'
-            'traefik.frontend.priority' = '20000'
-        "#
-        );
+    async fn should_restart_service() -> Result<(), AppsServiceError> {
+        let config = Config::default();
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
-
         let app_name = AppName::master();
 
-        let configs = vec![crate::sc!(
-            "openid",
-            labels = (),
-            env = ("VAR_1" => "efg"),
-            files = ()
-        )];
+        apps.create_or_update(
+            &app_name,
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
 
-        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &configs)
-            .await?;
+        let service = apps.restart_service(&app_name, "service-a").await?;
+        assert_eq!(
+            service.map(|service| service.status().clone()),
+            Some(ServiceStatus::Running)
+        );
+        assert_eq!(apps.restart_service(&app_name, "service-b").await?, None);
 
-        let deployed_apps = apps.get_apps().await?;
+        Ok(())
+    }
 
-        let services = deployed_apps.get_vec(&app_name).unwrap();
-        assert_eq!(services.len(), 1);
-        assert_contains_service!(services, "openid", ContainerType::Instance);
+    #[tokio::test]
+    async fn should_reject_restart_during_deployment() -> Result<(), AppsServiceError> {
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::with_delay(std::time::Duration::from_millis(500)));
+        let apps = Arc::new(AppsService::new(config, infrastructure)?);
+        let app_name = AppName::master();
 
-        let openid_configs: Vec<ServiceConfig> = apps
-            .infrastructure
-            .get_configs_of_app(&app_name)
-            .await?
-            .into_iter()
-            .filter(|config| config.service_name() == "openid")
-            .collect();
-        assert_eq!(openid_configs.len(), 1);
+        let deployment = {
+            let apps = apps.clone();
+            let app_name = app_name.clone();
+            tokio::spawn(async move {
+                apps.create_or_update(
+                    &app_name,
+                    &AppStatusChangeId::new(),
+                    None,
+                    &vec![sc!("service-a")],
+                    None,
+                )
+                .await
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
 
-        use secstr::SecUtf8;
-        let openid_env = openid_configs[0].env().unwrap();
-        assert_eq!(
-            openid_env.variable("VAR_1"),
-            Some(&EnvironmentVariable::new(
-                String::from("VAR_1"),
-                SecUtf8::from("efg")
-            ))
-        );
-        assert_eq!(
-            openid_env.variable("VAR_2"),
-            Some(&EnvironmentVariable::new(
-                String::from("VAR_2"),
-                SecUtf8::from("1234")
-            ))
-        );
+        assert!(matches!(
+            apps.restart_service(&app_name, "service-a").await,
+            Err(AppsServiceError::AppIsInDeployment { .. })
+        ));
+        assert!(deployment.await.unwrap().is_ok());
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_include_running_instance_in_templating() -> Result<(), AppsServiceError> {
+    async fn should_queue_restart_during_deployment() -> Result<(), AppsServiceError> {
         let config = config_from_str!(
             r#"
-            [companions.openid]
-            serviceName = 'openid'
-            type = 'application'
-            image = 'keycloak/keycloak:23.0'
-            env = [ """SERVICES={{~#each services~}}{{name}},{{~/each~}}""" ]
-        "#
+            [deployments]
+            queueing = 'queue'
+            "#
         );
+        let infrastructure = Box::new(Dummy::with_delay(std::time::Duration::from_millis(500)));
+        let apps = Arc::new(AppsService::new(config, infrastructure)?);
+        let app_name = AppName::master();
+
+        let deployment = {
+            let apps = apps.clone();
+            let app_name = app_name.clone();
+            tokio::spawn(async move {
+                apps.create_or_update(
+                    &app_name,
+                    &AppStatusChangeId::new(),
+                    None,
+                    &vec![sc!("service-a")],
+                    None,
+                )
+                .await
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        // The restart waits for the deployment and, thus, finds the deployed service.
+        let service = apps.restart_service(&app_name, "service-a").await?;
+        assert!(service.is_some());
+        assert!(deployment.await.unwrap().is_ok());
+
+        Ok(())
+    }
 
+    #[tokio::test]
+    async fn should_exec_command_in_service() -> Result<(), AppsServiceError> {
+        let config = Config::default();
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
         let app_name = AppName::master();
@@ -945,112 +2526,279 @@ Log msg 3 of service-a of app master
             &app_name,
             &AppStatusChangeId::new(),
             None,
-            &vec![crate::sc!("service-a")],
-        )
-        .await?;
-        apps.create_or_update(
-            &app_name,
-            &AppStatusChangeId::new(),
+            &vec![sc!("service-a")],
             None,
-            &vec![crate::sc!("service-b")],
         )
         .await?;
+
+        let output = apps
+            .exec(&app_name, "service-a", &[String::from("hello")])
+            .await?;
+        assert_eq!(output.exit_code(), Some(0));
+        assert_eq!(output.stdout(), "hello\n");
+
+        assert!(matches!(
+            apps.exec(&app_name, "service-b", &[String::from("hello")])
+                .await,
+            Err(AppsServiceError::ServiceNotFound { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_attach_to_tty_of_service() -> Result<(), AppsServiceError> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let config = Config::default();
+        let infrastructure = Box::new(Dummy::new());
+        let apps = AppsService::new(config, infrastructure)?;
+        let app_name = AppName::master();
+
         apps.create_or_update(
             &app_name,
             &AppStatusChangeId::new(),
             None,
-            &vec![crate::sc!("service-c")],
+            &vec![sc!("service-a")],
+            None,
         )
         .await?;
 
-        let services = apps.infrastructure.get_services().await?;
-        let openid_config = services
-            .get_vec(&AppName::master())
-            .unwrap()
-            .iter()
-            .find(|service| service.service_name() == "openid")
-            .map(|service| service.config())
-            .unwrap();
-        let openid_env = openid_config.env().unwrap().get(0).unwrap();
+        let session = apps
+            .attach_exec(&app_name, "service-a", &[String::from("sh")])
+            .await?;
+        let (mut input, mut output) = session.into_parts();
+        input.write_all(b"ls\n").await.unwrap();
 
-        assert_eq!(
-            openid_env.value().unsecure(),
-            "service-a,service-b,service-c,"
-        );
+        let mut buffer = [0u8; 3];
+        output.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"ls\n");
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_delete_apps() -> Result<(), AppsServiceError> {
+    async fn should_upload_and_download_files() -> Result<(), AppsServiceError> {
         let config = Config::default();
         let infrastructure = Box::new(Dummy::new());
         let apps = AppsService::new(config, infrastructure)?;
-
         let app_name = AppName::master();
+
         apps.create_or_update(
             &app_name,
             &AppStatusChangeId::new(),
             None,
             &vec![sc!("service-a")],
+            None,
         )
         .await?;
-        let deleted_services = apps
-            .delete_app(&app_name, &AppStatusChangeId::new())
+
+        assert!(apps
+            .download_files(&app_name, "service-a", "/reports")
+            .await
+            .is_err());
+
+        apps.upload_files(&app_name, "service-a", "/reports", b"archive".to_vec())
             .await?;
+        let archive = apps
+            .download_files(&app_name, "service-a", "/reports")
+            .await?
+            .map(|chunk| chunk.unwrap())
+            .concat()
+            .await;
+        assert_eq!(archive, b"archive".to_vec());
 
-        assert_eq!(
-            deleted_services,
-            vec![ServiceBuilder::new()
-                .id("service-a".to_string())
-                .app_name("master".to_string())
-                .config(crate::sc!("service-a"))
-                .started_at(
-                    DateTime::parse_from_rfc3339("2019-07-18T07:25:00.000000000Z")
-                        .unwrap()
-                        .with_timezone(&Utc),
+        assert!(matches!(
+            apps.upload_files(&app_name, "service-b", "/reports", Vec::new())
+                .await,
+            Err(AppsServiceError::ServiceNotFound { .. })
+        ));
+
+        Ok(())
+    }
+
+    /// Deploys three services to master, each one 100 ms after the previous one, and returns the
+    /// results of the deployments and the names of the deployed services.
+    async fn deploy_concurrently(
+        queueing: &str,
+    ) -> Result<(Vec<Result<Vec<Service>, AppsServiceError>>, Vec<String>), AppsServiceError> {
+        let config = config_from_str!(&format!(
+            r#"
+            [deployments]
+            queueing = '{queueing}'
+            "#
+        ));
+        let infrastructure = Box::new(Dummy::with_delay(std::time::Duration::from_millis(500)));
+        let apps = Arc::new(AppsService::new(config, infrastructure)?);
+
+        let mut handles = Vec::new();
+        for service_name in ["service-a", "service-b", "service-c"] {
+            let apps = apps.clone();
+            handles.push(tokio::spawn(async move {
+                apps.create_or_update(
+                    &AppName::master(),
+                    &AppStatusChangeId::new(),
+                    None,
+                    &vec![sc!(service_name)],
+                    None,
                 )
-                .build()
-                .unwrap()],
+                .await
+            }));
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap());
+        }
+
+        let mut service_names = apps
+            .get_apps()
+            .await?
+            .get_vec(&AppName::master())
+            .unwrap()
+            .iter()
+            .map(|service| service.service_name().clone())
+            .collect::<Vec<_>>();
+        service_names.sort();
+
+        Ok((results, service_names))
+    }
+
+    #[tokio::test]
+    async fn should_reject_concurrent_deployments() -> Result<(), AppsServiceError> {
+        let (results, service_names) = deploy_concurrently("reject").await?;
+
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(AppsServiceError::AppIsInDeployment { .. })
+        ));
+        assert!(matches!(
+            results[2],
+            Err(AppsServiceError::AppIsInDeployment { .. })
+        ));
+        assert_eq!(service_names, vec!["service-a"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_queue_concurrent_deployments() -> Result<(), AppsServiceError> {
+        let (results, service_names) = deploy_concurrently("queue").await?;
+
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(service_names, vec!["service-a", "service-b", "service-c"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_coalesce_queued_deployments() -> Result<(), AppsServiceError> {
+        let (results, service_names) = deploy_concurrently("coalesce").await?;
+
+        assert!(results.iter().all(Result::is_ok));
+        // The superseded request for service-b is deployed together with service-c
+        assert_eq!(service_names, vec!["service-a", "service-b", "service-c"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_not_coalesce_deployments_with_different_targets() -> Result<(), AppsServiceError>
+    {
+        let config = config_from_str!(
+            r#"
+            [deployments]
+            queueing = 'coalesce'
+            "#
         );
+        let infrastructure = Box::new(Dummy::with_delay(std::time::Duration::from_millis(500)));
+        let apps = Arc::new(AppsService::new(config, infrastructure)?);
+
+        let mut handles = Vec::new();
+        for (service_name, target) in [
+            ("service-a", None),
+            ("service-b", Some("unknown")),
+            ("service-c", None),
+        ] {
+            let apps = apps.clone();
+            handles.push(tokio::spawn(async move {
+                apps.create_or_update(
+                    &AppName::master(),
+                    &AppStatusChangeId::new(),
+                    None,
+                    &vec![sc!(service_name)],
+                    target.map(String::from),
+                )
+                .await
+            }));
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap());
+        }
+
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(AppsServiceError::InvalidDeploymentTarget { .. })
+        ));
+        assert!(results[2].is_ok());
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn should_delete_apps_from_parallel_threads_returning_the_same_result(
-    ) -> Result<(), AppsServiceError> {
-        let config = Config::default();
+    async fn should_remove_dropped_deployment_from_queue() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [deployments]
+            queueing = 'queue'
+            "#
+        );
         let infrastructure = Box::new(Dummy::with_delay(std::time::Duration::from_millis(500)));
         let apps = Arc::new(AppsService::new(config, infrastructure)?);
 
-        let app_name = AppName::master();
-        apps.create_or_update(
-            &app_name,
-            &AppStatusChangeId::new(),
-            None,
-            &vec![sc!("service-a")],
-        )
-        .await?;
+        let running = {
+            let apps = apps.clone();
+            tokio::spawn(async move {
+                apps.create_or_update(
+                    &AppName::master(),
+                    &AppStatusChangeId::new(),
+                    None,
+                    &vec![sc!("service-a")],
+                    None,
+                )
+                .await
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let status_id = AppStatusChangeId::new();
+        let queued = {
+            let apps = apps.clone();
+            tokio::spawn(async move {
+                apps.create_or_update(
+                    &AppName::master(),
+                    &status_id,
+                    None,
+                    &vec![sc!("service-b")],
+                    None,
+                )
+                .await
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(apps.queue_position(&AppName::master(), &status_id), Some(1));
 
-        let apps_clone = apps.clone();
-        let handle1 = std::thread::spawn(move || {
-            let rt = runtime::Builder::new_current_thread()
-                .enable_time()
-                .build()
-                .unwrap();
-            rt.block_on(apps_clone.delete_app(&app_name, &AppStatusChangeId::new()))
-        });
-        let app_name = AppName::master();
-        let handle2 = std::thread::spawn(move || {
-            let rt = runtime::Builder::new_current_thread()
-                .enable_time()
-                .build()
-                .unwrap();
-            rt.block_on(apps.delete_app(&app_name, &AppStatusChangeId::new()))
-        });
+        queued.abort();
+        assert!(queued.await.unwrap_err().is_cancelled());
+        assert_eq!(apps.queue_position(&AppName::master(), &status_id), None);
 
-        assert_eq!(handle1.join().unwrap()?, handle2.join().unwrap()?,);
+        assert!(running.await.unwrap().is_ok());
 
         Ok(())
     }
@@ -1081,7 +2829,7 @@ Log msg 3 of service-a of app master
 
         let app_name = AppName::master();
         let configs = vec![sc!("db1"), sc!("db2")];
-        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &configs)
+        apps.create_or_update(&app_name, &AppStatusChangeId::new(), None, &configs, None)
             .await?;
         let deployed_apps = apps.get_apps().await?;
 
@@ -1146,6 +2894,7 @@ Log msg 3 of service-a of app master
             &AppStatusChangeId::new(),
             None,
             &vec![sc!("service-a"), sc!("service-b")],
+            None,
         )
         .await?;
 
@@ -1175,6 +2924,7 @@ Log msg 3 of service-a of app master
             &AppStatusChangeId::new(),
             None,
             &vec![sc!("service-a"), sc!("service-b")],
+            None,
         )
         .await?;
 
@@ -1217,6 +2967,7 @@ Log msg 3 of service-a of app master
             &AppStatusChangeId::new(),
             None,
             &vec![sc!("service-a"), sc!("service-b")],
+            None,
         )
         .await?;
 
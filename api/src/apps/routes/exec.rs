@@ -0,0 +1,379 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use crate::{
+    apps::Apps,
+    config::Config,
+    http_result::HttpResult,
+    models::{AppName, AppNameError, ExecOutput},
+};
+use futures::{SinkExt, StreamExt};
+use http_api_problem::{HttpApiProblem, StatusCode};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    Request, State,
+};
+use rocket_ws::{Channel, Message, WebSocket};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Attaches a WebSocket to a command that runs with a TTY in the container of the service. The
+/// messages of the client are written to the TTY and the output of the TTY is sent as binary
+/// messages. The WebSocket is closed when the command exits.
+#[get("/<app_name>/services/<service_name>/exec?<command>")]
+pub(super) async fn attach_exec(
+    app_name: Result<AppName, AppNameError>,
+    service_name: String,
+    command: Vec<String>,
+    token: Option<BearerToken>,
+    config: &State<Config>,
+    apps: &State<Arc<Apps>>,
+    ws: WebSocket,
+) -> HttpResult<Channel<'static>> {
+    let app_name = app_name?;
+    authorize(config, &app_name, token)?;
+
+    let command = if command.is_empty() {
+        vec![String::from("/bin/sh")]
+    } else {
+        command
+    };
+    let session = apps.attach_exec(&app_name, &service_name, &command).await?;
+
+    Ok(ws.channel(move |stream| {
+        Box::pin(async move {
+            let (mut input, mut output) = session.into_parts();
+            let (mut sink, mut source) = stream.split();
+
+            let forward_input = async {
+                while let Some(message) = source.next().await {
+                    match message? {
+                        Message::Text(text) => input.write_all(text.as_bytes()).await?,
+                        Message::Binary(data) => input.write_all(&data).await?,
+                        Message::Close(_) => break,
+                        _ => continue,
+                    }
+                    input.flush().await?;
+                }
+                input.shutdown().await?;
+                Ok::<(), rocket_ws::result::Error>(())
+            };
+
+            let forward_output = async {
+                let mut buffer = vec![0u8; 4096];
+                loop {
+                    let read = output.read(&mut buffer).await?;
+                    if read == 0 {
+                        break;
+                    }
+                    sink.send(Message::Binary(buffer[..read].to_vec())).await?;
+                }
+                sink.close().await
+            };
+
+            tokio::select! {
+                result = forward_input => result,
+                result = forward_output => result,
+            }
+        })
+    }))
+}
+
+/// Runs a command in the container of the service until it exits and responds with its exit code
+/// and output.
+#[post(
+    "/<app_name>/services/<service_name>/exec",
+    format = "application/json",
+    data = "<exec_request>"
+)]
+pub(super) async fn exec(
+    app_name: Result<AppName, AppNameError>,
+    service_name: String,
+    exec_request: Json<ExecRequest>,
+    token: Option<BearerToken>,
+    config: &State<Config>,
+    apps: &State<Arc<Apps>>,
+) -> HttpResult<Json<ExecOutput>> {
+    let app_name = app_name?;
+    authorize(config, &app_name, token)?;
+
+    if exec_request.command.is_empty() {
+        return Err(HttpApiProblem::with_title_and_type(StatusCode::BAD_REQUEST)
+            .detail("The command must not be empty.")
+            .into());
+    }
+
+    let output = apps
+        .exec(&app_name, &service_name, &exec_request.command)
+        .await?;
+
+    Ok(Json(output))
+}
+
+pub(super) fn authorize(
+    config: &Config,
+    app_name: &AppName,
+    token: Option<BearerToken>,
+) -> HttpResult<()> {
+    let Some(exec) = config.exec() else {
+        return Err(HttpApiProblem::with_title_and_type(StatusCode::FORBIDDEN)
+            .detail("Accessing the services of apps through the API is disabled.")
+            .into());
+    };
+    let Some(BearerToken(token)) = token else {
+        return Err(
+            HttpApiProblem::with_title_and_type(StatusCode::UNAUTHORIZED)
+                .detail("Accessing the services of apps requires a bearer token.")
+                .into(),
+        );
+    };
+
+    if !exec.authorizes(app_name, &token) {
+        return Err(HttpApiProblem::with_title_and_type(StatusCode::FORBIDDEN)
+            .detail(format!(
+                "The token does not grant access to the services of {app_name}."
+            ))
+            .into());
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub(super) struct ExecRequest {
+    command: Vec<String>,
+}
+
+/// The token of the `Authorization: Bearer <token>` header.
+pub(super) struct BearerToken(pub(super) String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BearerToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        {
+            Some(token) => Outcome::Success(BearerToken(token.trim().to_string())),
+            None => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config_from_str, infrastructure::Dummy, models::AppStatusChangeId, sc};
+    use rocket::{
+        http::{ContentType, Header},
+        local::asynchronous::Client,
+    };
+    use serde_json::{json, Value};
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::{
+        client::IntoClientRequest, Error as ClientError, Message as ClientMessage,
+    };
+
+    async fn set_up_apps() -> Result<Arc<Apps>, crate::apps::AppsServiceError> {
+        let infrastructure = Box::new(Dummy::new());
+        let apps = Arc::new(Apps::new(Default::default(), infrastructure).unwrap());
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+        Ok(apps)
+    }
+
+    async fn set_up_rocket_with_exec_config(
+        config: Config,
+    ) -> Result<Client, crate::apps::AppsServiceError> {
+        let rocket = rocket::build()
+            .manage(config)
+            .manage(set_up_apps().await?)
+            .mount("/api/apps", routes![exec]);
+        Ok(Client::tracked(rocket).await.expect("valid rocket"))
+    }
+
+    /// Launches Rocket on a free port because the local client of Rocket cannot upgrade
+    /// connections to WebSockets. Returns the port.
+    async fn launch_rocket_with_exec_config(
+        config: Config,
+    ) -> Result<u16, crate::apps::AppsServiceError> {
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|listener| listener.local_addr())
+            .expect("free port")
+            .port();
+
+        let rocket = rocket::custom(rocket::Config {
+            address: Ipv4Addr::LOCALHOST.into(),
+            port,
+            log_level: rocket::config::LogLevel::Off,
+            ..rocket::Config::debug_default()
+        })
+        .manage(config)
+        .manage(set_up_apps().await?)
+        .mount("/api/apps", routes![attach_exec]);
+        tokio::spawn(rocket.launch());
+
+        while tokio::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(port)
+    }
+
+    fn attach_request(
+        port: u16,
+        token: &str,
+    ) -> tokio_tungstenite::tungstenite::handshake::client::Request {
+        let mut request =
+            format!("ws://localhost:{port}/api/apps/master/services/service-a/exec?command=sh")
+                .into_client_request()
+                .unwrap();
+        request
+            .headers_mut()
+            .insert("Authorization", format!("Bearer {token}").parse().unwrap());
+        request
+    }
+
+    async fn exec_status(client: &Client, token: Option<&str>) -> Status {
+        let mut request = client
+            .post("/api/apps/master/services/service-a/exec")
+            .header(ContentType::JSON)
+            .body(r#"{ "command": ["echo", "hello"] }"#);
+        if let Some(token) = token {
+            request = request.header(Header::new("Authorization", format!("Bearer {token}")));
+        }
+        request.dispatch().await.status()
+    }
+
+    #[tokio::test]
+    async fn exec_command_with_valid_token() -> Result<(), crate::apps::AppsServiceError> {
+        let client = set_up_rocket_with_exec_config(config_from_str!(
+            r#"
+            [exec]
+            tokens = ['secret']
+            "#
+        ))
+        .await?;
+
+        let response = client
+            .post("/api/apps/master/services/service-a/exec")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", "Bearer secret"))
+            .body(r#"{ "command": ["echo", "hello"] }"#)
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_json::<Value>().await,
+            Some(json!({ "exitCode": 0, "stdout": "echo hello\n", "stderr": "" }))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_exec_without_valid_token() -> Result<(), crate::apps::AppsServiceError> {
+        let client = set_up_rocket_with_exec_config(config_from_str!(
+            r#"
+            [exec]
+            tokens = ['secret']
+            "#
+        ))
+        .await?;
+
+        assert_eq!(exec_status(&client, None).await, Status::Unauthorized);
+        assert_eq!(exec_status(&client, Some("guess")).await, Status::Forbidden);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_exec_if_not_configured() -> Result<(), crate::apps::AppsServiceError> {
+        let client = set_up_rocket_with_exec_config(Config::default()).await?;
+
+        assert_eq!(
+            exec_status(&client, Some("secret")).await,
+            Status::Forbidden
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn attach_to_command_with_valid_token() -> Result<(), crate::apps::AppsServiceError> {
+        let port = launch_rocket_with_exec_config(config_from_str!(
+            r#"
+            [exec]
+            tokens = ['secret']
+            "#
+        ))
+        .await?;
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(attach_request(port, "secret"))
+            .await
+            .expect("WebSocket handshake");
+        socket
+            .send(ClientMessage::Text(String::from("hello")))
+            .await
+            .unwrap();
+
+        // The dummy infrastructure echoes the input of the TTY.
+        assert_eq!(
+            socket.next().await.map(Result::unwrap),
+            Some(ClientMessage::Binary(b"hello".to_vec()))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_attaching_without_valid_token() -> Result<(), crate::apps::AppsServiceError> {
+        let port = launch_rocket_with_exec_config(config_from_str!(
+            r#"
+            [exec]
+            tokens = ['secret']
+            "#
+        ))
+        .await?;
+
+        match tokio_tungstenite::connect_async(attach_request(port, "guess")).await {
+            Err(ClientError::Http(response)) => assert_eq!(response.status().as_u16(), 403),
+            Err(err) => panic!("Unexpected error: {err}"),
+            Ok(_) => panic!("The WebSocket must not be attached with an invalid token"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::exec::{authorize, BearerToken};
+use crate::{
+    apps::Apps,
+    config::Config,
+    http_result::HttpResult,
+    models::{AppName, AppNameError},
+};
+use async_stream::stream;
+use futures::stream::{BoxStream, StreamExt};
+use http_api_problem::{HttpApiProblem, StatusCode};
+use reqwest::header::CONTENT_DISPOSITION;
+use rocket::{
+    data::{Data, Limits, ToByteUnit},
+    http::{ContentType, Status},
+    response::{self, stream::ByteStream, Responder, Response},
+    Request, State,
+};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Responds with a tar archive of the file or directory at `path` in the container of the
+/// service.
+#[get("/<app_name>/services/<service_name>/files?<path>")]
+pub(super) async fn download_files(
+    app_name: Result<AppName, AppNameError>,
+    service_name: String,
+    path: String,
+    token: Option<BearerToken>,
+    config: &State<Config>,
+    apps: &State<Arc<Apps>>,
+) -> HttpResult<ArchiveResponse> {
+    let app_name = app_name?;
+    authorize(config, &app_name, token)?;
+    require_absolute_path(&path)?;
+
+    let archive = apps.download_files(&app_name, &service_name, &path).await?;
+
+    let file_name = Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| service_name.clone());
+    Ok(ArchiveResponse {
+        file_name: format!("{file_name}.tar"),
+        archive,
+    })
+}
+
+/// Extracts the uploaded tar archive into the directory at `path` in the container of the
+/// service. The size of the archive is limited by the `tar` limit of Rocket which defaults to
+/// 64 MiB.
+#[put(
+    "/<app_name>/services/<service_name>/files?<path>",
+    format = "application/x-tar",
+    data = "<archive>"
+)]
+pub(super) async fn upload_files(
+    app_name: Result<AppName, AppNameError>,
+    service_name: String,
+    path: String,
+    archive: Data<'_>,
+    token: Option<BearerToken>,
+    limits: &Limits,
+    config: &State<Config>,
+    apps: &State<Arc<Apps>>,
+) -> HttpResult<Status> {
+    let app_name = app_name?;
+    authorize(config, &app_name, token)?;
+    require_absolute_path(&path)?;
+
+    let limit = limits.get("tar").unwrap_or_else(|| 64.mebibytes());
+    let archive = match archive.open(limit).into_bytes().await {
+        Ok(archive) if archive.is_complete() => archive.into_inner(),
+        Ok(_) => {
+            return Err(
+                HttpApiProblem::with_title_and_type(StatusCode::PAYLOAD_TOO_LARGE)
+                    .detail(format!("The archive exceeds the limit of {limit}."))
+                    .into(),
+            )
+        }
+        Err(err) => {
+            return Err(HttpApiProblem::with_title_and_type(StatusCode::BAD_REQUEST)
+                .detail(format!("Cannot read the archive: {err}"))
+                .into())
+        }
+    };
+
+    apps.upload_files(&app_name, &service_name, &path, archive)
+        .await?;
+
+    Ok(Status::NoContent)
+}
+
+fn require_absolute_path(path: &str) -> HttpResult<()> {
+    if !path.starts_with('/') {
+        return Err(HttpApiProblem::with_title_and_type(StatusCode::BAD_REQUEST)
+            .detail(format!("The path {path} must be absolute."))
+            .into());
+    }
+    Ok(())
+}
+
+pub(super) struct ArchiveResponse {
+    file_name: String,
+    archive: BoxStream<'static, Result<Vec<u8>, failure::Error>>,
+}
+
+impl<'r> Responder<'r, 'static> for ArchiveResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut archive = self.archive;
+        let file_name = self.file_name.clone();
+        let chunks = stream! {
+            while let Some(chunk) = archive.next().await {
+                match chunk {
+                    Ok(chunk) => yield chunk,
+                    Err(err) => {
+                        error!("Cannot stream the archive {file_name}: {err}");
+                        break;
+                    }
+                }
+            }
+        };
+
+        Response::build_from(ByteStream(chunks).respond_to(request)?)
+            .header(ContentType::new("application", "x-tar"))
+            .raw_header(
+                CONTENT_DISPOSITION.as_str(),
+                format!("attachment; filename=\"{}\"", self.file_name),
+            )
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config_from_str, infrastructure::Dummy, models::AppStatusChangeId, sc};
+    use rocket::{http::Header, local::asynchronous::Client};
+
+    async fn set_up_rocket_with_a_running_app() -> Result<Client, crate::apps::AppsServiceError> {
+        let infrastructure = Box::new(Dummy::new());
+        let apps = Arc::new(Apps::new(Default::default(), infrastructure).unwrap());
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+
+        let config = config_from_str!(
+            r#"
+            [exec]
+            tokens = ['secret']
+            "#
+        );
+        let rocket = rocket::build()
+            .manage(config)
+            .manage(apps)
+            .mount("/api/apps", routes![download_files, upload_files]);
+        Ok(Client::tracked(rocket).await.expect("valid rocket"))
+    }
+
+    #[tokio::test]
+    async fn upload_and_download_archive() -> Result<(), crate::apps::AppsServiceError> {
+        let client = set_up_rocket_with_a_running_app().await?;
+
+        let response = client
+            .put("/api/apps/master/services/service-a/files?path=/var/reports")
+            .header(ContentType::new("application", "x-tar"))
+            .header(Header::new("Authorization", "Bearer secret"))
+            .body(b"archive")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        let response = client
+            .get("/api/apps/master/services/service-a/files?path=/var/reports")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"reports.tar\"")
+        );
+        assert_eq!(response.into_bytes().await, Some(b"archive".to_vec()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_relative_path_and_missing_token() -> Result<(), crate::apps::AppsServiceError> {
+        let client = set_up_rocket_with_a_running_app().await?;
+
+        let response = client
+            .get("/api/apps/master/services/service-a/files?path=reports")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client
+            .get("/api/apps/master/services/service-a/files?path=/var/reports")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::exec::BearerToken;
+use crate::{
+    config::{Config, FORWARDED_USER_HEADER},
+    http_result::HttpResult,
+    models::{AppName, AppNameError},
+};
+use http_api_problem::{HttpApiProblem, StatusCode};
+use rocket::{
+    http::{CookieJar, Status},
+    response::{Responder, Response},
+    Request, State,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// How long a validated session is trusted before the OIDC provider is asked again. Traefik calls
+/// the forward-auth endpoint for every request to the app.
+const SESSION_CACHE_DURATION: Duration = Duration::from_secs(60);
+
+/// Validates the OIDC session of a request to the routes of the app on behalf of the forward-auth
+/// middleware of the `oidc` access restriction. The access token is taken from the
+/// `Authorization` header or from the session cookie.
+#[get("/<app_name>/forward-auth")]
+pub(super) async fn forward_auth(
+    app_name: Result<AppName, AppNameError>,
+    config: &State<Config>,
+    validator: &State<OidcSessionValidator>,
+    token: Option<BearerToken>,
+    cookies: &CookieJar<'_>,
+) -> HttpResult<ForwardedUser> {
+    let app_name = app_name?;
+
+    let sessions = config
+        .access_restrictions(&app_name)
+        .into_iter()
+        .filter_map(|restriction| restriction.oidc_session())
+        .collect::<Vec<_>>();
+    if sessions.is_empty() {
+        return Err(HttpApiProblem::with_title_and_type(StatusCode::NOT_FOUND)
+            .detail(format!(
+                "The access to {app_name} is not restricted to OIDC sessions."
+            ))
+            .into());
+    }
+
+    let mut user = None;
+    for session in sessions {
+        let access_token = match &token {
+            Some(BearerToken(token)) => Some(token.clone()),
+            None => cookies
+                .get(session.cookie_name())
+                .map(|cookie| cookie.value().to_string()),
+        };
+        let Some(access_token) = access_token else {
+            return Err(
+                HttpApiProblem::with_title_and_type(StatusCode::UNAUTHORIZED)
+                    .detail(format!("Accessing {app_name} requires an OIDC session."))
+                    .into(),
+            );
+        };
+
+        match validator.validate(session.issuer(), &access_token).await {
+            Ok(Some(session_user)) => user = Some(session_user),
+            Ok(None) => {
+                return Err(
+                    HttpApiProblem::with_title_and_type(StatusCode::UNAUTHORIZED)
+                        .detail("The OIDC session is invalid or has expired.")
+                        .into(),
+                )
+            }
+            Err(err) => {
+                warn!(
+                    "Cannot validate OIDC session with {}: {}",
+                    session.issuer(),
+                    err
+                );
+                return Err(HttpApiProblem::with_title_and_type(StatusCode::BAD_GATEWAY)
+                    .detail("Cannot validate the OIDC session.")
+                    .into());
+            }
+        }
+    }
+
+    Ok(ForwardedUser(user.unwrap_or_default()))
+}
+
+/// Grants access to the app and tells Traefik which user the session belongs to.
+pub(super) struct ForwardedUser(String);
+
+impl<'r> Responder<'r, 'static> for ForwardedUser {
+    fn respond_to(self, _request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        Response::build()
+            .status(Status::Ok)
+            .raw_header(FORWARDED_USER_HEADER, self.0)
+            .ok()
+    }
+}
+
+/// Validates access tokens with the userinfo endpoint of the OIDC provider and remembers the
+/// valid sessions for a short time.
+#[derive(Default)]
+pub struct OidcSessionValidator {
+    client: reqwest::Client,
+    userinfo_endpoints: Mutex<HashMap<Url, Url>>,
+    sessions: Mutex<HashMap<(Url, String), (Instant, String)>>,
+}
+
+impl OidcSessionValidator {
+    /// Returns the user of the session or `None` if the provider does not accept the access token.
+    async fn validate(
+        &self,
+        issuer: &Url,
+        access_token: &str,
+    ) -> Result<Option<String>, reqwest::Error> {
+        #[derive(Deserialize)]
+        struct UserInfo {
+            sub: String,
+            preferred_username: Option<String>,
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(access_token);
+        let key = (issuer.clone(), format!("{:x}", hasher.finalize()));
+
+        let cached = self.sessions.lock().unwrap().get(&key).cloned();
+        if let Some((validated_at, user)) = cached {
+            if validated_at.elapsed() < SESSION_CACHE_DURATION {
+                return Ok(Some(user));
+            }
+        }
+
+        let userinfo_endpoint = self.userinfo_endpoint(issuer).await?;
+        let response = self
+            .client
+            .get(userinfo_endpoint)
+            .bearer_auth(access_token)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED
+            || response.status() == reqwest::StatusCode::FORBIDDEN
+        {
+            return Ok(None);
+        }
+
+        let user_info = response.error_for_status()?.json::<UserInfo>().await?;
+        let user = user_info.preferred_username.unwrap_or(user_info.sub);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (validated_at, _)| validated_at.elapsed() < SESSION_CACHE_DURATION);
+        sessions.insert(key, (Instant::now(), user.clone()));
+
+        Ok(Some(user))
+    }
+
+    /// Discovers the userinfo endpoint from the metadata of the OIDC provider.
+    async fn userinfo_endpoint(&self, issuer: &Url) -> Result<Url, reqwest::Error> {
+        #[derive(Deserialize)]
+        struct ProviderMetadata {
+            userinfo_endpoint: Url,
+        }
+
+        let cached = self.userinfo_endpoints.lock().unwrap().get(issuer).cloned();
+        if let Some(userinfo_endpoint) = cached {
+            return Ok(userinfo_endpoint);
+        }
+
+        let mut discovery_url = issuer.clone();
+        if let Ok(mut segments) = discovery_url.path_segments_mut() {
+            segments
+                .pop_if_empty()
+                .extend(&[".well-known", "openid-configuration"]);
+        }
+
+        let metadata = self
+            .client
+            .get(discovery_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ProviderMetadata>()
+            .await?;
+
+        self.userinfo_endpoints
+            .lock()
+            .unwrap()
+            .insert(issuer.clone(), metadata.userinfo_endpoint.clone());
+        Ok(metadata.userinfo_endpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_from_str;
+    use rocket::http::{Cookie, Header};
+    use rocket::local::asynchronous::Client;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Serves the discovery and the userinfo endpoint of an OIDC provider that only accepts the
+    /// access token `valid` and returns the request lines that it received.
+    fn start_provider(requests: usize) -> (Url, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = Url::parse(&format!(
+            "http://{}/realms/review",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let userinfo_endpoint = issuer.join("review/userinfo").unwrap();
+        let server = std::thread::spawn(move || {
+            let mut request_lines = Vec::new();
+            for _ in 0..requests {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 4096];
+                let len = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..len]).to_string();
+
+                let (status, body) = if request
+                    .starts_with("GET /realms/review/.well-known/openid-configuration ")
+                {
+                    (
+                        "200 OK",
+                        format!(r#"{{ "userinfo_endpoint": "{userinfo_endpoint}" }}"#),
+                    )
+                } else if request
+                    .to_lowercase()
+                    .contains("authorization: bearer valid")
+                {
+                    (
+                        "200 OK",
+                        String::from(r#"{ "sub": "1234", "preferred_username": "jane" }"#),
+                    )
+                } else {
+                    ("401 Unauthorized", String::new())
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+
+                request_lines.push(request.lines().next().unwrap_or_default().to_string());
+            }
+            request_lines
+        });
+
+        (issuer, server)
+    }
+
+    async fn client(issuer: &Url) -> Client {
+        let config = config_from_str!(&format!(
+            r#"
+            [[accessRestrictions]]
+            type = 'oidc'
+            issuer = '{issuer}'
+            prevantUrl = 'http://prevant'
+            appSelector = 'master'
+            "#
+        ));
+        let rocket = rocket::build()
+            .manage(config)
+            .manage(OidcSessionValidator::default())
+            .mount("/", rocket::routes![forward_auth]);
+        Client::tracked(rocket).await.expect("valid rocket")
+    }
+
+    #[tokio::test]
+    async fn grant_access_to_valid_sessions() {
+        let (issuer, provider) = start_provider(3);
+        let client = client(&issuer).await;
+
+        let response = client
+            .get("/master/forward-auth")
+            .header(Header::new("Authorization", "Bearer valid"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("X-Forwarded-User"), Some("jane"));
+
+        // The session is remembered, so the provider is not asked again.
+        let response = client
+            .get("/master/forward-auth")
+            .cookie(Cookie::new("prevant_session", "valid"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get("/master/forward-auth")
+            .header(Header::new("Authorization", "Bearer expired"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client.get("/master/forward-auth").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        assert_eq!(
+            provider.join().unwrap(),
+            vec![
+                "GET /realms/review/.well-known/openid-configuration HTTP/1.1",
+                "GET /realms/review/userinfo HTTP/1.1",
+                "GET /realms/review/userinfo HTTP/1.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_apps_without_oidc_restriction() {
+        let (issuer, _provider) = start_provider(0);
+        let client = client(&issuer).await;
+
+        let response = client
+            .get("/other/forward-auth")
+            .header(Header::new("Authorization", "Bearer valid"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
@@ -1,11 +1,12 @@
 use crate::{
     apps::Apps,
     http_result::HttpResult,
-    models::{AppName, AppNameError, LogChunk},
+    models::{AppName, AppNameError, LogChunk, LogFilter, LogStreams},
 };
-use chrono::DateTime;
+use chrono::{DateTime, FixedOffset};
 use futures::stream::StreamExt;
 use http_api_problem::HttpApiProblem;
+use regex::Regex;
 use reqwest::header::{ACCEPT, CONTENT_DISPOSITION, LINK};
 use rocket::{
     http::{Accept, ContentType, RawStr, Status},
@@ -25,31 +26,18 @@ pub(super) async fn logs<'r>(
     apps: &State<Arc<Apps>>,
 ) -> HttpResult<LogsResponse<'r>> {
     let app_name = app_name?;
-
-    let since = match log_query.since {
-        None => None,
-        Some(since) => match DateTime::parse_from_rfc3339(&since) {
-            Ok(since) => Some(since),
-            Err(err) => {
-                return Err(
-                    HttpApiProblem::with_title(http_api_problem::StatusCode::BAD_REQUEST)
-                        .detail(format!("{}", err))
-                        .into(),
-                );
-            }
-        },
-    };
+    let since = log_query.since()?;
+    let filter = log_query.filter()?;
 
     let log_chunk = apps
-        .get_logs(&app_name, service_name, &since, &log_query.limit)
+        .get_logs(&app_name, service_name, &since, &log_query.limit, &filter)
         .await?;
 
     Ok(LogsResponse {
         log_chunk,
         app_name,
-        service_name,
-        limit: log_query.limit,
-        as_attachment: log_query.as_attachment,
+        service_name: Some(service_name),
+        log_query,
     })
 }
 
@@ -65,23 +53,12 @@ pub(super) async fn stream_logs<'r>(
     apps: &'r State<Arc<Apps>>,
 ) -> HttpResult<EventStream![Event + 'r]> {
     let app_name = app_name?;
-    let since = match &log_query.since {
-        None => None,
-        Some(since) => match DateTime::parse_from_rfc3339(&since) {
-            Ok(since) => Some(since),
-            Err(err) => {
-                return Err(
-                    HttpApiProblem::with_title(http_api_problem::StatusCode::BAD_REQUEST)
-                        .detail(format!("{}", err))
-                        .into(),
-                );
-            }
-        },
-    };
+    let since = log_query.since()?;
+    let filter = log_query.filter()?;
 
     Ok(EventStream! {
         let mut log_chunk = apps
-            .stream_logs(&app_name, service_name, &since, &log_query.limit)
+            .stream_logs(&app_name, service_name, &since, &log_query.limit, &filter)
             .await;
 
         while let Some(result) = log_chunk.as_mut().next().await {
@@ -95,12 +72,73 @@ pub(super) async fn stream_logs<'r>(
     })
 }
 
+/// Responds with the log lines of all services of the app, ordered by their timestamps and
+/// prefixed with the name of the service.
+#[get("/<app_name>/logs?<log_query..>", rank = 1)]
+pub(super) async fn app_logs<'r>(
+    _apt: AcceptingPlainText,
+    app_name: Result<AppName, AppNameError>,
+    log_query: LogQuery,
+    apps: &State<Arc<Apps>>,
+) -> HttpResult<LogsResponse<'r>> {
+    let app_name = app_name?;
+    let since = log_query.since()?;
+    let filter = log_query.filter()?;
+
+    let log_chunk = apps
+        .get_app_logs(&app_name, &since, &log_query.limit, &filter)
+        .await?;
+
+    Ok(LogsResponse {
+        log_chunk: Some(log_chunk),
+        app_name,
+        service_name: None,
+        log_query,
+    })
+}
+
+#[get(
+    "/<app_name>/logs?<log_query..>",
+    format = "text/event-stream",
+    rank = 2
+)]
+pub(super) async fn stream_app_logs<'r>(
+    app_name: Result<AppName, AppNameError>,
+    log_query: LogQuery,
+    apps: &'r State<Arc<Apps>>,
+) -> HttpResult<EventStream![Event + 'r]> {
+    let app_name = app_name?;
+    let since = log_query.since()?;
+    let filter = log_query.filter()?;
+
+    // Responds with 404 before the stream starts if the app does not exist.
+    apps.service_names_of_app(&app_name).await?;
+
+    Ok(EventStream! {
+        let mut log_chunk = match apps
+            .stream_app_logs(&app_name, &since, &log_query.limit, &filter)
+            .await
+        {
+            Ok(log_chunk) => log_chunk,
+            Err(_e) => return,
+        };
+
+        while let Some(result) = log_chunk.as_mut().next().await {
+            match result {
+                Ok((_, log_line)) => yield Event::data(log_line),
+                Err(_e) => {
+                    break;
+                }
+            }
+        }
+    })
+}
+
 pub struct LogsResponse<'a> {
     log_chunk: Option<LogChunk>,
     app_name: AppName,
-    service_name: &'a str,
-    limit: Option<usize>,
-    as_attachment: bool,
+    service_name: Option<&'a str>,
+    log_query: LogQuery,
 }
 
 impl<'r, 'o: 'r> Responder<'r, 'o> for LogsResponse<'r> {
@@ -121,27 +159,35 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for LogsResponse<'r> {
 
         let from = *log_chunk.until() + chrono::Duration::milliseconds(1);
 
-        let next_logs_url = match self.limit {
-            Some(limit) => format!(
-                "/api/apps/{}/logs/{}?limit={}&since={}",
-                self.app_name,
-                self.service_name,
-                limit,
-                RawStr::new(&from.to_rfc3339()).percent_encode(),
-            ),
-            None => format!(
-                "/api/apps/{}/logs/{}?since={}",
-                self.app_name,
-                self.service_name,
-                RawStr::new(&from.to_rfc3339()).percent_encode(),
-            ),
+        let mut next_logs_url = match self.service_name {
+            Some(service_name) => format!("/api/apps/{}/logs/{}?", self.app_name, service_name),
+            None => format!("/api/apps/{}/logs?", self.app_name),
         };
+        if let Some(limit) = self.log_query.limit {
+            next_logs_url.push_str(&format!("limit={}&", limit));
+        }
+        next_logs_url.push_str(&format!(
+            "since={}",
+            RawStr::new(&from.to_rfc3339()).percent_encode()
+        ));
+        if let Some(until) = &self.log_query.until {
+            next_logs_url.push_str(&format!("&until={}", RawStr::new(until).percent_encode()));
+        }
+        if let Some(grep) = &self.log_query.grep {
+            next_logs_url.push_str(&format!("&grep={}", RawStr::new(grep).percent_encode()));
+        }
+        if let Some(stream) = &self.log_query.stream {
+            next_logs_url.push_str(&format!("&stream={}", stream));
+        }
 
-        let content_disposition_value = if self.as_attachment {
+        let content_disposition_value = if self.log_query.as_attachment {
+            let file_name = match self.service_name {
+                Some(service_name) => format!("{}_{}", self.app_name, service_name),
+                None => self.app_name.to_string(),
+            };
             format!(
-                "attachment; filename=\"{}_{}_{}.txt\"",
-                self.app_name,
-                self.service_name,
+                "attachment; filename=\"{}_{}.txt\"",
+                file_name,
                 log_chunk.until().format("%Y%m%d_%H%M%S")
             )
         } else {
@@ -161,11 +207,57 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for LogsResponse<'r> {
 #[derive(FromForm)]
 pub(super) struct LogQuery {
     since: Option<String>,
+    until: Option<String>,
     limit: Option<usize>,
+    /// A regular expression that the log lines must match.
+    grep: Option<String>,
+    stream: Option<LogStreams>,
     #[field(name = "asAttachment")]
     as_attachment: bool,
 }
 
+impl LogQuery {
+    fn since(&self) -> HttpResult<Option<DateTime<FixedOffset>>> {
+        parse_timestamp(&self.since)
+    }
+
+    fn filter(&self) -> HttpResult<LogFilter> {
+        let pattern = match &self.grep {
+            None => None,
+            Some(grep) => match Regex::new(grep) {
+                Ok(pattern) => Some(pattern),
+                Err(err) => {
+                    return Err(HttpApiProblem::with_title(
+                        http_api_problem::StatusCode::BAD_REQUEST,
+                    )
+                    .detail(format!("{}", err))
+                    .into());
+                }
+            },
+        };
+
+        Ok(LogFilter::new(
+            parse_timestamp(&self.until)?,
+            pattern,
+            self.stream.unwrap_or_default(),
+        ))
+    }
+}
+
+fn parse_timestamp(timestamp: &Option<String>) -> HttpResult<Option<DateTime<FixedOffset>>> {
+    match timestamp {
+        None => Ok(None),
+        Some(timestamp) => match DateTime::parse_from_rfc3339(timestamp) {
+            Ok(timestamp) => Ok(Some(timestamp)),
+            Err(err) => Err(
+                HttpApiProblem::with_title(http_api_problem::StatusCode::BAD_REQUEST)
+                    .detail(format!("{}", err))
+                    .into(),
+            ),
+        },
+    }
+}
+
 pub(super) struct AcceptingPlainText;
 
 #[rocket::async_trait]
@@ -196,7 +288,17 @@ impl<'r> FromRequest<'r> for AcceptingPlainText {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{apps::HostMetaCache, infrastructure::Dummy, models::AppStatusChangeId, sc};
+    use crate::{
+        apps::HostMetaCache,
+        config::ContainerConfig,
+        deployment::DeploymentUnit,
+        infrastructure::{Dummy, Infrastructure, UnsupportedLogStreams},
+        models::service::{Service, ServiceStatus},
+        models::AppStatusChangeId,
+        sc,
+    };
+    use futures::stream::BoxStream;
+    use multimap::MultiMap;
     use reqwest::header::CONTENT_TYPE;
     use rocket::{
         http::{Accept, Header},
@@ -214,19 +316,21 @@ mod test {
                 &AppStatusChangeId::new(),
                 None,
                 &vec![sc!("service-a")],
+                None,
             )
             .await?;
 
-        let rocket = rocket::build()
-            .manage(host_meta_cache)
-            .manage(apps)
-            .mount("/api/apps", routes![logs, stream_logs]);
+        let rocket = rocket::build().manage(host_meta_cache).manage(apps).mount(
+            "/api/apps",
+            routes![logs, stream_logs, app_logs, stream_app_logs],
+        );
         Ok(Client::tracked(rocket).await.expect("valid rocket"))
     }
 
     #[tokio::test]
     async fn log_weblink_with_no_limit() -> Result<(), crate::apps::AppsServiceError> {
-        let (host_meta_cache, mut _host_meta_crawler) = crate::host_meta_crawling();
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
 
         let client =
             set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
@@ -248,7 +352,8 @@ mod test {
 
     #[tokio::test]
     async fn log_weblink_with_some_limit() -> Result<(), crate::apps::AppsServiceError> {
-        let (host_meta_cache, mut _host_meta_crawler) = crate::host_meta_crawling();
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
 
         let client =
             set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
@@ -269,7 +374,8 @@ mod test {
     #[tokio::test]
     async fn log_content_disposition_for_downloading_as_attachment(
     ) -> Result<(), crate::apps::AppsServiceError> {
-        let (host_meta_cache, mut _host_meta_crawler) = crate::host_meta_crawling();
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
 
         let client =
             set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
@@ -290,7 +396,8 @@ mod test {
     #[tokio::test]
     async fn log_content_disposition_for_displaying_as_inline(
     ) -> Result<(), crate::apps::AppsServiceError> {
-        let (host_meta_cache, mut _host_meta_crawler) = crate::host_meta_crawling();
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
 
         let client =
             set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
@@ -309,7 +416,8 @@ mod test {
     #[tokio::test]
     async fn log_content_type_when_accepting_text_star() -> Result<(), crate::apps::AppsServiceError>
     {
-        let (host_meta_cache, mut _host_meta_crawler) = crate::host_meta_crawling();
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
 
         let client =
             set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
@@ -332,7 +440,8 @@ mod test {
     #[tokio::test]
     async fn respond_with_plain_log_content_type_when_accepting_with_firefox_accept_default_value(
     ) -> Result<(), crate::apps::AppsServiceError> {
-        let (host_meta_cache, mut _host_meta_crawler) = crate::host_meta_crawling();
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
 
         let client =
             set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
@@ -355,7 +464,8 @@ mod test {
     #[tokio::test]
     async fn log_content_type_when_accepting_text_stream(
     ) -> Result<(), crate::apps::AppsServiceError> {
-        let (host_meta_cache, mut _host_meta_crawler) = crate::host_meta_crawling();
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
 
         let client =
             set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
@@ -371,4 +481,166 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn app_logs_filtered_by_grep_and_until() -> Result<(), crate::apps::AppsServiceError> {
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
+
+        let client =
+            set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
+
+        let response = client
+            .get("/api/apps/master/logs?grep=msg%201&until=2019-07-18T07:25:00Z")
+            .header(Accept::Text)
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Link"),
+            Some("</api/apps/master/logs?since=2019-07-18T07:25:00.001%2B00:00&until=2019-07-18T07:25:00Z&grep=msg%201>;rel=next")
+        );
+        assert_eq!(
+            response.into_string().await,
+            Some(String::from(
+                "service-a | Log msg 1 of service-a of app master\n"
+            ))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn app_logs_with_invalid_grep() -> Result<(), crate::apps::AppsServiceError> {
+        let (host_meta_cache, mut _host_meta_crawler) =
+            crate::host_meta_crawling(Default::default());
+
+        let client =
+            set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
+
+        let response = client
+            .get("/api/apps/master/logs?grep=%28")
+            .header(Accept::Text)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client
+            .get("/api/apps/unknown/logs")
+            .header(Accept::Text)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        Ok(())
+    }
+
+    /// A runtime that, like Kubernetes, cannot tell the output streams of the services apart.
+    struct InterleavedStreams(Dummy);
+
+    #[async_trait::async_trait]
+    impl Infrastructure for InterleavedStreams {
+        async fn get_services(&self) -> Result<MultiMap<AppName, Service>, failure::Error> {
+            self.0.get_services().await
+        }
+
+        async fn deploy_services(
+            &self,
+            status_id: &str,
+            deployment_unit: &DeploymentUnit,
+            container_config: &ContainerConfig,
+        ) -> Result<Vec<Service>, failure::Error> {
+            self.0
+                .deploy_services(status_id, deployment_unit, container_config)
+                .await
+        }
+
+        async fn stop_services(
+            &self,
+            status_id: &str,
+            app_name: &AppName,
+        ) -> Result<Vec<Service>, failure::Error> {
+            self.0.stop_services(status_id, app_name).await
+        }
+
+        async fn stop_service(
+            &self,
+            app_name: &AppName,
+            service_name: &str,
+            remove_volumes: bool,
+        ) -> Result<Option<Service>, failure::Error> {
+            self.0
+                .stop_service(app_name, service_name, remove_volumes)
+                .await
+        }
+
+        async fn get_logs<'a>(
+            &'a self,
+            app_name: &'a AppName,
+            service_name: &'a str,
+            from: &'a Option<DateTime<FixedOffset>>,
+            limit: &'a Option<usize>,
+            streams: LogStreams,
+            follow: bool,
+        ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+            if streams != LogStreams::Both {
+                return Box::pin(futures::stream::once(async move {
+                    Err(UnsupportedLogStreams { streams }.into())
+                }));
+            }
+            self.0
+                .get_logs(app_name, service_name, from, limit, streams, follow)
+                .await
+        }
+
+        async fn change_status(
+            &self,
+            app_name: &AppName,
+            service_name: &str,
+            status: ServiceStatus,
+        ) -> Result<Option<Service>, failure::Error> {
+            self.0.change_status(app_name, service_name, status).await
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_of_unsupported_stream() -> Result<(), crate::apps::AppsServiceError> {
+        let infrastructure = Box::new(InterleavedStreams(Dummy::new()));
+        let apps = Arc::new(Apps::new(Default::default(), infrastructure).unwrap());
+        apps.create_or_update(
+            &AppName::master(),
+            &AppStatusChangeId::new(),
+            None,
+            &vec![sc!("service-a")],
+            None,
+        )
+        .await?;
+        let rocket = rocket::build()
+            .manage(apps)
+            .mount("/api/apps", routes![logs, app_logs]);
+        let client = Client::tracked(rocket).await.expect("valid rocket");
+
+        let response = client
+            .get("/api/apps/master/logs/service-a?stream=stdout")
+            .header(Accept::Text)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotImplemented);
+
+        let response = client
+            .get("/api/apps/master/logs?stream=stderr")
+            .header(Accept::Text)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotImplemented);
+
+        let response = client
+            .get("/api/apps/master/logs/service-a")
+            .header(Accept::Text)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        Ok(())
+    }
 }
@@ -26,7 +26,9 @@
 
 use crate::apps::HostMetaCache;
 use crate::apps::{Apps, AppsError};
+use crate::config::{BasicAuthCredentials, Config};
 use crate::http_result::{HttpApiError, HttpResult};
+use crate::infrastructure::DeploymentTargetError;
 use crate::models::request_info::RequestInfo;
 use crate::models::service::{Service, ServiceStatus};
 use crate::models::ServiceConfig;
@@ -35,28 +37,45 @@ use crate::models::{AppStatusChangeId, AppStatusChangeIdError};
 use http_api_problem::{HttpApiProblem, StatusCode};
 use multimap::MultiMap;
 use regex::Regex;
-use rocket::http::Status;
+use rocket::http::{ContentType, Status};
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket::response::{Responder, Response};
 use rocket::serde::json::Json;
 use rocket::State;
 use std::future::Future;
+use std::io::Cursor;
 use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
 use tokio::time::timeout;
 
+mod exec;
+mod files;
+mod forward_auth;
 mod logs;
 
+pub use forward_auth::OidcSessionValidator;
+
 pub fn apps_routes() -> Vec<rocket::Route> {
     rocket::routes![
         apps,
         delete_app,
+        delete_service,
+        restart_service,
+        redeploy_service,
+        exec::exec,
+        exec::attach_exec,
+        files::download_files,
+        files::upload_files,
         create_app,
         logs::logs,
         logs::stream_logs,
+        logs::app_logs,
+        logs::stream_app_logs,
         change_status,
         status_change,
+        credentials,
+        forward_auth::forward_auth,
     ]
 }
 
@@ -82,16 +101,41 @@ async fn status_change(
     let app_name = app_name?;
     let status_id = status_id?;
 
-    let apps = (**apps).clone();
-    let future = async move { apps.wait_for_status_change(&status_id).await };
+    let app_name_cloned = app_name.clone();
+    let apps_cloned = (**apps).clone();
+    let future = async move {
+        apps_cloned
+            .wait_for_status_change(&app_name_cloned, &status_id)
+            .await
+    };
 
     match spawn_with_options(options, future).await? {
-        Poll::Pending => Ok(AsyncCompletion::Pending(app_name, status_id)),
+        Poll::Pending => Ok(AsyncCompletion::pending(apps, app_name, status_id)),
         Poll::Ready(Ok(_)) => Err(HttpApiProblem::with_title(StatusCode::NOT_FOUND).into()),
         Poll::Ready(Err(err)) => Err(err.into()),
     }
 }
 
+/// Reveals the basic auth credentials that are required to access the routes of the app. Like
+/// running commands in the services, this requires one of the tokens of the exec configuration.
+#[get("/<app_name>/credentials", format = "application/json")]
+fn credentials(
+    app_name: Result<AppName, AppNameError>,
+    config: &State<Config>,
+    token: Option<exec::BearerToken>,
+) -> HttpResult<Json<Vec<BasicAuthCredentials>>> {
+    let app_name = app_name?;
+    exec::authorize(config, &app_name, token)?;
+
+    Ok(Json(
+        config
+            .access_restrictions(&app_name)
+            .into_iter()
+            .filter_map(|restriction| restriction.credentials(&app_name))
+            .collect(),
+    ))
+}
+
 #[delete("/<app_name>")]
 pub async fn delete_app(
     app_name: Result<AppName, AppNameError>,
@@ -117,13 +161,79 @@ pub async fn delete_app_sync(
     apps: &State<Arc<Apps>>,
 ) -> HttpResult<Json<Vec<Service>>> {
     match delete_app(app_name, apps, RunOptions::Sync).await? {
-        AsyncCompletion::Pending(_, _) => {
+        AsyncCompletion::Pending(..) | AsyncCompletion::Queued(..) => {
             Err(HttpApiProblem::with_title(StatusCode::INTERNAL_SERVER_ERROR).into())
         }
         AsyncCompletion::Ready(result) => Ok(result),
     }
 }
 
+#[delete("/<app_name>/services/<service_name>?<delete_service_form..>")]
+async fn delete_service(
+    app_name: Result<AppName, AppNameError>,
+    service_name: String,
+    apps: &State<Arc<Apps>>,
+    delete_service_form: DeleteServiceOptions,
+    options: RunOptions,
+) -> HttpResult<AsyncCompletion<Json<Vec<Service>>>> {
+    let app_name = app_name?;
+    let app_name_cloned = app_name.clone();
+    let status_id = AppStatusChangeId::new();
+    let replicate_from = delete_service_form.replicate_from().clone();
+    let remove_volumes = delete_service_form.remove_volumes();
+
+    let apps_state = apps;
+    let apps = (**apps).clone();
+    let future = async move {
+        apps.delete_service(
+            &app_name,
+            &service_name,
+            &status_id,
+            replicate_from,
+            remove_volumes,
+        )
+        .await
+    };
+
+    match spawn_with_options(options, future).await? {
+        Poll::Pending => Ok(AsyncCompletion::pending(
+            apps_state,
+            app_name_cloned,
+            status_id,
+        )),
+        Poll::Ready(Ok(services)) => Ok(AsyncCompletion::Ready(Json(services))),
+        Poll::Ready(Err(err)) => Err(err.into()),
+    }
+}
+
+#[post("/<app_name>/services/<service_name>/restart")]
+async fn restart_service(
+    app_name: Result<AppName, AppNameError>,
+    service_name: String,
+    apps: &State<Arc<Apps>>,
+) -> HttpResult<ServiceStatusResponse> {
+    let app_name = app_name?;
+
+    let service = apps.restart_service(&app_name, &service_name).await?;
+
+    Ok(ServiceStatusResponse { service })
+}
+
+/// Redeploys the service synchronously because the runtimes do not track the redeployment of a
+/// single service as a status change that could be polled.
+#[post("/<app_name>/services/<service_name>/redeploy")]
+async fn redeploy_service(
+    app_name: Result<AppName, AppNameError>,
+    service_name: String,
+    apps: &State<Arc<Apps>>,
+) -> HttpResult<Json<Vec<Service>>> {
+    let app_name = app_name?;
+
+    let services = apps.redeploy_service(&app_name, &service_name).await?;
+
+    Ok(Json(services))
+}
+
 #[post(
     "/<app_name>?<create_app_form..>",
     format = "application/json",
@@ -149,7 +259,9 @@ pub async fn create_app(
     let app_name = app_name?;
     let app_name_cloned = app_name.clone();
     let replicate_from = create_app_form.replicate_from().clone();
+    let deployment_target = create_app_form.target().clone();
 
+    let apps_state = apps;
     let apps = (**apps).clone();
     let future = async move {
         apps.create_or_update(
@@ -157,12 +269,17 @@ pub async fn create_app(
             &status_id,
             replicate_from,
             &service_configs,
+            deployment_target,
         )
         .await
     };
 
     match spawn_with_options(options, future).await? {
-        Poll::Pending => Ok(AsyncCompletion::Pending(app_name_cloned, status_id)),
+        Poll::Pending => Ok(AsyncCompletion::pending(
+            apps_state,
+            app_name_cloned,
+            status_id,
+        )),
         Poll::Ready(Ok(services)) => Ok(AsyncCompletion::Ready(Json(services))),
         Poll::Ready(Err(err)) => Err(err.into()),
     }
@@ -231,12 +348,35 @@ fn map_join_error(err: tokio::task::JoinError) -> HttpApiError {
 pub struct CreateAppOptions {
     #[field(name = "replicateFrom")]
     replicate_from: Option<AppName>,
+    target: Option<String>,
 }
 
 impl CreateAppOptions {
     fn replicate_from(&self) -> &Option<AppName> {
         &self.replicate_from
     }
+
+    fn target(&self) -> &Option<String> {
+        &self.target
+    }
+}
+
+#[derive(FromForm)]
+pub struct DeleteServiceOptions {
+    #[field(name = "replicateFrom")]
+    replicate_from: Option<AppName>,
+    #[field(name = "removeVolumes")]
+    remove_volumes: Option<bool>,
+}
+
+impl DeleteServiceOptions {
+    fn replicate_from(&self) -> &Option<AppName> {
+        &self.replicate_from
+    }
+
+    fn remove_volumes(&self) -> bool {
+        self.remove_volumes.unwrap_or(false)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -250,9 +390,20 @@ pub struct ServiceStatusResponse {
 
 pub enum AsyncCompletion<T> {
     Pending(AppName, AppStatusChangeId),
+    /// The request waits for the completion of other requests for the same app.
+    Queued(AppName, AppStatusChangeId, usize),
     Ready(T),
 }
 
+impl<T> AsyncCompletion<T> {
+    fn pending(apps: &Apps, app_name: AppName, status_id: AppStatusChangeId) -> Self {
+        match apps.queue_position(&app_name, &status_id) {
+            Some(position) => AsyncCompletion::Queued(app_name, status_id, position),
+            None => AsyncCompletion::Pending(app_name, status_id),
+        }
+    }
+}
+
 impl<'r, T> Responder<'r, 'static> for AsyncCompletion<T>
 where
     T: Responder<'r, 'static>,
@@ -266,6 +417,16 @@ where
                     .raw_header("Location", url)
                     .ok()
             }
+            AsyncCompletion::Queued(app_name, status_id, position) => {
+                let url = format!("/api/apps/{}/status-changes/{}", app_name, status_id);
+                let body = serde_json::json!({ "queuePosition": position }).to_string();
+                Response::build()
+                    .status(Status::Accepted)
+                    .raw_header("Location", url)
+                    .header(ContentType::JSON)
+                    .sized_body(body.len(), Cursor::new(body))
+                    .ok()
+            }
             AsyncCompletion::Ready(result) => result.respond_to(request),
         }
     }
@@ -288,12 +449,23 @@ impl From<AppsError> for HttpApiError {
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             },
             AppsError::AppNotFound { .. } => StatusCode::NOT_FOUND,
+            AppsError::ServiceNotFound { .. } => StatusCode::NOT_FOUND,
             AppsError::AppIsInDeployment { .. } => StatusCode::CONFLICT,
             AppsError::AppIsInDeletion { .. } => StatusCode::CONFLICT,
+            AppsError::DeploymentCancelled { .. } => StatusCode::CONFLICT,
+            AppsError::CyclicServiceDependencies { .. } => StatusCode::BAD_REQUEST,
+            AppsError::InvalidHostName { .. } => StatusCode::BAD_REQUEST,
+            AppsError::UnsupportedLogStreams { .. } => StatusCode::NOT_IMPLEMENTED,
+            AppsError::InvalidDeploymentTarget { error } => match error {
+                DeploymentTargetError::AppRunsOnOtherTarget { .. } => StatusCode::CONFLICT,
+                DeploymentTargetError::TargetUnreachable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::BAD_REQUEST,
+            },
             AppsError::InfrastructureError { .. }
             | AppsError::InvalidServerConfiguration { .. }
             | AppsError::InvalidTemplateFormat { .. }
-            | AppsError::InvalidDeploymentHook => {
+            | AppsError::InvalidDeploymentHook
+            | AppsError::InaccessibleLogArchive { .. } => {
                 error!("Internal server error: {}", error);
                 StatusCode::INTERNAL_SERVER_ERROR
             }
@@ -446,6 +618,81 @@ mod tests {
         }
     }
 
+    mod credentials {
+        use crate::apps::routes::*;
+        use crate::config_from_str;
+        use rocket::http::{Accept, Header, Status};
+        use rocket::local::asynchronous::Client;
+        use serde_json::{json, Value};
+
+        async fn client() -> Client {
+            let config = config_from_str!(
+                r#"
+                [exec]
+                tokens = ['token']
+
+                [[accessRestrictions]]
+                type = 'basicAuth'
+                username = 'user'
+                password = 'password'
+                appSelector = 'master'
+                "#
+            );
+            let rocket = rocket::build()
+                .manage(config)
+                .mount("/", rocket::routes![credentials]);
+            Client::tracked(rocket).await.expect("valid rocket")
+        }
+
+        #[tokio::test]
+        async fn reveal_basic_auth_credentials() -> Result<(), rocket::Error> {
+            let client = client().await;
+
+            let response = client
+                .get("/master/credentials")
+                .header(Accept::JSON)
+                .header(Header::new("Authorization", "Bearer token"))
+                .dispatch()
+                .await;
+            assert_eq!(
+                response.into_json::<Value>().await,
+                Some(json!([{ "username": "user", "password": "password" }]))
+            );
+
+            let response = client
+                .get("/other/credentials")
+                .header(Accept::JSON)
+                .header(Header::new("Authorization", "Bearer token"))
+                .dispatch()
+                .await;
+            assert_eq!(response.into_json::<Value>().await, Some(json!([])));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn reject_requests_without_valid_token() -> Result<(), rocket::Error> {
+            let client = client().await;
+
+            let response = client
+                .get("/master/credentials")
+                .header(Accept::JSON)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Unauthorized);
+
+            let response = client
+                .get("/master/credentials")
+                .header(Accept::JSON)
+                .header(Header::new("Authorization", "Bearer other"))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Forbidden);
+
+            Ok(())
+        }
+    }
+
     mod url_rendering {
         use crate::apps::{AppsService, HostMetaCache};
         use crate::infrastructure::Dummy;
@@ -472,6 +719,7 @@ mod tests {
                     &AppStatusChangeId::new(),
                     None,
                     &vec![sc!("service-a")],
+                    None,
                 )
                 .await?;
 
@@ -486,7 +734,8 @@ mod tests {
         #[tokio::test]
         async fn host_header_response_with_xforwardedhost_xforwardedproto_and_xforwardedport(
         ) -> Result<(), crate::apps::AppsServiceError> {
-            let (host_meta_cache, mut host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, mut host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let client =
                 set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
             host_meta_crawler.fake_empty_host_meta_info(AppName::master(), "service-a".to_string());
@@ -518,7 +767,8 @@ mod tests {
         #[tokio::test]
         async fn host_header_response_with_xforwardedproto_and_other_default_values(
         ) -> Result<(), crate::apps::AppsServiceError> {
-            let (host_meta_cache, mut host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, mut host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let client =
                 set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
             host_meta_crawler.fake_empty_host_meta_info(AppName::master(), "service-a".to_string());
@@ -548,7 +798,8 @@ mod tests {
         #[tokio::test]
         async fn host_header_response_with_xforwardedhost_and_other_default_values(
         ) -> Result<(), crate::apps::AppsServiceError> {
-            let (host_meta_cache, mut host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, mut host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let client =
                 set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
             host_meta_crawler.fake_empty_host_meta_info(AppName::master(), "service-a".to_string());
@@ -577,7 +828,8 @@ mod tests {
         #[tokio::test]
         async fn host_header_response_with_xforwardedport_and_default_values(
         ) -> Result<(), crate::apps::AppsServiceError> {
-            let (host_meta_cache, mut host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, mut host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let client =
                 set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
             host_meta_crawler.fake_empty_host_meta_info(AppName::master(), "service-a".to_string());
@@ -607,7 +859,8 @@ mod tests {
         #[tokio::test]
         async fn host_header_response_with_all_default_values(
         ) -> Result<(), crate::apps::AppsServiceError> {
-            let (host_meta_cache, mut host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, mut host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let client =
                 set_up_rocket_with_dummy_infrastructure_and_a_running_app(host_meta_cache).await?;
             host_meta_crawler.fake_empty_host_meta_info(AppName::master(), "service-a".to_string());
@@ -634,7 +887,8 @@ mod tests {
 
         #[tokio::test]
         async fn bad_request_without_host_header() {
-            let (host_meta_cache, _host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, _host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let infrastructure = Box::new(Dummy::new());
             let apps = Arc::new(AppsService::new(Default::default(), infrastructure).unwrap());
 
@@ -651,7 +905,8 @@ mod tests {
 
         #[tokio::test]
         async fn with_invalid_headers() {
-            let (host_meta_cache, _host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, _host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let infrastructure = Box::new(Dummy::new());
             let apps = Arc::new(AppsService::new(Default::default(), infrastructure).unwrap());
 
@@ -670,7 +925,8 @@ mod tests {
 
         #[tokio::test]
         async fn with_invalid_proto() {
-            let (host_meta_cache, _host_meta_crawler) = crate::host_meta_crawling();
+            let (host_meta_cache, _host_meta_crawler) =
+                crate::host_meta_crawling(Default::default());
             let infrastructure = Box::new(Dummy::new());
             let apps = Arc::new(AppsService::new(Default::default(), infrastructure).unwrap());
 
@@ -735,6 +991,43 @@ mod tests {
             );
         }
 
+        #[tokio::test]
+        async fn cyclic_service_dependencies() {
+            let infrastructure = Box::new(Dummy::new());
+            let apps = Arc::new(AppsService::new(Default::default(), infrastructure).unwrap());
+
+            let rocket = rocket::build()
+                .manage(apps)
+                .mount("/", routes![crate::apps::routes::create_app]);
+
+            let client = Client::tracked(rocket).await.expect("valid rocket");
+            let response = client
+                .post("/master")
+                .body(
+                    serde_json::json!([{
+                        "serviceName": "api",
+                        "image": "sha256:9895c9b90b58c9490471b877f6bb6a90e6bdc154da7fbb526a0322ea242fc913",
+                        "dependsOn": [ "db" ]
+                    }, {
+                        "serviceName": "db",
+                        "image": "sha256:6a5b5fc9d8fc2af8b0fc5c9c3a4b2bb5c3f2a4dd1b2c3e4f5a6b7c8d9e0f1a2b",
+                        "dependsOn": [ "api" ]
+                    }])
+                    .to_string(),
+                )
+                .header(ContentType::JSON)
+                .dispatch()
+                .await;
+
+            assert_eq!(response.status(), Status::BadRequest);
+
+            let body = response.into_json::<serde_json::Value>().await.unwrap();
+            assert_eq!(body["status"], 400);
+            assert!(body["detail"]
+                .as_str()
+                .is_some_and(|detail| detail.contains("contain a cycle")));
+        }
+
         #[tokio::test]
         async fn image_registry_authentication_error() {
             #[get("/")]
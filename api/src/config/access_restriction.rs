@@ -0,0 +1,463 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::AppSelector;
+use crate::infrastructure::TraefikMiddleware;
+use crate::models::AppName;
+use base64::{engine::general_purpose, Engine};
+use secstr::SecUtf8;
+use serde_value::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use url::Url;
+
+/// The header that PREvant's forward-auth endpoint passes the user of the OIDC session with.
+pub const FORWARDED_USER_HEADER: &str = "X-Forwarded-User";
+
+/// Restricts the access to the routes of review apps by attaching a
+/// [Traefik middleware](https://doc.traefik.io/traefik/middlewares/http/overview/) to them.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessRestriction {
+    #[serde(default)]
+    app_selector: AppSelector,
+    #[serde(flatten)]
+    kind: AccessRestrictionKind,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum AccessRestrictionKind {
+    #[serde(rename_all = "camelCase")]
+    BasicAuth {
+        #[serde(default = "AccessRestriction::default_username")]
+        username: String,
+        #[serde(flatten)]
+        password: BasicAuthPassword,
+    },
+    #[serde(rename_all = "camelCase")]
+    IpAllowList { source_range: Vec<String> },
+    #[serde(rename_all = "camelCase")]
+    ForwardAuth {
+        address: Url,
+        #[serde(default)]
+        auth_response_headers: Vec<String>,
+    },
+    /// Traefik asks PREvant itself whether the request belongs to a valid session of the OIDC
+    /// provider.
+    #[serde(rename_all = "camelCase")]
+    Oidc {
+        #[serde(flatten)]
+        session: OidcSession,
+        /// The URL that Traefik reaches PREvant with.
+        prevant_url: Url,
+    },
+}
+
+/// Identifies the OIDC provider whose sessions grant access to the review apps and how the
+/// session is passed to PREvant.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcSession {
+    issuer: Url,
+    /// The cookie that carries the access token if the request does not provide it in the
+    /// `Authorization` header.
+    #[serde(default = "OidcSession::default_cookie_name")]
+    cookie_name: String,
+}
+
+impl OidcSession {
+    fn default_cookie_name() -> String {
+        String::from("prevant_session")
+    }
+
+    pub fn issuer(&self) -> &Url {
+        &self.issuer
+    }
+
+    pub fn cookie_name(&self) -> &str {
+        &self.cookie_name
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum BasicAuthPassword {
+    Fixed {
+        password: SecUtf8,
+    },
+    /// The password of each app is derived from the seed and the app name so that every app
+    /// gets its own password that stays the same across redeployments.
+    #[serde(rename_all = "camelCase")]
+    Seeded {
+        password_seed: SecUtf8,
+    },
+}
+
+/// The credentials that are required to access the review app.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BasicAuthCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl AccessRestriction {
+    fn default_username() -> String {
+        String::from("prevant")
+    }
+
+    pub fn matches(&self, app_name: &AppName) -> bool {
+        self.app_selector.matches(app_name)
+    }
+
+    pub(super) fn app_selector(&self) -> &AppSelector {
+        &self.app_selector
+    }
+
+    pub(super) fn type_name(&self) -> &'static str {
+        match &self.kind {
+            AccessRestrictionKind::BasicAuth { .. } => "basicAuth",
+            AccessRestrictionKind::IpAllowList { .. } => "ipAllowList",
+            AccessRestrictionKind::ForwardAuth { .. } => "forwardAuth",
+            AccessRestrictionKind::Oidc { .. } => "oidc",
+        }
+    }
+
+    /// Returns the OIDC provider whose sessions PREvant validates for Traefik.
+    pub fn oidc_session(&self) -> Option<&OidcSession> {
+        match &self.kind {
+            AccessRestrictionKind::Oidc { session, .. } => Some(session),
+            _ => None,
+        }
+    }
+
+    pub fn credentials(&self, app_name: &AppName) -> Option<BasicAuthCredentials> {
+        match &self.kind {
+            AccessRestrictionKind::BasicAuth { username, password } => {
+                let password = match password {
+                    BasicAuthPassword::Fixed { password } => password.unsecure().to_string(),
+                    BasicAuthPassword::Seeded { password_seed } => {
+                        let mut hasher = Sha256::new();
+                        hasher.update(password_seed.unsecure());
+                        hasher.update(":");
+                        hasher.update(app_name.as_str());
+                        let mut password =
+                            general_purpose::URL_SAFE_NO_PAD.encode(hasher.finalize());
+                        password.truncate(24);
+                        password
+                    }
+                };
+
+                Some(BasicAuthCredentials {
+                    username: username.clone(),
+                    password,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn traefik_middleware(&self, app_name: &AppName) -> TraefikMiddleware {
+        let (name, key, spec) = match &self.kind {
+            AccessRestrictionKind::BasicAuth { .. } => {
+                let credentials = self
+                    .credentials(app_name)
+                    .expect("Basic auth must provide credentials");
+
+                (
+                    "basic-auth",
+                    "basicAuth",
+                    vec![(
+                        "users",
+                        Value::Seq(vec![Value::String(format!(
+                            "{}:{}",
+                            credentials.username,
+                            Self::htpasswd_hash(app_name, &credentials)
+                        ))]),
+                    )],
+                )
+            }
+            AccessRestrictionKind::IpAllowList { source_range } => (
+                "ip-allow-list",
+                "ipWhiteList",
+                vec![("sourceRange", Self::strings(source_range))],
+            ),
+            AccessRestrictionKind::ForwardAuth {
+                address,
+                auth_response_headers,
+            } => (
+                "forward-auth",
+                "forwardAuth",
+                vec![
+                    ("address", Value::String(address.to_string())),
+                    ("trustForwardHeader", Value::Bool(true)),
+                    ("authResponseHeaders", Self::strings(auth_response_headers)),
+                ],
+            ),
+            AccessRestrictionKind::Oidc { prevant_url, .. } => (
+                "oidc",
+                "forwardAuth",
+                vec![
+                    (
+                        "address",
+                        Value::String(Self::forward_auth_url(prevant_url, app_name).to_string()),
+                    ),
+                    ("trustForwardHeader", Value::Bool(true)),
+                    (
+                        "authResponseHeaders",
+                        Self::strings(&[String::from(FORWARDED_USER_HEADER)]),
+                    ),
+                ],
+            ),
+        };
+
+        let spec = spec
+            .into_iter()
+            .map(|(k, v)| (Value::String(k.to_string()), v))
+            .collect::<BTreeMap<_, _>>();
+
+        TraefikMiddleware::Spec {
+            name: format!("{app_name}-{name}"),
+            spec: Value::Map(BTreeMap::from([(
+                Value::String(key.to_string()),
+                Value::Map(spec),
+            )])),
+        }
+    }
+
+    /// The endpoint of PREvant that validates the OIDC sessions for the given app, keeping the
+    /// base path that PREvant might be served with.
+    fn forward_auth_url(prevant_url: &Url, app_name: &AppName) -> Url {
+        let mut url = prevant_url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments
+                .pop_if_empty()
+                .extend(&["api", "apps", app_name.as_str(), "forward-auth"]);
+        }
+        url
+    }
+
+    /// Hashes the password with bcrypt as required for htpasswd entries. The salt is derived from
+    /// the app and the credentials so that the middleware stays the same across redeployments and
+    /// does not cause the routes of the app to be recreated.
+    fn htpasswd_hash(app_name: &AppName, credentials: &BasicAuthCredentials) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(app_name.as_str());
+        hasher.update(":");
+        hasher.update(&credentials.username);
+        hasher.update(":");
+        hasher.update(&credentials.password);
+        let mut salt = [0u8; 16];
+        salt.copy_from_slice(&hasher.finalize()[..16]);
+
+        bcrypt::hash_with_salt(&credentials.password, bcrypt::DEFAULT_COST, salt)
+            .expect("bcrypt accepts the default cost")
+            .format_for_version(bcrypt::Version::TwoY)
+    }
+
+    fn strings(values: &[String]) -> Value {
+        Value::Seq(values.iter().cloned().map(Value::String).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_from_str;
+    use std::str::FromStr;
+
+    fn spec_of(middleware: TraefikMiddleware) -> serde_json::Value {
+        match middleware {
+            TraefikMiddleware::Spec { name, spec } => {
+                serde_json::json!({ "name": name, "spec": spec })
+            }
+            TraefikMiddleware::Ref(name) => panic!("Unexpected reference to {name}"),
+        }
+    }
+
+    #[test]
+    fn should_derive_password_per_app_from_seed() {
+        let config = config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'basicAuth'
+            passwordSeed = 'secret'
+            "#
+        );
+
+        let master = AppName::master();
+        let other = AppName::from_str("other").unwrap();
+        let restrictions = config.access_restrictions(&master);
+        let restriction = restrictions[0];
+
+        let credentials = restriction.credentials(&master).unwrap();
+        assert_eq!(credentials.username, "prevant");
+        assert_eq!(credentials.password.len(), 24);
+        assert_eq!(restriction.credentials(&master), Some(credentials.clone()));
+        assert_ne!(
+            restriction.credentials(&other).unwrap().password,
+            credentials.password
+        );
+    }
+
+    #[test]
+    fn should_create_basic_auth_middleware_with_fixed_password() {
+        let config = config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'basicAuth'
+            username = 'user'
+            password = 'password'
+            "#
+        );
+
+        let middleware = config.access_restrictions(&AppName::master())[0]
+            .traefik_middleware(&AppName::master());
+
+        let spec = spec_of(middleware);
+        assert_eq!(spec["name"], "master-basic-auth");
+        let users = spec["spec"]["basicAuth"]["users"].as_array().unwrap();
+        assert_eq!(users.len(), 1);
+        let (username, hash) = users[0].as_str().unwrap().split_once(':').unwrap();
+        assert_eq!(username, "user");
+        assert!(hash.starts_with("$2y$"));
+        assert!(bcrypt::verify("password", hash).unwrap());
+
+        assert_eq!(
+            spec_of(
+                config.access_restrictions(&AppName::master())[0]
+                    .traefik_middleware(&AppName::master())
+            ),
+            spec
+        );
+    }
+
+    #[test]
+    fn should_create_ip_allow_list_middleware() {
+        let config = config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'ipAllowList'
+            sourceRange = ['10.0.0.0/8', '192.168.1.7']
+            "#
+        );
+
+        let middleware = config.access_restrictions(&AppName::master())[0]
+            .traefik_middleware(&AppName::master());
+
+        assert_eq!(
+            spec_of(middleware),
+            serde_json::json!({
+                "name": "master-ip-allow-list",
+                "spec": {
+                    "ipWhiteList": { "sourceRange": ["10.0.0.0/8", "192.168.1.7"] }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_forward_auth_middleware() {
+        let config = config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'forwardAuth'
+            address = 'http://oauth2-proxy:4180/oauth2/auth'
+            authResponseHeaders = ['X-Auth-Request-User']
+            "#
+        );
+
+        let middleware = config.access_restrictions(&AppName::master())[0]
+            .traefik_middleware(&AppName::master());
+
+        assert_eq!(
+            spec_of(middleware),
+            serde_json::json!({
+                "name": "master-forward-auth",
+                "spec": {
+                    "forwardAuth": {
+                        "address": "http://oauth2-proxy:4180/oauth2/auth",
+                        "trustForwardHeader": true,
+                        "authResponseHeaders": ["X-Auth-Request-User"]
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_oidc_middleware() {
+        let config = config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'oidc'
+            issuer = 'https://login.example.com/realms/review'
+            prevantUrl = 'http://prevant'
+            "#
+        );
+
+        let restriction = config.access_restrictions(&AppName::master())[0];
+
+        assert_eq!(
+            spec_of(restriction.traefik_middleware(&AppName::master())),
+            serde_json::json!({
+                "name": "master-oidc",
+                "spec": {
+                    "forwardAuth": {
+                        "address": "http://prevant/api/apps/master/forward-auth",
+                        "trustForwardHeader": true,
+                        "authResponseHeaders": ["X-Forwarded-User"]
+                    }
+                }
+            })
+        );
+        assert_eq!(
+            restriction
+                .oidc_session()
+                .map(|session| session.cookie_name()),
+            Some("prevant_session")
+        );
+    }
+
+    #[test]
+    fn should_only_restrict_selected_apps() {
+        let config = config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'ipAllowList'
+            appSelector = 'feature-.+'
+            sourceRange = ['10.0.0.0/8']
+            "#
+        );
+
+        assert!(config.access_restrictions(&AppName::master()).is_empty());
+        assert_eq!(
+            config
+                .access_restrictions(&AppName::from_str("feature-1").unwrap())
+                .len(),
+            1
+        );
+    }
+}
@@ -26,7 +26,7 @@
 use crate::models::AppName;
 use regex::Regex;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub(super) struct AppSelector(Regex);
 
 impl AppSelector {
@@ -38,6 +38,12 @@ impl AppSelector {
     }
 }
 
+impl PartialEq for AppSelector {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
 impl Default for AppSelector {
     fn default() -> Self {
         AppSelector(Regex::new(".+").unwrap())
@@ -52,3 +58,12 @@ impl<'de> serde::Deserialize<'de> for AppSelector {
         serde_regex::deserialize(deserializer).map(AppSelector)
     }
 }
+
+impl serde::Serialize for AppSelector {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serde_regex::serialize(&self.0, serializer)
+    }
+}
@@ -23,11 +23,15 @@
  * THE SOFTWARE.
  * =========================LICENSE_END==================================
  */
-use crate::config::AppSelector;
+use crate::config::{AppSelector, REDACTED};
 use crate::models::service::ContainerType;
-use crate::models::{AppName, Environment, Image, Router, ServiceConfig};
+use crate::models::{
+    AdditionalContainer, AppName, Autoscaling, Environment, Image, Router, ServiceConfig,
+    ServicePort,
+};
 use handlebars::{Handlebars, RenderError};
 use secstr::SecUtf8;
+use serde::{Serialize, Serializer};
 use serde_value::Value;
 use std::collections::BTreeMap;
 use std::path::PathBuf;
@@ -60,9 +64,16 @@ pub(super) struct Companion {
     middlewares: Option<BTreeMap<String, Value>>,
     #[serde(default)]
     storage_strategy: StorageStrategy,
+    #[serde(default, deserialize_with = "ServiceConfig::parse_replicas")]
+    replicas: Option<u32>,
+    autoscaling: Option<Autoscaling>,
+    init_containers: Option<Vec<AdditionalContainer>>,
+    sidecars: Option<Vec<AdditionalContainer>>,
+    depends_on: Option<Vec<String>>,
+    ports: Option<Vec<ServicePort>>,
 }
 
-#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Deserialize, Debug, PartialEq, Serialize)]
 pub(super) enum CompanionType {
     #[serde(rename = "application")]
     Application,
@@ -70,7 +81,7 @@ pub(super) enum CompanionType {
     Service,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum StorageStrategy {
     #[serde(rename = "none")]
     NoMountVolumes,
@@ -78,7 +89,7 @@ pub enum StorageStrategy {
     MountDeclaredImageVolumes,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum DeploymentStrategy {
     #[serde(rename = "redeploy-always")]
     RedeployAlways,
@@ -126,6 +137,10 @@ impl Companions {
     pub(super) fn companion_bootstrapping_containers(&self) -> &Vec<BootstrappingContainer> {
         &self.bootstrapping.containers
     }
+
+    pub(super) fn companions(&self) -> &BTreeMap<String, Companion> {
+        &self.companions
+    }
 }
 
 impl Companion {
@@ -146,6 +161,101 @@ impl Companion {
     }
 }
 
+/// Serializes the companion without revealing the values of environment variables and files
+/// because they might contain credentials.
+impl Serialize for Companion {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RedactedCompanion<'a> {
+            service_name: &'a str,
+            #[serde(rename = "type")]
+            companion_type: &'a CompanionType,
+            image: &'a Image,
+            deployment_strategy: &'a DeploymentStrategy,
+            storage_strategy: &'a StorageStrategy,
+            app_selector: &'a AppSelector,
+            env: BTreeMap<&'a str, &'static str>,
+            labels: &'a Option<BTreeMap<String, String>>,
+            files: BTreeMap<&'a PathBuf, &'static str>,
+            router: &'a Option<Router>,
+            middlewares: Vec<&'a str>,
+            replicas: &'a Option<u32>,
+            autoscaling: &'a Option<Autoscaling>,
+            init_containers: Option<Vec<RedactedContainer<'a>>>,
+            sidecars: Option<Vec<RedactedContainer<'a>>>,
+            depends_on: &'a Option<Vec<String>>,
+            ports: &'a Option<Vec<ServicePort>>,
+        }
+
+        #[derive(Serialize)]
+        struct RedactedContainer<'a> {
+            name: &'a str,
+            image: &'a Image,
+            env: BTreeMap<&'a str, &'static str>,
+            args: Option<&'a Vec<String>>,
+        }
+
+        fn redact_env(env: Option<&Environment>) -> BTreeMap<&str, &'static str> {
+            env.iter()
+                .flat_map(|env| env.iter())
+                .map(|variable| (variable.key().as_str(), REDACTED))
+                .collect()
+        }
+
+        fn redact_containers(
+            containers: &Option<Vec<AdditionalContainer>>,
+        ) -> Option<Vec<RedactedContainer<'_>>> {
+            containers.as_ref().map(|containers| {
+                containers
+                    .iter()
+                    .map(|container| RedactedContainer {
+                        name: container.name(),
+                        image: container.image(),
+                        env: redact_env(container.env()),
+                        args: container.args(),
+                    })
+                    .collect()
+            })
+        }
+
+        let companion = RedactedCompanion {
+            service_name: &self.service_name,
+            companion_type: &self.companion_type,
+            image: &self.image,
+            deployment_strategy: &self.deployment_strategy,
+            storage_strategy: &self.storage_strategy,
+            app_selector: &self.app_selector,
+            env: redact_env(self.env.as_ref()),
+            labels: &self.labels,
+            files: self
+                .files
+                .iter()
+                .flat_map(|files| files.keys())
+                .map(|path| (path, REDACTED))
+                .collect(),
+            router: &self.router,
+            middlewares: self
+                .middlewares
+                .iter()
+                .flat_map(|middlewares| middlewares.keys())
+                .map(|name| name.as_str())
+                .collect(),
+            replicas: &self.replicas,
+            autoscaling: &self.autoscaling,
+            init_containers: redact_containers(&self.init_containers),
+            sidecars: redact_containers(&self.sidecars),
+            depends_on: &self.depends_on,
+            ports: &self.ports,
+        };
+
+        companion.serialize(serializer)
+    }
+}
+
 impl From<Companion> for ServiceConfig {
     fn from(companion: Companion) -> ServiceConfig {
         let mut config =
@@ -172,6 +282,13 @@ impl From<Companion> for ServiceConfig {
             config.set_middlewares(middlewares.clone());
         }
 
+        config.set_replicas(companion.replicas);
+        config.set_autoscaling(companion.autoscaling.clone());
+        config.set_init_containers(companion.init_containers.clone());
+        config.set_sidecars(companion.sidecars.clone());
+        config.set_depends_on(companion.depends_on.clone());
+        config.set_ports(companion.ports.clone());
+
         config.set_container_type(companion.companion_type.into());
 
         config
@@ -279,6 +396,126 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_serialize_companion_without_secret_values() {
+        let companion = companion_from_str!(
+            r#"
+            serviceName = 'openid'
+            type = 'application'
+            image = 'private.example.com/library/openid:latest'
+            env = [ 'CLIENT_SECRET=top-secret' ]
+
+            [files]
+            '/etc/openid/key.pem' = 'private-key'
+        "#
+        );
+
+        let json = serde_json::to_value(&companion).unwrap();
+
+        assert_eq!(
+            json["env"],
+            serde_json::json!({ "CLIENT_SECRET": "<redacted>" })
+        );
+        assert_eq!(
+            json["files"],
+            serde_json::json!({ "/etc/openid/key.pem": "<redacted>" })
+        );
+        assert_eq!(json["appSelector"], ".+");
+        assert_eq!(json["deploymentStrategy"], "redeploy-always");
+    }
+
+    #[test]
+    fn should_convert_companion_with_replicas_into_service_config() {
+        let companion = companion_from_str!(
+            r#"
+            serviceName = 'openid'
+            type = 'application'
+            image = 'private.example.com/library/openid:latest'
+            replicas = 2
+
+            [autoscaling]
+            minReplicas = 2
+            maxReplicas = 4
+            targetCpuUtilization = 50
+        "#
+        );
+
+        let config = ServiceConfig::from(companion);
+
+        assert_eq!(config.replicas(), Some(2));
+        assert_eq!(config.autoscaling().map(|a| a.max_replicas()), Some(4));
+        assert_eq!(
+            config.autoscaling().map(|a| a.target_cpu_utilization()),
+            Some(50)
+        );
+    }
+
+    #[test]
+    fn should_convert_companion_with_init_containers_and_sidecars_into_service_config() {
+        let companion = companion_from_str!(
+            r#"
+            serviceName = 'postgres'
+            type = 'service'
+            image = 'postgres:16'
+
+            [[initContainers]]
+            name = 'restore'
+            image = 'private.example.com/library/restore:latest'
+            args = [ '--from', 's3://backups/{{application.name}}' ]
+
+            [[sidecars]]
+            name = 'exporter'
+            image = 'prometheuscommunity/postgres-exporter'
+            env = [ 'DATA_SOURCE_PASS=secret' ]
+        "#
+        );
+
+        let json = serde_json::to_value(&companion).unwrap();
+        assert_eq!(
+            json["sidecars"][0]["env"],
+            serde_json::json!({ "DATA_SOURCE_PASS": "<redacted>" })
+        );
+
+        let config = ServiceConfig::from(companion);
+
+        assert_eq!(config.init_containers()[0].name(), "restore");
+        assert_eq!(
+            config.init_containers()[0].image(),
+            &Image::from_str("private.example.com/library/restore:latest").unwrap()
+        );
+        assert_eq!(config.sidecars()[0].name(), "exporter");
+    }
+
+    #[test]
+    fn should_not_parse_companion_without_replicas() {
+        let companion = toml::de::from_str::<Companion>(
+            r#"
+            serviceName = 'openid'
+            type = 'application'
+            image = 'private.example.com/library/openid:latest'
+            replicas = 0
+        "#,
+        );
+
+        assert!(companion.is_err());
+    }
+
+    #[test]
+    fn should_convert_companion_with_dependencies_into_service_config() {
+        let companion = companion_from_str!(
+            r#"
+            serviceName = 'openid'
+            type = 'application'
+            image = 'private.example.com/library/openid:latest'
+            dependsOn = [ 'postgres' ]
+        "#
+        );
+
+        let config = ServiceConfig::from(companion);
+
+        assert_eq!(config.depends_on(), &[String::from("postgres")]);
+    }
+
     #[test]
     fn should_parse_companion_bootstrap_containers() {
         let companions = companions_from_str!(
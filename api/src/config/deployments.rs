@@ -0,0 +1,102 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::AppSelector;
+use crate::models::AppName;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deployments {
+    #[serde(default)]
+    queueing: DeploymentQueueing,
+    image_watcher: Option<ImageWatcher>,
+}
+
+impl Deployments {
+    pub fn queueing(&self) -> &DeploymentQueueing {
+        &self.queueing
+    }
+
+    pub fn image_watcher(&self) -> Option<&ImageWatcher> {
+        self.image_watcher.as_ref()
+    }
+}
+
+/// Defines how PREvant handles a deployment request for an app that is still deployed or deleted
+/// by another request.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeploymentQueueing {
+    /// Rejects the deployment request.
+    #[default]
+    Reject,
+    /// Waits until the previous requests of the app have been processed.
+    Queue,
+    /// Waits like [`DeploymentQueueing::Queue`] but only the latest of the waiting deployment
+    /// requests is processed. It deploys the services of the superseded requests as well and the
+    /// superseded requests return its result.
+    Coalesce,
+}
+
+/// Configures the background task that redeploys services whose image tag refers to a new image,
+/// e.g. when a new `latest` image has been pushed.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageWatcher {
+    #[serde(default = "ImageWatcher::default_interval_secs")]
+    interval_secs: u64,
+    #[serde(default)]
+    app_selector: AppSelector,
+    #[serde(default = "ImageWatcher::default_max_redeploys")]
+    max_redeploys: usize,
+}
+
+impl ImageWatcher {
+    fn default_interval_secs() -> u64 {
+        300
+    }
+
+    fn default_max_redeploys() -> usize {
+        5
+    }
+
+    /// The duration between two resolutions of the image digests.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+
+    /// Returns `true` if the services of the app are watched.
+    pub fn watches(&self, app_name: &AppName) -> bool {
+        self.app_selector.matches(app_name)
+    }
+
+    /// The maximum number of services that are redeployed after one resolution of the digests.
+    /// Further services are redeployed after the next resolution.
+    pub fn max_redeploys(&self) -> usize {
+        self.max_redeploys
+    }
+}
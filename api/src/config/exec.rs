@@ -0,0 +1,47 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::AppSelector;
+use crate::models::AppName;
+use secstr::SecUtf8;
+use serde::Deserialize;
+
+/// Allows API clients to run commands in the containers of services, e.g. to open a shell for
+/// debugging. The clients authenticate with one of the tokens as bearer token.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exec {
+    #[serde(default)]
+    app_selector: AppSelector,
+    tokens: Vec<SecUtf8>,
+}
+
+impl Exec {
+    /// Returns `true` if the token grants access to the services of the app.
+    pub fn authorizes(&self, app_name: &AppName, token: &str) -> bool {
+        let token = SecUtf8::from(token);
+        self.app_selector.matches(app_name) && self.tokens.iter().any(|t| t == &token)
+    }
+}
@@ -0,0 +1,86 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Persists the logs of services in `path` so that they remain available after the containers
+/// have been deleted, restarted, or redeployed, e.g. for post-mortems of failed deployments.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogStore {
+    path: PathBuf,
+    #[serde(default = "LogStore::default_retention_hours")]
+    retention_hours: u64,
+    #[serde(default = "LogStore::default_interval_secs")]
+    interval_secs: u64,
+}
+
+impl LogStore {
+    fn default_retention_hours() -> u64 {
+        7 * 24
+    }
+
+    fn default_interval_secs() -> u64 {
+        300
+    }
+
+    /// The directory that contains the persisted logs.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The duration after which the persisted logs of a service are removed if no further log
+    /// lines of the service have been persisted.
+    pub fn retention(&self) -> Duration {
+        Duration::from_secs(self.retention_hours.saturating_mul(60 * 60))
+    }
+
+    /// The duration between two runs of the background task that persists the logs of previous
+    /// containers and removes the logs that exceed the retention.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_saturate_huge_retention() {
+        let log_store = toml::de::from_str::<LogStore>(&format!(
+            r#"
+            path = '/var/log/prevant'
+            retentionHours = {}
+            "#,
+            i64::MAX
+        ))
+        .unwrap();
+
+        assert_eq!(log_store.retention(), Duration::from_secs(u64::MAX));
+    }
+}
@@ -24,12 +24,25 @@
  * =========================LICENSE_END==================================
  */
 
+pub use self::access_restriction::{
+    AccessRestriction, BasicAuthCredentials, FORWARDED_USER_HEADER,
+};
 pub use self::companion::BootstrappingContainer;
 pub use self::companion::DeploymentStrategy;
 pub use self::companion::StorageStrategy;
 use self::companion::{Companion, CompanionType, Companions};
 pub use self::container::ContainerConfig;
-pub use self::runtime::Runtime;
+use self::deployments::Deployments;
+pub use self::deployments::{DeploymentQueueing, ImageWatcher};
+pub use self::exec::Exec;
+pub use self::log_store::LogStore;
+pub use self::routes::config_routes;
+pub use self::routing::{Routing, RoutingError};
+pub use self::runtime::{
+    DeploymentTarget, DockerRuntimeConfig, DockerTlsConfig, InMemoryRuntimeConfig,
+    KubernetesRoutingBackend, PodmanRuntimeConfig, Runtime,
+};
+pub use self::tls::Tls;
 use crate::models::AppName;
 use crate::models::ServiceConfig;
 pub(self) use app_selector::AppSelector;
@@ -37,6 +50,7 @@ use clap::Parser;
 use figment::providers::{Env, Format, Toml};
 use figment::value::{Dict, Map, Tag, Value};
 use figment::{Metadata, Profile};
+use kube::Client as KubeClient;
 pub(self) use secret::Secret;
 use secstr::SecUtf8;
 use std::collections::BTreeMap;
@@ -46,12 +60,23 @@ use std::io::Error as IOError;
 use std::path::PathBuf;
 use std::str::FromStr;
 use toml::de::Error as TomlError;
+use url::Url;
 
+mod access_restriction;
 mod app_selector;
 mod companion;
 mod container;
+mod deployments;
+mod exec;
+mod log_store;
+mod routes;
+mod routing;
 mod runtime;
 mod secret;
+mod tls;
+
+/// Replaces secret values when the configuration is exposed through the REST API.
+const REDACTED: &str = "<redacted>";
 
 #[derive(Default, Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -137,6 +162,13 @@ pub enum JiraAuth {
     },
 }
 
+/// Connection to a HashiCorp Vault server that stores secrets of services.
+#[derive(Clone, Deserialize)]
+pub struct VaultConfig {
+    address: Url,
+    token: SecUtf8,
+}
+
 #[derive(Clone, Deserialize)]
 struct Service {
     secrets: Option<Vec<Secret>>,
@@ -146,7 +178,11 @@ struct Service {
 pub struct Config {
     #[serde(default)]
     runtime: Runtime,
+    #[serde(default, rename = "deploymentTargets")]
+    deployment_targets: Vec<DeploymentTarget>,
     containers: Option<ContainerConfig>,
+    #[serde(default)]
+    deployments: Deployments,
     jira: Option<JiraConfig>,
     #[serde(default)]
     companions: Companions,
@@ -154,6 +190,15 @@ pub struct Config {
     hooks: Option<BTreeMap<String, PathBuf>>,
     #[serde(default)]
     registries: BTreeMap<String, Registry>,
+    vault: Option<VaultConfig>,
+    #[serde(default)]
+    routing: Routing,
+    tls: Option<Tls>,
+    #[serde(default, rename = "accessRestrictions")]
+    access_restrictions: Vec<AccessRestriction>,
+    exec: Option<Exec>,
+    #[serde(rename = "logStore")]
+    log_store: Option<LogStore>,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
@@ -179,6 +224,19 @@ impl Config {
         &self.runtime
     }
 
+    pub fn deployment_targets(&self) -> &Vec<DeploymentTarget> {
+        &self.deployment_targets
+    }
+
+    /// Returns a copy of this configuration that uses the given runtime, e.g. to create the
+    /// infrastructure of a deployment target.
+    pub fn with_runtime(&self, runtime: Runtime) -> Self {
+        Self {
+            runtime,
+            ..self.clone()
+        }
+    }
+
     pub fn container_config(&self) -> ContainerConfig {
         match &self.containers {
             Some(containers) => containers.clone(),
@@ -186,6 +244,40 @@ impl Config {
         }
     }
 
+    pub fn deployment_queueing(&self) -> &DeploymentQueueing {
+        self.deployments.queueing()
+    }
+
+    pub fn image_watcher(&self) -> Option<&ImageWatcher> {
+        self.deployments.image_watcher()
+    }
+
+    pub fn routing(&self) -> &Routing {
+        &self.routing
+    }
+
+    pub fn tls(&self) -> Option<&Tls> {
+        self.tls.as_ref()
+    }
+
+    /// Returns the access restrictions whose app selector matches the given app.
+    pub fn access_restrictions(&self, app_name: &AppName) -> Vec<&AccessRestriction> {
+        self.access_restrictions
+            .iter()
+            .filter(|restriction| restriction.matches(app_name))
+            .collect()
+    }
+
+    /// Returns the configuration that allows to run commands in services. `None` means that
+    /// commands cannot be run at all.
+    pub fn exec(&self) -> Option<&Exec> {
+        self.exec.as_ref()
+    }
+
+    pub fn log_store(&self) -> Option<&LogStore> {
+        self.log_store.as_ref()
+    }
+
     pub fn jira_config(&self) -> Option<JiraConfig> {
         self.jira.as_ref().cloned()
     }
@@ -223,12 +315,27 @@ impl Config {
         self.companions.companion_configs(app_name, predicate)
     }
 
-    pub fn add_secrets_to(&self, service_config: &mut ServiceConfig, app_name: &AppName) {
+    /// Resolves the secrets of the matching service from their sources and adds them as files to
+    /// the service configuration. Secrets stored in Kubernetes are read with `kubernetes_client`.
+    pub async fn add_secrets_to(
+        &self,
+        service_config: &mut ServiceConfig,
+        app_name: &AppName,
+        kubernetes_client: Option<&KubeClient>,
+    ) -> Result<(), ConfigError> {
         if let Some(services) = &self.services {
             if let Some(service) = services.get(service_config.service_name()) {
-                service.add_secrets_to(service_config, app_name);
+                service
+                    .add_secrets_to(
+                        service_config,
+                        app_name,
+                        self.vault.as_ref(),
+                        kubernetes_client,
+                    )
+                    .await?;
             }
         }
+        Ok(())
     }
 
     pub fn hook(&self, hook_name: &str) -> Option<&PathBuf> {
@@ -254,15 +361,36 @@ impl JiraConfig {
     }
 }
 
+impl VaultConfig {
+    pub fn address(&self) -> &Url {
+        &self.address
+    }
+
+    pub fn token(&self) -> &SecUtf8 {
+        &self.token
+    }
+}
+
 impl Service {
-    pub fn add_secrets_to(&self, service_config: &mut ServiceConfig, app_name: &AppName) {
+    pub async fn add_secrets_to(
+        &self,
+        service_config: &mut ServiceConfig,
+        app_name: &AppName,
+        vault: Option<&VaultConfig>,
+        kubernetes_client: Option<&KubeClient>,
+    ) -> Result<(), ConfigError> {
         if let Some(secrets) = &self.secrets {
             for s in secrets.iter().filter(|s| s.matches_app_name(app_name)) {
-                let (path, sec) = s.clone().into();
+                let (path, sec) = s.resolve(vault, kubernetes_client).await?;
 
+                if let Some(variable) = s.environment_variable() {
+                    let secret_key = format!("{}-{}", service_config.service_name(), s.name());
+                    service_config.add_secret_env(variable.clone(), sec.clone(), secret_key);
+                }
                 service_config.add_file(path, sec);
             }
         }
+        Ok(())
     }
 }
 
@@ -272,6 +400,8 @@ pub enum ConfigError {
     CannotOpenConfigFile { error: IOError },
     #[fail(display = "Invalid config file format. {}", error)]
     ConfigFormatError { error: TomlError },
+    #[fail(display = "Cannot resolve secret {}: {}", name, error)]
+    CannotResolveSecret { name: String, error: String },
 }
 
 impl From<IOError> for ConfigError {
@@ -499,8 +629,8 @@ mod tests {
         assert_eq!(companion_configs.len(), 0);
     }
 
-    #[test]
-    fn should_set_service_secrets_with_default_app_selector() {
+    #[tokio::test]
+    async fn should_set_service_secrets_with_default_app_selector() {
         let config = config_from_str!(
             r#"
             [services.mariadb]
@@ -511,7 +641,10 @@ mod tests {
         );
 
         let mut service_config = service_config!("mariadb");
-        config.add_secrets_to(&mut service_config, &AppName::master());
+        config
+            .add_secrets_to(&mut service_config, &AppName::master(), None)
+            .await
+            .unwrap();
         let secret_file_content = service_config
             .files()
             .expect("File content is missing")
@@ -520,8 +653,8 @@ mod tests {
         assert_eq!(secret_file_content, &SecUtf8::from("Hello"));
     }
 
-    #[test]
-    fn should_set_service_secrets_with_specific_app_selector() {
+    #[tokio::test]
+    async fn should_set_service_secrets_with_specific_app_selector() {
         let config = config_from_str!(
             r#"
             [services.mariadb]
@@ -533,7 +666,10 @@ mod tests {
         );
 
         let mut service_config = service_config!("mariadb");
-        config.add_secrets_to(&mut service_config, &AppName::master());
+        config
+            .add_secrets_to(&mut service_config, &AppName::master(), None)
+            .await
+            .unwrap();
 
         let secret_file_content = service_config
             .files()
@@ -543,8 +679,8 @@ mod tests {
         assert_eq!(secret_file_content, &SecUtf8::from("Hello"));
     }
 
-    #[test]
-    fn should_set_service_secrets_with_regex_app_selector() {
+    #[tokio::test]
+    async fn should_set_service_secrets_with_regex_app_selector() {
         let config = config_from_str!(
             r#"
             [services.mariadb]
@@ -556,10 +692,14 @@ mod tests {
         );
 
         let mut service_config = service_config!("mariadb");
-        config.add_secrets_to(
-            &mut service_config,
-            &AppName::from_str("master-1.x").unwrap(),
-        );
+        config
+            .add_secrets_to(
+                &mut service_config,
+                &AppName::from_str("master-1.x").unwrap(),
+                None,
+            )
+            .await
+            .unwrap();
 
         let secret_file_content = service_config
             .files()
@@ -569,29 +709,63 @@ mod tests {
         assert_eq!(secret_file_content, &SecUtf8::from("Hello"));
     }
 
-    #[test]
-    fn should_not_set_service_secrets_with_specific_app_selector() {
+    #[tokio::test]
+    async fn should_set_service_secrets_as_environment_variable() {
         let config = config_from_str!(
             r#"
             [services.mariadb]
             [[services.mariadb.secrets]]
             name = "user"
             data = "SGVsbG8="
-            appSelector = "master-.+"
+            environmentVariable = "MARIADB_USER"
             "#
         );
 
         let mut service_config = service_config!("mariadb");
-        config.add_secrets_to(
-            &mut service_config,
-            &AppName::from_str("random-app-name").unwrap(),
+        config
+            .add_secrets_to(&mut service_config, &AppName::master(), None)
+            .await
+            .unwrap();
+
+        let variable = service_config
+            .env()
+            .and_then(|env| env.variable("MARIADB_USER"))
+            .expect("No environment variable MARIADB_USER");
+        assert_eq!(variable.value(), &SecUtf8::from("Hello"));
+        assert_eq!(variable.secret_key(), Some(&String::from("mariadb-user")));
+        assert!(service_config
+            .files()
+            .expect("File content is missing")
+            .contains_key(&PathBuf::from("/run/secrets/user")));
+    }
+
+    #[tokio::test]
+    async fn should_not_set_service_secrets_with_specific_app_selector() {
+        let config = config_from_str!(
+            r#"
+            [services.mariadb]
+            [[services.mariadb.secrets]]
+            name = "user"
+            data = "SGVsbG8="
+            appSelector = "master-.+"
+            "#
         );
 
+        let mut service_config = service_config!("mariadb");
+        config
+            .add_secrets_to(
+                &mut service_config,
+                &AppName::from_str("random-app-name").unwrap(),
+                None,
+            )
+            .await
+            .unwrap();
+
         assert!(service_config.files().is_none());
     }
 
-    #[test]
-    fn should_not_set_service_secrets_with_partially_specific_app_selector() {
+    #[tokio::test]
+    async fn should_not_set_service_secrets_with_partially_specific_app_selector() {
         let config = config_from_str!(
             r#"
             [services.mariadb]
@@ -603,10 +777,14 @@ mod tests {
         );
 
         let mut service_config = service_config!("mariadb");
-        config.add_secrets_to(
-            &mut service_config,
-            &AppName::from_str("master-1.x").unwrap(),
-        );
+        config
+            .add_secrets_to(
+                &mut service_config,
+                &AppName::from_str("master-1.x").unwrap(),
+                None,
+            )
+            .await
+            .unwrap();
 
         assert_eq!(service_config.files(), None);
     }
@@ -615,7 +793,97 @@ mod tests {
     fn should_parse_config_with_default_container_runtime() {
         let config = config_from_str!("");
 
-        assert_eq!(config.runtime_config(), &Runtime::Docker);
+        assert_eq!(
+            config.runtime_config(),
+            &Runtime::Docker(Default::default())
+        );
+    }
+
+    #[test]
+    fn should_parse_deployment_targets() {
+        let config = config_from_str!(
+            r#"
+            [[deploymentTargets]]
+            name = 'cluster-b'
+            appSelector = 'team-b-.*'
+            [deploymentTargets.runtime]
+            type = 'Kubernetes'
+            context = 'cluster-b'
+
+            [[deploymentTargets]]
+            name = 'podman'
+            [deploymentTargets.runtime]
+            type = 'Podman'
+            "#
+        );
+
+        let targets = config.deployment_targets();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].name(), "cluster-b");
+        assert!(targets[0].places(&AppName::from_str("team-b-login").unwrap()));
+        assert!(!targets[0].places(&AppName::master()));
+        assert!(!targets[1].places(&AppName::master()));
+
+        let target_config = config.with_runtime(targets[0].runtime().clone());
+        let Runtime::Kubernetes(k8s_config) = target_config.runtime_config() else {
+            panic!("Need a K8s config")
+        };
+        assert_eq!(k8s_config.context(), &Some(String::from("cluster-b")));
+    }
+
+    #[test]
+    fn should_parse_image_watcher() {
+        let config = config_from_str!(
+            r#"
+            [deployments.imageWatcher]
+            intervalSecs = 60
+            appSelector = 'master'
+            "#
+        );
+
+        let watcher = config.image_watcher().unwrap();
+        assert_eq!(watcher.interval(), std::time::Duration::from_secs(60));
+        assert_eq!(watcher.max_redeploys(), 5);
+        assert!(watcher.watches(&AppName::master()));
+        assert!(!watcher.watches(&AppName::from_str("feature").unwrap()));
+        assert!(Config::default().image_watcher().is_none());
+    }
+
+    #[test]
+    fn should_parse_log_store() {
+        let config = config_from_str!(
+            r#"
+            [logStore]
+            path = '/var/lib/prevant/logs'
+            retentionHours = 48
+            "#
+        );
+
+        let log_store = config.log_store().unwrap();
+        assert_eq!(log_store.path(), PathBuf::from("/var/lib/prevant/logs"));
+        assert_eq!(
+            log_store.retention(),
+            std::time::Duration::from_secs(48 * 60 * 60)
+        );
+        assert_eq!(log_store.interval(), std::time::Duration::from_secs(300));
+        assert!(Config::default().log_store().is_none());
+    }
+
+    #[test]
+    fn should_authorize_exec_with_configured_tokens() {
+        let config = config_from_str!(
+            r#"
+            [exec]
+            tokens = ['secret']
+            appSelector = 'master'
+            "#
+        );
+
+        let exec = config.exec().unwrap();
+        assert!(exec.authorizes(&AppName::master(), "secret"));
+        assert!(!exec.authorizes(&AppName::master(), "guess"));
+        assert!(!exec.authorizes(&AppName::from_str("feature").unwrap(), "secret"));
+        assert!(Config::default().exec().is_none());
     }
 
     #[test]
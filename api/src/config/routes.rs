@@ -0,0 +1,328 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2021 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::companion::Companion;
+use super::{AppSelector, Config, Routing, Runtime, Secret, Tls};
+use crate::models::Image;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::collections::BTreeMap;
+use url::Url;
+
+pub fn config_routes() -> Vec<rocket::Route> {
+    rocket::routes![config]
+}
+
+/// Returns the effective configuration of PREvant. Secrets, e.g. registry passwords, secret data
+/// or the values of companion environment variables, are redacted.
+#[get("/config", format = "application/json")]
+fn config(config: &State<Config>) -> Json<ConfigView<'_>> {
+    Json(ConfigView::from(config.inner()))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigView<'a> {
+    runtime: &'a Runtime,
+    containers: ContainersView,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jira: Option<JiraView<'a>>,
+    companions: &'a BTreeMap<String, Companion>,
+    bootstrapping_containers: Vec<&'a Image>,
+    services: BTreeMap<&'a str, &'a [Secret]>,
+    hooks: Vec<&'a str>,
+    registries: BTreeMap<&'a str, RegistryView<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vault: Option<VaultView<'a>>,
+    routing: &'a Routing,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls: Option<&'a Tls>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    access_restrictions: Vec<AccessRestrictionView<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContainersView {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct JiraView<'a> {
+    host: &'a str,
+}
+
+#[derive(Serialize)]
+struct VaultView<'a> {
+    address: &'a Url,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessRestrictionView<'a> {
+    r#type: &'static str,
+    app_selector: &'a AppSelector,
+}
+
+#[derive(Serialize)]
+struct RegistryView<'a> {
+    username: &'a str,
+    password: &'static str,
+}
+
+impl<'a> From<&'a Config> for ConfigView<'a> {
+    fn from(config: &'a Config) -> Self {
+        ConfigView {
+            runtime: &config.runtime,
+            containers: ContainersView {
+                memory_limit: config
+                    .container_config()
+                    .memory_limit()
+                    .map(|limit| limit.as_u64()),
+            },
+            jira: config
+                .jira
+                .as_ref()
+                .map(|jira| JiraView { host: &jira.host }),
+            companions: config.companions.companions(),
+            bootstrapping_containers: config
+                .companion_bootstrapping_containers()
+                .iter()
+                .map(|container| container.image())
+                .collect(),
+            services: config
+                .services
+                .iter()
+                .flat_map(|services| services.iter())
+                .map(|(name, service)| {
+                    (
+                        name.as_str(),
+                        service.secrets.as_deref().unwrap_or_default(),
+                    )
+                })
+                .collect(),
+            hooks: config
+                .hooks
+                .iter()
+                .flat_map(|hooks| hooks.keys())
+                .map(|name| name.as_str())
+                .collect(),
+            registries: config
+                .registries
+                .iter()
+                .map(|(host, registry)| {
+                    (
+                        host.as_str(),
+                        RegistryView {
+                            username: &registry.username,
+                            password: super::REDACTED,
+                        },
+                    )
+                })
+                .collect(),
+            vault: config.vault.as_ref().map(|vault| VaultView {
+                address: vault.address(),
+            }),
+            routing: config.routing(),
+            tls: config.tls(),
+            access_restrictions: config
+                .access_restrictions
+                .iter()
+                .map(|restriction| AccessRestrictionView {
+                    r#type: restriction.type_name(),
+                    app_selector: restriction.app_selector(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_from_str;
+    use assert_json_diff::assert_json_eq;
+    use rocket::http::{Accept, Status};
+    use rocket::local::asynchronous::Client;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn should_redact_secrets() {
+        let config = config_from_str!(
+            r#"
+            [companions.openid]
+            serviceName = 'openid'
+            type = 'application'
+            image = 'private.example.com/library/openid:latest'
+            appSelector = 'master|develop'
+            env = [ 'CLIENT_SECRET=top-secret' ]
+
+            [companions.openid.files]
+            '/etc/openid/keys.pem' = 'private-key'
+
+            [[services.mariadb.secrets]]
+            name = "user"
+            data = "SGVsbG8="
+
+            [registries.'docker.io']
+            username = "user"
+            password = "pass"
+            "#
+        );
+
+        let view = serde_json::to_value(ConfigView::from(&config)).unwrap();
+
+        assert_json_eq!(
+            view,
+            json!({
+                "runtime": { "type": "Docker" },
+                "containers": {},
+                "companions": {
+                    "openid": {
+                        "serviceName": "openid",
+                        "type": "application",
+                        "image": "private.example.com/library/openid:latest",
+                        "appSelector": "master|develop",
+                        "deploymentStrategy": "redeploy-always",
+                        "storageStrategy": "none",
+                        "env": { "CLIENT_SECRET": "<redacted>" },
+                        "labels": null,
+                        "files": { "/etc/openid/keys.pem": "<redacted>" },
+                        "router": null,
+                        "middlewares": [],
+                        "replicas": null,
+                        "autoscaling": null,
+                        "initContainers": null,
+                        "sidecars": null,
+                        "dependsOn": null,
+                        "ports": null
+                    }
+                },
+                "bootstrappingContainers": [],
+                "services": {
+                    "mariadb": [{
+                        "name": "user",
+                        "data": "<redacted>",
+                        "appSelector": ".+",
+                        "path": "/run/secrets/user"
+                    }]
+                },
+                "hooks": [],
+                "registries": {
+                    "docker.io": { "username": "user", "password": "<redacted>" }
+                },
+                "routing": { "mode": "path" }
+            })
+        );
+    }
+
+    #[test]
+    fn should_render_kubernetes_runtime_and_limits() {
+        let config = config_from_str!(
+            r#"
+            [runtime]
+            type = 'Kubernetes'
+
+            [runtime.storageConfig]
+            storageClass = 'local-path'
+
+            [containers]
+            memory_limit = '1g'
+
+            [hooks]
+            deployment = 'hooks/deployment.js'
+
+            [routing]
+            mode = 'host'
+            domain = 'review.example.com'
+
+            [tls]
+            type = 'certManager'
+            issuerName = 'letsencrypt'
+
+            [[accessRestrictions]]
+            type = 'basicAuth'
+            appSelector = 'feature-.+'
+            passwordSeed = 'top-secret'
+            "#
+        );
+
+        let view = serde_json::to_value(ConfigView::from(&config)).unwrap();
+
+        assert_eq!(view["runtime"]["type"], "Kubernetes");
+        assert_eq!(
+            view["runtime"]["storageConfig"]["storageClass"],
+            "local-path"
+        );
+        assert_eq!(view["containers"]["memoryLimit"], 1073741824u64);
+        assert_eq!(view["hooks"], json!(["deployment"]));
+        assert_eq!(
+            view["routing"],
+            json!({ "mode": "host", "domain": "review.example.com", "certResolver": null })
+        );
+        assert_eq!(
+            view["tls"],
+            json!({ "type": "certManager", "issuerName": "letsencrypt", "issuerKind": "ClusterIssuer" })
+        );
+        assert_eq!(
+            view["accessRestrictions"],
+            json!([{ "type": "basicAuth", "appSelector": "feature-.+" }])
+        );
+    }
+
+    #[tokio::test]
+    async fn should_serve_config() -> Result<(), rocket::Error> {
+        let config = config_from_str!(
+            r#"
+            [registries.'docker.io']
+            username = "user"
+            password = "pass"
+            "#
+        );
+
+        let rocket = rocket::build()
+            .manage(config)
+            .mount("/api", config_routes());
+        let client = Client::tracked(rocket)
+            .await
+            .expect("valid rocket instance");
+
+        let response = client
+            .get("/api/config")
+            .header(Accept::JSON)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let body = response.into_json::<Value>().await.unwrap();
+        assert_eq!(
+            body["registries"],
+            json!({ "docker.io": { "username": "user", "password": "<redacted>" } })
+        );
+
+        Ok(())
+    }
+}
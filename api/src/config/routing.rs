@@ -0,0 +1,408 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use crate::infrastructure::{TraefikIngressRoute, TraefikTLS, TraefikTLSDomain};
+use crate::models::AppName;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// The maximum length of a label of a host name.
+const MAX_DNS_LABEL_LENGTH: usize = 63;
+
+/// Defines how the services of the review apps are exposed through Traefik.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum Routing {
+    /// Every service is served below the path `/<app>/<service>/` of PREvant's host.
+    #[default]
+    Path,
+    /// Every service is served on its own host `<service>.<app>.<domain>` which is required for
+    /// web applications that cannot be served below a base path.
+    Host(HostRouting),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostRouting {
+    domain: String,
+    /// If present, a wildcard certificate for `*.<app>.<domain>` is requested from this Traefik
+    /// cert resolver.
+    cert_resolver: Option<String>,
+}
+
+impl HostRouting {
+    fn app_host(&self, app_name: &str) -> String {
+        format!("{}.{}", dns_label_or_hash(app_name), self.domain)
+    }
+
+    fn service_host(&self, app_name: &str, service_name: &str) -> String {
+        format!(
+            "{}.{}",
+            dns_label_or_hash(service_name),
+            self.app_host(app_name)
+        )
+    }
+
+    fn wildcard_tls_domain(&self, app_name: &str) -> TraefikTLSDomain {
+        let app_host = self.app_host(app_name);
+        TraefikTLSDomain {
+            sans: vec![format!("*.{app_host}")],
+            main: app_host,
+        }
+    }
+
+    fn ingress_route(&self, host: String, app_name: &str) -> TraefikIngressRoute {
+        let mut route = TraefikIngressRoute::with_host_defaults(host);
+        if let Some(cert_resolver) = &self.cert_resolver {
+            route.set_tls(TraefikTLS {
+                cert_resolver: Some(cert_resolver.clone()),
+                secret_name: None,
+                domains: vec![self.wildcard_tls_domain(app_name)],
+            });
+        }
+        route
+    }
+}
+
+/// Converts a name into a valid [RFC 1123 label](https://datatracker.ietf.org/doc/html/rfc1123)
+/// so that it can be used as part of a host name, e.g. `Feature_X` becomes `feature-x-c6683d`.
+/// Like the Kubernetes namespace of an app, the label is lower case. If the name has to be changed
+/// or shortened to 63 characters, a short hash of the name is appended so that different names,
+/// e.g. `Feature_X` and `feature-x`, still result in different labels. Returns `None` if the name
+/// does not contain any letters or digits.
+fn dns_label(name: &str) -> Option<String> {
+    let label = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string();
+
+    if label.is_empty() {
+        return None;
+    }
+    if label == name && label.len() <= MAX_DNS_LABEL_LENGTH {
+        return Some(label);
+    }
+
+    let hash = name_hash(name);
+    let prefix =
+        label[..label.len().min(MAX_DNS_LABEL_LENGTH - hash.len() - 1)].trim_end_matches('-');
+    Some(format!("{prefix}-{hash}"))
+}
+
+/// Same as [`dns_label`] but falls back to the hash of the name for names without letters or
+/// digits, which are rejected by [`Routing::check_host_names`] before they are deployed.
+fn dns_label_or_hash(name: &str) -> String {
+    dns_label(name).unwrap_or_else(|| name_hash(name))
+}
+
+fn name_hash(name: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(name);
+    format!("{:x}", hasher.finalize())[..6].to_string()
+}
+
+#[derive(Debug, Clone, Fail, PartialEq)]
+pub enum RoutingError {
+    #[fail(
+        display = "{} cannot be served on its own host because its name does not contain any letters or digits.",
+        name
+    )]
+    InvalidHostName { name: String },
+}
+
+impl Routing {
+    /// The default route of a service of the given app.
+    pub fn service_ingress_route(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> TraefikIngressRoute {
+        match self {
+            Routing::Path => TraefikIngressRoute::with_defaults(app_name, service_name),
+            Routing::Host(host) => {
+                host.ingress_route(host.service_host(app_name, service_name), app_name)
+            }
+        }
+    }
+
+    /// The default route of the app itself, e.g. used for the app's bootstrapping containers.
+    pub fn app_ingress_route(&self, app_name: &AppName) -> TraefikIngressRoute {
+        match self {
+            Routing::Path => TraefikIngressRoute::with_app_only_defaults(app_name),
+            Routing::Host(host) => host.ingress_route(host.app_host(app_name), app_name),
+        }
+    }
+
+    /// Ensures that the app and its services can be served on their own hosts, which requires
+    /// their names to contain letters or digits.
+    pub fn check_host_names<'a, I>(
+        &self,
+        app_name: &AppName,
+        service_names: I,
+    ) -> Result<(), RoutingError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        if let Routing::Path = self {
+            return Ok(());
+        }
+
+        match std::iter::once(app_name.as_str())
+            .chain(service_names)
+            .find(|name| dns_label(name).is_none())
+        {
+            Some(name) => Err(RoutingError::InvalidHostName {
+                name: name.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The host that a service is served on if the services are routed by their host.
+    pub fn service_host(&self, app_name: &str, service_name: &str) -> Option<String> {
+        match self {
+            Routing::Path => None,
+            Routing::Host(host) => Some(host.service_host(app_name, service_name)),
+        }
+    }
+
+    /// Computes the URL of a service based on the URL that PREvant has been requested with.
+    pub fn service_url(&self, base_url: &Url, app_name: &str, service_name: &str) -> Option<Url> {
+        match self {
+            Routing::Path => base_url.join(&format!("/{app_name}/{service_name}/")).ok(),
+            Routing::Host(host) => {
+                let scheme = if host.cert_resolver.is_some() {
+                    "https"
+                } else {
+                    base_url.scheme()
+                };
+                Url::parse(&format!(
+                    "{scheme}://{}/",
+                    host.service_host(app_name, service_name)
+                ))
+                .ok()
+            }
+        }
+    }
+
+    /// The domains that a certificate of the app must be valid for. With host based routing, a
+    /// wildcard certificate covers all services of the app. Otherwise, the domains are derived
+    /// from the routing rules.
+    pub fn tls_domains(&self, app_name: &str) -> Vec<TraefikTLSDomain> {
+        match self {
+            Routing::Path => Vec::new(),
+            Routing::Host(host) => vec![host.wildcard_tls_domain(app_name)],
+        }
+    }
+
+    /// The path prefix that a service is served with and that the service needs to be aware of
+    /// when it renders links.
+    pub fn forwarded_prefix(&self, app_name: &str, service_name: &str) -> Option<String> {
+        match self {
+            Routing::Path => Some(format!("/{app_name}/{service_name}")),
+            Routing::Host(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn host_routing() -> Routing {
+        host_routing_with_cert_resolver(None)
+    }
+
+    fn host_routing_with_cert_resolver(cert_resolver: Option<&str>) -> Routing {
+        Routing::Host(HostRouting {
+            domain: String::from("review.example.com"),
+            cert_resolver: cert_resolver.map(String::from),
+        })
+    }
+
+    #[test]
+    fn should_compute_path_based_service_url() {
+        let base_url = Url::parse("http://prevant.example.com").unwrap();
+
+        let url = Routing::Path.service_url(&base_url, "master", "db");
+
+        assert_eq!(
+            url,
+            Url::parse("http://prevant.example.com/master/db/").ok()
+        );
+    }
+
+    #[test]
+    fn should_compute_host_based_service_url() {
+        let base_url = Url::parse("http://prevant.example.com:8080/some/path").unwrap();
+
+        let url = host_routing().service_url(&base_url, "master", "db");
+
+        assert_eq!(url, Url::parse("http://db.master.review.example.com/").ok());
+    }
+
+    #[test]
+    fn should_keep_scheme_of_host_based_service_url() {
+        let base_url = Url::parse("https://prevant.example.com").unwrap();
+
+        let url = host_routing().service_url(&base_url, "master", "db");
+
+        assert_eq!(
+            url,
+            Url::parse("https://db.master.review.example.com/").ok()
+        );
+    }
+
+    #[test]
+    fn should_compute_host_based_service_url_with_tls() {
+        let base_url = Url::parse("http://prevant.example.com").unwrap();
+
+        let url = host_routing_with_cert_resolver(Some("letsencrypt"))
+            .service_url(&base_url, "master", "db");
+
+        assert_eq!(
+            url,
+            Url::parse("https://db.master.review.example.com/").ok()
+        );
+    }
+
+    #[test]
+    fn should_create_host_based_service_route() {
+        let route = host_routing().service_ingress_route(&AppName::master(), "db");
+
+        assert_eq!(
+            route.routes()[0].rule(),
+            &crate::infrastructure::TraefikRouterRule::from_str(
+                "Host(`db.master.review.example.com`)"
+            )
+            .unwrap()
+        );
+        assert!(route.routes()[0].middlewares().is_empty());
+        assert_eq!(route.tls(), &None);
+    }
+
+    #[test]
+    fn should_create_host_based_app_route() {
+        let route = host_routing().app_ingress_route(&AppName::master());
+
+        assert_eq!(
+            route.to_url(),
+            Url::parse("http://master.review.example.com").ok()
+        );
+    }
+
+    #[test]
+    fn should_create_host_based_app_route_with_wildcard_tls() {
+        let route = host_routing_with_cert_resolver(Some("letsencrypt"))
+            .app_ingress_route(&AppName::master());
+
+        assert_eq!(
+            route.to_url(),
+            Url::parse("https://master.review.example.com").ok()
+        );
+        assert_eq!(
+            route.tls(),
+            &Some(TraefikTLS {
+                cert_resolver: Some(String::from("letsencrypt")),
+                secret_name: None,
+                domains: vec![TraefikTLSDomain {
+                    main: String::from("master.review.example.com"),
+                    sans: vec![String::from("*.master.review.example.com")],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn should_compute_host_based_service_url_with_valid_dns_labels() {
+        let base_url = Url::parse("https://prevant.example.com").unwrap();
+
+        let url = host_routing().service_url(&base_url, "Feature_1234", "Keycloak_DB");
+
+        assert_eq!(
+            url,
+            Url::parse("https://keycloak-db-cb1b15.feature-1234-51a6a8.review.example.com/").ok()
+        );
+    }
+
+    #[test]
+    fn should_keep_valid_dns_labels() {
+        assert_eq!(dns_label("master"), Some(String::from("master")));
+        assert_eq!(dns_label("feature-x"), Some(String::from("feature-x")));
+    }
+
+    #[test]
+    fn should_distinguish_changed_dns_labels() {
+        assert_eq!(
+            dns_label("Feature_X"),
+            Some(String::from("feature-x-c6683d"))
+        );
+        assert_ne!(dns_label("Feature_X"), dns_label("feature-x"));
+    }
+
+    #[test]
+    fn should_truncate_long_dns_labels() {
+        let label = dns_label(&"a".repeat(70)).unwrap();
+
+        assert_eq!(label.len(), 63);
+        assert_eq!(label, format!("{}-6bd5e5", "a".repeat(56)));
+    }
+
+    #[test]
+    fn should_reject_empty_dns_labels() {
+        assert_eq!(dns_label("__"), None);
+        assert_eq!(
+            host_routing().check_host_names(&AppName::master(), vec!["db", "__"]),
+            Err(RoutingError::InvalidHostName {
+                name: String::from("__")
+            })
+        );
+        assert_eq!(
+            host_routing().check_host_names(&AppName::master(), vec!["db"]),
+            Ok(())
+        );
+        assert_eq!(
+            Routing::Path.check_host_names(&AppName::master(), vec!["__"]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn should_compute_wildcard_tls_domains() {
+        assert_eq!(
+            host_routing().tls_domains("master"),
+            vec![TraefikTLSDomain {
+                main: String::from("master.review.example.com"),
+                sans: vec![String::from("*.master.review.example.com")],
+            }]
+        );
+        assert_eq!(Routing::Path.tls_domains("master"), Vec::new());
+    }
+}
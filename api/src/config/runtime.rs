@@ -23,35 +23,173 @@
  * THE SOFTWARE.
  * =========================LICENSE_END==================================
  */
+use super::app_selector::AppSelector;
+use crate::models::AppName;
 use bytesize::ByteSize;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use std::{collections::BTreeMap, path::PathBuf};
+use url::Url;
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(tag = "type")]
 pub enum Runtime {
-    Docker,
+    Docker(DockerRuntimeConfig),
     Kubernetes(KubernetesRuntimeConfig),
+    Podman(PodmanRuntimeConfig),
+    InMemory(InMemoryRuntimeConfig),
 }
 
 impl Default for Runtime {
     fn default() -> Self {
-        Self::Docker
+        Self::Docker(DockerRuntimeConfig::default())
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DockerRuntimeConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls: Option<DockerTlsConfig>,
+}
+
+impl DockerRuntimeConfig {
+    /// The Docker daemon to use, e.g. `unix:///var/run/docker.sock`, `tcp://build-host:2376`, or
+    /// `ssh://prevant@build-host`. Without host, `DOCKER_HOST` or the local socket is used.
+    pub fn host(&self) -> Option<&Url> {
+        self.host.as_ref()
+    }
+
+    /// The certificates to authenticate against a `tcp://` host.
+    pub fn tls(&self) -> Option<&DockerTlsConfig> {
+        self.tls.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DockerTlsConfig {
+    ca_cert: PathBuf,
+    client_cert: PathBuf,
+    client_key: PathBuf,
+}
+
+impl DockerTlsConfig {
+    pub fn ca_cert(&self) -> &PathBuf {
+        &self.ca_cert
+    }
+
+    pub fn client_cert(&self) -> &PathBuf {
+        &self.client_cert
+    }
+
+    pub fn client_key(&self) -> &PathBuf {
+        &self.client_key
+    }
+}
+
+/// A runtime that PREvant manages in addition to the default runtime, e.g. another Kubernetes
+/// cluster.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentTarget {
+    name: String,
+    runtime: Runtime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_selector: Option<AppSelector>,
+}
+
+impl DeploymentTarget {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// Returns `true` if new apps with the given name are placed on this target. Targets without
+    /// app selector only receive apps that are explicitly deployed to them.
+    pub fn places(&self, app_name: &AppName) -> bool {
+        self.app_selector
+            .as_ref()
+            .map(|selector| selector.matches(app_name))
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodmanRuntimeConfig {
+    socket: Option<PathBuf>,
+}
+
+impl PodmanRuntimeConfig {
+    /// The unix socket of Podman's REST API. Without explicit configuration, the socket of
+    /// rootless Podman is used if `XDG_RUNTIME_DIR` is set and the socket of rootful Podman
+    /// otherwise.
+    pub fn socket(&self) -> PathBuf {
+        match (&self.socket, std::env::var_os("XDG_RUNTIME_DIR")) {
+            (Some(socket), _) => socket.clone(),
+            (None, Some(runtime_dir)) => PathBuf::from(runtime_dir).join("podman/podman.sock"),
+            (None, None) => PathBuf::from("/run/podman/podman.sock"),
+        }
+    }
+}
+
+/// Keeps the deployed services in memory without starting any container, e.g. for frontend
+/// development or for integration tests of the REST API.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InMemoryRuntimeConfig {
+    #[serde(default)]
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failing_apps: Option<AppSelector>,
+}
+
+impl InMemoryRuntimeConfig {
+    /// The simulated duration of deployments, stops, and status changes.
+    pub fn latency(&self) -> Option<Duration> {
+        match self.latency_ms {
+            0 => None,
+            latency_ms => Some(Duration::from_millis(latency_ms)),
+        }
+    }
+
+    /// Returns `true` if all deployments, stops, and status changes of the app fail.
+    pub fn fails_for(&self, app_name: &AppName) -> bool {
+        self.failing_apps
+            .as_ref()
+            .map(|selector| selector.matches(app_name))
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct KubernetesRuntimeConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<String>,
     #[serde(default)]
     annotations: KubernetesAnnotationsConfig,
     #[serde(default)]
     downward_api: KubernetesDownwardApiConfig,
     #[serde(default)]
     storage_config: KubernetesStorageConfig,
+    #[serde(default)]
+    routing_backend: KubernetesRoutingBackend,
 }
 
 impl KubernetesRuntimeConfig {
+    /// The context of the kubeconfig that is used instead of the inferred configuration, e.g. the
+    /// in-cluster configuration.
+    pub fn context(&self) -> &Option<String> {
+        &self.context
+    }
+
     pub fn downward_api(&self) -> &KubernetesDownwardApiConfig {
         &self.downward_api
     }
@@ -63,9 +201,37 @@ impl KubernetesRuntimeConfig {
     pub fn annotations(&self) -> &KubernetesAnnotationsConfig {
         &self.annotations
     }
+
+    pub fn routing_backend(&self) -> &KubernetesRoutingBackend {
+        &self.routing_backend
+    }
 }
 
-#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+/// Defines the Kubernetes objects that expose the services of the review apps.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum KubernetesRoutingBackend {
+    /// Traefik's `IngressRoute` and `Middleware` custom resources.
+    #[default]
+    Traefik,
+    /// `networking.k8s.io/v1` Ingress objects, e.g. for ingress-nginx. Middlewares cannot be
+    /// expressed generically and, thus, controller specific annotations must be provided.
+    #[serde(rename_all = "camelCase")]
+    Ingress {
+        ingress_class_name: Option<String>,
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+    },
+    /// [Gateway API](https://gateway-api.sigs.k8s.io/) `HTTPRoute` objects that are attached to
+    /// an existing gateway.
+    #[serde(rename_all = "camelCase")]
+    GatewayApi {
+        gateway_name: String,
+        gateway_namespace: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Serialize)]
 pub struct KubernetesAnnotationsConfig {
     #[serde(default)]
     namespace: BTreeMap<String, String>,
@@ -77,7 +243,7 @@ impl KubernetesAnnotationsConfig {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct KubernetesDownwardApiConfig {
     labels_path: PathBuf,
@@ -97,7 +263,7 @@ impl Default for KubernetesDownwardApiConfig {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct KubernetesStorageConfig {
     #[serde(default = "KubernetesStorageConfig::default_storage_size")]
@@ -131,6 +297,7 @@ impl Default for KubernetesStorageConfig {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
     #[test]
     fn parse_from_minimal_config_as_docker_runtime() {
@@ -140,7 +307,33 @@ mod tests {
 
         let runtime = toml::de::from_str::<Runtime>(runtime_toml).unwrap();
 
-        assert_eq!(runtime, Runtime::Docker);
+        assert_eq!(runtime, Runtime::Docker(Default::default()));
+    }
+
+    #[test]
+    fn parse_as_docker_runtime_with_remote_host() {
+        let runtime_toml = r#"
+        type = 'Docker'
+        host = 'tcp://build-host:2376'
+        [tls]
+        caCert = '/etc/prevant/docker/ca.pem'
+        clientCert = '/etc/prevant/docker/cert.pem'
+        clientKey = '/etc/prevant/docker/key.pem'
+        "#;
+
+        let runtime = toml::de::from_str::<Runtime>(runtime_toml).unwrap();
+
+        let Runtime::Docker(docker) = runtime else {
+            panic!("Expected Docker runtime");
+        };
+        assert_eq!(
+            docker.host(),
+            Some(&Url::parse("tcp://build-host:2376").unwrap())
+        );
+        assert_eq!(
+            docker.tls().map(|tls| tls.client_key()),
+            Some(&PathBuf::from("/etc/prevant/docker/key.pem"))
+        );
     }
 
     #[test]
@@ -154,6 +347,42 @@ mod tests {
         assert_eq!(runtime, Runtime::Kubernetes(Default::default()));
     }
 
+    #[test]
+    fn parse_as_podman_runtime_with_socket() {
+        let runtime_toml = r#"
+        type = 'Podman'
+        socket = '/run/user/1000/podman/podman.sock'
+        "#;
+
+        let runtime = toml::de::from_str::<Runtime>(runtime_toml).unwrap();
+
+        let Runtime::Podman(podman) = runtime else {
+            panic!("Expected Podman runtime");
+        };
+        assert_eq!(
+            podman.socket(),
+            PathBuf::from("/run/user/1000/podman/podman.sock")
+        );
+    }
+
+    #[test]
+    fn parse_as_in_memory_runtime_with_latency_and_failures() {
+        let runtime_toml = r#"
+        type = 'InMemory'
+        latencyMs = 250
+        failingApps = 'broken-.*'
+        "#;
+
+        let runtime = toml::de::from_str::<Runtime>(runtime_toml).unwrap();
+
+        let Runtime::InMemory(in_memory) = runtime else {
+            panic!("Expected in-memory runtime");
+        };
+        assert_eq!(in_memory.latency(), Some(Duration::from_millis(250)));
+        assert!(in_memory.fails_for(&AppName::from_str("broken-login").unwrap()));
+        assert!(!in_memory.fails_for(&AppName::master()));
+    }
+
     #[test]
     fn parse_as_kubernetes_runtime_with_label_downward_path() {
         let runtime_toml = r#"
@@ -253,4 +482,51 @@ mod tests {
             Some(&String::from("rancher-project-id"))
         );
     }
+
+    #[test]
+    fn parse_as_kubernetes_runtime_with_ingress_routing_backend() {
+        let runtime_toml = r#"
+        type = 'Kubernetes'
+        [routingBackend]
+        type = 'ingress'
+        ingressClassName = 'nginx'
+        "#;
+
+        let Runtime::Kubernetes(config) = toml::de::from_str::<Runtime>(runtime_toml).unwrap()
+        else {
+            panic!("Need a K8s config")
+        };
+
+        assert_eq!(
+            config.routing_backend(),
+            &KubernetesRoutingBackend::Ingress {
+                ingress_class_name: Some(String::from("nginx")),
+                annotations: BTreeMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_as_kubernetes_runtime_with_gateway_api_routing_backend() {
+        let runtime_toml = r#"
+        type = 'Kubernetes'
+        [routingBackend]
+        type = 'gatewayApi'
+        gatewayName = 'review-apps'
+        gatewayNamespace = 'gateway'
+        "#;
+
+        let Runtime::Kubernetes(config) = toml::de::from_str::<Runtime>(runtime_toml).unwrap()
+        else {
+            panic!("Need a K8s config")
+        };
+
+        assert_eq!(
+            config.routing_backend(),
+            &KubernetesRoutingBackend::GatewayApi {
+                gateway_name: String::from("review-apps"),
+                gateway_namespace: Some(String::from("gateway")),
+            }
+        );
+    }
 }
@@ -23,25 +23,106 @@
  * THE SOFTWARE.
  * =========================LICENSE_END==================================
  */
-use crate::{config::AppSelector, models::AppName};
+use super::{ConfigError, VaultConfig};
+use crate::{
+    config::{AppSelector, REDACTED},
+    models::AppName,
+};
 use base64::{engine::general_purpose, Engine};
+use k8s_openapi::api::core::v1::Secret as V1Secret;
+use kube::{Api, Client};
 use secstr::SecUtf8;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
 use std::path::PathBuf;
 
 #[derive(Clone, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(try_from = "RawSecret")]
 pub(super) struct Secret {
     name: String,
-    #[serde(deserialize_with = "Secret::parse_secstr", rename = "data")]
-    secret: SecUtf8,
+    source: SecretSource,
+    app_selector: AppSelector,
+    path: Option<PathBuf>,
+    environment_variable: Option<String>,
+}
+
+/// Describes where the value of a [`Secret`] comes from. Only inline data is known at start-up;
+/// all other sources are looked up each time the secret is needed for a deployment.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum SecretSource {
+    #[serde(serialize_with = "SecretSource::redact")]
+    Data(SecUtf8),
+    File(PathBuf),
+    Env(String),
+    KubernetesSecret(KubernetesSecretRef),
+    Vault(VaultSecretRef),
+}
+
+/// References a key of an existing Kubernetes secret. Without namespace, the default namespace of
+/// the cluster configuration is used.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(super) struct KubernetesSecretRef {
+    name: String,
+    key: String,
+    namespace: Option<String>,
+}
+
+/// References a key of a secret stored in HashiCorp Vault's KV secrets engine (version 2).
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(super) struct VaultSecretRef {
+    #[serde(default = "VaultSecretRef::default_mount")]
+    mount: String,
+    path: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSecret {
+    name: String,
+    #[serde(default, deserialize_with = "Secret::parse_secstr")]
+    data: Option<SecUtf8>,
+    file: Option<PathBuf>,
+    env: Option<String>,
+    kubernetes_secret: Option<KubernetesSecretRef>,
+    vault: Option<VaultSecretRef>,
     #[serde(default = "AppSelector::default")]
     app_selector: AppSelector,
     path: Option<PathBuf>,
+    environment_variable: Option<String>,
+}
+
+impl TryFrom<RawSecret> for Secret {
+    type Error = String;
+
+    fn try_from(raw: RawSecret) -> Result<Self, Self::Error> {
+        let mut sources = Vec::with_capacity(1);
+        sources.extend(raw.data.map(SecretSource::Data));
+        sources.extend(raw.file.map(SecretSource::File));
+        sources.extend(raw.env.map(SecretSource::Env));
+        sources.extend(raw.kubernetes_secret.map(SecretSource::KubernetesSecret));
+        sources.extend(raw.vault.map(SecretSource::Vault));
+
+        if sources.len() != 1 {
+            return Err(format!(
+                "Secret {} must define exactly one of data, file, env, kubernetesSecret or vault",
+                raw.name
+            ));
+        }
+
+        Ok(Secret {
+            name: raw.name,
+            source: sources.remove(0),
+            app_selector: raw.app_selector,
+            path: raw.path,
+            environment_variable: raw.environment_variable,
+        })
+    }
 }
 
 impl Secret {
-    fn parse_secstr<'de, D>(deserializer: D) -> Result<SecUtf8, D::Error>
+    fn parse_secstr<'de, D>(deserializer: D) -> Result<Option<SecUtf8>, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -50,29 +131,200 @@ impl Secret {
             .decode(&secret)
             .map_err(de::Error::custom)?;
         let sec_value = String::from_utf8(decoded).map_err(de::Error::custom)?;
-        Ok(SecUtf8::from(sec_value))
+        Ok(Some(SecUtf8::from(sec_value)))
     }
 
     pub fn matches_app_name(&self, app_name: &AppName) -> bool {
         self.app_selector.matches(app_name)
     }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// The name of the environment variable that exposes the secret in addition to the file.
+    pub fn environment_variable(&self) -> Option<&String> {
+        self.environment_variable.as_ref()
+    }
+
+    fn target_path(&self) -> PathBuf {
+        self.path
+            .as_ref()
+            .map(|path| path.join(&self.name))
+            .unwrap_or(PathBuf::from(format!("/run/secrets/{}", &self.name)))
+    }
+
+    /// Looks up the secret value from its source and returns it together with the path the
+    /// secret has to be mounted at.
+    ///
+    /// `kubernetes_client` is the client of the cluster PREvant deploys to, if any, and it is used
+    /// to look up secrets referenced by `kubernetesSecret`.
+    pub async fn resolve(
+        &self,
+        vault: Option<&VaultConfig>,
+        kubernetes_client: Option<&Client>,
+    ) -> Result<(PathBuf, SecUtf8), ConfigError> {
+        let value = self
+            .source
+            .resolve(vault, kubernetes_client)
+            .await
+            .map_err(|error| ConfigError::CannotResolveSecret {
+                name: self.name.clone(),
+                error,
+            })?;
+
+        Ok((self.target_path(), value))
+    }
+}
+
+impl SecretSource {
+    async fn resolve(
+        &self,
+        vault: Option<&VaultConfig>,
+        kubernetes_client: Option<&Client>,
+    ) -> Result<SecUtf8, String> {
+        match self {
+            SecretSource::Data(data) => Ok(data.clone()),
+            SecretSource::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .map(SecUtf8::from)
+                .map_err(|err| format!("Cannot read {}: {}", path.display(), err)),
+            SecretSource::Env(variable) => std::env::var(variable)
+                .map(SecUtf8::from)
+                .map_err(|err| format!("Cannot read environment variable {}: {}", variable, err)),
+            SecretSource::KubernetesSecret(secret_ref) => {
+                secret_ref.resolve(kubernetes_client).await
+            }
+            SecretSource::Vault(secret_ref) => match vault {
+                Some(vault) => secret_ref.resolve(vault).await,
+                None => Err(String::from("There is no Vault configuration")),
+            },
+        }
+    }
+
+    fn redact<S>(_data: &SecUtf8, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl KubernetesSecretRef {
+    /// Reads the secret with the given client or, if PREvant does not deploy to Kubernetes, with
+    /// the client inferred from the environment.
+    async fn resolve(&self, client: Option<&Client>) -> Result<SecUtf8, String> {
+        let client = match client {
+            Some(client) => client.clone(),
+            None => Client::try_default()
+                .await
+                .map_err(|err| format!("Cannot connect to Kubernetes: {}", err))?,
+        };
+        let namespace = self
+            .namespace
+            .clone()
+            .unwrap_or_else(|| client.default_namespace().to_string());
+
+        let secret = Api::<V1Secret>::namespaced(client, &namespace)
+            .get(&self.name)
+            .await
+            .map_err(|err| format!("Cannot read secret {}/{}: {}", namespace, self.name, err))?;
+
+        let data = secret
+            .data
+            .and_then(|mut data| data.remove(&self.key))
+            .ok_or_else(|| {
+                format!(
+                    "Secret {}/{} does not contain the key {}",
+                    namespace, self.name, self.key
+                )
+            })?;
+
+        String::from_utf8(data.0)
+            .map(SecUtf8::from)
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl VaultSecretRef {
+    fn default_mount() -> String {
+        String::from("secret")
+    }
+
+    async fn resolve(&self, vault: &VaultConfig) -> Result<SecUtf8, String> {
+        #[derive(Deserialize)]
+        struct KvResponse {
+            data: KvData,
+        }
+        #[derive(Deserialize)]
+        struct KvData {
+            data: std::collections::HashMap<String, String>,
+        }
+
+        let url = vault
+            .address()
+            .join(&format!(
+                "v1/{}/data/{}",
+                self.mount.trim_matches('/'),
+                self.path.trim_matches('/')
+            ))
+            .map_err(|err| err.to_string())?;
+
+        let response = reqwest::Client::new()
+            .get(url.clone())
+            .header("X-Vault-Token", vault.token().unsecure())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("Cannot read {} from Vault: {}", url, err))?;
+
+        let mut kv = response
+            .json::<KvResponse>()
+            .await
+            .map_err(|err| format!("Invalid Vault response for {}: {}", url, err))?;
+
+        kv.data
+            .data
+            .remove(&self.key)
+            .map(SecUtf8::from)
+            .ok_or_else(|| format!("Vault secret {} does not contain the key {}", url, self.key))
+    }
 }
 
-impl Into<(PathBuf, SecUtf8)> for Secret {
-    fn into(self) -> (PathBuf, SecUtf8) {
-        let name = self.name;
-        (
-            self.path
-                .map(|path| path.join(&name))
-                .unwrap_or(PathBuf::from(format!("/run/secrets/{}", &name))),
-            self.secret,
-        )
+impl Serialize for Secret {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RedactedSecret<'a> {
+            name: &'a str,
+            #[serde(flatten)]
+            source: &'a SecretSource,
+            app_selector: &'a AppSelector,
+            path: PathBuf,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            environment_variable: &'a Option<String>,
+        }
+
+        RedactedSecret {
+            name: &self.name,
+            source: &self.source,
+            app_selector: &self.app_selector,
+            path: self.target_path(),
+            environment_variable: &self.environment_variable,
+        }
+        .serialize(serializer)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use url::Url;
 
     macro_rules! secret_from_str {
         ( $config_str:expr ) => {
@@ -90,7 +342,7 @@ mod tests {
         );
 
         assert_eq!(&secret.name, "user");
-        assert_eq!(&secret.secret.into_unsecure(), "Hello");
+        assert_eq!(secret.source, SecretSource::Data(SecUtf8::from("Hello")));
     }
 
     #[test]
@@ -105,7 +357,83 @@ mod tests {
     }
 
     #[test]
-    fn should_convert_into_pathbuf_and_value() {
+    fn should_not_parse_secret_without_source() {
+        let config_str = r#"
+            name = "user"
+        "#;
+
+        let parse_result = toml::de::from_str::<Secret>(config_str);
+        assert!(parse_result.is_err(), "should not parse secret");
+    }
+
+    #[test]
+    fn should_not_parse_secret_with_multiple_sources() {
+        let config_str = r#"
+            name = "user"
+            data = "SGVsbG8="
+            env = "USER"
+        "#;
+
+        let parse_result = toml::de::from_str::<Secret>(config_str);
+        assert!(parse_result.is_err(), "should not parse secret");
+    }
+
+    #[test]
+    fn should_parse_secret_with_environment_variable() {
+        let secret = secret_from_str!(
+            r#"
+            name = "db-password"
+            data = "SGVsbG8="
+            environmentVariable = "DB_PASSWORD"
+        "#
+        );
+
+        assert_eq!(
+            secret.environment_variable(),
+            Some(&String::from("DB_PASSWORD"))
+        );
+    }
+
+    #[test]
+    fn should_parse_secret_with_kubernetes_secret_reference() {
+        let secret = secret_from_str!(
+            r#"
+            name = "user"
+            kubernetesSecret = { name = "mariadb", key = "user" }
+        "#
+        );
+
+        assert_eq!(
+            secret.source,
+            SecretSource::KubernetesSecret(KubernetesSecretRef {
+                name: String::from("mariadb"),
+                key: String::from("user"),
+                namespace: None,
+            })
+        );
+    }
+
+    #[test]
+    fn should_parse_secret_with_vault_reference_and_default_mount() {
+        let secret = secret_from_str!(
+            r#"
+            name = "user"
+            vault = { path = "mariadb", key = "user" }
+        "#
+        );
+
+        assert_eq!(
+            secret.source,
+            SecretSource::Vault(VaultSecretRef {
+                mount: String::from("secret"),
+                path: String::from("mariadb"),
+                key: String::from("user"),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_resolve_into_pathbuf_and_value() {
         let secret = secret_from_str!(
             r#"
             name = "user"
@@ -113,14 +441,14 @@ mod tests {
         "#
         );
 
-        let (path, v) = secret.into();
+        let (path, v) = secret.resolve(None, None).await.unwrap();
 
         assert_eq!(path.as_os_str().to_str().unwrap(), "/run/secrets/user");
         assert_eq!(&v.into_unsecure(), "Hello");
     }
 
-    #[test]
-    fn should_convert_into_pathbuf_and_value_with_path() {
+    #[tokio::test]
+    async fn should_resolve_into_pathbuf_and_value_with_path() {
         let secret = secret_from_str!(
             r#"
             name = "user"
@@ -129,9 +457,113 @@ mod tests {
         "#
         );
 
-        let (path, v) = secret.into();
+        let (path, v) = secret.resolve(None, None).await.unwrap();
 
         assert_eq!(path.as_os_str().to_str().unwrap(), "/opt/user");
         assert_eq!(&v.into_unsecure(), "Hello");
     }
+
+    #[tokio::test]
+    async fn should_resolve_value_from_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "Hello").unwrap();
+
+        let secret = secret_from_str!(&format!(
+            r#"
+            name = "user"
+            file = "{}"
+            "#,
+            file.path().display()
+        ));
+
+        let (_path, v) = secret.resolve(None, None).await.unwrap();
+
+        assert_eq!(&v.into_unsecure(), "Hello");
+    }
+
+    #[tokio::test]
+    async fn should_resolve_value_from_env() {
+        std::env::set_var("PREVANT_TEST_SECRET_USER", "Hello");
+        let secret = secret_from_str!(
+            r#"
+            name = "user"
+            env = "PREVANT_TEST_SECRET_USER"
+        "#
+        );
+
+        let (_path, v) = secret.resolve(None, None).await.unwrap();
+
+        assert_eq!(&v.into_unsecure(), "Hello");
+    }
+
+    #[tokio::test]
+    async fn should_not_resolve_value_from_missing_env() {
+        let secret = secret_from_str!(
+            r#"
+            name = "user"
+            env = "PREVANT_TEST_SECRET_THAT_DOES_NOT_EXIST"
+        "#
+        );
+
+        assert!(secret.resolve(None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_not_resolve_value_from_vault_without_vault_config() {
+        let secret = secret_from_str!(
+            r#"
+            name = "user"
+            vault = { path = "mariadb", key = "user" }
+        "#
+        );
+
+        assert!(secret.resolve(None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_resolve_value_from_vault() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let len = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..len]).to_string();
+
+            let body = r#"{ "data": { "data": { "user": "Hello" }, "metadata": {} } }"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+
+            request
+        });
+
+        let vault = toml::de::from_str::<VaultConfig>(&format!(
+            r#"
+            address = "{}"
+            token = "root"
+            "#,
+            address
+        ))
+        .unwrap();
+        let secret = secret_from_str!(
+            r#"
+            name = "user"
+            vault = { path = "mariadb", key = "user" }
+        "#
+        );
+
+        let (_path, v) = secret.resolve(Some(&vault), None).await.unwrap();
+
+        assert_eq!(&v.into_unsecure(), "Hello");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /v1/secret/data/mariadb "));
+        assert!(request.to_lowercase().contains("x-vault-token: root"));
+    }
 }
@@ -0,0 +1,144 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::Routing;
+use crate::infrastructure::TraefikTLS;
+use crate::models::AppName;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Defines how the certificates of the review apps' routes are issued.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Tls {
+    /// Traefik requests the certificates from one of its
+    /// [cert resolvers](https://doc.traefik.io/traefik/https/acme/), e.g. Let's Encrypt.
+    CertResolver { name: String },
+    /// [cert-manager](https://cert-manager.io/) issues a certificate per app (Kubernetes only).
+    #[serde(rename_all = "camelCase")]
+    CertManager {
+        issuer_name: String,
+        #[serde(default = "Tls::default_issuer_kind")]
+        issuer_kind: String,
+    },
+    /// PREvant signs a certificate per app with its own self-signed CA and stores it in
+    /// `directory` which must be watched by Traefik's file provider (Docker only).
+    SelfSigned { directory: PathBuf },
+}
+
+impl Tls {
+    fn default_issuer_kind() -> String {
+        String::from("ClusterIssuer")
+    }
+
+    /// The name of the Kubernetes secret that holds the certificate of the app.
+    pub fn secret_name(app_name: &AppName) -> String {
+        format!("{}-tls", app_name.to_rfc1123_namespace_id())
+    }
+
+    pub fn traefik_tls(&self, app_name: &AppName, routing: &Routing) -> TraefikTLS {
+        let domains = routing.tls_domains(app_name);
+        match self {
+            Tls::CertResolver { name } => TraefikTLS {
+                cert_resolver: Some(name.clone()),
+                secret_name: None,
+                domains,
+            },
+            Tls::CertManager { .. } => TraefikTLS {
+                cert_resolver: None,
+                secret_name: Some(Self::secret_name(app_name)),
+                domains,
+            },
+            Tls::SelfSigned { .. } => TraefikTLS {
+                cert_resolver: None,
+                secret_name: None,
+                domains,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_from_str;
+
+    #[test]
+    fn should_parse_cert_manager_config_with_default_issuer_kind() {
+        let config = config_from_str!(
+            r#"
+            [tls]
+            type = 'certManager'
+            issuerName = 'letsencrypt'
+            "#
+        );
+
+        assert_eq!(
+            config.tls(),
+            Some(&Tls::CertManager {
+                issuer_name: String::from("letsencrypt"),
+                issuer_kind: String::from("ClusterIssuer"),
+            })
+        );
+    }
+
+    #[test]
+    fn should_request_wildcard_certificate_from_cert_resolver() {
+        let config = config_from_str!(
+            r#"
+            [routing]
+            mode = 'host'
+            domain = 'review.example.com'
+
+            [tls]
+            type = 'certResolver'
+            name = 'letsencrypt'
+            "#
+        );
+
+        let tls = config
+            .tls()
+            .unwrap()
+            .traefik_tls(&AppName::master(), config.routing());
+
+        assert_eq!(tls.cert_resolver, Some(String::from("letsencrypt")));
+        assert_eq!(tls.secret_name, None);
+        assert_eq!(tls.domains[0].main, "master.review.example.com");
+        assert_eq!(tls.domains[0].sans, vec!["*.master.review.example.com"]);
+    }
+
+    #[test]
+    fn should_refer_to_secret_of_cert_manager() {
+        let tls = Tls::CertManager {
+            issuer_name: String::from("letsencrypt"),
+            issuer_kind: String::from("Issuer"),
+        }
+        .traefik_tls(&AppName::master(), &Routing::Path);
+
+        assert_eq!(tls.cert_resolver, None);
+        assert_eq!(tls.secret_name, Some(String::from("master-tls")));
+        assert!(tls.domains.is_empty());
+    }
+}
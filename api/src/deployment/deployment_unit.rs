@@ -24,20 +24,30 @@
  * =========================LICENSE_END==================================
  */
 use crate::apps::AppsServiceError;
-use crate::config::{Config, StorageStrategy};
+use crate::config::{Config, Routing, StorageStrategy};
 use crate::deployment::hooks::Hooks;
-use crate::infrastructure::TraefikIngressRoute;
+use crate::infrastructure::{
+    TraefikIngressRoute, TraefikMiddleware, TraefikRouterRule, TraefikTLS,
+};
 use crate::models::{AppName, ContainerType, Image, ServiceConfig};
 use crate::registry::ImageInfo;
-use std::collections::{HashMap, HashSet};
+use kube::Client as KubeClient;
+use log::warn;
+use serde_value::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
 
 pub struct Initialized {
     app_name: AppName,
     configs: Vec<ServiceConfig>,
+    kubernetes_client: Option<KubeClient>,
 }
 
 pub struct WithCompanions {
     app_name: AppName,
+    routing: Routing,
+    tls: Option<TraefikTLS>,
+    middlewares: Vec<TraefikMiddleware>,
     configs: Vec<ServiceConfig>,
     service_companions: Vec<(
         ServiceConfig,
@@ -53,6 +63,9 @@ pub struct WithCompanions {
 
 pub struct WithTemplatedConfigs {
     app_name: AppName,
+    routing: Routing,
+    tls: Option<TraefikTLS>,
+    middlewares: Vec<TraefikMiddleware>,
     configs: Vec<ServiceConfig>,
     service_companions: Vec<(
         ServiceConfig,
@@ -69,6 +82,9 @@ pub struct WithTemplatedConfigs {
 
 pub struct WithResolvedImages {
     app_name: AppName,
+    routing: Routing,
+    tls: Option<TraefikTLS>,
+    middlewares: Vec<TraefikMiddleware>,
     configs: Vec<ServiceConfig>,
     service_companions: Vec<(
         ServiceConfig,
@@ -86,11 +102,17 @@ pub struct WithResolvedImages {
 
 pub struct WithAppliedTemplating {
     app_name: AppName,
+    routing: Routing,
+    tls: Option<TraefikTLS>,
+    middlewares: Vec<TraefikMiddleware>,
     services: Vec<DeployableService>,
 }
 
 pub struct WithAppliedHooks {
     app_name: AppName,
+    routing: Routing,
+    tls: Option<TraefikTLS>,
+    middlewares: Vec<TraefikMiddleware>,
     services: Vec<DeployableService>,
 }
 
@@ -180,6 +202,40 @@ impl DeploymentUnit {
     pub fn app_base_route(&self) -> &TraefikIngressRoute {
         &self.route
     }
+
+    /// Returns the services of this deployment unit that `service` depends on.
+    pub fn dependencies_of(&self, service: &DeployableService) -> Vec<&DeployableService> {
+        self.services
+            .iter()
+            .filter(|other| service.depends_on().contains(other.service_name()))
+            .collect()
+    }
+
+    /// Groups the services into stages that can be started concurrently: each service belongs
+    /// to a later stage than all of its dependencies.
+    pub fn startup_stages(&self) -> Vec<Vec<&DeployableService>> {
+        let mut stage_indices = HashMap::<&str, usize>::new();
+        let mut stages: Vec<Vec<&DeployableService>> = Vec::new();
+
+        // The services are ordered by their dependencies already.
+        for service in &self.services {
+            let index = self
+                .dependencies_of(service)
+                .iter()
+                .filter_map(|dependency| stage_indices.get(dependency.service_name().as_str()))
+                .map(|index| index + 1)
+                .max()
+                .unwrap_or(0);
+
+            stage_indices.insert(service.service_name(), index);
+            if stages.len() <= index {
+                stages.resize_with(index + 1, Vec::new);
+            }
+            stages[index].push(service);
+        }
+
+        stages
+    }
 }
 
 impl DeploymentUnitBuilder<Initialized> {
@@ -188,28 +244,70 @@ impl DeploymentUnitBuilder<Initialized> {
         configs: Vec<ServiceConfig>,
     ) -> DeploymentUnitBuilder<Initialized> {
         DeploymentUnitBuilder {
-            stage: Initialized { app_name, configs },
+            stage: Initialized {
+                app_name,
+                configs,
+                kubernetes_client: None,
+            },
         }
     }
+
+    /// Sets the client of the cluster the app is deployed to, which is used to resolve secrets
+    /// stored in Kubernetes.
+    pub fn with_kubernetes_client(mut self, kubernetes_client: Option<KubeClient>) -> Self {
+        self.stage.kubernetes_client = kubernetes_client;
+        self
+    }
 }
 
 impl DeploymentUnitBuilder<Initialized> {
-    pub fn extend_with_config(mut self, config: &Config) -> DeploymentUnitBuilder<WithCompanions> {
+    pub async fn extend_with_config(
+        mut self,
+        config: &Config,
+    ) -> Result<DeploymentUnitBuilder<WithCompanions>, AppsServiceError> {
         for service_config in self.stage.configs.iter_mut() {
-            config.add_secrets_to(service_config, &self.stage.app_name);
+            config
+                .add_secrets_to(
+                    service_config,
+                    &self.stage.app_name,
+                    self.stage.kubernetes_client.as_ref(),
+                )
+                .await?;
         }
 
         let service_companions = config.service_companion_configs(&self.stage.app_name);
         let app_companions = config.application_companion_configs(&self.stage.app_name);
+        let tls = config
+            .tls()
+            .map(|tls| tls.traefik_tls(&self.stage.app_name, config.routing()));
+        // Hashing the basic auth passwords with bcrypt is expensive and must not block the
+        // runtime.
+        let app_name = self.stage.app_name.clone();
+        let restrictions = config
+            .access_restrictions(&app_name)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let middlewares = tokio::task::spawn_blocking(move || {
+            restrictions
+                .iter()
+                .map(|restriction| restriction.traefik_middleware(&app_name))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(failure::Error::from)?;
 
-        DeploymentUnitBuilder {
+        Ok(DeploymentUnitBuilder {
             stage: WithCompanions {
                 app_name: self.stage.app_name,
+                routing: config.routing().clone(),
+                tls,
+                middlewares,
                 configs: self.stage.configs,
                 service_companions,
                 app_companions,
             },
-        }
+        })
     }
 }
 
@@ -221,6 +319,9 @@ impl DeploymentUnitBuilder<WithCompanions> {
         DeploymentUnitBuilder {
             stage: WithTemplatedConfigs {
                 app_name: self.stage.app_name,
+                routing: self.stage.routing,
+                tls: self.stage.tls,
+                middlewares: self.stage.middlewares,
                 configs: self.stage.configs,
                 service_companions: self.stage.service_companions,
                 app_companions: self.stage.app_companions,
@@ -289,6 +390,9 @@ impl DeploymentUnitBuilder<WithTemplatedConfigs> {
         DeploymentUnitBuilder {
             stage: WithResolvedImages {
                 app_name: self.stage.app_name,
+                routing: self.stage.routing,
+                tls: self.stage.tls,
+                middlewares: self.stage.middlewares,
                 configs: self.stage.configs,
                 service_companions: self.stage.service_companions,
                 app_companions: self.stage.app_companions,
@@ -326,10 +430,7 @@ impl DeploymentUnitBuilder<WithResolvedImages> {
                 DeployableService {
                     raw_service_config: templated_config,
                     strategy: DeploymentStrategy::RedeployAlways,
-                    ingress_route: TraefikIngressRoute::with_defaults(
-                        &self.stage.app_name,
-                        config.service_name(),
-                    ),
+                    ingress_route: self.service_ingress_route(config.service_name()),
                     declared_volumes: Vec::new(),
                 },
             );
@@ -445,17 +546,56 @@ impl DeploymentUnitBuilder<WithResolvedImages> {
         strategies.sort_unstable_by(|a, b| {
             let index1 = Self::container_type_index(a.container_type());
             let index2 = Self::container_type_index(b.container_type());
-            index1.cmp(&index2)
+            index1
+                .cmp(&index2)
+                .then_with(|| a.service_name().cmp(b.service_name()))
         });
 
         Ok(DeploymentUnitBuilder {
             stage: WithAppliedTemplating {
                 app_name: self.stage.app_name,
-                services: strategies,
+                routing: self.stage.routing,
+                tls: self.stage.tls,
+                middlewares: self.stage.middlewares,
+                services: Self::sort_by_dependencies(strategies)?,
             },
         })
     }
 
+    /// Orders the services so that every service comes after the services it depends on while
+    /// keeping the order by container type as far as possible. Dependencies on services that are
+    /// not part of the deployment are ignored because these services are running already.
+    fn sort_by_dependencies(
+        mut services: Vec<DeployableService>,
+    ) -> Result<Vec<DeployableService>, AppsServiceError> {
+        let mut sorted = Vec::with_capacity(services.len());
+
+        while !services.is_empty() {
+            let next = services.iter().position(|service| {
+                service.depends_on().iter().all(|dependency| {
+                    !services
+                        .iter()
+                        .any(|other| other.service_name() == dependency)
+                })
+            });
+
+            match next {
+                Some(index) => sorted.push(services.remove(index)),
+                None => {
+                    return Err(AppsServiceError::CyclicServiceDependencies {
+                        services: services
+                            .iter()
+                            .map(|service| service.service_name().as_str())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    })
+                }
+            }
+        }
+
+        Ok(sorted)
+    }
+
     fn deployable_service(
         &self,
         raw_service_config: ServiceConfig,
@@ -463,10 +603,7 @@ impl DeploymentUnitBuilder<WithResolvedImages> {
         storage_strategy: &StorageStrategy,
         image_infos: &HashMap<Image, ImageInfo>,
     ) -> DeployableService {
-        let ingress_route = TraefikIngressRoute::with_defaults(
-            &self.stage.app_name,
-            raw_service_config.service_name(),
-        );
+        let ingress_route = self.service_ingress_route(&raw_service_config);
 
         let volume_paths = match image_infos.get(raw_service_config.image()) {
             None => Vec::new(),
@@ -516,6 +653,54 @@ impl DeploymentUnitBuilder<WithResolvedImages> {
         }
     }
 
+    /// The route of the service which considers the `router` and `middlewares` that companions
+    /// might have defined for it.
+    fn service_ingress_route(&self, service_config: &ServiceConfig) -> TraefikIngressRoute {
+        let app_name = &self.stage.app_name;
+        let service_name = service_config.service_name();
+
+        let rule = service_config.router().and_then(|router| {
+            match TraefikRouterRule::from_str(router.rule()) {
+                Ok(rule) => Some(rule),
+                Err(err) => {
+                    warn!(
+                        "Cannot use the router rule {} of {service_name} in {app_name}: {err}",
+                        router.rule()
+                    );
+                    None
+                }
+            }
+        });
+        let mut route = match rule {
+            Some(rule) => TraefikIngressRoute::with_rule(rule),
+            None => self
+                .stage
+                .routing
+                .service_ingress_route(app_name, service_name),
+        };
+
+        if let Some(middlewares) = service_config.middlewares() {
+            let middlewares = middlewares
+                .iter()
+                .map(|(kind, spec)| {
+                    let mut middleware = BTreeMap::new();
+                    middleware.insert(Value::String(kind.clone()), spec.clone());
+                    TraefikMiddleware::Spec {
+                        name: format!("{app_name}-{service_name}-{}", kind.to_lowercase()),
+                        spec: Value::Map(middleware),
+                    }
+                })
+                .collect::<Vec<_>>();
+            route.append_middlewares(&middlewares);
+        }
+
+        if let Some(tls) = &self.stage.tls {
+            route.set_tls(tls.clone());
+        }
+        route.prepend_middlewares(&self.stage.middlewares);
+        route
+    }
+
     fn container_type_index(container_type: &ContainerType) -> i32 {
         match container_type {
             ContainerType::ApplicationCompanion => 0,
@@ -538,6 +723,9 @@ impl DeploymentUnitBuilder<WithAppliedTemplating> {
         Ok(DeploymentUnitBuilder {
             stage: WithAppliedHooks {
                 app_name: self.stage.app_name,
+                routing: self.stage.routing,
+                tls: self.stage.tls,
+                middlewares: self.stage.middlewares,
                 services,
             },
         })
@@ -549,15 +737,19 @@ impl DeploymentUnitBuilder<WithAppliedHooks> {
         mut self,
         route: TraefikIngressRoute,
     ) -> DeploymentUnitBuilder<WithAppliedIngressRoute> {
+        // The rules of the base route point to PREvant itself and, thus, they cannot be combined
+        // with the host of each service.
+        let mut route = match &self.stage.routing {
+            Routing::Path => route,
+            Routing::Host(_) => route.without_routes(),
+        };
+
         for service in &mut self.stage.services {
             let service_route = std::mem::replace(&mut service.ingress_route, route.clone());
             service.ingress_route.merge_with(service_route);
         }
 
-        let mut route = route;
-        route.merge_with(TraefikIngressRoute::with_app_only_defaults(
-            &self.stage.app_name,
-        ));
+        route.merge_with(self.app_ingress_route());
 
         DeploymentUnitBuilder {
             stage: WithAppliedIngressRoute {
@@ -569,13 +761,22 @@ impl DeploymentUnitBuilder<WithAppliedHooks> {
     }
 
     pub fn build(self) -> DeploymentUnit {
-        let route = TraefikIngressRoute::with_app_only_defaults(&self.stage.app_name);
+        let route = self.app_ingress_route();
         DeploymentUnit {
             app_name: self.stage.app_name,
             services: self.stage.services,
             route,
         }
     }
+
+    fn app_ingress_route(&self) -> TraefikIngressRoute {
+        let mut route = self.stage.routing.app_ingress_route(&self.stage.app_name);
+        if let Some(tls) = &self.stage.tls {
+            route.set_tls(tls.clone());
+        }
+        route.prepend_middlewares(&self.stage.middlewares);
+        route
+    }
 }
 
 impl DeploymentUnitBuilder<WithAppliedIngressRoute> {
@@ -591,10 +792,10 @@ impl DeploymentUnitBuilder<WithAppliedIngressRoute> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::infrastructure::TraefikRouterRule;
     use crate::models::{Environment, EnvironmentVariable};
     use crate::{config_from_str, sc};
     use secstr::SecUtf8;
+    use url::Url;
 
     #[tokio::test]
     async fn should_return_unique_images() -> Result<(), AppsServiceError> {
@@ -607,6 +808,7 @@ mod tests {
             ],
         )
         .extend_with_config(&config)
+        .await?
         .extend_with_templating_only_service_configs(Vec::new())
         .images();
 
@@ -636,6 +838,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -674,6 +877,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -729,6 +933,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -776,6 +981,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -820,6 +1026,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -855,6 +1062,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_configs])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -894,6 +1102,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -948,6 +1157,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(vec![sc!("postgres", "postgres:alpine")])
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -1002,6 +1212,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -1054,6 +1265,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -1105,6 +1317,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -1150,6 +1363,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -1180,6 +1394,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -1215,4 +1430,271 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn apply_base_traefik_ingress_route_with_host_routing() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [routing]
+            mode = 'host'
+            domain = 'review.example.com'
+            "#
+        );
+
+        let unit = DeploymentUnitBuilder::init(AppName::master(), vec![sc!("wordpress")])
+            .extend_with_config(&config)
+            .await?
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating()?
+            .apply_hooks(&config)
+            .await?
+            .apply_base_traefik_ingress_route(TraefikIngressRoute::with_existing_routing_rules(
+                vec![String::from("websecure")],
+                TraefikRouterRule::from_str("Host(`prevant.example.com`) && PathPrefix(`/`)")
+                    .unwrap(),
+                Vec::new(),
+                None,
+            ))
+            .build();
+
+        let service = unit.services().iter().next().unwrap();
+        assert_eq!(
+            service.ingress_route().to_url(),
+            Url::parse("https://wordpress.master.review.example.com").ok()
+        );
+        assert!(service
+            .ingress_route()
+            .routes()
+            .iter()
+            .all(|r| r.middlewares().is_empty()));
+        assert_eq!(
+            unit.app_base_route().to_url(),
+            Url::parse("https://master.review.example.com").ok()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_apply_tls_to_all_routes() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [tls]
+            type = 'certResolver'
+            name = 'letsencrypt'
+            "#
+        );
+
+        let unit = DeploymentUnitBuilder::init(AppName::master(), vec![sc!("wordpress")])
+            .extend_with_config(&config)
+            .await?
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating()?
+            .apply_hooks(&config)
+            .await?
+            .apply_base_traefik_ingress_route(TraefikIngressRoute::with_existing_routing_rules(
+                vec![String::from("web")],
+                TraefikRouterRule::from_str("Host(`prevant.example.com`)").unwrap(),
+                Vec::new(),
+                Some(String::from("base-resolver")),
+            ))
+            .build();
+
+        let service = unit.services().iter().next().unwrap();
+        assert_eq!(
+            service
+                .ingress_route()
+                .tls()
+                .as_ref()
+                .and_then(|tls| tls.cert_resolver.clone()),
+            Some(String::from("letsencrypt"))
+        );
+        assert_eq!(
+            unit.app_base_route().to_url(),
+            Url::parse("https://prevant.example.com/master/").ok()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_restrict_access_to_all_routes() -> Result<(), AppsServiceError> {
+        let config = config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'ipAllowList'
+            sourceRange = ['10.0.0.0/8']
+            "#
+        );
+
+        let unit = DeploymentUnitBuilder::init(AppName::master(), vec![sc!("wordpress")])
+            .extend_with_config(&config)
+            .await?
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating()?
+            .apply_hooks(&config)
+            .await?
+            .apply_base_traefik_ingress_route(TraefikIngressRoute::with_existing_routing_rules(
+                vec![String::from("web")],
+                TraefikRouterRule::from_str("Host(`prevant.example.com`)").unwrap(),
+                vec![String::from("traefik-forward-auth")],
+                None,
+            ))
+            .build();
+
+        let middleware_names = |route: &TraefikIngressRoute| {
+            route.routes()[0]
+                .middlewares()
+                .iter()
+                .map(|middleware| match middleware {
+                    TraefikMiddleware::Ref(name) => name.clone(),
+                    TraefikMiddleware::Spec { name, .. } => name.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let service = unit.services().iter().next().unwrap();
+        assert_eq!(
+            middleware_names(service.ingress_route()),
+            vec![
+                "traefik-forward-auth",
+                "master-ip-allow-list",
+                "master-wordpress-middleware"
+            ]
+        );
+        assert_eq!(
+            middleware_names(unit.app_base_route()),
+            vec![
+                "traefik-forward-auth",
+                "master-ip-allow-list",
+                "master-middleware"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_apply_router_and_middlewares_of_companions() -> Result<(), AppsServiceError> {
+        let mut wordpress = sc!("wordpress");
+        wordpress.set_router(crate::models::Router::new(
+            String::from("PathPrefix(`/master/blog/`)"),
+            None,
+        ));
+        let mut headers = BTreeMap::new();
+        headers.insert(
+            Value::String(String::from("customRequestHeaders")),
+            Value::Map(BTreeMap::from([(
+                Value::String(String::from("X-Forwarded-Prefix")),
+                Value::String(String::from("/master/blog")),
+            )])),
+        );
+        wordpress.set_middlewares(BTreeMap::from([(
+            String::from("headers"),
+            Value::Map(headers.clone()),
+        )]));
+
+        let config = Config::default();
+        let unit = DeploymentUnitBuilder::init(AppName::master(), vec![wordpress])
+            .extend_with_config(&config)
+            .await?
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating()?
+            .apply_hooks(&config)
+            .await?
+            .apply_base_traefik_ingress_route(TraefikIngressRoute::empty())
+            .build();
+
+        let service = unit.services().iter().next().unwrap();
+        let route = &service.ingress_route().routes()[0];
+        assert_eq!(
+            route.rule(),
+            &TraefikRouterRule::from_str("PathPrefix(`/master/blog/`)").unwrap()
+        );
+        assert_eq!(
+            route.middlewares(),
+            &vec![TraefikMiddleware::Spec {
+                name: String::from("master-wordpress-headers"),
+                spec: Value::Map(BTreeMap::from([(
+                    Value::String(String::from("headers")),
+                    Value::Map(headers),
+                )])),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_order_services_by_dependencies() -> Result<(), AppsServiceError> {
+        let mut wordpress = sc!("wordpress", "wordpress:alpine");
+        wordpress.set_depends_on(Some(vec![String::from("wordpress-db")]));
+        let mut wordpress_db = sc!("wordpress-db", "postgres:11-alpine");
+        wordpress_db.set_depends_on(Some(vec![String::from("already-running")]));
+        let adminer = sc!("adminer", "adminer");
+
+        let config = Config::default();
+
+        let unit =
+            DeploymentUnitBuilder::init(AppName::master(), vec![wordpress, adminer, wordpress_db])
+                .extend_with_config(&config)
+                .await?
+                .extend_with_templating_only_service_configs(Vec::new())
+                .extend_with_image_infos(HashMap::new())
+                .apply_templating()?
+                .apply_hooks(&config)
+                .await?
+                .build();
+
+        let names = unit
+            .services()
+            .iter()
+            .map(|service| service.service_name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["adminer", "wordpress-db", "wordpress"]);
+
+        let stages = unit
+            .startup_stages()
+            .into_iter()
+            .map(|stage| {
+                stage
+                    .iter()
+                    .map(|service| service.service_name().as_str())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            vec![vec!["adminer", "wordpress-db"], vec!["wordpress"]]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reject_cyclic_dependencies() -> Result<(), AppsServiceError> {
+        let mut wordpress = sc!("wordpress", "wordpress:alpine");
+        wordpress.set_depends_on(Some(vec![String::from("wordpress-db")]));
+        let mut wordpress_db = sc!("wordpress-db", "postgres:11-alpine");
+        wordpress_db.set_depends_on(Some(vec![String::from("wordpress")]));
+
+        let result = DeploymentUnitBuilder::init(AppName::master(), vec![wordpress, wordpress_db])
+            .extend_with_config(&Config::default())
+            .await?
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating();
+
+        assert!(matches!(
+            result,
+            Err(AppsServiceError::CyclicServiceDependencies { services })
+                if services == "wordpress, wordpress-db"
+        ));
+
+        Ok(())
+    }
 }
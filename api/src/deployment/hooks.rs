@@ -270,6 +270,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, service_configs)
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -322,6 +323,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -360,6 +362,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -407,6 +410,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -457,6 +461,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -497,6 +502,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -533,6 +539,7 @@ mod tests {
 
         let unit = DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -560,6 +567,7 @@ mod tests {
         let app_name = AppName::master();
         match DeploymentUnitBuilder::init(app_name, vec![service_config])
             .extend_with_config(&config)
+            .await?
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()?
@@ -24,16 +24,22 @@
  * =========================LICENSE_END==================================
  */
 
-use crate::config::{Config, ContainerConfig};
+use super::docker_host::{DockerHost, DockerHostError};
+use crate::config::{Config, ContainerConfig, Runtime, Tls};
 use crate::deployment::deployment_unit::{DeployableService, DeploymentStrategy};
 use crate::deployment::DeploymentUnit;
+use crate::infrastructure::self_signed_certificates::{
+    issue_app_certificate, remove_app_certificate,
+};
 use crate::infrastructure::{
-    Infrastructure, APP_NAME_LABEL, CONTAINER_TYPE_LABEL, IMAGE_LABEL, REPLICATED_ENV_LABEL,
-    SERVICE_NAME_LABEL, STATUS_ID,
+    Infrastructure, TraefikIngressRoute, TraefikMiddleware, ADDITIONAL_CONTAINER_LABEL,
+    APP_NAME_LABEL, CONTAINER_INDEX_LABEL, CONTAINER_TYPE_LABEL, FILES_LABEL, IMAGE_LABEL,
+    PARENT_SERVICE_LABEL, REPLICATED_ENV_LABEL, SERVICE_NAME_LABEL, SIDECAR_LABEL, STATUS_ID,
 };
 use crate::models::service::{ContainerType, Service, ServiceError, ServiceStatus};
 use crate::models::{
-    AppName, Environment, Image, ServiceBuilder, ServiceBuilderError, ServiceConfig,
+    AdditionalContainer, AppName, Environment, ExecOutput, ExecSession, Image, LogStreams,
+    PortProtocol, ServiceBuilder, ServiceBuilderError, ServiceConfig,
 };
 use async_stream::stream;
 use async_trait::async_trait;
@@ -50,17 +56,23 @@ use shiplift::tty::TtyChunk;
 use shiplift::volume::VolumeInfo;
 use shiplift::{
     ContainerConnectionOptions, ContainerFilter, ContainerListOptions, ContainerOptions, Docker,
-    LogsOptions, NetworkCreateOptions, PullOptions, RegistryAuth, VolumeCreateOptions,
+    Exec, ExecContainerOptions, LogsOptions, NetworkCreateOptions, PullOptions, RegistryAuth,
+    VolumeCreateOptions,
 };
 use std::collections::HashMap;
 use std::convert::{From, TryFrom};
+use std::io::Read;
 use std::net::{AddrParseError, IpAddr};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 static CONTAINER_PORT_LABEL: &str = "traefik.port";
+static DEPENDENCY_READINESS_TIMEOUT: Duration = Duration::from_secs(300);
 
 pub struct DockerInfrastructure {
     config: Config,
+    host: DockerHost,
 }
 
 #[derive(Debug, Fail, PartialEq)]
@@ -85,11 +97,33 @@ pub enum DockerInfrastructureError {
     UnknownServiceType { unknown_label: String },
     #[fail(display = "Unexpected container address: {}", internal_message)]
     InvalidContainerAddress { internal_message: String },
+    #[fail(
+        display = "The init container {} of service {} exited with status code {}.",
+        container_name, service_name, status_code
+    )]
+    InitContainerFailed {
+        container_name: String,
+        service_name: String,
+        status_code: u64,
+    },
+    #[fail(
+        display = "The service {} did not become ready to be used by its dependents.",
+        service_name
+    )]
+    ServiceNotReady { service_name: String },
 }
 
 impl DockerInfrastructure {
-    pub fn new(config: Config) -> Self {
-        Self { config }
+    pub fn new(config: Config) -> Result<Self, DockerHostError> {
+        let host = match config.runtime_config() {
+            Runtime::Docker(docker_config) => DockerHost::new(docker_config)?,
+            _ => DockerHost::Default,
+        };
+        Ok(Self { config, host })
+    }
+
+    fn docker(&self) -> Docker {
+        self.host.docker()
     }
 
     async fn find_status_change_container(
@@ -122,7 +156,7 @@ impl DockerInfrastructure {
 
         let image = Image::from_str("docker.io/library/busybox:stable").unwrap();
 
-        pull(&image, &self.config).await?;
+        pull(&self.docker(), &image, &self.config).await?;
 
         let mut labels: HashMap<&str, &str> = HashMap::new();
         labels.insert(APP_NAME_LABEL, app_name);
@@ -131,7 +165,7 @@ impl DockerInfrastructure {
         let mut options = ContainerOptions::builder(&image.to_string());
         options.labels(&labels);
 
-        let docker = Docker::new();
+        let docker = self.docker();
         let containers = docker.containers();
 
         trace!(
@@ -151,7 +185,7 @@ impl DockerInfrastructure {
 
         let network_name = format!("{}-net", app_name);
 
-        let docker = Docker::new();
+        let docker = self.docker();
         let network_id = docker
             .networks()
             .list(&Default::default())
@@ -180,7 +214,7 @@ impl DockerInfrastructure {
     }
 
     async fn connect_traefik(&self, network_id: &String) -> Result<(), ShipLiftError> {
-        let docker = Docker::new();
+        let docker = self.docker();
 
         let containers = docker
             .containers()
@@ -206,7 +240,7 @@ impl DockerInfrastructure {
     }
 
     async fn disconnect_traefik(&self, network_id: &String) -> Result<(), ShipLiftError> {
-        let docker = Docker::new();
+        let docker = self.docker();
 
         let containers = docker
             .containers()
@@ -231,7 +265,7 @@ impl DockerInfrastructure {
     async fn delete_network(&self, app_name: &String) -> Result<(), ShipLiftError> {
         let network_name = format!("{}-net", app_name);
 
-        let docker = Docker::new();
+        let docker = self.docker();
         for n in docker
             .networks()
             .list(&Default::default())
@@ -247,9 +281,9 @@ impl DockerInfrastructure {
     }
 
     async fn delete_volume_mount(&self, app_name: &String) -> Result<(), ShipLiftError> {
-        let docker = Docker::new();
+        let docker = self.docker();
         let docker_volumes = docker.volumes();
-        for volume in DockerInfrastructure::fetch_existing_volumes(app_name).await? {
+        for volume in self.fetch_existing_volumes(app_name).await? {
             docker_volumes.get(&volume.name).delete().await?
         }
         Ok(())
@@ -265,28 +299,92 @@ impl DockerInfrastructure {
         let network_id = self.create_or_get_network_id(app_name).await?;
 
         self.connect_traefik(&network_id).await?;
-        let existing_volumes = DockerInfrastructure::fetch_existing_volumes(app_name).await?;
-        let futures = services
-            .iter()
-            .map(|service| {
-                self.start_container(
-                    app_name,
-                    &network_id,
-                    service,
-                    container_config,
-                    &existing_volumes,
-                )
-            })
-            .collect::<Vec<_>>();
+        if let Some(Tls::SelfSigned { directory }) = self.config.tls() {
+            let mut dns_names = deployment_unit.app_base_route().certificate_dns_names();
+            if dns_names.is_empty() {
+                dns_names.push(String::from("localhost"));
+            }
+            issue_app_certificate(directory, app_name, dns_names).await?;
+        }
+
+        let existing_volumes = self.fetch_existing_volumes(app_name).await?;
+
+        let mut services: Vec<Service> = Vec::with_capacity(deployment_unit.services().len());
+        for stage in deployment_unit.startup_stages() {
+            let futures = stage
+                .iter()
+                .map(|service| {
+                    self.start_container(
+                        app_name,
+                        &network_id,
+                        service,
+                        container_config,
+                        &existing_volumes,
+                    )
+                })
+                .collect::<Vec<_>>();
 
-        let mut services: Vec<Service> = Vec::new();
-        for service in join_all(futures).await {
-            services.push(service?);
+            let mut started_services = Vec::with_capacity(stage.len());
+            for service in join_all(futures).await {
+                started_services.push(service?);
+            }
+
+            // Services of the next stage depend on some of these services and, thus, they can be
+            // started when these services are ready.
+            let depended_on = started_services.iter().filter(|service| {
+                deployment_unit
+                    .services()
+                    .iter()
+                    .any(|s| s.depends_on().contains(service.service_name()))
+            });
+            for service in depended_on {
+                self.wait_until_ready(service).await?;
+            }
+
+            services.extend(started_services);
         }
 
         Ok(services)
     }
 
+    /// Waits until the container of `service` is healthy or, if its image does not define a
+    /// `HEALTHCHECK`, until it runs.
+    async fn wait_until_ready(&self, service: &Service) -> Result<(), Error> {
+        let deadline = tokio::time::Instant::now() + DEPENDENCY_READINESS_TIMEOUT;
+
+        loop {
+            let container = self
+                .get_containers(vec![
+                    label_filter(APP_NAME_LABEL, Some(service.app_name().as_str())),
+                    label_filter(SERVICE_NAME_LABEL, Some(service.service_name().as_str())),
+                ])
+                .await?
+                .into_iter()
+                .find(|container| &container.id == service.id());
+
+            let ready = match container {
+                Some(container) if container.state == "running" => {
+                    health_of_status(&container.status).unwrap_or(true)
+                }
+                _ => false,
+            };
+            if ready {
+                debug!("Service {} is ready", service.service_name());
+                return Ok(());
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(DockerInfrastructureError::ServiceNotReady {
+                    service_name: service.service_name().clone(),
+                }
+                .into());
+            }
+
+            trace!("Waiting for service {} to be ready", service.service_name());
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
     async fn stop_services_impl(&self, app_name: &AppName) -> Result<Vec<Service>, Error> {
         let container_details = match self
             .get_container_details(Some(app_name), None)
@@ -297,10 +395,11 @@ impl DockerInfrastructure {
             Some(services) => services.clone(),
         };
 
+        let docker = self.docker();
         let futures = container_details
             .iter()
             .filter(|details| details.state.running)
-            .map(|details| stop(details.clone()));
+            .map(|details| stop(&docker, details.clone()));
         for container in join_all(futures).await {
             trace!("Stopped container {:?}", container?);
         }
@@ -308,16 +407,21 @@ impl DockerInfrastructure {
         let mut services = Vec::with_capacity(container_details.len());
         let futures = container_details
             .iter()
-            .map(|details| delete(details.clone()));
+            .map(|details| delete(&docker, details.clone()));
         for container in join_all(futures).await {
             let container = container?;
             trace!("Deleted container {:?}", container);
 
-            services.push(Service::try_from(&container)?);
+            if is_service_container(&container) {
+                services.push(Service::try_from(&container)?);
+            }
         }
 
         self.delete_network(app_name).await?;
         self.delete_volume_mount(app_name).await?;
+        if let Some(Tls::SelfSigned { directory }) = self.config.tls() {
+            remove_app_certificate(directory, app_name).await?;
+        }
 
         Ok(services)
     }
@@ -330,7 +434,7 @@ impl DockerInfrastructure {
         container_config: &ContainerConfig,
         existing_volumes: &[VolumeInfo],
     ) -> Result<Service, Error> {
-        let docker = Docker::new();
+        let docker = self.docker();
         let containers = docker.containers();
         let images = docker.images();
 
@@ -338,16 +442,17 @@ impl DockerInfrastructure {
             self.pull_image(app_name, service).await?;
         }
         let mut image_to_delete = None;
-        if let Some(ref container_info) = self
-            .get_app_container(app_name, service.service_name())
-            .await?
-        {
-            let container = containers.get(&container_info.id);
-            let container_details = container.inspect().await?;
+        let existing_containers = self
+            .get_app_containers(Some(app_name), Some(service.service_name()))
+            .await?;
+        if let Some(container_info) = existing_containers.first() {
+            let container_details = containers.get(&container_info.id).inspect().await?;
+            let is_scaled_as_desired =
+                existing_containers.len() == service.initial_replicas() as usize;
 
             match service.strategy() {
                 DeploymentStrategy::RedeployOnImageUpdate(image_id)
-                    if &container_details.image == image_id =>
+                    if &container_details.image == image_id && is_scaled_as_desired =>
                 {
                     debug!("Container {:?} of review app {:?} is still running with the desired image id {}", container_info, app_name, image_id);
                     return Ok(Service::try_from(&container_details)?);
@@ -362,63 +467,101 @@ impl DockerInfrastructure {
                 DeploymentStrategy::RedeployAlways
                 | DeploymentStrategy::RedeployOnImageUpdate(_) => {}
             };
+        }
 
+        // Sidecars share the network namespace of the service containers and, thus, they have to
+        // be removed first.
+        let additional_containers = self
+            .get_additional_containers(app_name, service.service_name())
+            .await?;
+        for container_info in additional_containers
+            .iter()
+            .chain(existing_containers.iter())
+        {
             info!(
                 "Removing container {:?} of review app {:?}",
                 container_info, app_name
             );
 
+            let container = containers.get(&container_info.id);
+            let container_details = container.inspect().await?;
             if container_details.state.running {
                 container
                     .stop(Some(core::time::Duration::from_secs(10)))
                     .await?;
             }
             container.delete().await?;
-            image_to_delete = Some(container_details.image);
+            if is_service_container(&container_details) {
+                image_to_delete = Some(container_details.image);
+            }
         }
 
+        self.run_init_containers(app_name, network_id, service, container_config)
+            .await?;
+
         info!(
-            "Creating new review app container for {:?}: service={:?} with image={:?} ({:?})",
+            "Creating {} new review app container(s) for {:?}: service={:?} with image={:?} ({:?})",
+            service.initial_replicas(),
             app_name,
             service.service_name(),
             service.image(),
             service.container_type(),
         );
 
-        let host_config_binds =
-            DockerInfrastructure::create_host_config_binds(app_name, existing_volumes, service)
-                .await?;
+        let host_config_binds = self
+            .create_host_config_binds(app_name, existing_volumes, service)
+            .await?;
 
-        let options = DockerInfrastructure::create_container_options(
-            app_name,
-            service,
-            container_config,
-            &host_config_binds,
-        );
+        let mut container_details = None;
+        for container_index in 0..service.initial_replicas() {
+            let options = DockerInfrastructure::create_container_options(
+                app_name,
+                service,
+                container_config,
+                service.ingress_route(),
+                &host_config_binds,
+                container_index,
+            );
 
-        let container_info = containers.create(&options).await?;
-        debug!("Created container: {:?}", container_info);
+            let container_info = containers.create(&options).await?;
+            debug!("Created container: {:?}", container_info);
 
-        self.copy_file_data(&container_info, service).await?;
+            self.copy_file_data(&container_info, service).await?;
 
-        containers.get(&container_info.id).start().await?;
-        debug!("Started container: {:?}", container_info);
+            containers.get(&container_info.id).start().await?;
+            debug!("Started container: {:?}", container_info);
 
-        docker
-            .networks()
-            .get(network_id)
-            .connect(
-                &ContainerConnectionOptions::builder(&container_info.id)
-                    .aliases(vec![service.service_name().as_str()])
-                    .build(),
-            )
-            .await?;
-        debug!(
-            "Connected container {:?} to {:?}",
-            container_info.id, network_id
-        );
+            // All containers of the service share the same alias so that Docker's DNS balances
+            // the requests of other services between them.
+            docker
+                .networks()
+                .get(network_id)
+                .connect(
+                    &ContainerConnectionOptions::builder(&container_info.id)
+                        .aliases(vec![service.service_name().as_str()])
+                        .build(),
+                )
+                .await?;
+            debug!(
+                "Connected container {:?} to {:?}",
+                container_info.id, network_id
+            );
 
-        let container_details = containers.get(&container_info.id).inspect().await?;
+            for sidecar in service.sidecars() {
+                self.start_sidecar(
+                    app_name,
+                    &container_info,
+                    service,
+                    sidecar,
+                    container_config,
+                )
+                .await?;
+            }
+
+            if container_details.is_none() {
+                container_details = Some(containers.get(&container_info.id).inspect().await?);
+            }
+        }
 
         if let Some(image) = image_to_delete {
             info!("Clean up image {:?} of app {:?}", image, app_name);
@@ -431,14 +574,156 @@ impl DockerInfrastructure {
                 Err(err) => debug!("Could not clean up image: {:?}", err),
             }
         }
+
+        let container_details = container_details.ok_or_else(|| {
+            format_err!(
+                "No container has been started for {} of {}",
+                service.service_name(),
+                app_name
+            )
+        })?;
         Ok(Service::try_from(&container_details)?)
     }
 
+    async fn run_init_containers(
+        &self,
+        app_name: &AppName,
+        network_id: &String,
+        service: &DeployableService,
+        container_config: &ContainerConfig,
+    ) -> Result<(), Error> {
+        let docker = self.docker();
+        let containers = docker.containers();
+
+        for init_container in service.init_containers() {
+            info!(
+                "Running init container {:?} of service {:?} in app {:?}",
+                init_container.name(),
+                service.service_name(),
+                app_name
+            );
+
+            pull(&docker, init_container.image(), &self.config).await?;
+
+            let options = DockerInfrastructure::create_additional_container_options(
+                app_name,
+                service,
+                init_container,
+                container_config,
+                None,
+            );
+            let container_info = containers.create(&options).await?;
+
+            docker
+                .networks()
+                .get(network_id)
+                .connect(&ContainerConnectionOptions::builder(&container_info.id).build())
+                .await?;
+
+            let container = containers.get(&container_info.id);
+            container.start().await?;
+
+            let exit = container.wait().await?;
+            if exit.status_code != 0 {
+                // The failed container is kept for inspecting its logs and it will be removed
+                // with the next deployment.
+                return Err(DockerInfrastructureError::InitContainerFailed {
+                    container_name: init_container.name().clone(),
+                    service_name: service.service_name().clone(),
+                    status_code: exit.status_code,
+                }
+                .into());
+            }
+
+            container.delete().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn start_sidecar(
+        &self,
+        app_name: &AppName,
+        service_container: &ContainerCreateInfo,
+        service: &DeployableService,
+        sidecar: &AdditionalContainer,
+        container_config: &ContainerConfig,
+    ) -> Result<(), Error> {
+        debug!(
+            "Starting sidecar {:?} for container {:?}",
+            sidecar.name(),
+            service_container.id
+        );
+
+        let docker = self.docker();
+        pull(&docker, sidecar.image(), &self.config).await?;
+
+        let network_mode = format!("container:{}", service_container.id);
+        let options = DockerInfrastructure::create_additional_container_options(
+            app_name,
+            service,
+            sidecar,
+            container_config,
+            Some(&network_mode),
+        );
+
+        let containers = docker.containers();
+        let container_info = containers.create(&options).await?;
+        containers.get(&container_info.id).start().await?;
+
+        Ok(())
+    }
+
+    /// Creates the options for init containers and sidecars. Sidecars provide the `network_mode`
+    /// of the service container whose network namespace they share.
+    fn create_additional_container_options(
+        app_name: &str,
+        service_config: &ServiceConfig,
+        container: &AdditionalContainer,
+        container_config: &ContainerConfig,
+        network_mode: Option<&str>,
+    ) -> ContainerOptions {
+        let mut options = ContainerOptions::builder(&container.image().to_string());
+        if let Some(env) = container.env() {
+            let variables = env
+                .iter()
+                .map(|e| format!("{}={}", e.key(), e.value().unsecure()))
+                .collect::<Vec<String>>();
+
+            options.env(variables.iter().map(|s| s.as_str()).collect::<Vec<&str>>());
+        }
+
+        if let Some(args) = container.args() {
+            options.cmd(args.iter().map(|arg| arg.as_str()).collect());
+        }
+
+        let mut labels: HashMap<&str, &str> = HashMap::new();
+        labels.insert(APP_NAME_LABEL, app_name);
+        labels.insert(PARENT_SERVICE_LABEL, service_config.service_name());
+        labels.insert(ADDITIONAL_CONTAINER_LABEL, container.name());
+
+        if let Some(network_mode) = network_mode {
+            labels.insert(SIDECAR_LABEL, "true");
+            options.network_mode(network_mode);
+            options.restart_policy("always", 5);
+        }
+        options.labels(&labels);
+
+        if let Some(memory_limit) = container_config.memory_limit() {
+            options.memory(memory_limit.as_u64());
+            options.memory_swap(memory_limit.as_u64() as i64);
+        }
+
+        options.build()
+    }
+
     fn create_container_options(
         app_name: &str,
         service_config: &ServiceConfig,
         container_config: &ContainerConfig,
+        ingress_route: &TraefikIngressRoute,
         host_config_binds: &[String],
+        container_index: u32,
     ) -> ContainerOptions {
         let mut options = ContainerOptions::builder(&service_config.image().to_string());
         if let Some(env) = service_config.env() {
@@ -452,12 +737,11 @@ impl DockerInfrastructure {
 
         let mut labels: HashMap<&str, &str> = HashMap::new();
 
-        let traefik_frontend = format!(
-            "PathPrefixStrip: /{app_name}/{service_name}/; PathPrefix:/{app_name}/{service_name}/;",
-            app_name = app_name,
-            service_name = service_config.service_name()
-        );
-        labels.insert("traefik.frontend.rule", &traefik_frontend);
+        let traefik_labels =
+            DockerInfrastructure::traefik_labels(app_name, service_config, ingress_route);
+        for (key, value) in &traefik_labels {
+            labels.insert(key, value);
+        }
 
         if let Some(config_labels) = service_config.labels() {
             for (k, v) in config_labels {
@@ -481,6 +765,25 @@ impl DockerInfrastructure {
             labels.insert(REPLICATED_ENV_LABEL, replicated_env);
         }
 
+        // The paths of the files let a redeployment copy them into the recreated container.
+        let files = service_config
+            .files()
+            .map(|files| serde_json::json!(files.keys().collect::<Vec<_>>()).to_string());
+        if let Some(files) = &files {
+            labels.insert(FILES_LABEL, files);
+        }
+
+        let port_labels = DockerInfrastructure::port_router_labels(app_name, service_config);
+        for (key, value) in &port_labels {
+            labels.insert(key, value);
+        }
+
+        // Traefik balances the load between all containers of the same service.
+        let container_index = container_index.to_string();
+        if service_config.initial_replicas() > 1 {
+            labels.insert(CONTAINER_INDEX_LABEL, &container_index);
+        }
+
         if !host_config_binds.is_empty() {
             options.volumes(host_config_binds.iter().map(|bind| bind.as_str()).collect());
         }
@@ -495,6 +798,187 @@ impl DockerInfrastructure {
         options.build()
     }
 
+    /// Creates the labels of Traefik's TCP and UDP routers for the ports that declare an entry
+    /// point. Traefik cannot distinguish plain TCP connections by host name, so each of these
+    /// entry points is dedicated to one service.
+    pub(super) fn port_router_labels(
+        app_name: &str,
+        service_config: &ServiceConfig,
+    ) -> Vec<(String, String)> {
+        let mut labels = Vec::new();
+
+        for port in service_config.ports() {
+            let Some(entry_point) = port.entry_point() else {
+                continue;
+            };
+
+            let name = format!(
+                "{}-{}-{}",
+                app_name,
+                service_config.service_name(),
+                port.name()
+            );
+            let protocol = match port.protocol() {
+                PortProtocol::Tcp => "tcp",
+                PortProtocol::Udp => "udp",
+            };
+
+            if port.protocol() == PortProtocol::Tcp {
+                labels.push((
+                    format!("traefik.tcp.routers.{name}.rule"),
+                    String::from("HostSNI(`*`)"),
+                ));
+            }
+            labels.push((
+                format!("traefik.{protocol}.routers.{name}.entrypoints"),
+                entry_point.clone(),
+            ));
+            labels.push((
+                format!("traefik.{protocol}.routers.{name}.service"),
+                name.clone(),
+            ));
+            labels.push((
+                format!("traefik.{protocol}.services.{name}.loadbalancer.server.port"),
+                port.port().to_string(),
+            ));
+        }
+
+        labels
+    }
+
+    /// Creates the labels of Traefik's HTTP router, service, and middlewares (Traefik v2 and
+    /// later) from the ingress route of the service.
+    pub(super) fn traefik_labels(
+        app_name: &str,
+        service_config: &ServiceConfig,
+        ingress_route: &TraefikIngressRoute,
+    ) -> Vec<(String, String)> {
+        let mut labels = Vec::new();
+        if ingress_route.routes().is_empty() {
+            return labels;
+        }
+
+        let name = format!("{}-{}", app_name, service_config.service_name());
+        labels.push((
+            format!("traefik.http.services.{name}.loadbalancer.server.port"),
+            service_config.port().to_string(),
+        ));
+
+        for (index, route) in ingress_route.routes().iter().enumerate() {
+            let router = match index {
+                0 => name.clone(),
+                index => format!("{name}-{index}"),
+            };
+
+            labels.push((
+                format!("traefik.http.routers.{router}.rule"),
+                route.rule().to_string(),
+            ));
+            labels.push((
+                format!("traefik.http.routers.{router}.service"),
+                name.clone(),
+            ));
+            if !ingress_route.entry_points().is_empty() {
+                labels.push((
+                    format!("traefik.http.routers.{router}.entrypoints"),
+                    ingress_route.entry_points().join(","),
+                ));
+            }
+
+            let mut middlewares = Vec::new();
+            for middleware in route.middlewares() {
+                match middleware {
+                    TraefikMiddleware::Ref(middleware) => middlewares.push(middleware.clone()),
+                    TraefikMiddleware::Spec {
+                        name: middleware,
+                        spec,
+                    } => {
+                        Self::middleware_labels(
+                            format!("traefik.http.middlewares.{middleware}"),
+                            &serde_json::json!(spec),
+                            &mut labels,
+                        );
+                        middlewares.push(middleware.clone());
+                    }
+                }
+            }
+            if !middlewares.is_empty() {
+                labels.push((
+                    format!("traefik.http.routers.{router}.middlewares"),
+                    middlewares.join(","),
+                ));
+            }
+
+            if let Some(tls) = ingress_route.tls() {
+                labels.push((
+                    format!("traefik.http.routers.{router}.tls"),
+                    String::from("true"),
+                ));
+                if let Some(cert_resolver) = &tls.cert_resolver {
+                    labels.push((
+                        format!("traefik.http.routers.{router}.tls.certresolver"),
+                        cert_resolver.clone(),
+                    ));
+                }
+                for (i, domain) in tls.domains.iter().enumerate() {
+                    labels.push((
+                        format!("traefik.http.routers.{router}.tls.domains[{i}].main"),
+                        domain.main.clone(),
+                    ));
+                    if !domain.sans.is_empty() {
+                        labels.push((
+                            format!("traefik.http.routers.{router}.tls.domains[{i}].sans"),
+                            domain.sans.join(","),
+                        ));
+                    }
+                }
+            }
+        }
+
+        labels
+    }
+
+    /// Flattens the middleware specification into labels, e.g. `{"stripPrefix": {"prefixes":
+    /// ["/a", "/b"]}}` into `<prefix>.stripPrefix.prefixes=/a,/b`.
+    fn middleware_labels(
+        prefix: String,
+        spec: &serde_json::Value,
+        labels: &mut Vec<(String, String)>,
+    ) {
+        use serde_json::Value;
+
+        match spec {
+            Value::Null => {}
+            Value::Bool(value) => labels.push((prefix, value.to_string())),
+            Value::Number(value) => labels.push((prefix, value.to_string())),
+            Value::String(value) => labels.push((prefix, value.clone())),
+            Value::Array(values)
+                if values
+                    .iter()
+                    .all(|value| !value.is_array() && !value.is_object()) =>
+            {
+                let values = values
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                labels.push((prefix, values.join(",")));
+            }
+            Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    Self::middleware_labels(format!("{prefix}[{i}]"), value, labels);
+                }
+            }
+            Value::Object(values) => {
+                for (key, value) in values {
+                    Self::middleware_labels(format!("{prefix}.{key}"), value, labels);
+                }
+            }
+        }
+    }
+
     async fn copy_file_data(
         &self,
         container_info: &ContainerCreateInfo,
@@ -511,7 +995,7 @@ impl DockerInfrastructure {
             service_config.service_name()
         );
 
-        let docker = Docker::new();
+        let docker = self.docker();
         let containers = docker.containers();
 
         for (path, data) in files.into_iter() {
@@ -524,8 +1008,40 @@ impl DockerInfrastructure {
         Ok(())
     }
 
-    async fn fetch_existing_volumes(app_name: &String) -> Result<Vec<VolumeInfo>, ShipLiftError> {
-        let docker = Docker::new();
+    /// Reads the files that have been copied into the container on deployment so that they can be
+    /// copied into a recreated container.
+    async fn read_copied_files(
+        &self,
+        details: &ContainerDetails,
+    ) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+        let Some(paths) = details
+            .config
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(FILES_LABEL))
+        else {
+            return Ok(Vec::new());
+        };
+
+        let docker = self.docker();
+        let container = docker.containers().get(&details.id);
+        let mut files = Vec::new();
+        for path in serde_json::from_str::<Vec<PathBuf>>(paths)? {
+            let archive = container.copy_from(&path).try_concat().await?;
+            let mut data = Vec::new();
+            if let Some(entry) = tar::Archive::new(archive.as_slice()).entries()?.next() {
+                entry?.read_to_end(&mut data)?;
+            }
+            files.push((path, data));
+        }
+        Ok(files)
+    }
+
+    async fn fetch_existing_volumes(
+        &self,
+        app_name: &String,
+    ) -> Result<Vec<VolumeInfo>, ShipLiftError> {
+        let docker = self.docker();
         docker.volumes().list().await.map(|volume_infos| {
             volume_infos
                 .into_iter()
@@ -540,10 +1056,11 @@ impl DockerInfrastructure {
     }
 
     async fn create_docker_volume(
+        &self,
         app_name: &str,
         service: &DeployableService,
     ) -> Result<String, ShipLiftError> {
-        let docker = Docker::new();
+        let docker = self.docker();
         let volumes = docker.volumes();
 
         let mut labels: HashMap<&str, &str> = HashMap::new();
@@ -558,6 +1075,7 @@ impl DockerInfrastructure {
     }
 
     async fn create_host_config_binds(
+        &self,
         app_name: &str,
         existing_volume: &[VolumeInfo],
         service: &DeployableService,
@@ -580,7 +1098,7 @@ impl DockerInfrastructure {
 
         let volume_name = match service_volume {
             Some(name) => String::from(name),
-            None => DockerInfrastructure::create_docker_volume(app_name, service).await?,
+            None => self.create_docker_volume(app_name, service).await?,
         };
 
         for declared_volume in service.declared_volumes() {
@@ -604,7 +1122,7 @@ impl DockerInfrastructure {
             app_name
         );
 
-        let pull_results = pull(image, &self.config).await?;
+        let pull_results = pull(&self.docker(), image, &self.config).await?;
 
         for pull_result in pull_results {
             debug!("{:?}", pull_result);
@@ -617,7 +1135,7 @@ impl DockerInfrastructure {
         &self,
         filters: Vec<ContainerFilter>,
     ) -> Result<Vec<ContainerInfo>, ShipLiftError> {
-        let docker = Docker::new();
+        let docker = self.docker();
         let containers = docker.containers();
 
         let list_options = ContainerListOptions::builder()
@@ -640,6 +1158,18 @@ impl DockerInfrastructure {
         self.get_containers(filters).await
     }
 
+    async fn get_additional_containers(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Vec<ContainerInfo>, ShipLiftError> {
+        let filters = vec![
+            label_filter(APP_NAME_LABEL, Some(app_name.as_str())),
+            label_filter(PARENT_SERVICE_LABEL, Some(service_name)),
+        ];
+        self.get_containers(filters).await
+    }
+
     async fn get_status_change_containers(
         &self,
         app_name: Option<&AppName>,
@@ -652,16 +1182,92 @@ impl DockerInfrastructure {
         self.get_containers(filters).await
     }
 
+    /// Returns the containers of all replicas of a service, ordered by their replica index.
+    async fn get_replica_containers(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Vec<ContainerInfo>, ShipLiftError> {
+        let mut containers = self
+            .get_app_containers(Some(app_name), Some(service_name))
+            .await?;
+        containers.sort_by_key(replica_index);
+        Ok(containers)
+    }
+
+    /// Returns the container of the first replica of a service so that commands and files always
+    /// target the same container, even if the service has been scaled to multiple replicas.
     async fn get_app_container(
         &self,
         app_name: &AppName,
         service_name: &str,
     ) -> Result<Option<ContainerInfo>, ShipLiftError> {
-        self.get_app_containers(Some(app_name), Some(service_name))
+        self.get_replica_containers(app_name, service_name)
             .await
             .map(|list| list.into_iter().next())
     }
 
+    fn get_container_logs<'a>(
+        &'a self,
+        container_id: String,
+        from: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        streams: LogStreams,
+        follow: bool,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+        stream! {
+            let docker = self.docker();
+
+            trace!(
+                "Acquiring logs of container {} since {:?}",
+                container_id,
+                from
+            );
+
+            let mut log_options = LogsOptions::builder();
+            log_options
+                .stdout(streams.stdout())
+                .stderr(streams.stderr())
+                .timestamps(true);
+
+            if let Some(since) = from {
+                log_options.since(since);
+            }
+
+            log_options.follow(follow);
+
+            let logs = docker
+                .containers()
+                .get(&container_id)
+                .logs(&log_options.build());
+
+            let mut logs = match limit {
+                Some(log_limit) => Box::pin(logs.take(*log_limit))
+                    as BoxStream<Result<TtyChunk, shiplift::Error>>,
+                None => Box::pin(logs) as BoxStream<Result<TtyChunk, shiplift::Error>>,
+            };
+
+            while let Some(result) = logs.next().await {
+                match result {
+                    Ok(chunk) => {
+                        let line = String::from_utf8_lossy(&chunk.to_vec()).to_string();
+
+                        let mut iter = line.splitn(2, ' ');
+                        let timestamp = iter.next()
+                            .expect("This should never happen: docker should return timestamps, separated by space");
+
+                        let datetime = DateTime::parse_from_rfc3339(timestamp)
+                            .expect("Expecting a valid timestamp");
+                        let log_line: String = iter.collect::<Vec<&str>>().join(" ");
+                        yield Ok((datetime, log_line))
+                    }
+                    Err(e) => yield Err(e.into()),
+                }
+            }
+        }
+        .boxed()
+    }
+
     async fn get_container_details(
         &self,
         app_name: Option<&AppName>,
@@ -671,9 +1277,10 @@ impl DockerInfrastructure {
 
         let container_list = self.get_app_containers(app_name, service_name).await?;
 
+        let docker = self.docker();
         let mut container_details = MultiMap::new();
         for container in container_list.into_iter() {
-            if let Some(details) = not_found_to_none(inspect(container).await)? {
+            if let Some(details) = not_found_to_none(inspect(&docker, container).await)? {
                 let app_name = match app_name {
                     Some(app_name) => app_name.clone(),
                     None => details
@@ -700,7 +1307,7 @@ impl Infrastructure for DockerInfrastructure {
         let container_details = self.get_container_details(None, None).await?;
 
         for (app_name, details_vec) in container_details.iter_all() {
-            for details in details_vec {
+            for details in details_vec.iter().filter(|d| is_service_container(d)) {
                 let service = match Service::try_from(details) {
                     Ok(service) => service,
                     Err(e) => {
@@ -730,7 +1337,7 @@ impl Infrastructure for DockerInfrastructure {
             .deploy_services_impl(deployment_unit, container_config)
             .await;
 
-        delete(deployment_container).await?;
+        delete(&self.docker(), deployment_container).await?;
 
         result
     }
@@ -751,152 +1358,513 @@ impl Infrastructure for DockerInfrastructure {
                         .await?
                         .get_vec(&app_name)
                     {
-                        for container in container_details {
+                        for container in container_details
+                            .iter()
+                            .filter(|details| is_service_container(details))
+                        {
                             services.push(Service::try_from(container)?);
                         }
                     }
 
-                    Some(services)
-                }
-                None => None,
-            },
-        )
+                    Some(services)
+                }
+                None => None,
+            },
+        )
+    }
+
+    /// Deletes all services for the given `app_name`.
+    async fn stop_services(
+        &self,
+        status_id: &str,
+        app_name: &AppName,
+    ) -> Result<Vec<Service>, Error> {
+        let deployment_container = self
+            .create_status_change_container(status_id, app_name)
+            .await?;
+
+        let result = self.stop_services_impl(app_name).await;
+
+        delete(&self.docker(), deployment_container).await?;
+
+        result
+    }
+
+    async fn stop_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        remove_volumes: bool,
+    ) -> Result<Option<Service>, Error> {
+        let service_containers = self
+            .get_app_containers(Some(app_name), Some(service_name))
+            .await?;
+        if service_containers.is_empty() {
+            return Ok(None);
+        }
+
+        let additional_containers = self
+            .get_additional_containers(app_name, service_name)
+            .await?;
+
+        let docker = self.docker();
+        let mut service = None;
+        for container in service_containers.into_iter().chain(additional_containers) {
+            let details = match not_found_to_none(inspect(&docker, container).await)? {
+                Some(details) => details,
+                None => continue,
+            };
+
+            let details = if details.state.running {
+                stop(&docker, details).await?
+            } else {
+                details
+            };
+            let details = delete(&docker, details).await?;
+            trace!("Deleted container {:?}", details);
+
+            if service.is_none() && is_service_container(&details) {
+                service = Some(Service::try_from(&details)?);
+            }
+        }
+
+        if remove_volumes {
+            let volumes = docker.volumes();
+            for volume in self.fetch_existing_volumes(app_name).await? {
+                let belongs_to_service = volume
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get(SERVICE_NAME_LABEL))
+                    .map_or(false, |name| name == service_name);
+                if belongs_to_service {
+                    volumes.get(&volume.name).delete().await?;
+                }
+            }
+        }
+
+        Ok(service)
+    }
+
+    async fn get_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        service_name: &'a str,
+        from: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        streams: LogStreams,
+        follow: bool,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+        stream! {
+            let containers = match self.get_replica_containers(app_name, service_name).await {
+                Ok(containers) => containers,
+                Err(e) => {
+                    yield Err(e.into());
+                    return;
+                }
+            };
+
+            // The lines of a service with multiple replicas are prefixed with the replica that
+            // logged them, so that they can be told apart.
+            let prefix_lines = containers.len() > 1;
+            let logs = containers.into_iter().map(|container| {
+                let index = replica_index(&container);
+                self.get_container_logs(container.id, from, limit, streams, follow)
+                    .map_ok(move |(timestamp, line)| {
+                        if prefix_lines {
+                            (timestamp, replica_log_line(index, &line))
+                        } else {
+                            (timestamp, line)
+                        }
+                    })
+                    .boxed()
+            });
+            let mut logs = futures::stream::select_all(logs);
+
+            if follow {
+                while let Some(result) = logs.next().await {
+                    yield result;
+                }
+            } else {
+                let mut lines = Vec::new();
+                while let Some(result) = logs.next().await {
+                    match result {
+                        Ok(line) => lines.push(line),
+                        Err(e) => {
+                            yield Err(e);
+                            return;
+                        }
+                    }
+                }
+
+                for line in merge_replica_logs(lines, limit) {
+                    yield Ok(line);
+                }
+            }
+        }
+        .boxed()
+    }
+
+    async fn change_status(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        status: ServiceStatus,
+    ) -> Result<Option<Service>, failure::Error> {
+        let docker = self.docker();
+        let containers = docker.containers();
+
+        let service_containers = self
+            .get_app_containers(Some(app_name), Some(service_name))
+            .await?;
+        if service_containers.is_empty() {
+            return Ok(None);
+        }
+
+        let sidecars = self
+            .get_additional_containers(app_name, service_name)
+            .await?
+            .into_iter()
+            .filter(|container| container.labels.contains_key(SIDECAR_LABEL));
+
+        let mut service = None;
+        for container in service_containers.into_iter().chain(sidecars) {
+            let c = containers.get(&container.id);
+
+            let details = c.inspect().await?;
+
+            macro_rules! run_future_and_map_err {
+                ( $future:expr, $log_format:expr ) => {
+                    if let Err(err) = $future.await {
+                        match err {
+                            ShipLiftError::Fault { code, message } if code.as_u16() == 304 => {
+                                trace!(
+                                    "Container {} already in desired state: {}",
+                                    details.id,
+                                    message
+                                );
+                            }
+                            err => {
+                                error!($log_format, err);
+                                return Err(failure::Error::from(err));
+                            }
+                        };
+                    }
+                };
+            }
+
+            match status {
+                ServiceStatus::Running => {
+                    if !details.state.running {
+                        run_future_and_map_err!(c.start(), "Could not start container: {}");
+                    }
+                }
+                ServiceStatus::Paused => {
+                    if details.state.running {
+                        run_future_and_map_err!(c.stop(None), "Could not pause container: {}");
+                    }
+                }
+            }
+
+            if service.is_none() && is_service_container(&details) {
+                service = Some(Service::try_from(&details)?);
+            }
+        }
+
+        Ok(service)
+    }
+
+    /// Restarts the service containers first because their sidecars share their network
+    /// namespace.
+    async fn restart_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<Service>, Error> {
+        let service_containers = self
+            .get_app_containers(Some(app_name), Some(service_name))
+            .await?;
+        if service_containers.is_empty() {
+            return Ok(None);
+        }
+
+        let sidecars = self
+            .get_additional_containers(app_name, service_name)
+            .await?
+            .into_iter()
+            .filter(|container| container.labels.contains_key(SIDECAR_LABEL));
+
+        let docker = self.docker();
+        let containers = docker.containers();
+        let mut service = None;
+        for container in service_containers.into_iter().chain(sidecars) {
+            let c = containers.get(&container.id);
+            c.restart(Some(Duration::from_secs(10))).await?;
+            debug!("Restarted container {}", container.id);
+
+            let details = c.inspect().await?;
+            if service.is_none() && is_service_container(&details) {
+                service = Some(Service::try_from(&details)?);
+            }
+        }
+
+        Ok(service)
+    }
+
+    /// Pulls the image and recreates the containers of the service and its sidecars from their
+    /// current settings. Files that have been copied into the containers on deployment are copied
+    /// into the recreated containers.
+    async fn redeploy_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        image: &Image,
+        digest: Option<&str>,
+    ) -> Result<Option<Service>, Error> {
+        let service_containers = self
+            .get_app_containers(Some(app_name), Some(service_name))
+            .await?;
+        if service_containers.is_empty() {
+            return Ok(None);
+        }
+
+        let docker = self.docker();
+        let containers = docker.containers();
+        if let Image::Named { .. } = image {
+            info!("Pulling {image} ({digest:?}) for {service_name} of app {app_name}");
+            pull(&docker, image, &self.config).await?;
+        }
+
+        // Sidecars share the network namespace of the service containers and, thus, they have to
+        // be removed first.
+        let mut sidecars = Vec::new();
+        for container in self
+            .get_additional_containers(app_name, service_name)
+            .await?
+            .into_iter()
+            .filter(|container| container.labels.contains_key(SIDECAR_LABEL))
+        {
+            let details = inspect(&docker, container).await?;
+            if details.state.running {
+                stop(&docker, details.clone()).await?;
+            }
+            sidecars.push(delete(&docker, details).await?);
+        }
+
+        let network_id = self.create_or_get_network_id(app_name).await?;
+        let mut service = None;
+        for container in service_containers {
+            let details = inspect(&docker, container).await?;
+            if details.state.running {
+                stop(&docker, details.clone()).await?;
+            }
+            let files = self.read_copied_files(&details).await?;
+            let details = delete(&docker, details).await?;
+
+            // The command of the container is the default command of the old image because
+            // PREvant does not set any command for services.
+            let options = recreated_container_options(&details, &image.to_string(), None, None);
+            let container_info = containers.create(&options).await?;
+            for (path, data) in &files {
+                containers
+                    .get(&container_info.id)
+                    .copy_file_into(path, data)
+                    .await?;
+            }
+            containers.get(&container_info.id).start().await?;
+            docker
+                .networks()
+                .get(&network_id)
+                .connect(
+                    &ContainerConnectionOptions::builder(&container_info.id)
+                        .aliases(vec![service_name])
+                        .build(),
+                )
+                .await?;
+            debug!(
+                "Recreated container {} as {}",
+                details.id, container_info.id
+            );
+
+            let old_network_mode = format!("container:{}", details.id);
+            let network_mode = format!("container:{}", container_info.id);
+            for sidecar in sidecars
+                .iter()
+                .filter(|sidecar| sidecar.host_config.network_mode == old_network_mode)
+            {
+                let options = recreated_container_options(
+                    sidecar,
+                    &sidecar.config.image,
+                    sidecar.config.cmd.as_deref(),
+                    Some(&network_mode),
+                );
+                let sidecar_info = containers.create(&options).await?;
+                containers.get(&sidecar_info.id).start().await?;
+            }
+
+            if service.is_none() {
+                let details = containers.get(&container_info.id).inspect().await?;
+                service = Some(Service::try_from(&details)?);
+            }
+        }
+
+        Ok(service)
     }
 
-    /// Deletes all services for the given `app_name`.
-    async fn stop_services(
+    async fn exec(
         &self,
-        status_id: &str,
         app_name: &AppName,
-    ) -> Result<Vec<Service>, Error> {
-        let deployment_container = self
-            .create_status_change_container(status_id, app_name)
-            .await?;
-
-        let result = self.stop_services_impl(app_name).await;
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecOutput>, Error> {
+        let Some(container) = self.get_app_container(app_name, service_name).await? else {
+            return Ok(None);
+        };
 
-        delete(deployment_container).await?;
+        let docker = self.docker();
+        let options = ExecContainerOptions::builder()
+            .cmd(command.iter().map(|arg| arg.as_str()).collect())
+            .attach_stdout(true)
+            .attach_stderr(true)
+            .build();
+        let exec = Exec::create(&docker, &container.id, &options).await?;
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut chunks = exec.start();
+        while let Some(chunk) = chunks.next().await {
+            match chunk? {
+                TtyChunk::StdOut(bytes) => stdout.extend(bytes),
+                TtyChunk::StdErr(bytes) => stderr.extend(bytes),
+                TtyChunk::StdIn(_) => {}
+            }
+        }
 
-        result
+        let exit_code = exec.inspect().await?.exit_code.map(|code| code as i64);
+        Ok(Some(ExecOutput::new(
+            exit_code,
+            String::from_utf8_lossy(&stdout).into_owned(),
+            String::from_utf8_lossy(&stderr).into_owned(),
+        )))
     }
 
-    async fn get_logs<'a>(
-        &'a self,
-        app_name: &'a AppName,
-        service_name: &'a str,
-        from: &'a Option<DateTime<FixedOffset>>,
-        limit: &'a Option<usize>,
-        follow: bool,
-    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
-        stream! {
-            match self
-                .get_app_container(&AppName::from_str(app_name).unwrap(), service_name)
-                .await
-            {
-                Ok(None) => {}
-                Ok(Some(container)) => {
-                    let docker = Docker::new();
-
-                    trace!(
-                        "Acquiring logs of container {} since {:?}",
-                        container.id,
-                        from
-                    );
+    async fn attach_exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecSession>, Error> {
+        let Some(container) = self.get_app_container(app_name, service_name).await? else {
+            return Ok(None);
+        };
 
-                    let mut log_options = LogsOptions::builder();
-                    log_options.stdout(true).stderr(true).timestamps(true);
+        let tty = self.host.start_tty_exec(&container.id, command).await?;
+        debug!("Attached to {command:?} in container {}", container.id);
 
-                    if let Some(since) = from {
-                        log_options.since(since);
-                    }
+        let (output, input) = tokio::io::split(tty);
+        Ok(Some(ExecSession::new(input, output)))
+    }
 
-                    log_options.follow(follow);
-                    
-                    let logs = docker
-                        .containers()
-                        .get(&container.id)
-                        .logs(&log_options.build());
-
-                    let mut logs = match limit {
-                        Some(log_limit) => Box::pin(logs.take(*log_limit))
-                            as BoxStream<Result<TtyChunk, shiplift::Error>>,
-                        None => Box::pin(logs) as BoxStream<Result<TtyChunk, shiplift::Error>>,
-                    };
-
-                    while let Some(result) = logs.next().await {
-                        match result {
-                            Ok(chunk) => {
-                                let line = String::from_utf8_lossy(&chunk.to_vec()).to_string();
-
-                                let mut iter = line.splitn(2, ' ');
-                                let timestamp = iter.next()
-                                    .expect("This should never happen: docker should return timestamps, separated by space");
-
-                                let datetime = DateTime::parse_from_rfc3339(timestamp)
-                                    .expect("Expecting a valid timestamp");
-                                let log_line: String = iter.collect::<Vec<&str>>().join(" ");
-                                yield Ok((datetime, log_line))
-                            }
-                            Err(e) => yield Err(e.into()),
-                        }
-                    }
+    async fn download_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+    ) -> Result<Option<BoxStream<'static, Result<Vec<u8>, Error>>>, Error> {
+        let Some(container) = self.get_app_container(app_name, service_name).await? else {
+            return Ok(None);
+        };
+
+        let docker = self.docker();
+        let path = PathBuf::from(path);
+        Ok(Some(
+            stream! {
+                let container = docker.containers().get(&container.id);
+                let mut archive = container.copy_from(&path);
+                while let Some(chunk) = archive.next().await {
+                    yield chunk.map_err(Error::from);
                 }
-                Err(e) => yield Err(e.into()),
             }
-        }.boxed()
+            .boxed(),
+        ))
     }
 
-    async fn change_status(
+    async fn upload_files(
         &self,
         app_name: &AppName,
         service_name: &str,
-        status: ServiceStatus,
-    ) -> Result<Option<Service>, failure::Error> {
-        match self.get_app_container(app_name, service_name).await? {
-            Some(container) => {
-                let docker = Docker::new();
-                let containers = docker.containers();
-                let c = containers.get(&container.id);
-
-                let details = c.inspect().await?;
-
-                macro_rules! run_future_and_map_err {
-                    ( $future:expr, $log_format:expr ) => {
-                        if let Err(err) = $future.await {
-                            match err {
-                                ShipLiftError::Fault { code, message } if code.as_u16() == 304 => {
-                                    trace!(
-                                        "Container {} already in desired state: {}",
-                                        details.id,
-                                        message
-                                    );
-                                }
-                                err => {
-                                    error!($log_format, err);
-                                    return Err(failure::Error::from(err));
-                                }
-                            };
-                        }
-                    };
-                }
+        path: &str,
+        archive: Vec<u8>,
+    ) -> Result<bool, Error> {
+        let Some(container) = self.get_app_container(app_name, service_name).await? else {
+            return Ok(false);
+        };
 
-                match status {
-                    ServiceStatus::Running => {
-                        if !details.state.running {
-                            run_future_and_map_err!(c.start(), "Could not start container: {}");
-                        }
-                    }
-                    ServiceStatus::Paused => {
-                        if details.state.running {
-                            run_future_and_map_err!(c.stop(None), "Could not pause container: {}");
-                        }
-                    }
-                }
+        debug!("Copy archive into {path} of container {}", container.id);
+        self.docker()
+            .containers()
+            .get(&container.id)
+            .copy_to(Path::new(path), archive.into())
+            .await?;
+        Ok(true)
+    }
+}
 
-                Ok(Some(Service::try_from(&details)?))
-            }
-            None => Ok(None),
-        }
+/// Creates the options that recreate a container from the settings of the given container, e.g.
+/// to start it with a newer image.
+fn recreated_container_options(
+    details: &ContainerDetails,
+    image: &str,
+    cmd: Option<&[String]>,
+    network_mode: Option<&str>,
+) -> ContainerOptions {
+    let mut options = ContainerOptions::builder(image);
+    if let Some(env) = &details.config.env {
+        options.env(env.iter().map(|s| s.as_str()).collect::<Vec<&str>>());
+    }
+    if let Some(cmd) = cmd {
+        options.cmd(cmd.iter().map(|arg| arg.as_str()).collect());
+    }
+    if let Some(labels) = &details.config.labels {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect::<HashMap<&str, &str>>();
+        options.labels(&labels);
+    }
+
+    let binds = details
+        .mounts
+        .iter()
+        .map(|mount| format!("{}:{}", mount.source, mount.destination))
+        .collect::<Vec<String>>();
+    if !binds.is_empty() {
+        options.volumes(binds.iter().map(|bind| bind.as_str()).collect());
+    }
+
+    if let Some(network_mode) = network_mode {
+        options.network_mode(network_mode);
+    }
+    options.restart_policy("always", 5);
+    if let Some(memory) = details.host_config.memory {
+        options.memory(memory);
+    }
+    if let Some(memory_swap) = details.host_config.memory_swap {
+        options.memory_swap(memory_swap);
+    }
+
+    options.build()
+}
+
+/// Returns `false` for the additional containers of a service that has been scaled to multiple
+/// replicas, and for its init containers and sidecars, so that each service is reported only once.
+fn is_service_container(details: &ContainerDetails) -> bool {
+    match details.config.labels.as_ref() {
+        Some(labels) if labels.contains_key(PARENT_SERVICE_LABEL) => false,
+        Some(labels) => labels
+            .get(CONTAINER_INDEX_LABEL)
+            .map(|index| index == "0")
+            .unwrap_or(true),
+        None => true,
     }
 }
 
@@ -912,6 +1880,46 @@ where
     }
 }
 
+/// Returns the index of the replica that a container belongs to. Services with a single replica
+/// do not label their container with an index.
+fn replica_index(container: &ContainerInfo) -> usize {
+    container
+        .labels
+        .get(CONTAINER_INDEX_LABEL)
+        .and_then(|index| index.parse().ok())
+        .unwrap_or(0)
+}
+
+fn replica_log_line(index: usize, line: &str) -> String {
+    format!("replica-{index} | {line}")
+}
+
+/// Orders the log lines of all replicas of a service by their timestamp and keeps at most `limit`
+/// lines.
+fn merge_replica_logs(
+    mut lines: Vec<(DateTime<FixedOffset>, String)>,
+    limit: &Option<usize>,
+) -> Vec<(DateTime<FixedOffset>, String)> {
+    lines.sort_by_key(|(timestamp, _)| *timestamp);
+    if let Some(limit) = limit {
+        lines.truncate(*limit);
+    }
+    lines
+}
+
+/// Returns whether a container is healthy according to the status of the container list, e.g.
+/// `Up 2 minutes (healthy)`, because shiplift does not expose the health of the container details.
+/// Returns `None` if the container has no health check.
+fn health_of_status(status: &str) -> Option<bool> {
+    if status.contains("(healthy)") {
+        Some(true)
+    } else if status.contains("(health: starting)") || status.contains("(unhealthy)") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
 /// Helper function to map ShipLift 404 errors to None
 fn not_found_to_none<T>(result: Result<T, ShipLiftError>) -> Result<Option<T>, ShipLiftError> {
     match result {
@@ -922,7 +1930,11 @@ fn not_found_to_none<T>(result: Result<T, ShipLiftError>) -> Result<Option<T>, S
 }
 
 /// Helper function to pull images
-async fn pull(image: &Image, config: &Config) -> Result<Vec<serde_json::Value>, ShipLiftError> {
+async fn pull(
+    docker: &Docker,
+    image: &Image,
+    config: &Config,
+) -> Result<Vec<serde_json::Value>, ShipLiftError> {
     let mut pull_options_builder = PullOptions::builder();
     pull_options_builder.image(&image.to_string());
 
@@ -937,7 +1949,6 @@ async fn pull(image: &Image, config: &Config) -> Result<Vec<serde_json::Value>,
         }
     }
 
-    let docker = Docker::new();
     let images = docker.images();
 
     images
@@ -947,24 +1958,30 @@ async fn pull(image: &Image, config: &Config) -> Result<Vec<serde_json::Value>,
 }
 
 /// Helper function to stop containers with the aid of futures::future::join_all
-async fn stop(details: ContainerDetails) -> Result<ContainerDetails, ShipLiftError> {
-    let docker = Docker::new();
+async fn stop(
+    docker: &Docker,
+    details: ContainerDetails,
+) -> Result<ContainerDetails, ShipLiftError> {
     let containers = docker.containers();
     containers.get(&details.id).stop(None).await?;
     Ok(details)
 }
 
 /// Helper function to delete containers with the aid of futures::future::join_all
-async fn delete(details: ContainerDetails) -> Result<ContainerDetails, ShipLiftError> {
-    let docker = Docker::new();
+async fn delete(
+    docker: &Docker,
+    details: ContainerDetails,
+) -> Result<ContainerDetails, ShipLiftError> {
     let containers = docker.containers();
     containers.get(&details.id).delete().await?;
     Ok(details)
 }
 
 /// Helper function to inspect containers with the aid of futures::future::join_all
-async fn inspect(container: ContainerInfo) -> Result<ContainerDetails, ShipLiftError> {
-    let docker = Docker::new();
+async fn inspect(
+    docker: &Docker,
+    container: ContainerInfo,
+) -> Result<ContainerDetails, ShipLiftError> {
     let containers = docker.containers();
     containers.get(&container.id).inspect().await
 }
@@ -973,7 +1990,18 @@ fn find_port(
     container_details: &ContainerDetails,
     labels: Option<&HashMap<String, String>>,
 ) -> Result<u16, DockerInfrastructureError> {
-    if let Some(port) = labels.and_then(|labels| labels.get(CONTAINER_PORT_LABEL)) {
+    let port_label = labels.and_then(|labels| {
+        labels.get(CONTAINER_PORT_LABEL).or_else(|| {
+            labels
+                .iter()
+                .find(|(key, _)| {
+                    key.starts_with("traefik.http.services.")
+                        && key.ends_with(".loadbalancer.server.port")
+                })
+                .map(|(_, port)| port)
+        })
+    });
+    if let Some(port) = port_label {
         match port.parse::<u16>() {
             Ok(port) => Ok(port),
             Err(err) => Err(DockerInfrastructureError::UnexpectedError {
@@ -1029,7 +2057,8 @@ impl TryFrom<&ContainerDetails> for Service {
             .app_name(app_name.clone())
             .config(ServiceConfig::try_from(container_details)?)
             .service_status(status)
-            .started_at(started_at);
+            .started_at(started_at)
+            .image_digest(container_details.image.clone());
 
         if !container_details.network_settings.ip_address.is_empty() {
             let addr = IpAddr::from_str(&container_details.network_settings.ip_address)?;
@@ -1141,9 +2170,10 @@ impl From<ServiceBuilderError> for DockerInfrastructureError {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{Environment, EnvironmentVariable};
+    use crate::models::{Environment, EnvironmentVariable, ServicePort};
     use crate::sc;
     use secstr::SecUtf8;
+    use std::collections::BTreeMap;
 
     macro_rules! container_details {
         ($id:expr, $app_name:expr, $service_name:expr, $image:expr, $container_type:expr, $($l_key:expr => $l_value:expr),* ) => {{
@@ -1247,7 +2277,128 @@ mod tests {
             &String::from("master"),
             &config,
             &ContainerConfig::default(),
+            &TraefikIngressRoute::with_defaults(&AppName::master(), "db"),
+            &Vec::new(),
+            0,
+        );
+
+        let json = serde_json::to_value(&options).unwrap();
+        assert_json_diff::assert_json_eq!(
+            json,
+            serde_json::json!({
+              "name": null,
+              "params": {
+                "HostConfig.RestartPolicy.Name": "always",
+                "Image": "docker.io/library/mariadb:10.3.17",
+                "Labels": {
+                  "com.aixigo.preview.servant.app-name": "master",
+                  "com.aixigo.preview.servant.container-type": "instance",
+                  "com.aixigo.preview.servant.service-name": "db",
+                  "com.aixigo.preview.servant.image": "docker.io/library/mariadb:10.3.17",
+                  "traefik.http.services.master-db.loadbalancer.server.port": "80",
+                  "traefik.http.routers.master-db.rule": "PathPrefix(`/master/db/`)",
+                  "traefik.http.routers.master-db.service": "master-db",
+                  "traefik.http.routers.master-db.middlewares": "master-db-middleware",
+                  "traefik.http.middlewares.master-db-middleware.stripPrefix.prefixes": "/master/db/"
+                }
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_container_options_with_host_based_routing() {
+        let config = sc!("db", "mariadb:10.3.17");
+        let routing = crate::config_from_str!(
+            r#"
+            [routing]
+            mode = 'host'
+            domain = 'review.example.com'
+            "#
+        )
+        .routing()
+        .clone();
+
+        let route = routing.service_ingress_route(&AppName::master(), "db");
+
+        let options = DockerInfrastructure::create_container_options(
+            &String::from("master"),
+            &config,
+            &ContainerConfig::default(),
+            &route,
+            &Vec::new(),
+            0,
+        );
+
+        let json = serde_json::to_value(&options).unwrap();
+        assert_eq!(
+            json["params"]["Labels"]["traefik.http.routers.master-db.rule"],
+            "Host(`db.master.review.example.com`)"
+        );
+    }
+
+    #[test]
+    fn should_create_container_options_with_access_restrictions() {
+        let config = sc!("db", "mariadb:10.3.17");
+        let app_name = AppName::master();
+        let restrictions = crate::config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'basicAuth'
+            username = 'user'
+            password = 'password'
+
+            [[accessRestrictions]]
+            type = 'ipAllowList'
+            sourceRange = ['10.0.0.0/8', '192.168.1.7']
+            "#
+        );
+        let mut route = TraefikIngressRoute::with_defaults(&app_name, "db");
+        route.prepend_middlewares(
+            &restrictions
+                .access_restrictions(&app_name)
+                .into_iter()
+                .map(|restriction| restriction.traefik_middleware(&app_name))
+                .collect::<Vec<_>>(),
+        );
+
+        let options = DockerInfrastructure::create_container_options(
+            &String::from("master"),
+            &config,
+            &ContainerConfig::default(),
+            &route,
+            &Vec::new(),
+            0,
+        );
+
+        let json = serde_json::to_value(&options).unwrap();
+        assert_eq!(
+            json["params"]["Labels"]["traefik.http.routers.master-db.middlewares"],
+            "master-basic-auth,master-ip-allow-list,master-db-middleware"
+        );
+        assert_eq!(
+            json["params"]["Labels"]["traefik.http.middlewares.master-basic-auth.basicAuth.users"],
+            "user:{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="
+        );
+        assert_eq!(
+            json["params"]["Labels"]
+                ["traefik.http.middlewares.master-ip-allow-list.ipWhiteList.sourceRange"],
+            "10.0.0.0/8,192.168.1.7"
+        );
+    }
+
+    #[test]
+    fn should_create_container_options_with_replica_index() {
+        let mut config = sc!("db", "mariadb:10.3.17");
+        config.set_replicas(Some(2));
+
+        let options = DockerInfrastructure::create_container_options(
+            &String::from("master"),
+            &config,
+            &ContainerConfig::default(),
+            &TraefikIngressRoute::with_defaults(&AppName::master(), "db"),
             &Vec::new(),
+            1,
         );
 
         let json = serde_json::to_value(&options).unwrap();
@@ -1263,7 +2414,98 @@ mod tests {
                   "com.aixigo.preview.servant.container-type": "instance",
                   "com.aixigo.preview.servant.service-name": "db",
                   "com.aixigo.preview.servant.image": "docker.io/library/mariadb:10.3.17",
-                  "traefik.frontend.rule": "PathPrefixStrip: /master/db/; PathPrefix:/master/db/;"
+                  "com.aixigo.preview.servant.container-index": "1",
+                  "traefik.http.services.master-db.loadbalancer.server.port": "80",
+                  "traefik.http.routers.master-db.rule": "PathPrefix(`/master/db/`)",
+                  "traefik.http.routers.master-db.service": "master-db",
+                  "traefik.http.routers.master-db.middlewares": "master-db-middleware",
+                  "traefik.http.middlewares.master-db-middleware.stripPrefix.prefixes": "/master/db/"
+                }
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_container_options_with_tcp_router() {
+        let mut config = sc!("db", "postgres:16");
+        config.set_ports(Some(vec![
+            ServicePort::new(
+                String::from("sql"),
+                5432,
+                PortProtocol::Tcp,
+                Some(String::from("postgres")),
+            ),
+            ServicePort::new(String::from("metrics"), 9187, PortProtocol::Tcp, None),
+        ]));
+
+        let options = DockerInfrastructure::create_container_options(
+            &String::from("master"),
+            &config,
+            &ContainerConfig::default(),
+            &TraefikIngressRoute::with_defaults(&AppName::master(), "db"),
+            &Vec::new(),
+            0,
+        );
+
+        let json = serde_json::to_value(&options).unwrap();
+        assert_json_diff::assert_json_eq!(
+            json,
+            serde_json::json!({
+              "name": null,
+              "params": {
+                "HostConfig.RestartPolicy.Name": "always",
+                "Image": "docker.io/library/postgres:16",
+                "Labels": {
+                  "com.aixigo.preview.servant.app-name": "master",
+                  "com.aixigo.preview.servant.container-type": "instance",
+                  "com.aixigo.preview.servant.service-name": "db",
+                  "com.aixigo.preview.servant.image": "docker.io/library/postgres:16",
+                  "traefik.http.services.master-db.loadbalancer.server.port": "80",
+                  "traefik.http.routers.master-db.rule": "PathPrefix(`/master/db/`)",
+                  "traefik.http.routers.master-db.service": "master-db",
+                  "traefik.http.routers.master-db.middlewares": "master-db-middleware",
+                  "traefik.http.middlewares.master-db-middleware.stripPrefix.prefixes": "/master/db/",
+                  "traefik.tcp.routers.master-db-sql.rule": "HostSNI(`*`)",
+                  "traefik.tcp.routers.master-db-sql.entrypoints": "postgres",
+                  "traefik.tcp.routers.master-db-sql.service": "master-db-sql",
+                  "traefik.tcp.services.master-db-sql.loadbalancer.server.port": "5432"
+                }
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_sidecar_container_options() {
+        let config = sc!("db", "mariadb:10.3.17");
+        let sidecar = AdditionalContainer::new(
+            String::from("exporter"),
+            Image::from_str("prom/mysqld-exporter").unwrap(),
+        );
+
+        let options = DockerInfrastructure::create_additional_container_options(
+            &String::from("master"),
+            &config,
+            &sidecar,
+            &ContainerConfig::default(),
+            Some("container:4711"),
+        );
+
+        let json = serde_json::to_value(&options).unwrap();
+        assert_json_diff::assert_json_eq!(
+            json,
+            serde_json::json!({
+              "name": null,
+              "params": {
+                "HostConfig.NetworkMode": "container:4711",
+                "HostConfig.RestartPolicy.Name": "always",
+                "Image": "docker.io/prom/mysqld-exporter:latest",
+                "Labels": {
+                  "com.aixigo.preview.servant.app-name": "master",
+                  "com.aixigo.preview.servant.parent-service": "db",
+                  "com.aixigo.preview.servant.additional-container": "exporter",
+                  "com.aixigo.preview.servant.sidecar": "true"
                 }
               }
             })
@@ -1282,7 +2524,9 @@ mod tests {
             &String::from("master"),
             &config,
             &ContainerConfig::default(),
+            &TraefikIngressRoute::with_defaults(&AppName::master(), "db"),
             &Vec::new(),
+            0,
         );
 
         let json = serde_json::to_value(&options).unwrap();
@@ -1298,7 +2542,11 @@ mod tests {
                   "com.aixigo.preview.servant.container-type": "instance",
                   "com.aixigo.preview.servant.service-name": "db",
                   "com.aixigo.preview.servant.image": "docker.io/library/mariadb:10.3.17",
-                  "traefik.frontend.rule": "PathPrefixStrip: /master/db/; PathPrefix:/master/db/;"
+                  "traefik.http.services.master-db.loadbalancer.server.port": "80",
+                  "traefik.http.routers.master-db.rule": "PathPrefix(`/master/db/`)",
+                  "traefik.http.routers.master-db.service": "master-db",
+                  "traefik.http.routers.master-db.middlewares": "master-db-middleware",
+                  "traefik.http.middlewares.master-db-middleware.stripPrefix.prefixes": "/master/db/"
                 },
                 "Env": [
                   "MYSQL_ROOT_PASSWORD=example"
@@ -1322,7 +2570,9 @@ mod tests {
             &String::from("master"),
             &config,
             &ContainerConfig::default(),
+            &TraefikIngressRoute::with_defaults(&AppName::master(), "db"),
             &Vec::new(),
+            0,
         );
 
         let json = serde_json::to_value(&options).unwrap();
@@ -1345,7 +2595,11 @@ mod tests {
                         "replicate": true,
                       }
                     }).to_string(),
-                    "traefik.frontend.rule": "PathPrefixStrip: /master/db/; PathPrefix:/master/db/;"
+                    "traefik.http.services.master-db.loadbalancer.server.port": "80",
+                    "traefik.http.routers.master-db.rule": "PathPrefix(`/master/db/`)",
+                    "traefik.http.routers.master-db.service": "master-db",
+                    "traefik.http.routers.master-db.middlewares": "master-db-middleware",
+                    "traefik.http.middlewares.master-db-middleware.stripPrefix.prefixes": "/master/db/"
                   },
                   "Env": [
                     "MYSQL_ROOT_PASSWORD=example"
@@ -1447,7 +2701,9 @@ mod tests {
             &String::from("master"),
             &config,
             &ContainerConfig::default(),
+            &TraefikIngressRoute::with_defaults(&AppName::master(), "db"),
             &[String::from("test-volume:/var/lib/mysql")],
+            0,
         );
 
         let json = serde_json::to_value(&options).unwrap();
@@ -1464,10 +2720,117 @@ mod tests {
                   "com.aixigo.preview.servant.container-type": "instance",
                   "com.aixigo.preview.servant.service-name": "db",
                   "com.aixigo.preview.servant.image": "docker.io/library/mariadb:10.3.17",
-                  "traefik.frontend.rule": "PathPrefixStrip: /master/db/; PathPrefix:/master/db/;"
+                  "traefik.http.services.master-db.loadbalancer.server.port": "80",
+                  "traefik.http.routers.master-db.rule": "PathPrefix(`/master/db/`)",
+                  "traefik.http.routers.master-db.service": "master-db",
+                  "traefik.http.routers.master-db.middlewares": "master-db-middleware",
+                  "traefik.http.middlewares.master-db-middleware.stripPrefix.prefixes": "/master/db/"
                 }
               }
             })
         );
     }
+
+    #[test]
+    fn should_create_container_options_with_paths_of_files() {
+        let mut config = sc!("nginx", "nginx:latest");
+        config.set_files(Some(BTreeMap::from([
+            (
+                PathBuf::from("/etc/nginx/conf.d/default.conf"),
+                SecUtf8::from("server {}"),
+            ),
+            (
+                PathBuf::from("/usr/share/nginx/html/index.html"),
+                SecUtf8::from(""),
+            ),
+        ])));
+
+        let options = DockerInfrastructure::create_container_options(
+            &String::from("master"),
+            &config,
+            &ContainerConfig::default(),
+            &TraefikIngressRoute::with_defaults(&AppName::master(), "nginx"),
+            &[],
+            0,
+        );
+
+        let json = serde_json::to_value(&options).unwrap();
+        assert_eq!(
+            json["params"]["Labels"][FILES_LABEL],
+            serde_json::json!(serde_json::json!([
+                "/etc/nginx/conf.d/default.conf",
+                "/usr/share/nginx/html/index.html"
+            ])
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn should_read_health_of_container_status() {
+        assert_eq!(health_of_status("Up 2 minutes (healthy)"), Some(true));
+        assert_eq!(
+            health_of_status("Up 3 seconds (health: starting)"),
+            Some(false)
+        );
+        assert_eq!(health_of_status("Up 2 minutes (unhealthy)"), Some(false));
+        assert_eq!(health_of_status("Up 2 minutes"), None);
+    }
+
+    #[test]
+    fn should_merge_logs_of_replicas() {
+        let timestamp = |s: &str| DateTime::parse_from_rfc3339(s).unwrap();
+        let lines = vec![
+            (
+                timestamp("2019-07-18T07:30:00.000000000Z"),
+                replica_log_line(1, "Started"),
+            ),
+            (
+                timestamp("2019-07-18T07:25:00.000000000Z"),
+                replica_log_line(0, "Starting"),
+            ),
+            (
+                timestamp("2019-07-18T07:35:00.000000000Z"),
+                replica_log_line(0, "Started"),
+            ),
+            (
+                timestamp("2019-07-18T07:28:00.000000000Z"),
+                replica_log_line(1, "Starting"),
+            ),
+        ];
+
+        assert_eq!(
+            merge_replica_logs(lines.clone(), &None),
+            vec![
+                (
+                    timestamp("2019-07-18T07:25:00.000000000Z"),
+                    String::from("replica-0 | Starting")
+                ),
+                (
+                    timestamp("2019-07-18T07:28:00.000000000Z"),
+                    String::from("replica-1 | Starting")
+                ),
+                (
+                    timestamp("2019-07-18T07:30:00.000000000Z"),
+                    String::from("replica-1 | Started")
+                ),
+                (
+                    timestamp("2019-07-18T07:35:00.000000000Z"),
+                    String::from("replica-0 | Started")
+                ),
+            ]
+        );
+        assert_eq!(
+            merge_replica_logs(lines, &Some(2)),
+            vec![
+                (
+                    timestamp("2019-07-18T07:25:00.000000000Z"),
+                    String::from("replica-0 | Starting")
+                ),
+                (
+                    timestamp("2019-07-18T07:28:00.000000000Z"),
+                    String::from("replica-1 | Starting")
+                ),
+            ]
+        );
+    }
 }
@@ -0,0 +1,509 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+
+use crate::config::{DockerRuntimeConfig, DockerTlsConfig};
+use failure::format_err;
+use hyper::header::{HeaderValue, CONNECTION, CONTENT_TYPE, HOST, UPGRADE};
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::{json, Value};
+use shiplift::Docker;
+use std::fs::{DirBuilder, File};
+use std::io::{self, BufReader};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{copy, copy_bidirectional, AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixListener, UnixStream};
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// Connects shiplift to the Docker daemon of the runtime configuration.
+///
+/// shiplift speaks plain HTTP over unix sockets or TCP only. Therefore, PREvant forwards the
+/// connections to daemons that require TLS client certificates or that are reachable via SSH
+/// through a local unix socket.
+pub(super) enum DockerHost {
+    /// Uses shiplift's defaults, i.e. `DOCKER_HOST` or `/var/run/docker.sock`.
+    Default,
+    Unix(String),
+    Tcp(String),
+    Forwarded(ForwardedSocket),
+}
+
+/// The local unix socket that forwards the connections to a remote Docker host. The socket is
+/// created in a directory that only PREvant's user can access because every connection to the
+/// socket gains full access to the Docker host. The directory is removed when the socket is not
+/// used anymore.
+pub(super) struct ForwardedSocket {
+    directory: PathBuf,
+    socket: PathBuf,
+    forwarding: JoinHandle<()>,
+}
+
+impl ForwardedSocket {
+    fn bind(remote: Remote) -> io::Result<Self> {
+        let directory =
+            std::env::temp_dir().join(format!("prevant-docker-{}", uuid::Uuid::new_v4().simple()));
+        DirBuilder::new().mode(0o700).create(&directory)?;
+
+        let socket = directory.join("docker.sock");
+        let listener = match bind(&socket) {
+            Ok(listener) => listener,
+            Err(err) => {
+                let _ = std::fs::remove_dir_all(&directory);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            directory,
+            socket,
+            forwarding: tokio::spawn(forward(listener, remote)),
+        })
+    }
+}
+
+impl Drop for ForwardedSocket {
+    fn drop(&mut self) {
+        self.forwarding.abort();
+        if let Err(err) = std::fs::remove_dir_all(&self.directory) {
+            warn!(
+                "Cannot remove the socket {} of the Docker host: {err}",
+                self.socket.display()
+            );
+        }
+    }
+}
+
+impl DockerHost {
+    pub fn new(config: &DockerRuntimeConfig) -> Result<Self, DockerHostError> {
+        let Some(host) = config.host() else {
+            return Ok(DockerHost::Default);
+        };
+
+        let remote = match (host.scheme(), config.tls()) {
+            ("unix", _) => return Ok(DockerHost::Unix(String::from(host.path()))),
+            ("tcp", None) => {
+                warn!("The connection to the Docker host {host} is not encrypted.");
+                // hyper only connects to http URLs
+                return Ok(DockerHost::Tcp(format!(
+                    "http://{}:{}",
+                    host_name(host)?,
+                    host.port().unwrap_or(2375)
+                )));
+            }
+            ("tcp", Some(tls)) => Remote::Tls {
+                host: host_name(host)?,
+                port: host.port().unwrap_or(2376),
+                connector: tls_connector(tls)?,
+            },
+            ("ssh", _) => Remote::Ssh {
+                destination: match host.username() {
+                    "" => host_name(host)?,
+                    user => format!("{user}@{}", host_name(host)?),
+                },
+                port: host.port(),
+            },
+            _ => {
+                return Err(DockerHostError::UnsupportedHost {
+                    host: host.to_string(),
+                })
+            }
+        };
+
+        let forwarded =
+            ForwardedSocket::bind(remote).map_err(|err| DockerHostError::CannotForward {
+                host: host.to_string(),
+                err: err.to_string(),
+            })?;
+
+        debug!(
+            "Forwarding the Docker host {host} through {}",
+            forwarded.socket.display()
+        );
+
+        Ok(DockerHost::Forwarded(forwarded))
+    }
+
+    pub fn docker(&self) -> Docker {
+        match self {
+            DockerHost::Default => Docker::new(),
+            DockerHost::Unix(path) => Docker::unix(path.clone()),
+            DockerHost::Tcp(host) => Docker::host(host.parse().expect("The host is a valid URL")),
+            DockerHost::Forwarded(forwarded) => Docker::unix(forwarded.socket.to_string_lossy()),
+        }
+    }
+
+    /// Starts `command` in the container with a TTY and returns the connection that is attached
+    /// to the TTY. shiplift cannot attach to the input of exec instances, thus, PREvant talks to
+    /// the Docker API directly.
+    pub async fn start_tty_exec(
+        &self,
+        container_id: &str,
+        command: &[String],
+    ) -> Result<Upgraded, failure::Error> {
+        let response = self
+            .send(post_json(
+                &format!("/containers/{container_id}/exec"),
+                json!({
+                    "AttachStdin": true,
+                    "AttachStdout": true,
+                    "AttachStderr": true,
+                    "Tty": true,
+                    "Cmd": command,
+                }),
+            )?)
+            .await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        if !status.is_success() {
+            return Err(format_err!(
+                "Cannot create exec instance in container {container_id}: {}",
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        let exec_id = serde_json::from_slice::<Value>(&body)?["Id"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| format_err!("The Docker host did not return an exec instance id"))?;
+
+        let mut request = post_json(
+            &format!("/exec/{exec_id}/start"),
+            json!({ "Detach": false, "Tty": true }),
+        )?;
+        let headers = request.headers_mut();
+        headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("tcp"));
+
+        let response = self.send(request).await?;
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Err(format_err!(
+                "Cannot start exec instance {exec_id}: {}",
+                response.status()
+            ));
+        }
+
+        Ok(hyper::upgrade::on(response).await?)
+    }
+
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, failure::Error> {
+        let (mut sender, connection) =
+            hyper::client::conn::handshake(self.connect().await?).await?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.with_upgrades().await {
+                debug!("Connection to the Docker host failed: {err}");
+            }
+        });
+
+        Ok(sender.send_request(request).await?)
+    }
+
+    async fn connect(&self) -> io::Result<Box<dyn Connection>> {
+        let connection: Box<dyn Connection> = match self {
+            DockerHost::Default => match std::env::var("DOCKER_HOST") {
+                Ok(host) if host.starts_with("tcp://") => {
+                    Box::new(TcpStream::connect(host.trim_start_matches("tcp://")).await?)
+                }
+                Ok(host) => {
+                    Box::new(UnixStream::connect(host.trim_start_matches("unix://")).await?)
+                }
+                Err(_) => Box::new(UnixStream::connect("/var/run/docker.sock").await?),
+            },
+            DockerHost::Unix(path) => Box::new(UnixStream::connect(path).await?),
+            DockerHost::Tcp(host) => {
+                Box::new(TcpStream::connect(host.trim_start_matches("http://")).await?)
+            }
+            DockerHost::Forwarded(forwarded) => {
+                Box::new(UnixStream::connect(&forwarded.socket).await?)
+            }
+        };
+        Ok(connection)
+    }
+}
+
+#[derive(Clone)]
+enum Remote {
+    Tls {
+        host: String,
+        port: u16,
+        connector: TlsConnector,
+    },
+    Ssh {
+        destination: String,
+        port: Option<u16>,
+    },
+}
+
+impl Remote {
+    async fn forward(&self, local: &mut UnixStream) -> io::Result<()> {
+        match self {
+            Remote::Tls {
+                host,
+                port,
+                connector,
+            } => {
+                let server_name = ServerName::try_from(host.as_str())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                let tcp = TcpStream::connect((host.as_str(), *port)).await?;
+                let mut remote = connector.connect(server_name, tcp).await?;
+
+                copy_bidirectional(local, &mut remote).await?;
+            }
+            Remote::Ssh { destination, port } => {
+                let mut command = Command::new("ssh");
+                command.args(["-o", "BatchMode=yes"]);
+                if let Some(port) = port {
+                    command.arg("-p").arg(port.to_string());
+                }
+
+                // The same approach as the Docker CLI: the remote Docker CLI pipes the connection
+                // to its daemon through stdin and stdout.
+                let mut child = command
+                    .arg("--")
+                    .arg(destination)
+                    .args(["docker", "system", "dial-stdio"])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()?;
+                let mut stdin = child.stdin.take().expect("stdin is piped");
+                let mut stdout = child.stdout.take().expect("stdout is piped");
+
+                let (mut reader, mut writer) = local.split();
+                tokio::try_join!(
+                    async {
+                        copy(&mut reader, &mut stdin).await?;
+                        stdin.shutdown().await
+                    },
+                    copy(&mut stdout, &mut writer),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn forward(listener: UnixListener, remote: Remote) {
+    loop {
+        let mut local = match listener.accept().await {
+            Ok((local, _)) => local,
+            Err(err) => {
+                error!("Cannot accept connection to the Docker host: {err}");
+                continue;
+            }
+        };
+
+        let remote = remote.clone();
+        tokio::spawn(async move {
+            if let Err(err) = remote.forward(&mut local).await {
+                warn!("Cannot forward connection to the Docker host: {err}");
+            }
+        });
+    }
+}
+
+trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+fn post_json(path: &str, body: Value) -> Result<Request<Body>, hyper::http::Error> {
+    Request::post(path)
+        .header(HOST, "docker")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+}
+
+fn bind(socket: &Path) -> io::Result<UnixListener> {
+    let listener = StdUnixListener::bind(socket)?;
+    listener.set_nonblocking(true)?;
+    UnixListener::from_std(listener)
+}
+
+fn host_name(host: &Url) -> Result<String, DockerHostError> {
+    host.host_str()
+        .map(String::from)
+        .ok_or_else(|| DockerHostError::UnsupportedHost {
+            host: host.to_string(),
+        })
+}
+
+fn tls_connector(tls: &DockerTlsConfig) -> Result<TlsConnector, DockerHostError> {
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(&read_pem(tls.ca_cert(), rustls_pemfile::certs)?);
+
+    let certs = read_pem(tls.client_cert(), rustls_pemfile::certs)?
+        .into_iter()
+        .map(Certificate)
+        .collect::<Vec<_>>();
+
+    let key = read_pem(tls.client_key(), rustls_pemfile::read_all)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| DockerHostError::InvalidTlsMaterial {
+            path: tls.client_key().clone(),
+            err: String::from("no private key found"),
+        })?;
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(certs, key)
+        .map_err(|err| DockerHostError::InvalidTlsMaterial {
+            path: tls.client_cert().clone(),
+            err: err.to_string(),
+        })?;
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+fn read_pem<T>(
+    path: &Path,
+    parse: fn(&mut dyn io::BufRead) -> io::Result<Vec<T>>,
+) -> Result<Vec<T>, DockerHostError> {
+    File::open(path)
+        .and_then(|file| parse(&mut BufReader::new(file)))
+        .map_err(|err| DockerHostError::InvalidTlsMaterial {
+            path: path.to_path_buf(),
+            err: err.to_string(),
+        })
+}
+
+#[derive(Debug, Fail)]
+pub enum DockerHostError {
+    #[fail(
+        display = "The Docker host {} is not supported. Use unix://, tcp://, or ssh://.",
+        host
+    )]
+    UnsupportedHost { host: String },
+    #[fail(display = "Cannot read TLS material {:?}: {}", path, err)]
+    InvalidTlsMaterial { path: PathBuf, err: String },
+    #[fail(
+        display = "Cannot forward connections to the Docker host {}: {}",
+        host, err
+    )]
+    CannotForward { host: String, err: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Runtime;
+    use crate::config_from_str;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn docker_host(runtime: &str) -> Result<DockerHost, DockerHostError> {
+        match config_from_str!(runtime).runtime_config() {
+            Runtime::Docker(config) => DockerHost::new(config),
+            runtime => panic!("Unexpected runtime {:?}", runtime),
+        }
+    }
+
+    #[test]
+    fn should_connect_to_unix_socket() {
+        let host = docker_host(
+            r#"
+            [runtime]
+            type = 'Docker'
+            host = 'unix:///run/user/1000/docker.sock'
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(host, DockerHost::Unix(path) if path == "/run/user/1000/docker.sock"));
+    }
+
+    #[test]
+    fn should_connect_to_tcp_without_tls() {
+        let host = docker_host(
+            r#"
+            [runtime]
+            type = 'Docker'
+            host = 'tcp://build-host'
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(host, DockerHost::Tcp(url) if url == "http://build-host:2375"));
+    }
+
+    #[test]
+    fn should_reject_unsupported_host() {
+        let err = docker_host(
+            r#"
+            [runtime]
+            type = 'Docker'
+            host = 'fd://3'
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            err.to_string(),
+            "The Docker host fd://3 is not supported. Use unix://, tcp://, or ssh://."
+        );
+    }
+
+    #[tokio::test]
+    async fn should_forward_ssh_host_through_local_socket() {
+        let host = docker_host(
+            r#"
+            [runtime]
+            type = 'Docker'
+            host = 'ssh://prevant@build-host:2222'
+            "#,
+        )
+        .unwrap();
+
+        let DockerHost::Forwarded(forwarded) = &host else {
+            panic!("Expected a forwarded Docker host");
+        };
+        let socket = forwarded.socket.clone();
+        let directory = forwarded.directory.clone();
+        assert!(socket.exists());
+        assert_eq!(
+            std::fs::metadata(&directory).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+
+        drop(host);
+
+        assert!(!socket.exists());
+        assert!(!directory.exists());
+    }
+}
@@ -24,56 +24,71 @@
  * =========================LICENSE_END==================================
  */
 
-use crate::config::ContainerConfig;
+use crate::config::{ContainerConfig, InMemoryRuntimeConfig};
 use crate::deployment::deployment_unit::DeployableService;
 use crate::deployment::DeploymentUnit;
 use crate::infrastructure::Infrastructure;
 use crate::models::service::{Service, ServiceStatus};
-use crate::models::{AppName, ServiceBuilder, ServiceConfig};
+use crate::models::{
+    AppName, ExecOutput, ExecSession, Image, LogStreams, ServiceBuilder, ServiceConfig,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, FixedOffset, Utc};
+use failure::format_err;
 use futures::stream::{self, BoxStream};
 use multimap::MultiMap;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Mutex;
 use std::time::Duration;
 
 use super::TraefikIngressRoute;
 
-#[cfg(test)]
+/// Keeps the deployed services in memory instead of running containers. Besides the unit tests,
+/// this infrastructure backs the `InMemory` runtime that allows to use PREvant's REST API
+/// without Docker, Podman, or Kubernetes.
 pub struct DummyInfrastructure {
     delay: Option<Duration>,
+    config: InMemoryRuntimeConfig,
     services: Mutex<MultiMap<AppName, DeployableService>>,
+    paused_services: Mutex<HashSet<(AppName, String)>>,
+    archives: Mutex<HashMap<(AppName, String, String), Vec<u8>>>,
     base_ingress_route: Option<TraefikIngressRoute>,
 }
 
-#[cfg(test)]
 impl DummyInfrastructure {
     pub fn new() -> Self {
+        Self::from_config(InMemoryRuntimeConfig::default())
+    }
+
+    pub fn from_config(config: InMemoryRuntimeConfig) -> Self {
         Self {
-            delay: None,
+            delay: config.latency(),
+            config,
             services: Mutex::new(MultiMap::new()),
+            paused_services: Mutex::new(HashSet::new()),
+            archives: Mutex::new(HashMap::new()),
             base_ingress_route: None,
         }
     }
 
+    #[cfg(test)]
     pub fn with_delay(delay: Duration) -> Self {
         Self {
             delay: Some(delay),
-            services: Mutex::new(MultiMap::new()),
-            base_ingress_route: None,
+            ..Self::new()
         }
     }
 
+    #[cfg(test)]
     pub fn with_base_route(base_ingress_route: TraefikIngressRoute) -> Self {
         Self {
-            delay: None,
-            services: Mutex::new(MultiMap::new()),
             base_ingress_route: Some(base_ingress_route),
+            ..Self::new()
         }
     }
 
+    #[cfg(test)]
     pub fn services(&self) -> Vec<DeployableService> {
         self.services
             .lock()
@@ -82,18 +97,57 @@ impl DummyInfrastructure {
             .flat_map(|(_, v)| v.iter().cloned())
             .collect::<Vec<_>>()
     }
-}
 
-#[cfg(test)]
-impl DummyInfrastructure {
     async fn delay_if_configured(&self) {
         if let Some(delay) = &self.delay {
             tokio::time::sleep(*delay).await;
         }
     }
+
+    fn fail_if_configured(&self, app_name: &AppName) -> Result<(), failure::Error> {
+        if self.config.fails_for(app_name) {
+            return Err(format_err!("Simulated failure of the app {}", app_name));
+        }
+        Ok(())
+    }
+
+    fn runs_service(&self, app_name: &AppName, service_name: &str) -> bool {
+        self.services
+            .lock()
+            .unwrap()
+            .get_vec(app_name)
+            .map_or(false, |configs| {
+                configs.iter().any(|c| c.service_name() == service_name)
+            })
+    }
+
+    fn service(&self, app_name: &AppName, config: &DeployableService) -> Service {
+        let status = if self
+            .paused_services
+            .lock()
+            .unwrap()
+            .contains(&(app_name.clone(), config.service_name().clone()))
+        {
+            ServiceStatus::Paused
+        } else {
+            ServiceStatus::Running
+        };
+
+        ServiceBuilder::new()
+            .id(format!("{}", config.service_name()))
+            .app_name(app_name.to_string())
+            .config(ServiceConfig::clone(config))
+            .service_status(status)
+            .started_at(
+                DateTime::parse_from_rfc3339("2019-07-18T07:30:00.000000000Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            )
+            .build()
+            .unwrap()
+    }
 }
 
-#[cfg(test)]
 #[async_trait]
 impl Infrastructure for DummyInfrastructure {
     async fn get_services(&self) -> Result<MultiMap<AppName, Service>, failure::Error> {
@@ -102,20 +156,7 @@ impl Infrastructure for DummyInfrastructure {
         let services = self.services.lock().unwrap();
         for (app, configs) in services.iter_all() {
             for config in configs {
-                let service = ServiceBuilder::new()
-                    .id(format!("{}", config.service_name()))
-                    .app_name(app.to_string())
-                    .config(ServiceConfig::clone(config))
-                    .service_status(ServiceStatus::Running)
-                    .started_at(
-                        DateTime::parse_from_rfc3339("2019-07-18T07:30:00.000000000Z")
-                            .unwrap()
-                            .with_timezone(&Utc),
-                    )
-                    .build()
-                    .unwrap();
-
-                s.insert(AppName::from_str(app).unwrap(), service);
+                s.insert(AppName::from_str(app).unwrap(), self.service(app, config));
             }
         }
 
@@ -129,6 +170,7 @@ impl Infrastructure for DummyInfrastructure {
         _container_config: &ContainerConfig,
     ) -> Result<Vec<Service>, failure::Error> {
         self.delay_if_configured().await;
+        self.fail_if_configured(deployment_unit.app_name())?;
 
         let mut services = self.services.lock().unwrap();
         let app_name = deployment_unit.app_name();
@@ -142,8 +184,10 @@ impl Infrastructure for DummyInfrastructure {
             running_services.retain(|config| !service_names.contains(config.service_name()));
         }
 
+        let mut paused_services = self.paused_services.lock().unwrap();
         for config in deployable_services {
             info!("started {} for {}.", config.service_name(), app_name);
+            paused_services.remove(&(app_name.clone(), config.service_name().clone()));
             services.insert(app_name.clone(), config.clone());
         }
         Ok(vec![])
@@ -155,8 +199,13 @@ impl Infrastructure for DummyInfrastructure {
         app_name: &AppName,
     ) -> Result<Vec<Service>, failure::Error> {
         self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
 
         let mut services = self.services.lock().unwrap();
+        self.paused_services
+            .lock()
+            .unwrap()
+            .retain(|(app, _)| app != app_name);
 
         match services.remove(&app_name) {
             Some(services) => Ok(services
@@ -179,12 +228,46 @@ impl Infrastructure for DummyInfrastructure {
         }
     }
 
+    async fn stop_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        _remove_volumes: bool,
+    ) -> Result<Option<Service>, failure::Error> {
+        self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
+
+        let mut services = self.services.lock().unwrap();
+        let Some(configs) = services.get_vec_mut(app_name) else {
+            return Ok(None);
+        };
+        let Some(position) = configs
+            .iter()
+            .position(|config| config.service_name() == service_name)
+        else {
+            return Ok(None);
+        };
+
+        let config = configs.remove(position);
+        if configs.is_empty() {
+            services.remove(app_name);
+        }
+
+        let service = self.service(app_name, &config);
+        self.paused_services
+            .lock()
+            .unwrap()
+            .remove(&(app_name.clone(), config.service_name().clone()));
+        Ok(Some(service))
+    }
+
     async fn get_logs<'a>(
         &'a self,
         app_name: &'a AppName,
         service_name: &'a str,
-        _from: &'a Option<DateTime<FixedOffset>>,
-        _limit: &'a Option<usize>,
+        from: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        streams: LogStreams,
         _follow: bool,
     ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
         Box::pin(stream::iter(
@@ -203,26 +286,290 @@ impl Infrastructure for DummyInfrastructure {
                 ),
             ]
             .into_iter()
+            .filter(move |_| streams.stdout())
+            .filter(move |(timestamp, _)| from.map_or(true, |from| *timestamp >= from))
+            .take(limit.unwrap_or(usize::MAX))
             .map(|s| Ok(s)),
         ))
     }
 
     async fn change_status(
         &self,
-        _app_name: &AppName,
-        _service_name: &str,
-        _status: ServiceStatus,
+        app_name: &AppName,
+        service_name: &str,
+        status: ServiceStatus,
+    ) -> Result<Option<Service>, failure::Error> {
+        self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
+
+        let services = self.services.lock().unwrap();
+        let Some(config) = services
+            .get_vec(app_name)
+            .and_then(|configs| configs.iter().find(|c| c.service_name() == service_name))
+        else {
+            return Ok(None);
+        };
+
+        let key = (app_name.clone(), String::from(service_name));
+        match status {
+            ServiceStatus::Running => self.paused_services.lock().unwrap().remove(&key),
+            ServiceStatus::Paused => self.paused_services.lock().unwrap().insert(key),
+        };
+
+        Ok(Some(self.service(app_name, config)))
+    }
+
+    async fn redeploy_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        _image: &Image,
+        _digest: Option<&str>,
     ) -> Result<Option<Service>, failure::Error> {
-        Ok(None)
+        self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
+
+        let services = self.services.lock().unwrap();
+        let Some(config) = services
+            .get_vec(app_name)
+            .and_then(|configs| configs.iter().find(|c| c.service_name() == service_name))
+        else {
+            return Ok(None);
+        };
+
+        self.paused_services
+            .lock()
+            .unwrap()
+            .remove(&(app_name.clone(), String::from(service_name)));
+        Ok(Some(self.service(app_name, config)))
+    }
+
+    /// Behaves like `echo`: the output consists of the command and its arguments.
+    async fn exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecOutput>, failure::Error> {
+        self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
+
+        if !self.runs_service(app_name, service_name) {
+            return Ok(None);
+        }
+
+        Ok(Some(ExecOutput::new(
+            Some(0),
+            format!("{}\n", command.join(" ")),
+            String::new(),
+        )))
+    }
+
+    /// Behaves like `cat`: the TTY prints everything that is written to it.
+    async fn attach_exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        _command: &[String],
+    ) -> Result<Option<ExecSession>, failure::Error> {
+        self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
+
+        if !self.runs_service(app_name, service_name) {
+            return Ok(None);
+        }
+
+        let (tty, container) = tokio::io::duplex(1024);
+        let (mut container_input, mut container_output) = tokio::io::split(container);
+        tokio::spawn(
+            async move { tokio::io::copy(&mut container_input, &mut container_output).await },
+        );
+
+        let (output, input) = tokio::io::split(tty);
+        Ok(Some(ExecSession::new(input, output)))
+    }
+
+    /// Returns the archive that has been uploaded to the same path before.
+    async fn download_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+    ) -> Result<Option<BoxStream<'static, Result<Vec<u8>, failure::Error>>>, failure::Error> {
+        self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
+
+        if !self.runs_service(app_name, service_name) {
+            return Ok(None);
+        }
+
+        let archive = self
+            .archives
+            .lock()
+            .unwrap()
+            .get(&(app_name.clone(), service_name.to_string(), path.to_string()))
+            .cloned()
+            .ok_or_else(|| format_err!("Could not find the file {path} in {service_name}"))?;
+        Ok(Some(Box::pin(stream::iter([Ok::<_, failure::Error>(
+            archive,
+        )]))))
+    }
+
+    async fn upload_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> Result<bool, failure::Error> {
+        self.delay_if_configured().await;
+        self.fail_if_configured(app_name)?;
+
+        if !self.runs_service(app_name, service_name) {
+            return Ok(false);
+        }
+
+        self.archives.lock().unwrap().insert(
+            (app_name.clone(), service_name.to_string(), path.to_string()),
+            archive,
+        );
+        Ok(true)
     }
 
     async fn base_traefik_ingress_route(
         &self,
+        _app_name: &AppName,
     ) -> Result<Option<TraefikIngressRoute>, failure::Error> {
         Ok(self.base_ingress_route.clone())
     }
 
+    #[cfg(test)]
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deployment::deployment_unit::DeploymentUnitBuilder;
+    use crate::{config_from_str, sc};
+    use std::collections::HashMap;
+
+    async fn deployment_unit(app_name: AppName) -> DeploymentUnit {
+        let config = crate::config::Config::default();
+        DeploymentUnitBuilder::init(app_name, vec![sc!("db")])
+            .extend_with_config(&config)
+            .await
+            .unwrap()
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating()
+            .unwrap()
+            .apply_hooks(&config)
+            .await
+            .unwrap()
+            .apply_base_traefik_ingress_route(TraefikIngressRoute::empty())
+            .build()
+    }
+
+    fn in_memory_config(runtime: &str) -> InMemoryRuntimeConfig {
+        match config_from_str!(runtime).runtime_config() {
+            crate::config::Runtime::InMemory(config) => config.clone(),
+            runtime => panic!("Unexpected runtime {:?}", runtime),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_pause_and_resume_service() -> Result<(), failure::Error> {
+        let infrastructure = DummyInfrastructure::new();
+        infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit(AppName::master()).await,
+                &ContainerConfig::default(),
+            )
+            .await?;
+
+        let service = infrastructure
+            .change_status(&AppName::master(), "db", ServiceStatus::Paused)
+            .await?
+            .unwrap();
+        assert_eq!(service.status(), &ServiceStatus::Paused);
+
+        let services = infrastructure.get_services().await?;
+        assert_eq!(
+            services.get(&AppName::master()).unwrap().status(),
+            &ServiceStatus::Paused
+        );
+
+        let service = infrastructure
+            .change_status(&AppName::master(), "db", ServiceStatus::Running)
+            .await?
+            .unwrap();
+        assert_eq!(service.status(), &ServiceStatus::Running);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_simulate_failures_of_selected_apps() -> Result<(), failure::Error> {
+        let infrastructure = DummyInfrastructure::from_config(in_memory_config(
+            r#"
+            [runtime]
+            type = 'InMemory'
+            failingApps = 'broken-.*'
+            "#,
+        ));
+
+        let broken_app = AppName::from_str("broken-login")?;
+        let result = infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit(broken_app.clone()).await,
+                &ContainerConfig::default(),
+            )
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Simulated failure of the app broken-login"
+        );
+
+        infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit(AppName::master()).await,
+                &ContainerConfig::default(),
+            )
+            .await?;
+        assert_eq!(infrastructure.services().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_limit_logs() {
+        let infrastructure = DummyInfrastructure::new();
+        let from = Some(DateTime::parse_from_rfc3339("2019-07-18T07:30:00.000000000Z").unwrap());
+
+        let logs = futures::StreamExt::collect::<Vec<_>>(
+            infrastructure
+                .get_logs(
+                    &AppName::master(),
+                    "db",
+                    &from,
+                    &Some(1),
+                    LogStreams::Both,
+                    false,
+                )
+                .await,
+        )
+        .await;
+
+        assert_eq!(logs.len(), 1);
+        assert_eq!(
+            logs[0].as_ref().unwrap().1,
+            "Log msg 2 of db of app master\n"
+        );
+    }
+}
@@ -24,15 +24,19 @@
  * =========================LICENSE_END==================================
  */
 
+use super::multi_target::DeploymentTargetError;
 use super::traefik::TraefikIngressRoute;
 use crate::config::ContainerConfig;
 use crate::deployment::DeploymentUnit;
 use crate::models::service::{Service, ServiceStatus};
-use crate::models::{AppName, ContainerType, ServiceConfig};
+use crate::models::{
+    AppName, ContainerType, ExecOutput, ExecSession, Image, LogStreams, ServiceConfig,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, FixedOffset};
-use failure::Error;
-use futures::stream::BoxStream;
+use failure::{format_err, Error};
+use futures::stream::{self, BoxStream};
+use kube::Client as KubeClient;
 use multimap::MultiMap;
 
 #[async_trait]
@@ -69,16 +73,41 @@ pub trait Infrastructure: Send + Sync {
         app_name: &AppName,
     ) -> Result<Vec<Service>, Error>;
 
-    /// Streams the log lines with a the corresponding timestamps in it.
+    /// Stops and removes a single service of the given `app_name`, including its sidecars, and
+    /// removes the volumes of the service if `remove_volumes` is set.
+    ///
+    /// The implementation must return `None` if the app does not run a service with that name.
+    async fn stop_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        remove_volumes: bool,
+    ) -> Result<Option<Service>, Error>;
+
+    /// Streams the log lines with a the corresponding timestamps in it. Runtimes that cannot tell
+    /// the output streams of a service apart fail with [`UnsupportedLogStreams`] unless both
+    /// streams are selected.
     async fn get_logs<'a>(
         &'a self,
         app_name: &'a AppName,
         service_name: &'a str,
         from: &'a Option<DateTime<FixedOffset>>,
         limit: &'a Option<usize>,
+        streams: LogStreams,
         follow: bool,
     ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>>;
 
+    /// Streams the log lines of the previous container of the service, e.g. of a container that
+    /// crashed and has been replaced. Runtimes that restart containers in place keep their logs
+    /// and, therefore, there are no previous logs by default.
+    async fn get_previous_logs<'a>(
+        &'a self,
+        _app_name: &'a AppName,
+        _service_name: &'a str,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+        Box::pin(stream::empty())
+    }
+
     /// Changes the status of a service, for example, the service might me stopped or started.
     async fn change_status(
         &self,
@@ -87,9 +116,134 @@ pub trait Infrastructure: Send + Sync {
         status: ServiceStatus,
     ) -> Result<Option<Service>, Error>;
 
+    /// Restarts the containers of a service, including its sidecars.
+    ///
+    /// The default implementation pauses and resumes the service.
+    async fn restart_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<Service>, Error> {
+        if self
+            .change_status(app_name, service_name, ServiceStatus::Paused)
+            .await?
+            .is_none()
+        {
+            return Ok(None);
+        }
+        self.change_status(app_name, service_name, ServiceStatus::Running)
+            .await
+    }
+
+    /// Redeploys a service with the current image of its tag. `digest` is the digest that the
+    /// registry resolved for `image`. All other settings of the service are kept.
+    async fn redeploy_service(
+        &self,
+        app_name: &AppName,
+        _service_name: &str,
+        _image: &Image,
+        _digest: Option<&str>,
+    ) -> Result<Option<Service>, Error> {
+        Err(format_err!(
+            "Cannot redeploy a single service of {}: the runtime does not support it",
+            app_name
+        ))
+    }
+
+    /// Runs `command` in the container of a service until it exits and returns its output.
+    ///
+    /// The implementation must return `None` if the app does not run a service with that name.
+    async fn exec(
+        &self,
+        app_name: &AppName,
+        _service_name: &str,
+        _command: &[String],
+    ) -> Result<Option<ExecOutput>, Error> {
+        Err(format_err!(
+            "Cannot execute commands in the services of {}: the runtime does not support it",
+            app_name
+        ))
+    }
+
+    /// Starts `command` with a TTY in the container of a service, e.g. to open a shell. The
+    /// command runs until its input is closed or it exits by itself.
+    ///
+    /// The implementation must return `None` if the app does not run a service with that name.
+    async fn attach_exec(
+        &self,
+        app_name: &AppName,
+        _service_name: &str,
+        _command: &[String],
+    ) -> Result<Option<ExecSession>, Error> {
+        Err(format_err!(
+            "Cannot attach to the services of {}: the runtime does not support it",
+            app_name
+        ))
+    }
+
+    /// Streams a tar archive of the file or directory at `path` in the container of a service.
+    ///
+    /// The implementation must return `None` if the app does not run a service with that name.
+    async fn download_files(
+        &self,
+        app_name: &AppName,
+        _service_name: &str,
+        _path: &str,
+    ) -> Result<Option<BoxStream<'static, Result<Vec<u8>, Error>>>, Error> {
+        Err(format_err!(
+            "Cannot copy files from the services of {}: the runtime does not support it",
+            app_name
+        ))
+    }
+
+    /// Extracts the tar `archive` into the directory `path` in the container of a service.
+    ///
+    /// The implementation must return `false` if the app does not run a service with that name.
+    async fn upload_files(
+        &self,
+        app_name: &AppName,
+        _service_name: &str,
+        _path: &str,
+        _archive: Vec<u8>,
+    ) -> Result<bool, Error> {
+        Err(format_err!(
+            "Cannot copy files into the services of {}: the runtime does not support it",
+            app_name
+        ))
+    }
+
     /// Determines the [router rule](https://doc.traefik.io/traefik/routing/routers/) that points
-    /// to PREvant it self so services will be reachable on the same route, e.g. host name.
-    async fn base_traefik_ingress_route(&self) -> Result<Option<TraefikIngressRoute>, Error> {
+    /// to PREvant it self so services of the given app will be reachable on the same route, e.g.
+    /// host name.
+    async fn base_traefik_ingress_route(
+        &self,
+        _app_name: &AppName,
+    ) -> Result<Option<TraefikIngressRoute>, Error> {
+        Ok(None)
+    }
+
+    /// Returns the infrastructure that deploys the given app if this infrastructure manages
+    /// multiple deployment targets. An app stays on the target it runs on. New apps are placed on
+    /// the requested `target` or on the target chosen by the placement rules.
+    ///
+    /// `None` means that the app is deployed by this infrastructure itself.
+    async fn placement<'a>(
+        &'a self,
+        _app_name: &AppName,
+        target: Option<&str>,
+    ) -> Result<Option<&'a dyn Infrastructure>, Error> {
+        match target {
+            Some(target) => Err(DeploymentTargetError::UnknownTarget {
+                target: String::from(target),
+            }
+            .into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the client of the Kubernetes cluster this infrastructure deploys to. It is used to
+    /// resolve secrets that are stored in that cluster.
+    async fn kubernetes_client(&self) -> Result<Option<KubeClient>, Error> {
         Ok(None)
     }
 
@@ -120,3 +274,14 @@ impl dyn Infrastructure {
             }))
     }
 }
+
+/// Will be used by runtimes that cannot provide the log lines of a single output stream because
+/// they interleave stdout and stderr.
+#[derive(Debug, Fail, PartialEq)]
+#[fail(
+    display = "The runtime cannot provide the log lines of {} only.",
+    streams
+)]
+pub struct UnsupportedLogStreams {
+    pub streams: LogStreams,
+}
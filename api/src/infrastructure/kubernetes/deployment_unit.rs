@@ -1,12 +1,14 @@
 use super::{
     infrastructure::KubernetesInfrastructureError,
     payloads::{
-        convert_k8s_ingress_to_traefik_ingress, IngressRoute as TraefikIngressRoute,
-        Middleware as TraefikMiddleware,
+        app_secret_field_manager, convert_k8s_ingress_to_traefik_ingress, Certificate,
+        IngressRoute as TraefikIngressRoute, IngressRouteTcp as TraefikIngressRouteTcp,
+        IngressRouteUdp as TraefikIngressRouteUdp, Middleware as TraefikMiddleware,
     },
+    routing::{HttpRoute, RoutingPayload},
 };
 use crate::{
-    config::BootstrappingContainer,
+    config::{BootstrappingContainer, KubernetesRoutingBackend},
     deployment::DeploymentUnit,
     infrastructure::{APP_NAME_LABEL, CONTAINER_TYPE_LABEL, SERVICE_NAME_LABEL},
     models::{AppName, ContainerType, Image},
@@ -17,6 +19,7 @@ use handlebars::RenderError;
 use k8s_openapi::{
     api::{
         apps::v1::{Deployment, StatefulSet},
+        autoscaling::v2::HorizontalPodAutoscaler,
         batch::v1::Job,
         core::v1::{
             ConfigMap, Container, LocalObjectReference, PersistentVolumeClaim, Pod, PodSpec,
@@ -47,6 +50,7 @@ pub(super) struct K8sDeploymentUnit {
     stateful_sets: Vec<StatefulSet>,
     config_maps: Vec<ConfigMap>,
     secrets: Vec<Secret>,
+    app_secrets: Vec<(String, Secret)>,
     pvcs: Vec<PersistentVolumeClaim>,
     services: Vec<Service>,
     pods: Vec<Pod>,
@@ -54,7 +58,13 @@ pub(super) struct K8sDeploymentUnit {
     jobs: Vec<Job>,
     service_accounts: Vec<ServiceAccount>,
     traefik_ingresses: Vec<TraefikIngressRoute>,
+    traefik_tcp_ingresses: Vec<TraefikIngressRouteTcp>,
+    traefik_udp_ingresses: Vec<TraefikIngressRouteUdp>,
     traefik_middlewares: Vec<TraefikMiddleware>,
+    ingresses: Vec<Ingress>,
+    http_routes: Vec<HttpRoute>,
+    horizontal_pod_autoscalers: Vec<HorizontalPodAutoscaler>,
+    certificates: Vec<Certificate>,
 }
 
 impl K8sDeploymentUnit {
@@ -180,6 +190,7 @@ impl K8sDeploymentUnit {
         client: Client,
         bootstrapping_container: &[BootstrappingContainer],
         image_pull_secret: Option<Secret>,
+        routing_backend: &KubernetesRoutingBackend,
     ) -> Result<Self, Error> {
         if bootstrapping_container.is_empty() {
             return Ok(Default::default());
@@ -196,7 +207,8 @@ impl K8sDeploymentUnit {
         )
         .await?;
 
-        let result = Self::parse_from_log_streams(deployment_unit, &mut log_streams).await;
+        let result =
+            Self::parse_from_log_streams(deployment_unit, routing_backend, &mut log_streams).await;
 
         let pod_api: Api<Pod> = Api::namespaced(client, &app_name.to_rfc1123_namespace_id());
         pod_api
@@ -208,6 +220,7 @@ impl K8sDeploymentUnit {
 
     async fn parse_from_log_streams<L>(
         deployment_unit: &DeploymentUnit,
+        routing_backend: &KubernetesRoutingBackend,
         log_streams: L,
     ) -> Result<Self, Error>
     where
@@ -458,7 +471,12 @@ impl K8sDeploymentUnit {
         let mut traefik_ingresses = Vec::new();
         let mut traefik_middlewares = Vec::new();
 
-        for ingress in ingresses {
+        // Without Traefik, the bootstrapped ingresses are deployed as they are.
+        let ingresses_to_convert = match routing_backend {
+            KubernetesRoutingBackend::Traefik => std::mem::take(&mut ingresses),
+            _ => Vec::new(),
+        };
+        for ingress in ingresses_to_convert {
             let Ok((route, middlewares)) = convert_k8s_ingress_to_traefik_ingress(
                 ingress,
                 deployment_unit.app_base_route().clone(),
@@ -476,6 +494,7 @@ impl K8sDeploymentUnit {
             stateful_sets,
             config_maps,
             secrets,
+            app_secrets: Vec::new(),
             pvcs,
             services,
             pods,
@@ -483,7 +502,13 @@ impl K8sDeploymentUnit {
             jobs,
             service_accounts,
             traefik_ingresses,
+            traefik_tcp_ingresses: Vec::new(),
+            traefik_udp_ingresses: Vec::new(),
             traefik_middlewares,
+            ingresses,
+            http_routes: Vec::new(),
+            horizontal_pod_autoscalers: Vec::new(),
+            certificates: Vec::new(),
         })
     }
 
@@ -492,8 +517,7 @@ impl K8sDeploymentUnit {
         secret: Option<Secret>,
         service: Service,
         deployment: Deployment,
-        ingress: TraefikIngressRoute,
-        middlewares: Vec<TraefikMiddleware>,
+        routing: RoutingPayload,
     ) {
         let mut deployment = deployment;
 
@@ -577,8 +601,26 @@ impl K8sDeploymentUnit {
                 self.secrets.extend(secret);
                 self.services.push(service);
                 self.deployments.push(deployment);
-                self.traefik_ingresses.push(ingress);
-                self.traefik_middlewares.extend(middlewares);
+                match routing {
+                    RoutingPayload::Traefik {
+                        ingress_route,
+                        middlewares,
+                    } => {
+                        self.traefik_ingresses.push(ingress_route);
+                        for middleware in middlewares {
+                            // The services share the middlewares of the app's access restrictions
+                            if !self
+                                .traefik_middlewares
+                                .iter()
+                                .any(|m| m.metadata.name == middleware.metadata.name)
+                            {
+                                self.traefik_middlewares.push(middleware);
+                            }
+                        }
+                    }
+                    RoutingPayload::Ingress(ingress) => self.ingresses.push(ingress),
+                    RoutingPayload::HttpRoutes(http_routes) => self.http_routes.extend(http_routes),
+                }
             }
         }
     }
@@ -685,6 +727,31 @@ impl K8sDeploymentUnit {
         images
     }
 
+    /// Adds the part of the app's secret that belongs to the service `service_name`.
+    pub(super) fn add_app_secret(&mut self, service_name: &str, secret: Secret) {
+        self.app_secrets.push((service_name.to_string(), secret));
+    }
+
+    pub(super) fn add_middleware_secrets(&mut self, secrets: Vec<Secret>) {
+        self.secrets.extend(secrets);
+    }
+
+    pub(super) fn add_horizontal_pod_autoscaler(&mut self, hpa: HorizontalPodAutoscaler) {
+        self.horizontal_pod_autoscalers.push(hpa);
+    }
+
+    pub(super) fn add_certificate(&mut self, certificate: Certificate) {
+        self.certificates.push(certificate);
+    }
+
+    pub(super) fn add_traefik_tcp_ingress(&mut self, ingress: TraefikIngressRouteTcp) {
+        self.traefik_tcp_ingresses.push(ingress);
+    }
+
+    pub(super) fn add_traefik_udp_ingress(&mut self, ingress: TraefikIngressRouteUdp) {
+        self.traefik_udp_ingresses.push(ingress);
+    }
+
     pub(super) fn apply_image_pull_secret(&mut self, image_pull_secret: Secret) {
         let pull_secret_reference = LocalObjectReference {
             name: Some(image_pull_secret.metadata.name.clone().unwrap_or_default()),
@@ -749,6 +816,9 @@ impl K8sDeploymentUnit {
         for secret in self.secrets {
             create_or_patch(client.clone(), app_name, secret).await?;
         }
+        for (service_name, secret) in self.app_secrets {
+            apply_app_secret(client.clone(), app_name, &service_name, secret).await?;
+        }
         for pvc in self.pvcs {
             create_or_patch(client.clone(), app_name, pvc).await?;
         }
@@ -762,18 +832,36 @@ impl K8sDeploymentUnit {
             let deployment = create_or_patch(client.clone(), app_name, deployment).await?;
             deployments.push(deployment);
         }
+        for hpa in self.horizontal_pod_autoscalers {
+            create_or_patch(client.clone(), app_name, hpa).await?;
+        }
         for job in self.jobs {
             create_or_patch(client.clone(), app_name, job).await?;
         }
         for stateful_set in self.stateful_sets {
             create_or_patch(client.clone(), app_name, stateful_set).await?;
         }
+        for certificate in self.certificates {
+            create_or_patch(client.clone(), app_name, certificate).await?;
+        }
         for ingress in self.traefik_ingresses {
             create_or_patch(client.clone(), app_name, ingress).await?;
         }
+        for ingress in self.traefik_tcp_ingresses {
+            create_or_patch(client.clone(), app_name, ingress).await?;
+        }
+        for ingress in self.traefik_udp_ingresses {
+            create_or_patch(client.clone(), app_name, ingress).await?;
+        }
         for middleware in self.traefik_middlewares {
             create_or_patch(client.clone(), app_name, middleware).await?;
         }
+        for ingress in self.ingresses {
+            create_or_patch(client.clone(), app_name, ingress).await?;
+        }
+        for http_route in self.http_routes {
+            create_or_patch(client.clone(), app_name, http_route).await?;
+        }
         for pod in self.pods {
             create_or_patch(client.clone(), app_name, pod).await?;
         }
@@ -808,10 +896,43 @@ where
     }
 }
 
+/// Applies the keys of the app's secret that belong to `service_name` server-side. Each service
+/// has its own field manager and, thus, keys the service does not use anymore are removed while
+/// the keys of the other services remain untouched.
+pub(super) async fn apply_app_secret(
+    client: Client,
+    app_name: &AppName,
+    service_name: &str,
+    secret: Secret,
+) -> Result<(), Error> {
+    let api = Api::<Secret>::namespaced(client, &app_name.to_rfc1123_namespace_id());
+    let name = secret.metadata.name.clone().unwrap_or_default();
+
+    let has_keys = secret.data.as_ref().is_some_and(|data| !data.is_empty());
+    if !has_keys && api.get_opt(&name).await?.is_none() {
+        return Ok(());
+    }
+
+    api.patch(
+        &name,
+        &PatchParams::apply(&app_secret_field_manager(service_name)).force(),
+        &Patch::Apply(&secret),
+    )
+    .await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use super::super::payloads::deployment_payload;
     use super::*;
-    use crate::{deployment::deployment_unit::DeploymentUnitBuilder, models::ServiceBuilder};
+    use crate::{
+        config::DeploymentStrategy,
+        deployment::deployment_unit::{DeployableService, DeploymentUnitBuilder},
+        infrastructure::TraefikIngressRoute as ServiceIngressRoute,
+        models::{AdditionalContainer, ServiceBuilder},
+        sc,
+    };
     use k8s_openapi::api::{
         apps::v1::DeploymentSpec,
         core::v1::{ContainerPort, EnvVar, PodTemplateSpec},
@@ -823,6 +944,8 @@ mod tests {
 
         let deployment_unit = DeploymentUnitBuilder::init(AppName::master(), Vec::new())
             .extend_with_config(&Default::default())
+            .await
+            .unwrap()
             .extend_with_templating_only_service_configs(Vec::new())
             .extend_with_image_infos(HashMap::new())
             .apply_templating()
@@ -837,9 +960,13 @@ mod tests {
             )
             .build();
 
-        K8sDeploymentUnit::parse_from_log_streams(&deployment_unit, log_streams)
-            .await
-            .unwrap()
+        K8sDeploymentUnit::parse_from_log_streams(
+            &deployment_unit,
+            &Default::default(),
+            log_streams,
+        )
+        .await
+        .unwrap()
     }
 
     #[tokio::test]
@@ -1054,11 +1181,13 @@ mod tests {
                 }),
                 ..Default::default()
             },
-            TraefikIngressRoute {
-                metadata: Default::default(),
-                spec: Default::default(),
+            RoutingPayload::Traefik {
+                ingress_route: TraefikIngressRoute {
+                    metadata: Default::default(),
+                    spec: Default::default(),
+                },
+                middlewares: Vec::new(),
             },
-            Vec::new(),
         );
 
         assert!(unit.secrets.is_empty());
@@ -1117,6 +1246,47 @@ mod tests {
         )
     }
 
+    #[test]
+    fn images_include_init_containers_and_sidecars() {
+        let mut config = sc!("db", "private.example.com/library/mariadb:10.3.17");
+        config.set_init_containers(Some(vec![AdditionalContainer::new(
+            String::from("migration"),
+            Image::from_str("registry.example.com/flyway:9").unwrap(),
+        )]));
+        config.set_sidecars(Some(vec![AdditionalContainer::new(
+            String::from("exporter"),
+            Image::from_str("other.example.com/mysqld-exporter:latest").unwrap(),
+        )]));
+        let service = DeployableService::new(
+            config,
+            DeploymentStrategy::RedeployAlways,
+            ServiceIngressRoute::empty(),
+            Vec::new(),
+        );
+
+        let mut unit = K8sDeploymentUnit::default();
+        unit.merge(
+            None,
+            Service::default(),
+            deployment_payload(&AppName::master(), &service, &Default::default(), &None),
+            RoutingPayload::Ingress(Ingress::default()),
+        );
+
+        let registries = unit
+            .images()
+            .iter()
+            .filter_map(|image| image.registry())
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            registries,
+            HashSet::from([
+                String::from("private.example.com"),
+                String::from("registry.example.com"),
+                String::from("other.example.com"),
+            ])
+        );
+    }
+
     #[tokio::test]
     async fn filter_by_instances_and_replicas() {
         let mut unit = parse_unit(
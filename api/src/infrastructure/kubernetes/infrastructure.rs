@@ -24,50 +24,68 @@
  * =========================LICENSE_END==================================
  */
 use super::super::{
-    APP_NAME_LABEL, CONTAINER_TYPE_LABEL, IMAGE_LABEL, REPLICATED_ENV_LABEL, SERVICE_NAME_LABEL,
-    STORAGE_TYPE_LABEL,
+    APP_NAME_LABEL, CONTAINER_TYPE_LABEL, IMAGE_LABEL, REPLICAS_LABEL, REPLICATED_ENV_LABEL,
+    SERVICE_NAME_LABEL, STORAGE_TYPE_LABEL,
 };
-use super::deployment_unit::K8sDeploymentUnit;
+use super::deployment_unit::{apply_app_secret, K8sDeploymentUnit};
 use super::payloads::{
-    deployment_payload, image_pull_secret_payload, ingress_route_payload, middleware_payload,
+    app_secret_payload, certificate_payload, deployment_payload, horizontal_pod_autoscaler_payload,
+    image_pull_secret_payload, ingress_route_tcp_payloads, ingress_route_udp_payloads,
     namespace_payload, persistent_volume_claim_payload, secrets_payload, service_payload,
-    IngressRoute, Middleware,
+    wait_for_dependencies_init_containers, IngressRoute, IngressRouteTcp, IngressRouteUdp,
+    Middleware,
 };
-use crate::config::{Config as PREvantConfig, ContainerConfig, Runtime};
+use super::routing::{middleware_secrets_payloads, routing_payload, HttpRoute, RoutingPayload};
+use crate::config::{Config as PREvantConfig, ContainerConfig, KubernetesRoutingBackend, Runtime};
 use crate::deployment::deployment_unit::{DeployableService, DeploymentUnit};
 use crate::infrastructure::traefik::TraefikIngressRoute;
-use crate::infrastructure::Infrastructure;
+use crate::infrastructure::{Infrastructure, UnsupportedLogStreams};
 use crate::models::service::{ContainerType, Service, ServiceError, ServiceStatus};
 use crate::models::{
-    AppName, Environment, Image, ServiceBuilder, ServiceBuilderError, ServiceConfig,
+    AppName, Environment, ExecOutput, ExecSession, Image, LogStreams, ServiceBuilder,
+    ServiceBuilderError, ServiceConfig,
 };
 use async_stream::stream;
 use async_trait::async_trait;
 use chrono::{DateTime, FixedOffset, Utc};
-use failure::Error;
+use failure::{format_err, Error};
 use futures::stream::FuturesUnordered;
 use futures::stream::{self, BoxStream};
 use futures::StreamExt;
 use futures::{AsyncBufReadExt, TryStreamExt};
-use k8s_openapi::api::core::v1::PersistentVolumeClaim;
+use k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler;
+use k8s_openapi::api::core::v1::{PersistentVolumeClaim, PodTemplateSpec};
+use k8s_openapi::api::networking::v1::Ingress;
 use k8s_openapi::api::storage::v1::StorageClass;
 use k8s_openapi::api::{
     apps::v1::Deployment as V1Deployment, core::v1::Namespace as V1Namespace,
     core::v1::Pod as V1Pod, core::v1::Secret as V1Secret, core::v1::Service as V1Service,
 };
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Status;
 use kube::{
-    api::{Api, DeleteParams, ListParams, LogParams, Patch, PatchParams, PostParams},
+    api::{
+        Api, AttachParams, DeleteParams, ListParams, LogParams, ObjectMeta, Patch, PatchParams,
+        PostParams,
+    },
     client::Client,
-    config::Config,
+    config::{Config, KubeConfigOptions},
     error::{Error as KubeError, ErrorResponse},
+    Resource, ResourceExt,
 };
 use log::{debug, warn};
 use multimap::MultiMap;
 use secstr::SecUtf8;
-use std::collections::{BTreeMap, HashMap};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::{From, TryFrom};
+use std::fmt::Debug;
 use std::net::IpAddr;
+use std::path::Path;
 use std::str::FromStr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+static RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+static IMAGE_DIGEST_ANNOTATION: &str = "com.aixigo.preview.servant.image-digest";
 
 pub struct KubernetesInfrastructure {
     config: PREvantConfig,
@@ -102,13 +120,30 @@ impl KubernetesInfrastructure {
     }
 
     async fn client(&self) -> Result<Client, KubernetesInfrastructureError> {
-        let configuration = Config::infer().await.map_err(|err| {
-            KubernetesInfrastructureError::UnexpectedError {
+        let context = match self.config.runtime_config() {
+            Runtime::Kubernetes(k8s_config) => k8s_config.context().clone(),
+            _ => None,
+        };
+
+        let configuration = match context {
+            Some(context) => Config::from_kubeconfig(&KubeConfigOptions {
+                context: Some(context.clone()),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| KubernetesInfrastructureError::UnexpectedError {
                 internal_message: format!(
-                    "Failed to read Kube configuration from cluster env: {err}"
+                    "Failed to read Kube configuration of context {context}: {err}"
                 ),
-            }
-        })?;
+            })?,
+            None => Config::infer().await.map_err(|err| {
+                KubernetesInfrastructureError::UnexpectedError {
+                    internal_message: format!(
+                        "Failed to read Kube configuration from cluster env: {err}"
+                    ),
+                }
+            })?,
+        };
 
         Client::try_from(configuration).map_err(|err| {
             KubernetesInfrastructureError::UnexpectedError {
@@ -117,6 +152,22 @@ impl KubernetesInfrastructure {
         })
     }
 
+    /// Returns the name of the pod that runs the service or fails if the service is scaled down.
+    async fn running_pod_name(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<String>, Error> {
+        let Some((_deployment, pod)) = self.get_deployment_and_pod(app_name, service_name).await?
+        else {
+            return Ok(None);
+        };
+
+        pod.and_then(|pod| pod.metadata.name)
+            .map(Some)
+            .ok_or_else(|| format_err!("The service {service_name} of {app_name} does not run"))
+    }
+
     async fn get_deployment_and_pod(
         &self,
         app_name: &AppName,
@@ -167,8 +218,37 @@ impl KubernetesInfrastructure {
     ) -> Result<Service, KubernetesInfrastructureError> {
         let mut builder = ServiceBuilder::try_from(deployment.clone())?;
 
+        // A redeployment records the digest that the registry resolved and that may differ from
+        // the one that the pods report, e.g. for images with multiple platforms.
+        let recorded_digest = deployment
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.template.metadata.as_ref())
+            .and_then(|metadata| metadata.annotations.as_ref())
+            .and_then(|annotations| annotations.get(IMAGE_DIGEST_ANNOTATION))
+            .cloned();
+        if let Some(digest) = &recorded_digest {
+            builder = builder.image_digest(digest.clone());
+        }
+
         if let Some(pod) = pod {
             if let Some(container) = pod.spec.as_ref().and_then(|spec| spec.containers.first()) {
+                let image_id = pod
+                    .status
+                    .as_ref()
+                    .and_then(|status| status.container_statuses.as_ref())
+                    .and_then(|statuses| {
+                        statuses.iter().find(|status| status.name == container.name)
+                    })
+                    .map(|status| status.image_id.as_str());
+                // The image id has the form `docker.io/library/nginx@sha256:…`
+                if let (None, Some((_, digest))) = (
+                    &recorded_digest,
+                    image_id.and_then(|id| id.rsplit_once('@')),
+                ) {
+                    builder = builder.image_digest(digest.to_string());
+                }
+
                 builder = builder.started_at(
                     pod.status
                         .as_ref()
@@ -197,6 +277,40 @@ impl KubernetesInfrastructure {
         Ok(builder.build()?)
     }
 
+    /// Applies `patch` to the pod template of the service's deployment, which makes Kubernetes
+    /// roll out new pods.
+    async fn patch_pod_template<F>(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        patch: F,
+    ) -> Result<Option<Service>, KubernetesInfrastructureError>
+    where
+        F: FnOnce(&mut PodTemplateSpec) + Send,
+    {
+        let Some((mut deployment, pod)) =
+            self.get_deployment_and_pod(app_name, service_name).await?
+        else {
+            return Ok(None);
+        };
+
+        let service = Self::create_service_from_deployment_and_pod(deployment.clone(), pod)?;
+        let Some(spec) = deployment.spec.as_mut() else {
+            return Ok(None);
+        };
+        patch(&mut spec.template);
+
+        Api::<V1Deployment>::namespaced(self.client().await?, &app_name.to_rfc1123_namespace_id())
+            .patch(
+                &deployment.metadata.name.clone().unwrap(),
+                &PatchParams::default(),
+                &Patch::Merge(deployment),
+            )
+            .await?;
+
+        Ok(Some(service))
+    }
+
     async fn get_services_of_app(
         &self,
         app_name: &AppName,
@@ -317,15 +431,10 @@ impl KubernetesInfrastructure {
         &self,
         app_name: &AppName,
         deployable_service: &DeployableService,
+        dependencies: &[&DeployableService],
         container_config: &ContainerConfig,
     ) -> Result<
-        (
-            Option<V1Secret>,
-            V1Service,
-            V1Deployment,
-            IngressRoute,
-            Vec<Middleware>,
-        ),
+        (Option<V1Secret>, V1Service, V1Deployment, RoutingPayload),
         KubernetesInfrastructureError,
     > {
         let secret = deployable_service
@@ -334,7 +443,7 @@ impl KubernetesInfrastructure {
 
         let service = service_payload(app_name, deployable_service);
 
-        let deployment = deployment_payload(
+        let mut deployment = deployment_payload(
             app_name,
             deployable_service,
             container_config,
@@ -343,10 +452,34 @@ impl KubernetesInfrastructure {
                 .await?,
         );
 
-        let ingress_route = ingress_route_payload(app_name, deployable_service);
-        let middlewares = middleware_payload(app_name, deployable_service.ingress_route());
+        let wait_for_dependencies = wait_for_dependencies_init_containers(dependencies);
+        if let Some(pod_spec) = deployment
+            .spec
+            .as_mut()
+            .and_then(|spec| spec.template.spec.as_mut())
+            .filter(|_| !wait_for_dependencies.is_empty())
+        {
+            let init_containers = pod_spec.init_containers.take().unwrap_or_default();
+            pod_spec.init_containers = Some(
+                wait_for_dependencies
+                    .into_iter()
+                    .chain(init_containers)
+                    .collect(),
+            );
+        }
+
+        let routing = routing_payload(&self.routing_backend(), app_name, deployable_service);
 
-        Ok((secret, service, deployment, ingress_route, middlewares))
+        Ok((secret, service, deployment, routing))
+    }
+
+    fn routing_backend(&self) -> KubernetesRoutingBackend {
+        match self.config.runtime_config() {
+            Runtime::Kubernetes(k8s_config) => k8s_config.routing_backend().clone(),
+            Runtime::Docker(_) | Runtime::Podman(_) | Runtime::InMemory(_) => {
+                KubernetesRoutingBackend::default()
+            }
+        }
     }
 
     async fn create_persistent_volume_claim<'a>(
@@ -519,6 +652,7 @@ impl Infrastructure for KubernetesInfrastructure {
             client.clone(),
             self.config.companion_bootstrapping_containers(),
             bootstrap_image_pull_secret,
+            &self.routing_backend(),
         )
         .await?;
 
@@ -526,11 +660,47 @@ impl Infrastructure for KubernetesInfrastructure {
         k8s_deployment_unit.filter_by_instances_and_replicas(&services);
 
         for deployable_service in deployment_unit.services() {
-            let (secret, service, deployment, ingress_route, middlewares) = self
-                .create_payloads(app_name, deployable_service, container_config)
+            let (secret, service, deployment, routing) = self
+                .create_payloads(
+                    app_name,
+                    deployable_service,
+                    &deployment_unit.dependencies_of(deployable_service),
+                    container_config,
+                )
                 .await?;
 
-            k8s_deployment_unit.merge(secret, service, deployment, ingress_route, middlewares);
+            k8s_deployment_unit.merge(secret, service, deployment, routing);
+
+            if let Some(hpa) = horizontal_pod_autoscaler_payload(app_name, deployable_service) {
+                k8s_deployment_unit.add_horizontal_pod_autoscaler(hpa);
+            }
+            for ingress in ingress_route_tcp_payloads(app_name, deployable_service) {
+                k8s_deployment_unit.add_traefik_tcp_ingress(ingress);
+            }
+            for ingress in ingress_route_udp_payloads(app_name, deployable_service) {
+                k8s_deployment_unit.add_traefik_udp_ingress(ingress);
+            }
+        }
+
+        k8s_deployment_unit.add_middleware_secrets(middleware_secrets_payloads(
+            &self.routing_backend(),
+            app_name,
+            deployment_unit.services(),
+        ));
+
+        for deployable_service in deployment_unit.services() {
+            k8s_deployment_unit.add_app_secret(
+                deployable_service.service_name(),
+                app_secret_payload(app_name, deployable_service.env()),
+            );
+        }
+
+        if let Some(certificate) = self
+            .config
+            .tls()
+            .and_then(|tls| certificate_payload(app_name, deployment_unit.app_base_route(), tls))
+        {
+            k8s_deployment_unit.add_certificate(certificate);
         }
 
         if let Some(image_pull_secret) =
@@ -570,14 +740,93 @@ impl Infrastructure for KubernetesInfrastructure {
         Ok(services)
     }
 
+    async fn stop_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        remove_volumes: bool,
+    ) -> Result<Option<Service>, Error> {
+        let Some((deployment, pod)) = self.get_deployment_and_pod(app_name, service_name).await?
+        else {
+            return Ok(None);
+        };
+
+        let client = self.client().await?;
+        let namespace = app_name.to_rfc1123_namespace_id();
+        let prefix = format!("{namespace}-{service_name}");
+
+        let deployment_name = deployment.metadata.name.clone().unwrap_or_default();
+        let service = Self::create_service_from_deployment_and_pod(deployment, pod)?;
+
+        delete_if_exists(
+            &Api::<V1Deployment>::namespaced(client.clone(), &namespace),
+            &deployment_name,
+        )
+        .await?;
+        delete_if_exists(
+            &Api::<V1Service>::namespaced(client.clone(), &namespace),
+            service_name,
+        )
+        .await?;
+        delete_if_exists(
+            &Api::<V1Secret>::namespaced(client.clone(), &namespace),
+            &format!("{prefix}-secret"),
+        )
+        .await?;
+        delete_if_exists(
+            &Api::<HorizontalPodAutoscaler>::namespaced(client.clone(), &namespace),
+            &format!("{prefix}-hpa"),
+        )
+        .await?;
+        // Removes the keys of the service from the app's secret
+        apply_app_secret(
+            client.clone(),
+            app_name,
+            service_name,
+            app_secret_payload(app_name, None),
+        )
+        .await?;
+
+        delete_routes_of_service(
+            client.clone(),
+            &self.routing_backend(),
+            &namespace,
+            service_name,
+        )
+        .await?;
+        // The certificate is requested for all host names of the app and, thus, it is only
+        // removed with the namespace of the app.
+
+        if remove_volumes {
+            let p = ListParams {
+                label_selector: Some(format!(
+                    "{APP_NAME_LABEL}={app_name},{SERVICE_NAME_LABEL}={service_name}"
+                )),
+                ..Default::default()
+            };
+            Api::<PersistentVolumeClaim>::namespaced(client, &namespace)
+                .delete_collection(&DeleteParams::default(), &p)
+                .await?;
+        }
+
+        Ok(Some(service))
+    }
+
     async fn get_logs<'a>(
         &'a self,
         app_name: &'a AppName,
         service_name: &'a str,
         from: &'a Option<DateTime<FixedOffset>>,
         limit: &'a Option<usize>,
+        streams: LogStreams,
         follow: bool,
     ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+        // The log API of pods interleaves stdout and stderr without telling them apart.
+        if streams != LogStreams::Both {
+            return stream::once(async move { Err(UnsupportedLogStreams { streams }.into()) })
+                .boxed();
+        }
+
         let Some((_deployment, Some(pod))) =
             (match self.get_deployment_and_pod(app_name, service_name).await {
                 Ok(result) => result,
@@ -623,6 +872,70 @@ impl Infrastructure for KubernetesInfrastructure {
         }.boxed()
     }
 
+    /// Returns the logs of the terminated container of the pod, which Kubernetes keeps after it
+    /// restarted the container, e.g. because it crashed.
+    async fn get_previous_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        service_name: &'a str,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+        let Some((_deployment, Some(pod))) =
+            (match self.get_deployment_and_pod(app_name, service_name).await {
+                Ok(result) => result,
+                Err(_) => return stream::empty().boxed(),
+            })
+        else {
+            return stream::empty().boxed();
+        };
+
+        stream! {
+            let p = LogParams {
+                previous: true,
+                timestamps: true,
+                ..Default::default()
+            };
+            let client = match self.client().await {
+                Ok(client) => client,
+                Err(err) => {
+                    yield Err(err.into());
+                    return;
+                }
+            };
+            let namespace = app_name.to_rfc1123_namespace_id();
+
+            let logs = match Api::<V1Pod>::namespaced(client, &namespace)
+                .logs(&pod.metadata.name.unwrap(), &p)
+                .await
+            {
+                Ok(logs) => logs,
+                // The container has not been restarted yet.
+                Err(KubeError::Api(ErrorResponse { code, .. })) if code == 400 => return,
+                Err(err) => {
+                    yield Err(err.into());
+                    return;
+                }
+            };
+
+            for line in logs.lines() {
+                let mut iter = line.splitn(2, ' ');
+                match DateTime::parse_from_rfc3339(iter.next().unwrap_or_default()) {
+                    Ok(datetime) => {
+                        let mut log_line: String = iter.collect::<Vec<&str>>().join(" ");
+                        log_line.push('\n');
+                        yield Ok((datetime, log_line))
+                    }
+                    Err(err) => {
+                        yield Err(format_err!(
+                            "Kubernetes returned a log line without valid timestamp: {}",
+                            err
+                        ))
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+
     async fn change_status(
         &self,
         app_name: &AppName,
@@ -645,7 +958,13 @@ impl Infrastructure for KubernetesInfrastructure {
         };
 
         spec.replicas = Some(match status {
-            ServiceStatus::Running => 1,
+            ServiceStatus::Running => deployment
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(REPLICAS_LABEL))
+                .and_then(|replicas| replicas.parse::<i32>().ok())
+                .unwrap_or(1),
             ServiceStatus::Paused => 0,
         });
 
@@ -660,7 +979,264 @@ impl Infrastructure for KubernetesInfrastructure {
         Ok(Some(service))
     }
 
-    async fn base_traefik_ingress_route(&self) -> Result<Option<TraefikIngressRoute>, Error> {
+    /// Restarts the pods of the service like `kubectl rollout restart` does.
+    async fn restart_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<Service>, Error> {
+        let restarted_at = Utc::now().to_rfc3339();
+        self.patch_pod_template(app_name, service_name, |template| {
+            template
+                .metadata
+                .get_or_insert_with(Default::default)
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert(String::from(RESTARTED_AT_ANNOTATION), restarted_at);
+        })
+        .await
+    }
+
+    /// Rolls out new pods that pull the image again. The digest is recorded in an annotation of
+    /// the pod template so that the rollout only restarts pods if the digest changed.
+    async fn redeploy_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        image: &Image,
+        digest: Option<&str>,
+    ) -> Result<Option<Service>, Error> {
+        let image = image.to_string();
+        let digest = digest
+            .map(String::from)
+            .unwrap_or_else(|| Utc::now().to_rfc3339());
+        self.patch_pod_template(app_name, service_name, |template| {
+            template
+                .metadata
+                .get_or_insert_with(Default::default)
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert(String::from(IMAGE_DIGEST_ANNOTATION), digest);
+
+            if let Some(spec) = template.spec.as_mut() {
+                for container in spec
+                    .containers
+                    .iter_mut()
+                    .filter(|container| container.image.as_ref() == Some(&image))
+                {
+                    container.image_pull_policy = Some(String::from("Always"));
+                }
+            }
+        })
+        .await
+    }
+
+    async fn exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecOutput>, Error> {
+        let Some(pod_name) = self.running_pod_name(app_name, service_name).await? else {
+            return Ok(None);
+        };
+
+        let params = AttachParams::default()
+            .container(service_name)
+            .stdin(false)
+            .stdout(true)
+            .stderr(true);
+        let mut process =
+            Api::<V1Pod>::namespaced(self.client().await?, &app_name.to_rfc1123_namespace_id())
+                .exec(&pod_name, command.to_vec(), &params)
+                .await?;
+
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        if let (Some(mut out), Some(mut err)) = (process.stdout(), process.stderr()) {
+            futures::try_join!(
+                out.read_to_string(&mut stdout),
+                err.read_to_string(&mut stderr)
+            )?;
+        }
+
+        let exit_code = match process.take_status() {
+            Some(status) => status.await.as_ref().and_then(exit_code),
+            None => None,
+        };
+
+        Ok(Some(ExecOutput::new(exit_code, stdout, stderr)))
+    }
+
+    async fn attach_exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecSession>, Error> {
+        let Some(pod_name) = self.running_pod_name(app_name, service_name).await? else {
+            return Ok(None);
+        };
+
+        let params = AttachParams::interactive_tty().container(service_name);
+        let mut process =
+            Api::<V1Pod>::namespaced(self.client().await?, &app_name.to_rfc1123_namespace_id())
+                .exec(&pod_name, command.to_vec(), &params)
+                .await?;
+
+        let (Some(input), Some(output)) = (process.stdin(), process.stdout()) else {
+            return Err(format_err!("Cannot attach to the TTY of pod {pod_name}"));
+        };
+
+        if let Some(status) = process.take_status() {
+            let command = command.join(" ");
+            tokio::spawn(async move {
+                debug!("“{command}” in pod {pod_name} exited: {:?}", status.await);
+            });
+        }
+
+        Ok(Some(ExecSession::new(input, output)))
+    }
+
+    /// Archives the path with `tar` in the container, i.e. `tar` must be installed in the image.
+    async fn download_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+    ) -> Result<Option<BoxStream<'static, Result<Vec<u8>, Error>>>, Error> {
+        let Some(pod_name) = self.running_pod_name(app_name, service_name).await? else {
+            return Ok(None);
+        };
+
+        // Like Docker's archives, the archive contains the file or directory itself and not its
+        // complete path.
+        let path = Path::new(path);
+        let (directory, name) = match (path.parent(), path.file_name()) {
+            (Some(directory), Some(name)) => (directory, name.to_string_lossy()),
+            _ => (Path::new("/"), ".".into()),
+        };
+        let command = vec![
+            String::from("tar"),
+            String::from("cf"),
+            String::from("-"),
+            String::from("-C"),
+            directory.to_string_lossy().into_owned(),
+            name.into_owned(),
+        ];
+
+        let params = AttachParams::default()
+            .container(service_name)
+            .stdin(false)
+            .stdout(true)
+            .stderr(true);
+        let mut process =
+            Api::<V1Pod>::namespaced(self.client().await?, &app_name.to_rfc1123_namespace_id())
+                .exec(&pod_name, command, &params)
+                .await?;
+
+        let (Some(mut stdout), stderr, status) =
+            (process.stdout(), process.stderr(), process.take_status())
+        else {
+            return Err(format_err!(
+                "Cannot read the output of tar in pod {pod_name}"
+            ));
+        };
+
+        Ok(Some(
+            stream! {
+                let mut buffer = vec![0u8; 8192];
+                loop {
+                    match stdout.read(&mut buffer).await {
+                        Ok(0) => break,
+                        Ok(read) => yield Ok(buffer[..read].to_vec()),
+                        Err(err) => {
+                            yield Err(Error::from(err));
+                            break;
+                        }
+                    }
+                }
+
+                let status = match status {
+                    Some(status) => status.await,
+                    None => None,
+                };
+                if let Some(code) = status.as_ref().and_then(exit_code).filter(|code| *code != 0) {
+                    let mut message = String::new();
+                    if let Some(mut stderr) = stderr {
+                        let _ = stderr.read_to_string(&mut message).await;
+                    }
+                    yield Err(format_err!("tar exited with {code}: {message}"));
+                }
+            }
+            .boxed(),
+        ))
+    }
+
+    /// Extracts the archive with `tar` in the container, i.e. `tar` must be installed in the
+    /// image.
+    async fn upload_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> Result<bool, Error> {
+        let Some(pod_name) = self.running_pod_name(app_name, service_name).await? else {
+            return Ok(false);
+        };
+
+        let command = vec![
+            String::from("tar"),
+            String::from("xf"),
+            String::from("-"),
+            String::from("-C"),
+            String::from(path),
+        ];
+        let params = AttachParams::default()
+            .container(service_name)
+            .stdin(true)
+            .stdout(false)
+            .stderr(true);
+        let mut process =
+            Api::<V1Pod>::namespaced(self.client().await?, &app_name.to_rfc1123_namespace_id())
+                .exec(&pod_name, command, &params)
+                .await?;
+
+        let Some(mut stdin) = process.stdin() else {
+            return Err(format_err!("Cannot write to tar in pod {pod_name}"));
+        };
+        stdin.write_all(&archive).await?;
+        // Closing the input makes tar finish even if the archive lacks the end-of-archive
+        // marker.
+        stdin.shutdown().await?;
+        drop(stdin);
+
+        let mut message = String::new();
+        if let Some(mut stderr) = process.stderr() {
+            stderr.read_to_string(&mut message).await?;
+        }
+        let status = match process.take_status() {
+            Some(status) => status.await,
+            None => None,
+        };
+
+        match status.as_ref().and_then(exit_code) {
+            Some(0) | None => Ok(true),
+            Some(code) => Err(format_err!(
+                "tar exited with {code} while extracting the archive into {path}: {message}"
+            )),
+        }
+    }
+
+    async fn kubernetes_client(&self) -> Result<Option<Client>, Error> {
+        Ok(Some(self.client().await?))
+    }
+
+    async fn base_traefik_ingress_route(
+        &self,
+        _app_name: &AppName,
+    ) -> Result<Option<TraefikIngressRoute>, Error> {
         let Runtime::Kubernetes(k8s_config) = self.config.runtime_config() else {
             return Ok(None);
         };
@@ -835,6 +1411,152 @@ impl TryFrom<&V1Deployment> for ServiceConfig {
     }
 }
 
+/// Deletes the object with the given name and ignores objects that do not exist (anymore).
+async fn delete_if_exists<K>(api: &Api<K>, name: &str) -> Result<(), KubeError>
+where
+    K: Clone + DeserializeOwned + Debug,
+{
+    match api.delete(name, &DeleteParams::background()).await {
+        Ok(_) => Ok(()),
+        Err(KubeError::Api(ErrorResponse { code, .. })) if code == 404 => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Deletes the routing objects of the service that depend on the routing backend as well as its
+/// TCP and UDP routes.
+async fn delete_routes_of_service(
+    client: Client,
+    routing_backend: &KubernetesRoutingBackend,
+    namespace: &str,
+    service_name: &str,
+) -> Result<(), KubeError> {
+    match routing_backend {
+        KubernetesRoutingBackend::Traefik => {
+            let routes = Api::<IngressRoute>::namespaced(client.clone(), namespace);
+            let (removed_routes, remaining_routes): (Vec<_>, Vec<_>) = routes
+                .list(&ListParams::default())
+                .await?
+                .items
+                .into_iter()
+                .partition(|route| is_object_of_service(&route.metadata, service_name));
+
+            for route in &removed_routes {
+                delete_if_exists(&routes, &route.name_any()).await?;
+            }
+
+            // Middlewares, e.g. the access restrictions of the app, are shared by the routes
+            // of the services and, thus, they must only be removed with their last route.
+            for middleware in unreferenced_middlewares(&removed_routes, &remaining_routes) {
+                delete_if_exists(
+                    &Api::<Middleware>::namespaced(client.clone(), namespace),
+                    &middleware,
+                )
+                .await?;
+                delete_if_exists(
+                    &Api::<V1Secret>::namespaced(client.clone(), namespace),
+                    &middleware,
+                )
+                .await?;
+            }
+        }
+        KubernetesRoutingBackend::Ingress { .. } => {
+            delete_objects_of_service(
+                &Api::<Ingress>::namespaced(client.clone(), namespace),
+                service_name,
+            )
+            .await?
+        }
+        KubernetesRoutingBackend::GatewayApi { .. } => {
+            // A service has multiple HTTPRoutes if its routes are served on different hosts
+            delete_objects_of_service(
+                &Api::<HttpRoute>::namespaced(client.clone(), namespace),
+                service_name,
+            )
+            .await?
+        }
+    }
+    // Services can declare TCP and UDP routes with any routing backend but clusters without
+    // Traefik lack their custom resources, which delete_objects_of_service tolerates.
+    delete_objects_of_service(
+        &Api::<IngressRouteTcp>::namespaced(client.clone(), namespace),
+        service_name,
+    )
+    .await?;
+    delete_objects_of_service(
+        &Api::<IngressRouteUdp>::namespaced(client, namespace),
+        service_name,
+    )
+    .await
+}
+
+/// Deletes the objects that PREvant created for the service. Objects are identified by the labels
+/// of the service or, for objects that were created by earlier versions, by their annotations.
+async fn delete_objects_of_service<K>(api: &Api<K>, service_name: &str) -> Result<(), KubeError>
+where
+    K: Resource + Clone + DeserializeOwned + Debug,
+{
+    let objects = match api.list(&ListParams::default()).await {
+        Ok(objects) => objects.items,
+        // The custom resource is not installed and, thus, there is nothing to delete.
+        Err(KubeError::Api(ErrorResponse { code, .. })) if code == 404 => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for object in objects {
+        if is_object_of_service(object.meta(), service_name) {
+            delete_if_exists(api, &object.name_any()).await?;
+        }
+    }
+    Ok(())
+}
+
+fn is_object_of_service(metadata: &ObjectMeta, service_name: &str) -> bool {
+    [&metadata.labels, &metadata.annotations]
+        .into_iter()
+        .flatten()
+        .any(|values| values.get(SERVICE_NAME_LABEL).map(String::as_str) == Some(service_name))
+}
+
+/// Computes the names of the middlewares that are referenced by the removed routes but not by any
+/// of the remaining routes.
+fn unreferenced_middlewares(
+    removed_routes: &[IngressRoute],
+    remaining_routes: &[IngressRoute],
+) -> BTreeSet<String> {
+    fn middlewares(routes: &[IngressRoute]) -> BTreeSet<String> {
+        routes
+            .iter()
+            .flat_map(|route| route.spec.routes.iter().flatten())
+            .flat_map(|rule| rule.middlewares.iter().flatten())
+            .map(|middleware| middleware.name.clone())
+            .collect()
+    }
+
+    middlewares(removed_routes)
+        .difference(&middlewares(remaining_routes))
+        .cloned()
+        .collect()
+}
+
+/// Extracts the exit code from the status that Kubernetes sends when an exec has finished.
+fn exit_code(status: &Status) -> Option<i64> {
+    if status.status.as_deref() == Some("Success") {
+        return Some(0);
+    }
+
+    status
+        .details
+        .as_ref()?
+        .causes
+        .as_ref()?
+        .iter()
+        .find(|cause| cause.reason.as_deref() == Some("ExitCode"))?
+        .message
+        .as_ref()?
+        .parse()
+        .ok()
+}
+
 impl From<KubeError> for KubernetesInfrastructureError {
     fn from(err: KubeError) -> Self {
         KubernetesInfrastructureError::UnexpectedError {
@@ -871,7 +1593,7 @@ mod tests {
     use super::*;
     use crate::models::EnvironmentVariable;
     use k8s_openapi::api::apps::v1::DeploymentSpec;
-    use kube::api::ObjectMeta;
+    use std::sync::{Arc, Mutex};
 
     macro_rules! deployment_object {
         ($deployment_name:expr, $app_name:expr, $service_name:expr, $image:expr, $container_type:expr, $($a_key:expr => $a_value:expr),*) => {{
@@ -1064,4 +1786,205 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn should_extract_exit_code_of_exec() {
+        let status = serde_json::from_value::<Status>(serde_json::json!({
+            "status": "Failure",
+            "reason": "NonZeroExitCode",
+            "details": {
+                "causes": [{ "reason": "ExitCode", "message": "127" }]
+            }
+        }))
+        .unwrap();
+        assert_eq!(exit_code(&status), Some(127));
+
+        let status =
+            serde_json::from_value::<Status>(serde_json::json!({ "status": "Success" })).unwrap();
+        assert_eq!(exit_code(&status), Some(0));
+    }
+
+    fn ingress_route_with_middlewares(service_name: &str, middlewares: &[&str]) -> IngressRoute {
+        serde_json::from_value(serde_json::json!({
+          "apiVersion": "traefik.containo.us/v1alpha1",
+          "kind": "IngressRoute",
+          "metadata": {
+            "name": format!("master-{service_name}-ingress-route"),
+            "labels": {
+              APP_NAME_LABEL: "master",
+              SERVICE_NAME_LABEL: service_name
+            }
+          },
+          "spec": {
+            "routes": [{
+              "kind": "Rule",
+              "match": format!("PathPrefix(`/master/{service_name}/`)"),
+              "services": [{ "name": service_name }],
+              "middlewares": middlewares
+                .iter()
+                .map(|name| serde_json::json!({ "name": name }))
+                .collect::<Vec<_>>()
+            }]
+          }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn should_identify_objects_of_service_by_labels_and_annotations() {
+        let labelled = ObjectMeta {
+            labels: Some(BTreeMap::from([(
+                SERVICE_NAME_LABEL.to_string(),
+                "db".to_string(),
+            )])),
+            ..Default::default()
+        };
+        let annotated = ObjectMeta {
+            annotations: Some(BTreeMap::from([(
+                SERVICE_NAME_LABEL.to_string(),
+                "db".to_string(),
+            )])),
+            ..Default::default()
+        };
+
+        assert!(is_object_of_service(&labelled, "db"));
+        assert!(is_object_of_service(&annotated, "db"));
+        assert!(!is_object_of_service(&labelled, "api"));
+        assert!(!is_object_of_service(&ObjectMeta::default(), "db"));
+    }
+
+    #[test]
+    fn should_keep_middlewares_that_are_referenced_by_remaining_routes() {
+        let removed_routes = vec![ingress_route_with_middlewares(
+            "db",
+            &["master-access-restriction", "master-db-middleware"],
+        )];
+        let remaining_routes = vec![ingress_route_with_middlewares(
+            "api",
+            &["master-access-restriction", "master-api-middleware"],
+        )];
+
+        assert_eq!(
+            unreferenced_middlewares(&removed_routes, &remaining_routes),
+            BTreeSet::from([String::from("master-db-middleware")])
+        );
+    }
+
+    /// Creates a client of a cluster that lacks Traefik's custom resources and whose lists
+    /// contain one object of the service `db`. The client records the requests.
+    fn client_of_cluster_without_traefik(requests: Arc<Mutex<Vec<String>>>) -> Client {
+        let service = hyper::service::service_fn(move |request: hyper::Request<hyper::Body>| {
+            let requests = requests.clone();
+            async move {
+                let path = request.uri().path().to_string();
+                requests
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {path}", request.method()));
+
+                let (status, body) = if path.starts_with("/apis/traefik.containo.us/") {
+                    (
+                        404,
+                        serde_json::json!({
+                          "apiVersion": "v1",
+                          "kind": "Status",
+                          "metadata": {},
+                          "status": "Failure",
+                          "reason": "NotFound",
+                          "message": "the server could not find the requested resource",
+                          "code": 404
+                        }),
+                    )
+                } else if request.method() == hyper::Method::DELETE {
+                    (
+                        200,
+                        serde_json::json!({
+                          "apiVersion": "v1",
+                          "kind": "Status",
+                          "metadata": {},
+                          "status": "Success"
+                        }),
+                    )
+                } else {
+                    let (api_version, kind) = if path.contains("/ingresses") {
+                        ("networking.k8s.io/v1", "Ingress")
+                    } else {
+                        ("gateway.networking.k8s.io/v1", "HTTPRoute")
+                    };
+                    (
+                        200,
+                        serde_json::json!({
+                          "apiVersion": api_version,
+                          "kind": format!("{kind}List"),
+                          "metadata": {},
+                          "items": [{
+                            "apiVersion": api_version,
+                            "kind": kind,
+                            "metadata": {
+                              "name": "master-db-route",
+                              "namespace": "master",
+                              "labels": {
+                                APP_NAME_LABEL: "master",
+                                SERVICE_NAME_LABEL: "db"
+                              }
+                            },
+                            "spec": { "parentRefs": [], "rules": [] }
+                          }]
+                        }),
+                    )
+                };
+
+                Ok::<_, std::convert::Infallible>(
+                    hyper::Response::builder()
+                        .status(status)
+                        .header("Content-Type", "application/json")
+                        .body(hyper::Body::from(body.to_string()))
+                        .unwrap(),
+                )
+            }
+        });
+        Client::new(service, "default")
+    }
+
+    #[tokio::test]
+    async fn should_delete_ingress_of_service_without_traefik() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        delete_routes_of_service(
+            client_of_cluster_without_traefik(requests.clone()),
+            &KubernetesRoutingBackend::Ingress {
+                ingress_class_name: None,
+                annotations: BTreeMap::new(),
+            },
+            "master",
+            "db",
+        )
+        .await
+        .unwrap();
+
+        assert!(requests.lock().unwrap().contains(&String::from(
+            "DELETE /apis/networking.k8s.io/v1/namespaces/master/ingresses/master-db-route"
+        )));
+    }
+
+    #[tokio::test]
+    async fn should_delete_http_routes_of_service_without_traefik() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        delete_routes_of_service(
+            client_of_cluster_without_traefik(requests.clone()),
+            &KubernetesRoutingBackend::GatewayApi {
+                gateway_name: String::from("gateway"),
+                gateway_namespace: None,
+            },
+            "master",
+            "db",
+        )
+        .await
+        .unwrap();
+
+        assert!(requests.lock().unwrap().contains(&String::from(
+            "DELETE /apis/gateway.networking.k8s.io/v1/namespaces/master/httproutes/master-db-route"
+        )));
+    }
 }
@@ -24,22 +24,25 @@
  * =========================LICENSE_END==================================
  */
 use super::super::{
-    APP_NAME_LABEL, CONTAINER_TYPE_LABEL, IMAGE_LABEL, REPLICATED_ENV_LABEL, SERVICE_NAME_LABEL,
-    STORAGE_TYPE_LABEL,
+    APP_NAME_LABEL, CONTAINER_TYPE_LABEL, IMAGE_LABEL, REPLICAS_LABEL, REPLICATED_ENV_LABEL,
+    SERVICE_NAME_LABEL, STORAGE_TYPE_LABEL,
 };
-use crate::config::{Config, ContainerConfig};
+use crate::config::{Config, ContainerConfig, Tls};
 use crate::deployment::deployment_unit::{DeployableService, DeploymentStrategy};
 use crate::infrastructure::traefik::TraefikMiddleware;
-use crate::infrastructure::{TraefikIngressRoute, TraefikRouterRule};
-use crate::models::{AppName, ServiceConfig};
+use crate::infrastructure::{TraefikIngressRoute, TraefikRouterRule, TraefikTLS};
+use crate::models::{
+    AdditionalContainer, AppName, Environment, PortProtocol, ServiceConfig, ServicePort,
+};
 use base64::{engine::general_purpose, Engine};
 use bytesize::ByteSize;
 use chrono::Utc;
 use k8s_openapi::api::apps::v1::DeploymentSpec;
+use k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler;
 use k8s_openapi::api::core::v1::{
-    Container, ContainerPort, EnvVar, KeyToPath, PersistentVolumeClaim, PersistentVolumeClaimSpec,
-    PersistentVolumeClaimVolumeSource, PodSpec, PodTemplateSpec, ResourceRequirements,
-    SecretVolumeSource, Volume, VolumeMount,
+    Container, ContainerPort, EnvVar, EnvVarSource, KeyToPath, PersistentVolumeClaim,
+    PersistentVolumeClaimSpec, PersistentVolumeClaimVolumeSource, PodSpec, PodTemplateSpec,
+    ResourceRequirements, SecretKeySelector, SecretVolumeSource, Volume, VolumeMount,
 };
 use k8s_openapi::api::networking::v1::Ingress;
 use k8s_openapi::api::{
@@ -102,7 +105,102 @@ pub struct TraefikRuleMiddleware {
 #[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct TraefikTls {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub cert_resolver: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domains: Option<Vec<TraefikTlsDomain>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct TraefikTlsDomain {
+    pub main: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sans: Vec<String>,
+}
+
+impl From<&TraefikTLS> for TraefikTls {
+    fn from(tls: &TraefikTLS) -> Self {
+        Self {
+            cert_resolver: tls.cert_resolver.clone(),
+            secret_name: tls.secret_name.clone(),
+            domains: (!tls.domains.is_empty()).then(|| {
+                tls.domains
+                    .iter()
+                    .map(|domain| TraefikTlsDomain {
+                        main: domain.main.clone(),
+                        sans: domain.sans.clone(),
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+#[derive(CustomResource, Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "cert-manager.io",
+    version = "v1",
+    kind = "Certificate",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateSpec {
+    pub secret_name: String,
+    pub dns_names: Vec<String>,
+    pub issuer_ref: CertificateIssuerRef,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct CertificateIssuerRef {
+    pub name: String,
+    pub kind: String,
+}
+
+#[derive(CustomResource, Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "traefik.containo.us",
+    version = "v1alpha1",
+    kind = "IngressRouteTCP",
+    struct = "IngressRouteTcp",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteTcpSpec {
+    pub entry_points: Option<Vec<String>>,
+    pub routes: Option<Vec<TraefikTcpRuleSpec>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct TraefikTcpRuleSpec {
+    pub r#match: String,
+    pub services: Vec<TraefikPortService>,
+}
+
+#[derive(CustomResource, Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "traefik.containo.us",
+    version = "v1alpha1",
+    kind = "IngressRouteUDP",
+    struct = "IngressRouteUdp",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteUdpSpec {
+    pub entry_points: Option<Vec<String>>,
+    pub routes: Option<Vec<TraefikUdpRuleSpec>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct TraefikUdpRuleSpec {
+    pub services: Vec<TraefikPortService>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct TraefikPortService {
+    pub name: String,
+    pub port: u16,
 }
 
 #[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
@@ -291,9 +389,7 @@ pub fn convert_k8s_ingress_to_traefik_ingress(
         spec: IngressRouteSpec {
             routes: Some(routes),
             entry_points: Some(route.entry_points().clone()),
-            tls: route.tls().as_ref().map(|tls| TraefikTls {
-                cert_resolver: Some(tls.cert_resolver.clone()),
-            }),
+            tls: route.tls().as_ref().map(TraefikTls::from),
         },
     };
 
@@ -304,7 +400,9 @@ pub fn convert_k8s_ingress_to_traefik_ingress(
 /// Namespaces](https://kubernetes.io/docs/tasks/administer-cluster/namespaces/)
 pub fn namespace_payload(app_name: &AppName, config: &Config) -> V1Namespace {
     let annotations = match config.runtime_config() {
-        crate::config::Runtime::Docker => None,
+        crate::config::Runtime::Docker(_)
+        | crate::config::Runtime::Podman(_)
+        | crate::config::Runtime::InMemory(_) => None,
         crate::config::Runtime::Kubernetes(runtime) => {
             let annotations = runtime.annotations().namespace();
 
@@ -345,17 +443,9 @@ pub fn deployment_payload(
     container_config: &ContainerConfig,
     persistent_volume_map: &Option<HashMap<&String, PersistentVolumeClaim>>,
 ) -> V1Deployment {
-    let env = service.env().map(|env| {
-        env.iter()
-            .map(|env| EnvVar {
-                name: env.key().to_string(),
-                value: Some(env.value().unsecure().to_string()),
-                ..Default::default()
-            })
-            .collect()
-    });
+    let env = service.env().map(|env| env_vars(app_name, env));
 
-    let annotations = if let Some(replicated_env) = service
+    let mut annotations = if let Some(replicated_env) = service
         .env()
         .and_then(super::super::replicated_environment_variable_to_json)
     {
@@ -366,6 +456,11 @@ pub fn deployment_payload(
     } else {
         BTreeMap::from([(IMAGE_LABEL.to_string(), service.image().to_string())])
     };
+    // Remembers the number of replicas so that resuming a paused service restores it.
+    annotations.insert(
+        REPLICAS_LABEL.to_string(),
+        service.initial_replicas().to_string(),
+    );
 
     let volume_mounts = service.files().map(|files| {
         let parent_paths = files
@@ -453,6 +548,35 @@ pub fn deployment_payload(
             ..Default::default()
         });
 
+    let init_containers = if service.init_containers().is_empty() {
+        None
+    } else {
+        Some(
+            service
+                .init_containers()
+                .iter()
+                .map(|container| additional_container(app_name, container, &resources))
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    let mut containers = vec![Container {
+        name: service.service_name().to_string(),
+        image: Some(service.image().to_string()),
+        image_pull_policy: Some(String::from("Always")),
+        env,
+        volume_mounts,
+        ports: Some(container_ports(service)),
+        resources: resources.clone(),
+        ..Default::default()
+    }];
+    containers.extend(
+        service
+            .sidecars()
+            .iter()
+            .map(|sidecar| additional_container(app_name, sidecar, &resources)),
+    );
+
     let labels = BTreeMap::from([
         (APP_NAME_LABEL.to_string(), app_name.to_string()),
         (
@@ -478,7 +602,11 @@ pub fn deployment_payload(
             ..Default::default()
         },
         spec: Some(DeploymentSpec {
-            replicas: Some(1),
+            // The horizontal pod autoscaler owns the replicas if the service is autoscaled.
+            replicas: match service.autoscaling() {
+                Some(_) => None,
+                None => Some(service.initial_replicas() as i32),
+            },
             selector: LabelSelector {
                 match_labels: Some(labels.clone()),
                 ..Default::default()
@@ -491,19 +619,8 @@ pub fn deployment_payload(
                 }),
                 spec: Some(PodSpec {
                     volumes,
-                    containers: vec![Container {
-                        name: service.service_name().to_string(),
-                        image: Some(service.image().to_string()),
-                        image_pull_policy: Some(String::from("Always")),
-                        env,
-                        volume_mounts,
-                        ports: Some(vec![ContainerPort {
-                            container_port: service.port() as i32,
-                            ..Default::default()
-                        }]),
-                        resources,
-                        ..Default::default()
-                    }],
+                    init_containers,
+                    containers,
                     ..Default::default()
                 }),
             },
@@ -513,6 +630,107 @@ pub fn deployment_payload(
     }
 }
 
+fn env_vars(app_name: &AppName, env: &Environment) -> Vec<EnvVar> {
+    env.iter()
+        .map(|env| match env.secret_key() {
+            Some(secret_key) => EnvVar {
+                name: env.key().to_string(),
+                value_from: Some(EnvVarSource {
+                    secret_key_ref: Some(SecretKeySelector {
+                        name: Some(app_secret_name(app_name)),
+                        key: secret_key.clone(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            None => EnvVar {
+                name: env.key().to_string(),
+                value: Some(env.value().unsecure().to_string()),
+                ..Default::default()
+            },
+        })
+        .collect()
+}
+
+fn container_ports(service: &ServiceConfig) -> Vec<ContainerPort> {
+    std::iter::once(ContainerPort {
+        container_port: service.port() as i32,
+        ..Default::default()
+    })
+    .chain(
+        additional_ports(service)
+            .into_iter()
+            .map(|port| ContainerPort {
+                name: Some(port.name().clone()),
+                container_port: port.port() as i32,
+                protocol: Some(protocol_name(port.protocol()).to_string()),
+                ..Default::default()
+            }),
+    )
+    .collect()
+}
+
+/// The declared ports of the service without those that equal the HTTP port of the service or a
+/// port declared before because Kubernetes rejects duplicate ports.
+fn additional_ports(service: &ServiceConfig) -> Vec<&ServicePort> {
+    let mut ports = HashSet::from([(service.port(), PortProtocol::Tcp)]);
+    service
+        .ports()
+        .iter()
+        .filter(|port| ports.insert((port.port(), port.protocol())))
+        .collect()
+}
+
+fn protocol_name(protocol: PortProtocol) -> &'static str {
+    match protocol {
+        PortProtocol::Tcp => "TCP",
+        PortProtocol::Udp => "UDP",
+    }
+}
+
+/// Init containers and sidecars get the same resource limits as the service container.
+fn additional_container(
+    app_name: &AppName,
+    container: &AdditionalContainer,
+    resources: &Option<ResourceRequirements>,
+) -> Container {
+    Container {
+        name: container.name().to_string(),
+        image: Some(container.image().to_string()),
+        image_pull_policy: Some(String::from("Always")),
+        env: container.env().map(|env| env_vars(app_name, env)),
+        args: container.args().cloned(),
+        resources: resources.clone(),
+        ..Default::default()
+    }
+}
+
+/// Creates init containers that block the start of a service until the Kubernetes services of its
+/// dependencies have ready endpoints, i.e. until they accept TCP connections.
+pub fn wait_for_dependencies_init_containers(
+    dependencies: &[&DeployableService],
+) -> Vec<Container> {
+    dependencies
+        .iter()
+        .map(|dependency| Container {
+            name: format!("wait-for-{}", dependency.service_name()),
+            image: Some(String::from("docker.io/library/busybox:stable")),
+            command: Some(vec![
+                String::from("sh"),
+                String::from("-c"),
+                format!(
+                    "until nc -z -w 2 {service} {port}; do echo waiting for {service}; sleep 2; done",
+                    service = dependency.service_name(),
+                    port = dependency.port()
+                ),
+            ]),
+            ..Default::default()
+        })
+        .collect()
+}
+
 /// Creates the value of an [annotations object](https://kubernetes.io/docs/concepts/overview/working-with-objects/annotations/)
 /// so that the underlying pod will be deployed according to its [deployment strategy](`DeploymentStrategy`).
 ///
@@ -563,6 +781,139 @@ pub fn secrets_payload(
     .expect("Cannot convert value to core/v1/Secret")
 }
 
+/// Creates a [Horizontal Pod
+/// Autoscaler](https://kubernetes.io/docs/tasks/run-application/horizontal-pod-autoscale/) for
+/// services that have an autoscaling configuration.
+pub fn horizontal_pod_autoscaler_payload(
+    app_name: &AppName,
+    service: &DeployableService,
+) -> Option<HorizontalPodAutoscaler> {
+    let autoscaling = service.autoscaling()?;
+
+    Some(
+        serde_json::from_value(serde_json::json!({
+          "apiVersion": "autoscaling/v2",
+          "kind": "HorizontalPodAutoscaler",
+          "metadata": {
+            "name": format!("{}-{}-hpa", app_name.to_rfc1123_namespace_id(), service.service_name()),
+            "namespace": app_name.to_rfc1123_namespace_id(),
+            "labels": {
+              APP_NAME_LABEL: app_name,
+              SERVICE_NAME_LABEL: service.service_name(),
+              CONTAINER_TYPE_LABEL: service.container_type().to_string()
+            }
+          },
+          "spec": {
+            "scaleTargetRef": {
+              "apiVersion": "apps/v1",
+              "kind": "Deployment",
+              "name": format!("{}-{}-deployment", app_name.to_rfc1123_namespace_id(), service.service_name())
+            },
+            "minReplicas": autoscaling.min_replicas(),
+            "maxReplicas": autoscaling.max_replicas(),
+            "metrics": [{
+              "type": "Resource",
+              "resource": {
+                "name": "cpu",
+                "target": {
+                  "type": "Utilization",
+                  "averageUtilization": autoscaling.target_cpu_utilization()
+                }
+              }
+            }]
+          }
+        }))
+        .expect("Cannot convert value to autoscaling/v2/HorizontalPodAutoscaler"),
+    )
+}
+
+/// Creates a [cert-manager Certificate](https://cert-manager.io/docs/usage/certificate/) that
+/// covers all hosts of the app if cert-manager is configured to issue the certificates. The
+/// Traefik routes of the app refer to the resulting secret.
+pub fn certificate_payload(
+    app_name: &AppName,
+    app_route: &TraefikIngressRoute,
+    tls: &Tls,
+) -> Option<Certificate> {
+    let Tls::CertManager {
+        issuer_name,
+        issuer_kind,
+    } = tls
+    else {
+        return None;
+    };
+
+    let dns_names = app_route.certificate_dns_names();
+    if dns_names.is_empty() {
+        return None;
+    }
+
+    Some(Certificate {
+        metadata: ObjectMeta {
+            name: Some(Tls::secret_name(app_name)),
+            namespace: Some(app_name.to_rfc1123_namespace_id()),
+            labels: Some(BTreeMap::from([(
+                APP_NAME_LABEL.to_string(),
+                app_name.to_string(),
+            )])),
+            ..Default::default()
+        },
+        spec: CertificateSpec {
+            secret_name: Tls::secret_name(app_name),
+            dns_names,
+            issuer_ref: CertificateIssuerRef {
+                name: issuer_name.clone(),
+                kind: issuer_kind.clone(),
+            },
+        },
+    })
+}
+
+fn app_secret_name(app_name: &AppName) -> String {
+    format!("{}-secrets", app_name.to_rfc1123_namespace_id())
+}
+
+/// Creates the part of the app's [Kubernetes Secret](https://kubernetes.io/docs/concepts/configuration/secret/)
+/// that holds the secrets of a service which are exposed as environment variables through `env`.
+/// The service refers to these keys through `secretKeyRef`.
+///
+/// The payload is meant to be applied server-side with a field manager per service, see
+/// [`app_secret_field_manager`], so that keys the service does not use anymore are removed.
+pub fn app_secret_payload(app_name: &AppName, env: Option<&Environment>) -> V1Secret {
+    let secrets = env
+        .into_iter()
+        .flat_map(|env| env.iter())
+        .filter_map(|env| {
+            env.secret_key().map(|secret_key| {
+                (
+                    secret_key.clone(),
+                    Value::String(general_purpose::STANDARD.encode(env.value().unsecure())),
+                )
+            })
+        })
+        .collect::<Map<String, Value>>();
+
+    serde_json::from_value(serde_json::json!({
+      "apiVersion": "v1",
+      "kind": "Secret",
+      "metadata": {
+        "name": app_secret_name(app_name),
+        "namespace": app_name.to_rfc1123_namespace_id(),
+        "labels": {
+          APP_NAME_LABEL: app_name,
+        }
+      },
+      "type": "Opaque",
+      "data": secrets
+    }))
+    .expect("Cannot convert value to core/v1/Secret")
+}
+
+/// The field manager that owns the keys of the app's secret that belong to `service_name`.
+pub fn app_secret_field_manager(service_name: &str) -> String {
+    format!("prevant-{service_name}")
+}
+
 pub fn image_pull_secret_payload(
     app_name: &AppName,
     registries_and_credentials: BTreeMap<String, (&str, &SecUtf8)>,
@@ -617,6 +968,21 @@ pub fn image_pull_secret_payload(
 
 /// Creates a JSON payload suitable for [Kubernetes' Services](https://kubernetes.io/docs/concepts/services-networking/service/)
 pub fn service_payload(app_name: &AppName, service_config: &ServiceConfig) -> V1Service {
+    let ports = std::iter::once(serde_json::json!({
+      "name": service_config.service_name(),
+      "targetPort": service_config.port(),
+      "port": service_config.port()
+    }))
+    .chain(additional_ports(service_config).into_iter().map(|port| {
+        serde_json::json!({
+          "name": port.name(),
+          "targetPort": port.port(),
+          "port": port.port(),
+          "protocol": protocol_name(port.protocol())
+        })
+    }))
+    .collect::<Vec<_>>();
+
     serde_json::from_value(serde_json::json!({
       "apiVersion": "v1",
       "kind": "Service",
@@ -628,13 +994,7 @@ pub fn service_payload(app_name: &AppName, service_config: &ServiceConfig) -> V1
         CONTAINER_TYPE_LABEL: service_config.container_type().to_string()
       },
       "spec": {
-        "ports": [
-          {
-            "name": service_config.service_name(),
-            "targetPort": service_config.port(),
-            "port": service_config.port()
-          }
-        ],
+        "ports": ports,
         "selector": {
           APP_NAME_LABEL: app_name,
           SERVICE_NAME_LABEL: service_config.service_name(),
@@ -696,6 +1056,7 @@ pub fn ingress_route_payload(app_name: &AppName, service: &DeployableService) ->
                 service.service_name()
             )),
             namespace: Some(app_name.to_rfc1123_namespace_id()),
+            labels: Some(service_labels(app_name, service.service_name())),
             annotations: Some(BTreeMap::from([
                 (APP_NAME_LABEL.to_string(), app_name.to_string()),
                 (
@@ -716,46 +1077,195 @@ pub fn ingress_route_payload(app_name: &AppName, service: &DeployableService) ->
         spec: IngressRouteSpec {
             routes: Some(rules),
             entry_points: Some(route.entry_points().clone()),
-            tls: route.tls().as_ref().map(|tls| TraefikTls {
-                cert_resolver: Some(tls.cert_resolver.clone()),
-            }),
+            tls: route.tls().as_ref().map(TraefikTls::from),
         },
     }
 }
 
+/// Creates [TCP routes](https://doc.traefik.io/traefik/routing/routers/#configuring-tcp-routers)
+/// for the TCP ports of `service` that declare an entry point. Without TLS, Traefik cannot
+/// distinguish TCP connections by host name and, thus, the entry point is dedicated to one
+/// service.
+pub fn ingress_route_tcp_payloads(
+    app_name: &AppName,
+    service: &DeployableService,
+) -> Vec<IngressRouteTcp> {
+    service
+        .ports()
+        .iter()
+        .filter(|port| port.protocol() == PortProtocol::Tcp)
+        .filter_map(|port| port.entry_point().map(|entry_point| (port, entry_point)))
+        .map(|(port, entry_point)| IngressRouteTcp {
+            metadata: port_route_metadata(app_name, service, port.name(), "ingress-route-tcp"),
+            spec: IngressRouteTcpSpec {
+                entry_points: Some(vec![entry_point.clone()]),
+                routes: Some(vec![TraefikTcpRuleSpec {
+                    r#match: String::from("HostSNI(`*`)"),
+                    services: vec![TraefikPortService {
+                        name: service.service_name().to_string(),
+                        port: port.port(),
+                    }],
+                }]),
+            },
+        })
+        .collect()
+}
+
+/// Creates [UDP routes](https://doc.traefik.io/traefik/routing/routers/#configuring-udp-routers)
+/// for the UDP ports of `service` that declare an entry point.
+pub fn ingress_route_udp_payloads(
+    app_name: &AppName,
+    service: &DeployableService,
+) -> Vec<IngressRouteUdp> {
+    service
+        .ports()
+        .iter()
+        .filter(|port| port.protocol() == PortProtocol::Udp)
+        .filter_map(|port| port.entry_point().map(|entry_point| (port, entry_point)))
+        .map(|(port, entry_point)| IngressRouteUdp {
+            metadata: port_route_metadata(app_name, service, port.name(), "ingress-route-udp"),
+            spec: IngressRouteUdpSpec {
+                entry_points: Some(vec![entry_point.clone()]),
+                routes: Some(vec![TraefikUdpRuleSpec {
+                    services: vec![TraefikPortService {
+                        name: service.service_name().to_string(),
+                        port: port.port(),
+                    }],
+                }]),
+            },
+        })
+        .collect()
+}
+
+fn port_route_metadata(
+    app_name: &AppName,
+    service: &DeployableService,
+    port_name: &str,
+    suffix: &str,
+) -> ObjectMeta {
+    ObjectMeta {
+        name: Some(format!(
+            "{}-{}-{}-{}",
+            app_name.to_rfc1123_namespace_id(),
+            service.service_name(),
+            port_name,
+            suffix
+        )),
+        namespace: Some(app_name.to_rfc1123_namespace_id()),
+        labels: Some(service_labels(app_name, service.service_name())),
+        annotations: Some(BTreeMap::from([
+            (APP_NAME_LABEL.to_string(), app_name.to_string()),
+            (
+                SERVICE_NAME_LABEL.to_string(),
+                service.service_name().to_string(),
+            ),
+            (
+                CONTAINER_TYPE_LABEL.to_string(),
+                service.container_type().to_string(),
+            ),
+        ])),
+        ..Default::default()
+    }
+}
+
+/// The labels of the objects that belong to a single service so that they can be found with a
+/// label selector when the service is removed from the app.
+pub fn service_labels(app_name: &AppName, service_name: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (APP_NAME_LABEL.to_string(), app_name.to_string()),
+        (SERVICE_NAME_LABEL.to_string(), service_name.to_string()),
+    ])
+}
+
 /// See [Traefik Routers](https://docs.traefik.io/v2.0/user-guides/crd-acme/#traefik-routers)
 /// for more information.
 pub fn middleware_payload(
     app_name: &AppName,
     ingress_route: &TraefikIngressRoute,
 ) -> Vec<Middleware> {
-    ingress_route
-        .routes()
-        .iter()
-        .flat_map(|r| {
-            r.middlewares()
-                .iter()
-                .filter_map(|middleware| match middleware {
-                    TraefikMiddleware::Ref(_) => None,
-                    TraefikMiddleware::Spec { name, spec } => Some((
-                        AppName::from_str(name)
-                            .map(|app_name| app_name.to_rfc1123_namespace_id())
-                            .unwrap_or_else(|_| name.clone()),
-                        spec,
-                    )),
-                })
+    middleware_specs(ingress_route)
+        .map(|(name, spec)| {
+            // Traefik's middleware CRD does not accept the users of the basic auth inline but
+            // only as a reference to a secret, see middleware_secrets_payload.
+            let spec = match basic_auth_users(&spec) {
+                Some(_) => serde_json::json!({ "basicAuth": { "secret": name } }),
+                None => spec,
+            };
+
+            Middleware {
+                metadata: ObjectMeta {
+                    name: Some(name),
+                    namespace: Some(app_name.to_rfc1123_namespace_id()),
+                    ..Default::default()
+                },
+                spec: MiddlewareSpec(spec),
+            }
         })
-        .map(|(name, spec)| Middleware {
-            metadata: ObjectMeta {
-                name: Some(name),
-                namespace: Some(app_name.to_rfc1123_namespace_id()),
-                ..Default::default()
-            },
-            spec: MiddlewareSpec(serde_json::json!(spec)),
+        .collect::<Vec<_>>()
+}
+
+/// Creates the secrets that hold the users of the
+/// [basic auth middlewares](https://doc.traefik.io/traefik/middlewares/http/basicauth/#users)
+/// of the routes. Middlewares that are shared by the routes, e.g. the access restrictions of the
+/// app, result in a single secret.
+pub fn middleware_secrets_payload<'a>(
+    app_name: &AppName,
+    ingress_routes: impl IntoIterator<Item = &'a TraefikIngressRoute>,
+) -> Vec<V1Secret> {
+    let mut names = HashSet::new();
+    ingress_routes
+        .into_iter()
+        .flat_map(middleware_specs)
+        .filter_map(|(name, spec)| basic_auth_users(&spec).map(|users| (name, users)))
+        .filter(|(name, _)| names.insert(name.clone()))
+        .map(|(name, users)| {
+            serde_json::from_value(serde_json::json!({
+              "apiVersion": "v1",
+              "kind": "Secret",
+              "metadata": {
+                "name": name,
+                "namespace": app_name.to_rfc1123_namespace_id(),
+                "labels": {
+                  APP_NAME_LABEL: app_name,
+                }
+              },
+              "type": "Opaque",
+              "data": {
+                "users": general_purpose::STANDARD.encode(users.join("\n"))
+              }
+            }))
+            .expect("Cannot convert value to core/v1/Secret")
         })
         .collect::<Vec<_>>()
 }
 
+fn middleware_specs(
+    ingress_route: &TraefikIngressRoute,
+) -> impl Iterator<Item = (String, Value)> + '_ {
+    ingress_route.routes().iter().flat_map(|r| {
+        r.middlewares()
+            .iter()
+            .filter_map(|middleware| match middleware {
+                TraefikMiddleware::Ref(_) => None,
+                TraefikMiddleware::Spec { name, spec } => Some((
+                    AppName::from_str(name)
+                        .map(|app_name| app_name.to_rfc1123_namespace_id())
+                        .unwrap_or_else(|_| name.clone()),
+                    serde_json::json!(spec),
+                )),
+            })
+    })
+}
+
+fn basic_auth_users(spec: &Value) -> Option<Vec<String>> {
+    spec.pointer("/basicAuth/users")?.as_array().map(|users| {
+        users
+            .iter()
+            .filter_map(|user| user.as_str().map(String::from))
+            .collect()
+    })
+}
+
 pub fn pvc_volume_mount_payload(
     path: &str,
     persitent_volume_claim: &PersistentVolumeClaim,
@@ -851,7 +1361,10 @@ pub fn persistent_volume_claim_payload(
 mod tests {
     use super::*;
     use crate::infrastructure::{TraefikIngressRoute, TraefikRouterRule};
-    use crate::models::{AppName, Environment, EnvironmentVariable};
+    use crate::models::{
+        AdditionalContainer, AppName, Autoscaling, Environment, EnvironmentVariable, Image,
+        PortProtocol, ServicePort,
+    };
     use crate::sc;
     use std::str::FromStr;
 
@@ -1321,19 +1834,77 @@ mod tests {
     }
 
     #[test]
-    fn should_create_deployment_payload_with_persistent_volume_claim() {
-        let config = sc!("db", "mariadb:10.3.17");
+    fn should_store_users_of_basic_auth_middleware_in_secret() {
+        let config = crate::config_from_str!(
+            r#"
+            [[accessRestrictions]]
+            type = 'basicAuth'
+            username = 'user'
+            password = 'password'
+            "#
+        );
+        let app_name = AppName::master();
+        let basic_auth = config.access_restrictions(&app_name)[0].traefik_middleware(&app_name);
+        let users = match &basic_auth {
+            TraefikMiddleware::Spec { spec, .. } => basic_auth_users(&serde_json::json!(spec)),
+            TraefikMiddleware::Ref(_) => None,
+        }
+        .unwrap();
+        let mut db_route = TraefikIngressRoute::with_defaults(&app_name, "db");
+        db_route.prepend_middlewares(&[basic_auth.clone()]);
+        let mut api_route = TraefikIngressRoute::with_defaults(&app_name, "api");
+        api_route.prepend_middlewares(&[basic_auth]);
 
-        let persistent_volume_claim = PersistentVolumeClaim {
-            metadata: ObjectMeta {
-                name: Some(String::from("master-db-pvc-abc")),
-                namespace: Some(String::from("master")),
-                labels: Some(BTreeMap::from([
-                    (APP_NAME_LABEL.to_owned(), "master".to_owned()),
-                    (SERVICE_NAME_LABEL.to_owned(), "db".to_owned()),
-                    (STORAGE_TYPE_LABEL.to_owned(), "data".to_owned()),
-                ])),
-                ..Default::default()
+        let middlewares = middleware_payload(&app_name, &db_route);
+        let secrets = middleware_secrets_payload(&app_name, [&db_route, &api_route]);
+
+        assert_json_diff::assert_json_include!(
+            actual: middlewares,
+            expected: serde_json::json!([{
+              "metadata": {
+                "name": "master-basic-auth",
+                "namespace": "master",
+              },
+              "spec": {
+                "basicAuth": {
+                  "secret": "master-basic-auth"
+                }
+              },
+            }, {
+              "metadata": {
+                "name": "master-db-middleware",
+              },
+            }]),
+        );
+        assert_eq!(secrets.len(), 1);
+        assert_json_diff::assert_json_include!(
+            actual: secrets,
+            expected: serde_json::json!([{
+              "metadata": {
+                "name": "master-basic-auth",
+                "namespace": "master",
+              },
+              "data": {
+                "users": general_purpose::STANDARD.encode(users.join("\n"))
+              },
+            }]),
+        );
+    }
+
+    #[test]
+    fn should_create_deployment_payload_with_persistent_volume_claim() {
+        let config = sc!("db", "mariadb:10.3.17");
+
+        let persistent_volume_claim = PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some(String::from("master-db-pvc-abc")),
+                namespace: Some(String::from("master")),
+                labels: Some(BTreeMap::from([
+                    (APP_NAME_LABEL.to_owned(), "master".to_owned()),
+                    (SERVICE_NAME_LABEL.to_owned(), "db".to_owned()),
+                    (STORAGE_TYPE_LABEL.to_owned(), "data".to_owned()),
+                ])),
+                ..Default::default()
             },
             spec: Some(PersistentVolumeClaimSpec {
                 storage_class_name: Some("local-path".to_owned()),
@@ -1588,6 +2159,403 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_create_deployment_with_replicas() {
+        let mut config = sc!("db", "mariadb:10.3.17");
+        config.set_replicas(Some(3));
+
+        let payload = deployment_payload(
+            &AppName::master(),
+            &DeployableService::new(
+                config,
+                DeploymentStrategy::RedeployAlways,
+                TraefikIngressRoute::with_rule(TraefikRouterRule::path_prefix_rule(&[
+                    "master", "db",
+                ])),
+                Vec::new(),
+            ),
+            &ContainerConfig::default(),
+            &None,
+        );
+
+        assert_json_diff::assert_json_include!(
+            actual: payload,
+            expected: serde_json::json!({
+              "metadata": {
+                "annotations": {
+                  "com.aixigo.preview.servant.replicas": "3"
+                }
+              },
+              "spec": {
+                "replicas": 3
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_deployment_with_init_containers_and_sidecars() {
+        let mut config = sc!("db", "mariadb:10.3.17");
+        config.set_init_containers(Some(vec![AdditionalContainer::new(
+            String::from("migration"),
+            Image::from_str("flyway/flyway:9").unwrap(),
+        )]));
+        config.set_sidecars(Some(vec![AdditionalContainer::new(
+            String::from("exporter"),
+            Image::from_str("prom/mysqld-exporter").unwrap(),
+        )]));
+
+        let payload = deployment_payload(
+            &AppName::master(),
+            &DeployableService::new(
+                config,
+                DeploymentStrategy::RedeployAlways,
+                TraefikIngressRoute::with_rule(TraefikRouterRule::path_prefix_rule(&[
+                    "master", "db",
+                ])),
+                Vec::new(),
+            ),
+            &ContainerConfig::default(),
+            &None,
+        );
+
+        assert_json_diff::assert_json_include!(
+            actual: payload,
+            expected: serde_json::json!({
+              "spec": {
+                "template": {
+                  "spec": {
+                    "initContainers": [{
+                      "name": "migration",
+                      "image": "docker.io/flyway/flyway:9",
+                      "imagePullPolicy": "Always"
+                    }],
+                    "containers": [{
+                      "name": "db",
+                      "image": "docker.io/library/mariadb:10.3.17"
+                    }, {
+                      "name": "exporter",
+                      "image": "docker.io/prom/mysqld-exporter:latest",
+                      "imagePullPolicy": "Always"
+                    }]
+                  }
+                }
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_init_containers_waiting_for_dependencies() {
+        let db = DeployableService::new(
+            sc!("db", "mariadb:10.3.17"),
+            DeploymentStrategy::RedeployAlways,
+            TraefikIngressRoute::with_rule(TraefikRouterRule::path_prefix_rule(&["master", "db"])),
+            Vec::new(),
+        );
+
+        let containers = wait_for_dependencies_init_containers(&[&db]);
+
+        assert_json_diff::assert_json_eq!(
+            containers,
+            serde_json::json!([{
+              "name": "wait-for-db",
+              "image": "docker.io/library/busybox:stable",
+              "command": [
+                "sh",
+                "-c",
+                "until nc -z -w 2 db 80; do echo waiting for db; sleep 2; done"
+              ]
+            }])
+        );
+    }
+
+    #[test]
+    fn should_create_tcp_and_udp_routes_for_ports_with_entry_points() {
+        let mut config = sc!("db", "postgres:16");
+        config.set_ports(Some(vec![
+            ServicePort::new(
+                String::from("sql"),
+                5432,
+                PortProtocol::Tcp,
+                Some(String::from("postgres")),
+            ),
+            ServicePort::new(String::from("metrics"), 9187, PortProtocol::Tcp, None),
+            ServicePort::new(
+                String::from("stats"),
+                8125,
+                PortProtocol::Udp,
+                Some(String::from("statsd")),
+            ),
+        ]));
+        let service = DeployableService::new(
+            config,
+            DeploymentStrategy::RedeployAlways,
+            TraefikIngressRoute::with_rule(TraefikRouterRule::path_prefix_rule(&["master", "db"])),
+            Vec::new(),
+        );
+
+        let tcp_routes = ingress_route_tcp_payloads(&AppName::master(), &service);
+        assert_json_diff::assert_json_eq!(
+            tcp_routes,
+            serde_json::json!([{
+              "apiVersion": "traefik.containo.us/v1alpha1",
+              "kind": "IngressRouteTCP",
+              "metadata": {
+                "name": "master-db-sql-ingress-route-tcp",
+                "namespace": "master",
+                "labels": {
+                  "com.aixigo.preview.servant.app-name": "master",
+                  "com.aixigo.preview.servant.service-name": "db"
+                },
+                "annotations": {
+                  "com.aixigo.preview.servant.app-name": "master",
+                  "com.aixigo.preview.servant.container-type": "instance",
+                  "com.aixigo.preview.servant.service-name": "db"
+                }
+              },
+              "spec": {
+                "entryPoints": [ "postgres" ],
+                "routes": [{
+                  "match": "HostSNI(`*`)",
+                  "services": [{ "name": "db", "port": 5432 }]
+                }]
+              }
+            }])
+        );
+
+        let udp_routes = ingress_route_udp_payloads(&AppName::master(), &service);
+        assert_json_diff::assert_json_include!(
+            actual: udp_routes,
+            expected: serde_json::json!([{
+              "kind": "IngressRouteUDP",
+              "metadata": {
+                "name": "master-db-stats-ingress-route-udp"
+              },
+              "spec": {
+                "entryPoints": [ "statsd" ],
+                "routes": [{
+                  "services": [{ "name": "db", "port": 8125 }]
+                }]
+              }
+            }])
+        );
+
+        let k8s_service = service_payload(&AppName::master(), &service);
+        assert_json_diff::assert_json_include!(
+            actual: k8s_service,
+            expected: serde_json::json!({
+              "spec": {
+                "ports": [
+                  { "name": "db", "port": 80, "targetPort": 80 },
+                  { "name": "sql", "port": 5432, "targetPort": 5432, "protocol": "TCP" },
+                  { "name": "metrics", "port": 9187, "targetPort": 9187, "protocol": "TCP" },
+                  { "name": "stats", "port": 8125, "targetPort": 8125, "protocol": "UDP" }
+                ]
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_not_duplicate_ports_that_equal_the_service_port() {
+        let mut config = sc!("keycloak", "keycloak/keycloak:24");
+        config.set_port(8080);
+        config.set_ports(Some(vec![
+            ServicePort::new(String::from("http"), 8080, PortProtocol::Tcp, None),
+            ServicePort::new(String::from("discovery"), 8080, PortProtocol::Udp, None),
+            ServicePort::new(String::from("management"), 9000, PortProtocol::Tcp, None),
+            ServicePort::new(String::from("health"), 9000, PortProtocol::Tcp, None),
+        ]));
+        let service = DeployableService::new(
+            config,
+            DeploymentStrategy::RedeployAlways,
+            TraefikIngressRoute::empty(),
+            Vec::new(),
+        );
+
+        let k8s_service = service_payload(&AppName::master(), &service);
+        assert_json_diff::assert_json_include!(
+            actual: k8s_service,
+            expected: serde_json::json!({
+              "spec": {
+                "ports": [
+                  { "name": "keycloak", "port": 8080, "targetPort": 8080 },
+                  { "name": "discovery", "port": 8080, "targetPort": 8080, "protocol": "UDP" },
+                  { "name": "management", "port": 9000, "targetPort": 9000, "protocol": "TCP" }
+                ]
+              }
+            })
+        );
+        assert_eq!(
+            k8s_service
+                .spec
+                .and_then(|spec| spec.ports)
+                .map(|ports| ports.len()),
+            Some(3)
+        );
+
+        let deployment = deployment_payload(
+            &AppName::master(),
+            &service,
+            &ContainerConfig::default(),
+            &None,
+        );
+        let ports = deployment.spec.unwrap().template.spec.unwrap().containers[0]
+            .ports
+            .clone()
+            .unwrap();
+        assert_eq!(
+            ports
+                .iter()
+                .map(|port| (port.container_port, port.protocol.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![(8080, None), (8080, Some("UDP")), (9000, Some("TCP"))]
+        );
+    }
+
+    #[test]
+    fn should_create_autoscaled_deployment_without_replicas() {
+        let mut config = sc!("db", "mariadb:10.3.17");
+        config.set_autoscaling(Some(Autoscaling::new(2, 5, 75)));
+        let service = DeployableService::new(
+            config,
+            DeploymentStrategy::RedeployAlways,
+            TraefikIngressRoute::with_rule(TraefikRouterRule::path_prefix_rule(&["master", "db"])),
+            Vec::new(),
+        );
+
+        let payload = deployment_payload(
+            &AppName::master(),
+            &service,
+            &ContainerConfig::default(),
+            &None,
+        );
+        assert_eq!(payload.spec.unwrap().replicas, None);
+
+        let hpa = horizontal_pod_autoscaler_payload(&AppName::master(), &service);
+        assert_json_diff::assert_json_include!(
+            actual: hpa,
+            expected: serde_json::json!({
+              "apiVersion": "autoscaling/v2",
+              "kind": "HorizontalPodAutoscaler",
+              "metadata": {
+                "name": "master-db-hpa",
+                "namespace": "master"
+              },
+              "spec": {
+                "scaleTargetRef": {
+                  "apiVersion": "apps/v1",
+                  "kind": "Deployment",
+                  "name": "master-db-deployment"
+                },
+                "minReplicas": 2,
+                "maxReplicas": 5,
+                "metrics": [{
+                  "type": "Resource",
+                  "resource": {
+                    "name": "cpu",
+                    "target": {
+                      "type": "Utilization",
+                      "averageUtilization": 75
+                    }
+                  }
+                }]
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_not_create_horizontal_pod_autoscaler_without_autoscaling() {
+        let service = DeployableService::new(
+            sc!("db", "mariadb:10.3.17"),
+            DeploymentStrategy::RedeployAlways,
+            TraefikIngressRoute::empty(),
+            Vec::new(),
+        );
+
+        assert!(horizontal_pod_autoscaler_payload(&AppName::master(), &service).is_none());
+    }
+
+    #[test]
+    fn should_create_deployment_with_secret_environment_variable() {
+        let mut config = sc!("db", "mariadb:10.3.17");
+        config.add_secret_env(
+            String::from("MYSQL_ROOT_PASSWORD"),
+            SecUtf8::from("example"),
+            String::from("db-password"),
+        );
+
+        let payload = deployment_payload(
+            &AppName::master(),
+            &DeployableService::new(
+                config,
+                DeploymentStrategy::RedeployAlways,
+                TraefikIngressRoute::with_rule(TraefikRouterRule::path_prefix_rule(&[
+                    "master", "db",
+                ])),
+                Vec::new(),
+            ),
+            &ContainerConfig::default(),
+            &None,
+        );
+
+        let env = &payload.spec.unwrap().template.spec.unwrap().containers[0]
+            .env
+            .clone()
+            .unwrap()[0];
+        assert_eq!(env.value, None);
+        assert_eq!(
+            env.value_from,
+            Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: Some(String::from("master-secrets")),
+                    key: String::from("db-password"),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_app_secret_with_keys_of_service() {
+        let mut db = sc!("db", "mariadb:10.3.17");
+        db.add_secret_env(
+            String::from("MYSQL_ROOT_PASSWORD"),
+            SecUtf8::from("example"),
+            String::from("db-password"),
+        );
+        let payload = app_secret_payload(&AppName::master(), db.env());
+
+        assert_json_diff::assert_json_eq!(
+            payload,
+            serde_json::json!({
+              "apiVersion": "v1",
+              "kind": "Secret",
+              "metadata": {
+                "name": "master-secrets",
+                "namespace": "master",
+                "labels": {
+                  "com.aixigo.preview.servant.app-name": "master"
+                }
+              },
+              "type": "Opaque",
+              "data": {
+                "db-password": "ZXhhbXBsZQ=="
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_app_secret_without_keys_for_service_without_environment_variables() {
+        let payload = app_secret_payload(&AppName::master(), None);
+
+        assert_eq!(payload.data, Some(BTreeMap::new()));
+    }
+
     #[test]
     fn create_image_pull_secrets() {
         let payload = image_pull_secret_payload(
@@ -1631,4 +2599,78 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn should_create_wildcard_certificate_with_host_based_routing() {
+        let config = crate::config_from_str!(
+            r#"
+            [routing]
+            mode = 'host'
+            domain = 'review.example.com'
+
+            [tls]
+            type = 'certManager'
+            issuerName = 'letsencrypt'
+            "#
+        );
+        let app_name = AppName::master();
+        let tls = config.tls().unwrap();
+        let mut route = config.routing().app_ingress_route(&app_name);
+        route.set_tls(tls.traefik_tls(&app_name, config.routing()));
+
+        let payload = certificate_payload(&app_name, &route, tls).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(payload).unwrap(),
+            serde_json::json!({
+                "apiVersion": "cert-manager.io/v1",
+                "kind": "Certificate",
+                "metadata": {
+                    "name": "master-tls",
+                    "namespace": "master",
+                    "labels": {
+                        "com.aixigo.preview.servant.app-name": "master"
+                    }
+                },
+                "spec": {
+                    "secretName": "master-tls",
+                    "dnsNames": ["master.review.example.com", "*.master.review.example.com"],
+                    "issuerRef": {
+                        "name": "letsencrypt",
+                        "kind": "ClusterIssuer"
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_certificate_for_hosts_of_base_route() {
+        let tls = Tls::CertManager {
+            issuer_name: String::from("letsencrypt"),
+            issuer_kind: String::from("Issuer"),
+        };
+        let route = TraefikIngressRoute::with_rule(
+            TraefikRouterRule::from_str("Host(`prevant.example.com`) && PathPrefix(`/master/`)")
+                .unwrap(),
+        );
+
+        let payload = certificate_payload(&AppName::master(), &route, &tls).unwrap();
+
+        assert_eq!(payload.spec.dns_names, vec!["prevant.example.com"]);
+        assert_eq!(payload.spec.issuer_ref.kind, "Issuer");
+    }
+
+    #[test]
+    fn should_not_create_certificate_without_cert_manager() {
+        let tls = Tls::CertResolver {
+            name: String::from("letsencrypt"),
+        };
+        let route =
+            TraefikIngressRoute::with_rule(TraefikRouterRule::host_rule(vec![String::from(
+                "prevant.example.com",
+            )]));
+
+        assert!(certificate_payload(&AppName::master(), &route, &tls).is_none());
+    }
 }
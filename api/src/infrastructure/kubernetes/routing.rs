@@ -0,0 +1,635 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::payloads::{
+    ingress_route_payload, middleware_payload, middleware_secrets_payload, service_labels,
+    IngressRoute, Middleware,
+};
+use crate::config::KubernetesRoutingBackend;
+use crate::deployment::deployment_unit::DeployableService;
+use crate::infrastructure::traefik::{Matcher, TraefikMiddleware, TraefikRoute};
+use crate::infrastructure::{APP_NAME_LABEL, CONTAINER_TYPE_LABEL, SERVICE_NAME_LABEL};
+use crate::models::AppName;
+use k8s_openapi::api::core::v1::Secret as V1Secret;
+use k8s_openapi::api::networking::v1::{
+    HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule,
+    IngressServiceBackend, IngressSpec, IngressTLS, ServiceBackendPort,
+};
+use kube::core::ObjectMeta;
+use kube::CustomResource;
+use log::warn;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(CustomResource, Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "gateway.networking.k8s.io",
+    version = "v1",
+    kind = "HTTPRoute",
+    struct = "HttpRoute",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRouteSpec {
+    pub parent_refs: Vec<HttpRouteParentRef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hostnames: Vec<String>,
+    pub rules: Vec<HttpRouteRule>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct HttpRouteParentRef {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRouteRule {
+    pub matches: Vec<HttpRouteMatch>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<HttpRouteFilter>,
+    pub backend_refs: Vec<HttpRouteBackendRef>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct HttpRouteMatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<HttpRoutePathMatch>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<HttpRouteHeaderMatch>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct HttpRoutePathMatch {
+    pub r#type: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct HttpRouteHeaderMatch {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRouteFilter {
+    pub r#type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_rewrite: Option<HttpRouteUrlRewrite>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct HttpRouteUrlRewrite {
+    pub path: HttpRoutePathModifier,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRoutePathModifier {
+    pub r#type: String,
+    pub replace_prefix_match: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct HttpRouteBackendRef {
+    pub name: String,
+    pub port: u16,
+}
+
+/// The Kubernetes objects that route the requests to a service of a review app, depending on the
+/// configured [routing backend](KubernetesRoutingBackend).
+pub(super) enum RoutingPayload {
+    Traefik {
+        ingress_route: IngressRoute,
+        middlewares: Vec<Middleware>,
+    },
+    Ingress(Ingress),
+    HttpRoutes(Vec<HttpRoute>),
+}
+
+pub(super) fn routing_payload(
+    backend: &KubernetesRoutingBackend,
+    app_name: &AppName,
+    service: &DeployableService,
+) -> RoutingPayload {
+    match backend {
+        KubernetesRoutingBackend::Traefik => RoutingPayload::Traefik {
+            ingress_route: ingress_route_payload(app_name, service),
+            middlewares: middleware_payload(app_name, service.ingress_route()),
+        },
+        KubernetesRoutingBackend::Ingress {
+            ingress_class_name,
+            annotations,
+        } => RoutingPayload::Ingress(ingress_payload(
+            app_name,
+            service,
+            ingress_class_name.as_deref(),
+            annotations,
+        )),
+        KubernetesRoutingBackend::GatewayApi {
+            gateway_name,
+            gateway_namespace,
+        } => RoutingPayload::HttpRoutes(http_route_payload(
+            app_name,
+            service,
+            gateway_name,
+            gateway_namespace.as_deref(),
+        )),
+    }
+}
+
+/// Creates the secrets that the Traefik middlewares of the app's services refer to. They are
+/// created once per app because the services share the middlewares of the access restrictions.
+pub(super) fn middleware_secrets_payloads(
+    backend: &KubernetesRoutingBackend,
+    app_name: &AppName,
+    services: &[DeployableService],
+) -> Vec<V1Secret> {
+    match backend {
+        KubernetesRoutingBackend::Traefik => middleware_secrets_payload(
+            app_name,
+            services.iter().map(|service| service.ingress_route()),
+        ),
+        KubernetesRoutingBackend::Ingress { .. } | KubernetesRoutingBackend::GatewayApi { .. } => {
+            Vec::new()
+        }
+    }
+}
+
+/// Creates a [`networking.k8s.io/v1` Ingress](https://kubernetes.io/docs/concepts/services-networking/ingress/)
+/// from the Traefik route of the service. Ingress objects can neither match headers nor apply
+/// middlewares. Prefix stripping must be configured through the annotations of the ingress
+/// controller.
+pub fn ingress_payload(
+    app_name: &AppName,
+    service: &DeployableService,
+    ingress_class_name: Option<&str>,
+    annotations: &BTreeMap<String, String>,
+) -> Ingress {
+    let route = service.ingress_route();
+    let backend = IngressBackend {
+        service: Some(IngressServiceBackend {
+            name: service.service_name().to_string(),
+            port: Some(ServiceBackendPort {
+                number: Some(i32::from(service.port())),
+                ..Default::default()
+            }),
+        }),
+        ..Default::default()
+    };
+
+    let mut rules = Vec::new();
+    for r in route.routes() {
+        warn_about_unsupported_middlewares(app_name, service, r, "Ingress");
+
+        let mut hosts = Vec::new();
+        let mut paths = Vec::new();
+        for m in r.rule().matches() {
+            match m {
+                Matcher::Host { domains } => hosts.extend(domains.iter().cloned()),
+                Matcher::PathPrefix { paths: prefixes } => paths.extend(prefixes.iter().cloned()),
+                Matcher::Headers { key, .. } => warn!(
+                    "Ingress cannot match the header {key} of {} in {app_name}",
+                    service.service_name()
+                ),
+            }
+        }
+        if paths.is_empty() {
+            paths.push(String::from("/"));
+        }
+
+        let http = HTTPIngressRuleValue {
+            paths: paths
+                .into_iter()
+                .map(|path| HTTPIngressPath {
+                    path: Some(path),
+                    path_type: String::from("Prefix"),
+                    backend: backend.clone(),
+                })
+                .collect(),
+        };
+
+        if hosts.is_empty() {
+            rules.push(IngressRule {
+                host: None,
+                http: Some(http),
+            });
+        } else {
+            rules.extend(hosts.into_iter().map(|host| IngressRule {
+                host: Some(host),
+                http: Some(http.clone()),
+            }));
+        }
+    }
+
+    let tls = route
+        .tls()
+        .as_ref()
+        .and_then(|tls| tls.secret_name.as_ref())
+        .map(|secret_name| {
+            vec![IngressTLS {
+                hosts: Some(route.certificate_dns_names()),
+                secret_name: Some(secret_name.clone()),
+            }]
+        });
+
+    let mut metadata_annotations = routing_annotations(app_name, service);
+    metadata_annotations.extend(annotations.clone());
+
+    Ingress {
+        metadata: ObjectMeta {
+            name: Some(format!(
+                "{}-{}-ingress",
+                app_name.to_rfc1123_namespace_id(),
+                service.service_name()
+            )),
+            namespace: Some(app_name.to_rfc1123_namespace_id()),
+            labels: Some(service_labels(app_name, service.service_name())),
+            annotations: Some(metadata_annotations),
+            ..Default::default()
+        },
+        spec: Some(IngressSpec {
+            ingress_class_name: ingress_class_name.map(String::from),
+            rules: Some(rules),
+            tls,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Creates the [Gateway API HTTPRoutes](https://gateway-api.sigs.k8s.io/api-types/httproute/)
+/// that attach the Traefik route of the service to the given gateway. Prefix stripping is
+/// expressed as `URLRewrite` filter and other middlewares are not supported.
+///
+/// Gateway API defines the host names per HTTPRoute instead of per rule. Thus, the routes of the
+/// service are grouped by their host names and each group results in its own HTTPRoute.
+pub fn http_route_payload(
+    app_name: &AppName,
+    service: &DeployableService,
+    gateway_name: &str,
+    gateway_namespace: Option<&str>,
+) -> Vec<HttpRoute> {
+    let route = service.ingress_route();
+
+    let mut rules_by_hostnames: Vec<(Vec<String>, Vec<HttpRouteRule>)> = Vec::new();
+    for r in route.routes() {
+        warn_about_unsupported_middlewares(app_name, service, r, "HTTPRoute");
+
+        let mut hostnames = Vec::new();
+        let mut paths = Vec::new();
+        let mut headers = Vec::new();
+        for m in r.rule().matches() {
+            match m {
+                Matcher::Host { domains } => hostnames.extend(domains.iter().cloned()),
+                Matcher::PathPrefix { paths: prefixes } => paths.extend(prefixes.iter().map(
+                    |prefix| match prefix.trim_end_matches('/') {
+                        "" => String::from("/"),
+                        prefix => prefix.to_string(),
+                    },
+                )),
+                Matcher::Headers { key, value } => headers.push(HttpRouteHeaderMatch {
+                    name: key.clone(),
+                    value: value.clone(),
+                }),
+            }
+        }
+        hostnames.sort();
+        hostnames.dedup();
+
+        let matches = if paths.is_empty() {
+            vec![HttpRouteMatch {
+                path: None,
+                headers,
+            }]
+        } else {
+            paths
+                .into_iter()
+                .map(|path| HttpRouteMatch {
+                    path: Some(HttpRoutePathMatch {
+                        r#type: String::from("PathPrefix"),
+                        value: path,
+                    }),
+                    headers: headers.clone(),
+                })
+                .collect()
+        };
+
+        let filters = if r.middlewares().iter().any(is_strip_prefix) {
+            vec![HttpRouteFilter {
+                r#type: String::from("URLRewrite"),
+                url_rewrite: Some(HttpRouteUrlRewrite {
+                    path: HttpRoutePathModifier {
+                        r#type: String::from("ReplacePrefixMatch"),
+                        replace_prefix_match: String::from("/"),
+                    },
+                }),
+            }]
+        } else {
+            Vec::new()
+        };
+
+        let rule = HttpRouteRule {
+            matches,
+            filters,
+            backend_refs: vec![HttpRouteBackendRef {
+                name: service.service_name().to_string(),
+                port: service.port(),
+            }],
+        };
+
+        match rules_by_hostnames.iter_mut().find(|(h, _)| h == &hostnames) {
+            Some((_, rules)) => rules.push(rule),
+            None => rules_by_hostnames.push((hostnames, vec![rule])),
+        }
+    }
+
+    rules_by_hostnames
+        .into_iter()
+        .enumerate()
+        .map(|(index, (hostnames, rules))| {
+            // The first HTTPRoute keeps the name without index so that it stays the same when
+            // the service has only one route.
+            let name = match index {
+                0 => format!(
+                    "{}-{}-http-route",
+                    app_name.to_rfc1123_namespace_id(),
+                    service.service_name()
+                ),
+                index => format!(
+                    "{}-{}-http-route-{index}",
+                    app_name.to_rfc1123_namespace_id(),
+                    service.service_name()
+                ),
+            };
+
+            HttpRoute {
+                metadata: ObjectMeta {
+                    name: Some(name),
+                    namespace: Some(app_name.to_rfc1123_namespace_id()),
+                    labels: Some(service_labels(app_name, service.service_name())),
+                    annotations: Some(routing_annotations(app_name, service)),
+                    ..Default::default()
+                },
+                spec: HttpRouteSpec {
+                    parent_refs: vec![HttpRouteParentRef {
+                        name: gateway_name.to_string(),
+                        namespace: gateway_namespace.map(String::from),
+                    }],
+                    hostnames,
+                    rules,
+                },
+            }
+        })
+        .collect()
+}
+
+fn routing_annotations(
+    app_name: &AppName,
+    service: &DeployableService,
+) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (APP_NAME_LABEL.to_string(), app_name.to_string()),
+        (
+            SERVICE_NAME_LABEL.to_string(),
+            service.service_name().to_string(),
+        ),
+        (
+            CONTAINER_TYPE_LABEL.to_string(),
+            service.container_type().to_string(),
+        ),
+    ])
+}
+
+fn is_strip_prefix(middleware: &TraefikMiddleware) -> bool {
+    match middleware {
+        TraefikMiddleware::Ref(_) => false,
+        TraefikMiddleware::Spec { spec, .. } => {
+            serde_json::json!(spec).get("stripPrefix").is_some()
+        }
+    }
+}
+
+fn warn_about_unsupported_middlewares(
+    app_name: &AppName,
+    service: &DeployableService,
+    route: &TraefikRoute,
+    kind: &str,
+) {
+    for middleware in route.middlewares() {
+        let name = match middleware {
+            TraefikMiddleware::Ref(name) => name,
+            TraefikMiddleware::Spec { .. } if is_strip_prefix(middleware) => continue,
+            TraefikMiddleware::Spec { name, .. } => name,
+        };
+        warn!(
+            "{kind} cannot apply the middleware {name} to {} in {app_name}",
+            service.service_name()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deployment::deployment_unit::DeploymentStrategy;
+    use crate::infrastructure::{TraefikIngressRoute, TraefikRouterRule, TraefikTLS};
+    use crate::sc;
+    use std::str::FromStr;
+
+    fn service_with_route(route: TraefikIngressRoute) -> DeployableService {
+        DeployableService::new(
+            sc!("db", "mariadb:10.3.17"),
+            DeploymentStrategy::RedeployAlways,
+            route,
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn should_create_ingress_with_path_prefix() {
+        let app_name = AppName::master();
+        let service = service_with_route(TraefikIngressRoute::with_defaults(&app_name, "db"));
+
+        let payload = ingress_payload(
+            &app_name,
+            &service,
+            Some("nginx"),
+            &BTreeMap::from([(
+                String::from("nginx.ingress.kubernetes.io/use-regex"),
+                String::from("true"),
+            )]),
+        );
+
+        assert_json_diff::assert_json_include!(
+            actual: payload,
+            expected: serde_json::json!({
+              "apiVersion": "networking.k8s.io/v1",
+              "kind": "Ingress",
+              "metadata": {
+                "name": "master-db-ingress",
+                "namespace": "master",
+                "annotations": {
+                  "nginx.ingress.kubernetes.io/use-regex": "true"
+                }
+              },
+              "spec": {
+                "ingressClassName": "nginx",
+                "rules": [{
+                  "http": {
+                    "paths": [{
+                      "path": "/master/db/",
+                      "pathType": "Prefix",
+                      "backend": {
+                        "service": { "name": "db", "port": { "number": 80 } }
+                      }
+                    }]
+                  }
+                }]
+              }
+            }),
+        );
+    }
+
+    #[test]
+    fn should_create_ingress_with_host_and_tls() {
+        let app_name = AppName::master();
+        let mut route = TraefikIngressRoute::with_rule(
+            TraefikRouterRule::from_str("Host(`db.master.example.com`)").unwrap(),
+        );
+        route.set_tls(TraefikTLS {
+            cert_resolver: None,
+            secret_name: Some(String::from("master-tls")),
+            domains: Vec::new(),
+        });
+        let service = service_with_route(route);
+
+        let payload = ingress_payload(&app_name, &service, None, &BTreeMap::new());
+
+        assert_json_diff::assert_json_include!(
+            actual: payload,
+            expected: serde_json::json!({
+              "spec": {
+                "rules": [{
+                  "host": "db.master.example.com",
+                  "http": {
+                    "paths": [{ "path": "/", "pathType": "Prefix" }]
+                  }
+                }],
+                "tls": [{
+                  "hosts": ["db.master.example.com"],
+                  "secretName": "master-tls"
+                }]
+              }
+            }),
+        );
+    }
+
+    #[test]
+    fn should_create_http_route_that_strips_prefix() {
+        let app_name = AppName::master();
+        let mut route = TraefikIngressRoute::with_rule(
+            TraefikRouterRule::from_str("Host(`prevant.example.com`)").unwrap(),
+        );
+        route.merge_with(TraefikIngressRoute::with_defaults(&app_name, "db"));
+        let service = service_with_route(route);
+
+        let payload = http_route_payload(&app_name, &service, "review-apps", Some("gateway"));
+
+        assert_json_diff::assert_json_eq!(
+            payload,
+            serde_json::json!([{
+              "apiVersion": "gateway.networking.k8s.io/v1",
+              "kind": "HTTPRoute",
+              "metadata": {
+                "name": "master-db-http-route",
+                "namespace": "master",
+                "labels": {
+                  APP_NAME_LABEL: "master",
+                  SERVICE_NAME_LABEL: "db"
+                },
+                "annotations": {
+                  APP_NAME_LABEL: "master",
+                  SERVICE_NAME_LABEL: "db",
+                  CONTAINER_TYPE_LABEL: "instance"
+                }
+              },
+              "spec": {
+                "parentRefs": [{ "name": "review-apps", "namespace": "gateway" }],
+                "hostnames": ["prevant.example.com"],
+                "rules": [{
+                  "matches": [{
+                    "path": { "type": "PathPrefix", "value": "/master/db" }
+                  }],
+                  "filters": [{
+                    "type": "URLRewrite",
+                    "urlRewrite": {
+                      "path": { "type": "ReplacePrefixMatch", "replacePrefixMatch": "/" }
+                    }
+                  }],
+                  "backendRefs": [{ "name": "db", "port": 80 }]
+                }]
+              }
+            }]),
+        );
+    }
+
+    #[test]
+    fn should_create_http_route_per_host_names() {
+        let app_name = AppName::master();
+        let mut route = TraefikIngressRoute::with_rule(
+            TraefikRouterRule::from_str("Host(`db.master.example.com`)").unwrap(),
+        );
+        route.add_route(
+            TraefikRouterRule::from_str("Host(`db.example.com`) && PathPrefix(`/admin`)").unwrap(),
+        );
+        let service = service_with_route(route);
+
+        let payload = http_route_payload(&app_name, &service, "review-apps", None);
+
+        assert_json_diff::assert_json_include!(
+            actual: payload,
+            expected: serde_json::json!([{
+              "metadata": { "name": "master-db-http-route" },
+              "spec": {
+                "hostnames": ["db.master.example.com"],
+                "rules": [{ "matches": [{}] }]
+              }
+            }, {
+              "metadata": { "name": "master-db-http-route-1" },
+              "spec": {
+                "hostnames": ["db.example.com"],
+                "rules": [{
+                  "matches": [{ "path": { "type": "PathPrefix", "value": "/admin" } }]
+                }]
+              }
+            }]),
+        );
+    }
+}
@@ -26,18 +26,24 @@
 
 use crate::models::Environment;
 pub use docker::DockerInfrastructure as Docker;
-#[cfg(test)]
 pub use dummy_infrastructure::DummyInfrastructure as Dummy;
-pub use infrastructure::Infrastructure;
+pub use infrastructure::{Infrastructure, UnsupportedLogStreams};
 pub use kubernetes::KubernetesInfrastructure as Kubernetes;
+pub use multi_target::{DeploymentTargetError, MultiTargetInfrastructure as MultiTarget};
+pub use podman::PodmanInfrastructure as Podman;
 use serde_json::{map::Map, Value};
-pub use traefik::{TraefikIngressRoute, TraefikRouterRule};
+pub use traefik::{
+    TraefikIngressRoute, TraefikMiddleware, TraefikRouterRule, TraefikTLS, TraefikTLSDomain,
+};
 
 mod docker;
-#[cfg(test)]
+mod docker_host;
 mod dummy_infrastructure;
 mod infrastructure;
 mod kubernetes;
+mod multi_target;
+mod podman;
+mod self_signed_certificates;
 mod traefik;
 
 static APP_NAME_LABEL: &str = "com.aixigo.preview.servant.app-name";
@@ -47,6 +53,12 @@ static REPLICATED_ENV_LABEL: &str = "com.aixigo.preview.servant.replicated-env";
 static IMAGE_LABEL: &str = "com.aixigo.preview.servant.image";
 static STATUS_ID: &str = "com.aixigo.preview.servant.status-id";
 static STORAGE_TYPE_LABEL: &str = "com.aixigo.preview.servant.storage-type";
+static REPLICAS_LABEL: &str = "com.aixigo.preview.servant.replicas";
+static CONTAINER_INDEX_LABEL: &str = "com.aixigo.preview.servant.container-index";
+static PARENT_SERVICE_LABEL: &str = "com.aixigo.preview.servant.parent-service";
+static ADDITIONAL_CONTAINER_LABEL: &str = "com.aixigo.preview.servant.additional-container";
+static SIDECAR_LABEL: &str = "com.aixigo.preview.servant.sidecar";
+static FILES_LABEL: &str = "com.aixigo.preview.servant.files";
 
 /// This function converts the environment variables and adds all variables, that
 /// must be replicated, into a JSON object. This function should be used by implementations
@@ -0,0 +1,944 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+
+use super::{Infrastructure, TraefikIngressRoute};
+use crate::config::{ContainerConfig, DeploymentTarget};
+use crate::deployment::DeploymentUnit;
+use crate::models::service::{Service, ServiceStatus};
+use crate::models::{AppName, ExecOutput, ExecSession, Image, LogStreams, ServiceBuilder};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use failure::Error;
+use futures::future::join_all;
+use futures::stream::{self, BoxStream};
+use multimap::MultiMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// The name of the deployment target that is backed by the `[runtime]` configuration.
+pub static DEFAULT_DEPLOYMENT_TARGET: &str = "default";
+
+/// Combines the infrastructures of multiple deployment targets, e.g. multiple Kubernetes clusters
+/// and a Docker host, and delegates each app to the infrastructure it is placed on.
+pub struct MultiTargetInfrastructure {
+    /// The first target is always the default target.
+    targets: Vec<Target>,
+    /// The indices of the targets that run the apps. The operations on an app only query the
+    /// cached target as long as it still runs the app.
+    placements: Mutex<HashMap<AppName, usize>>,
+}
+
+struct Target {
+    config: Option<DeploymentTarget>,
+    infrastructure: Box<dyn Infrastructure>,
+}
+
+impl Target {
+    fn name(&self) -> &str {
+        self.config
+            .as_ref()
+            .map_or(DEFAULT_DEPLOYMENT_TARGET, |config| config.name())
+    }
+
+    fn places(&self, app_name: &AppName) -> bool {
+        self.config
+            .as_ref()
+            .map(|config| config.places(app_name))
+            .unwrap_or(false)
+    }
+}
+
+impl MultiTargetInfrastructure {
+    pub fn new(
+        default_infrastructure: Box<dyn Infrastructure>,
+        deployment_targets: Vec<(DeploymentTarget, Box<dyn Infrastructure>)>,
+    ) -> Result<Self, DeploymentTargetError> {
+        let mut names = HashSet::new();
+        names.insert(String::from(DEFAULT_DEPLOYMENT_TARGET));
+
+        let mut targets = vec![Target {
+            config: None,
+            infrastructure: default_infrastructure,
+        }];
+        for (config, infrastructure) in deployment_targets {
+            if !names.insert(String::from(config.name())) {
+                return Err(DeploymentTargetError::DuplicateTarget {
+                    target: String::from(config.name()),
+                });
+            }
+
+            targets.push(Target {
+                config: Some(config),
+                infrastructure,
+            });
+        }
+
+        Ok(Self {
+            targets,
+            placements: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn services_of_targets(
+        &self,
+    ) -> Vec<(&Target, Result<MultiMap<AppName, Service>, Error>)> {
+        let services = join_all(
+            self.targets
+                .iter()
+                .map(|target| target.infrastructure.get_services()),
+        )
+        .await;
+
+        self.targets.iter().zip(services).collect()
+    }
+
+    /// Returns the target that runs the given app. Only the cached target is queried if it still
+    /// runs the app. Otherwise, all targets are queried and the lookup fails if the app cannot be
+    /// found and some target cannot be reached because the app might run on that target.
+    async fn target_of(&self, app_name: &AppName) -> Result<Option<&Target>, Error> {
+        let cached = self.placements.lock().unwrap().get(app_name).copied();
+        if let Some(index) = cached {
+            let target = &self.targets[index];
+            if target
+                .infrastructure
+                .get_services()
+                .await?
+                .contains_key(app_name)
+            {
+                return Ok(Some(target));
+            }
+        }
+
+        let mut unreachable = None;
+        for (target, services) in self.services_of_targets().await {
+            match services {
+                Ok(services) if services.contains_key(app_name) => {
+                    self.remember_placement(app_name, target);
+                    return Ok(Some(target));
+                }
+                Ok(_) => {}
+                Err(err) => unreachable = unreachable.or(Some(target_unreachable(target, err))),
+            }
+        }
+
+        self.placements.lock().unwrap().remove(app_name);
+        match unreachable {
+            Some(err) => Err(err.into()),
+            None => Ok(None),
+        }
+    }
+
+    fn remember_placement(&self, app_name: &AppName, target: &Target) {
+        if let Some(index) = self.targets.iter().position(|t| t.name() == target.name()) {
+            self.placements
+                .lock()
+                .unwrap()
+                .insert(app_name.clone(), index);
+        }
+    }
+
+    /// Determines the target of the app for a deployment. In contrast to [`Self::target_of`],
+    /// all targets must be reachable to ensure that an app is never deployed to multiple
+    /// targets.
+    async fn place(&self, app_name: &AppName, target: Option<&str>) -> Result<&Target, Error> {
+        let requested_target = match target {
+            Some(name) => Some(
+                self.targets
+                    .iter()
+                    .find(|target| target.name() == name)
+                    .ok_or_else(|| DeploymentTargetError::UnknownTarget {
+                        target: String::from(name),
+                    })?,
+            ),
+            None => None,
+        };
+
+        let mut current_target = None;
+        for (target, services) in self.services_of_targets().await {
+            let services = services.map_err(|err| target_unreachable(target, err))?;
+            if services.contains_key(app_name) {
+                current_target = Some(target);
+            }
+        }
+        if let Some(current) = current_target {
+            self.remember_placement(app_name, current);
+        }
+
+        match (current_target, requested_target) {
+            (Some(current), Some(requested)) if current.name() != requested.name() => {
+                Err(DeploymentTargetError::AppRunsOnOtherTarget {
+                    app_name: app_name.clone(),
+                    target: String::from(current.name()),
+                }
+                .into())
+            }
+            (Some(current), _) => Ok(current),
+            (None, Some(requested)) => Ok(requested),
+            (None, None) => Ok(self
+                .targets
+                .iter()
+                .find(|target| target.places(app_name))
+                .unwrap_or(&self.targets[0])),
+        }
+    }
+}
+
+#[async_trait]
+impl Infrastructure for MultiTargetInfrastructure {
+    /// Returns the services of all targets that are reachable. A target that cannot be reached,
+    /// e.g. because its cluster is down, must not prevent the apps of the other targets from
+    /// being listed. Thus, its failure is logged and the target is skipped.
+    async fn get_services(&self) -> Result<MultiMap<AppName, Service>, Error> {
+        let mut apps = MultiMap::new();
+        for (target, services) in self.services_of_targets().await {
+            let services = match services {
+                Ok(services) => services,
+                Err(err) => {
+                    warn!(
+                        "Cannot fetch the services of the deployment target {}: {}",
+                        target.name(),
+                        err
+                    );
+                    continue;
+                }
+            };
+            for (app_name, services) in services {
+                for service in services {
+                    let service = ServiceBuilder::from(service)
+                        .deployment_target(String::from(target.name()))
+                        .build()
+                        .expect("The service has been built before");
+                    apps.insert(app_name.clone(), service);
+                }
+            }
+        }
+
+        Ok(apps)
+    }
+
+    async fn deploy_services(
+        &self,
+        status_id: &str,
+        deployment_unit: &DeploymentUnit,
+        container_config: &ContainerConfig,
+    ) -> Result<Vec<Service>, Error> {
+        let target = self.place(deployment_unit.app_name(), None).await?;
+        debug!(
+            "Deploying {} to the deployment target {}",
+            deployment_unit.app_name(),
+            target.name()
+        );
+
+        target
+            .infrastructure
+            .deploy_services(status_id, deployment_unit, container_config)
+            .await
+    }
+
+    async fn get_status_change(&self, status_id: &str) -> Result<Option<Vec<Service>>, Error> {
+        for target in &self.targets {
+            if let Some(services) = target.infrastructure.get_status_change(status_id).await? {
+                return Ok(Some(services));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn stop_services(
+        &self,
+        status_id: &str,
+        app_name: &AppName,
+    ) -> Result<Vec<Service>, Error> {
+        let services = match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .stop_services(status_id, app_name)
+                    .await?
+            }
+            None => Vec::new(),
+        };
+        self.placements.lock().unwrap().remove(app_name);
+        Ok(services)
+    }
+
+    async fn stop_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        remove_volumes: bool,
+    ) -> Result<Option<Service>, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .stop_service(app_name, service_name, remove_volumes)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn get_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        service_name: &'a str,
+        from: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        streams: LogStreams,
+        follow: bool,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), Error>> {
+        match self.target_of(app_name).await {
+            Ok(Some(target)) => {
+                target
+                    .infrastructure
+                    .get_logs(app_name, service_name, from, limit, streams, follow)
+                    .await
+            }
+            Ok(None) => Box::pin(stream::empty()),
+            Err(err) => Box::pin(stream::once(async { Err(err) })),
+        }
+    }
+
+    async fn get_previous_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        service_name: &'a str,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), Error>> {
+        match self.target_of(app_name).await {
+            Ok(Some(target)) => {
+                target
+                    .infrastructure
+                    .get_previous_logs(app_name, service_name)
+                    .await
+            }
+            Ok(None) => Box::pin(stream::empty()),
+            Err(err) => Box::pin(stream::once(async { Err(err) })),
+        }
+    }
+
+    async fn change_status(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        status: ServiceStatus,
+    ) -> Result<Option<Service>, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .change_status(app_name, service_name, status)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn restart_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<Service>, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .restart_service(app_name, service_name)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn redeploy_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        image: &Image,
+        digest: Option<&str>,
+    ) -> Result<Option<Service>, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .redeploy_service(app_name, service_name, image, digest)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecOutput>, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .exec(app_name, service_name, command)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn attach_exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecSession>, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .attach_exec(app_name, service_name, command)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn download_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+    ) -> Result<Option<BoxStream<'static, Result<Vec<u8>, Error>>>, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .download_files(app_name, service_name, path)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn upload_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> Result<bool, Error> {
+        match self.target_of(app_name).await? {
+            Some(target) => {
+                target
+                    .infrastructure
+                    .upload_files(app_name, service_name, path, archive)
+                    .await
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn base_traefik_ingress_route(
+        &self,
+        app_name: &AppName,
+    ) -> Result<Option<TraefikIngressRoute>, Error> {
+        self.place(app_name, None)
+            .await?
+            .infrastructure
+            .base_traefik_ingress_route(app_name)
+            .await
+    }
+
+    async fn placement<'a>(
+        &'a self,
+        app_name: &AppName,
+        target: Option<&str>,
+    ) -> Result<Option<&'a dyn Infrastructure>, Error> {
+        let target = self.place(app_name, target).await?;
+        Ok(Some(&*target.infrastructure))
+    }
+}
+
+#[derive(Debug, Clone, Fail, PartialEq)]
+pub enum DeploymentTargetError {
+    #[fail(display = "Unknown deployment target {}.", target)]
+    UnknownTarget { target: String },
+    #[fail(
+        display = "The app {} already runs on the deployment target {}.",
+        app_name, target
+    )]
+    AppRunsOnOtherTarget { app_name: AppName, target: String },
+    #[fail(
+        display = "The deployment target {} is configured more than once.",
+        target
+    )]
+    DuplicateTarget { target: String },
+    #[fail(
+        display = "The deployment target {} cannot be reached: {}",
+        target, error
+    )]
+    TargetUnreachable { target: String, error: String },
+}
+
+fn target_unreachable(target: &Target, error: Error) -> DeploymentTargetError {
+    DeploymentTargetError::TargetUnreachable {
+        target: String::from(target.name()),
+        error: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::deployment::deployment_unit::DeploymentUnitBuilder;
+    use crate::infrastructure::Dummy;
+    use crate::{config_from_str, sc};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    async fn deployment_unit(app_name: &str) -> DeploymentUnit {
+        let config = Config::default();
+        DeploymentUnitBuilder::init(AppName::from_str(app_name).unwrap(), vec![sc!("db")])
+            .extend_with_config(&config)
+            .await
+            .unwrap()
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating()
+            .unwrap()
+            .apply_hooks(&config)
+            .await
+            .unwrap()
+            .apply_base_traefik_ingress_route(TraefikIngressRoute::empty())
+            .build()
+    }
+
+    fn infrastructure() -> MultiTargetInfrastructure {
+        let config = config_from_str!(
+            r#"
+            [[deploymentTargets]]
+            name = 'cluster-b'
+            appSelector = 'team-b-.*'
+            [deploymentTargets.runtime]
+            type = 'InMemory'
+
+            [[deploymentTargets]]
+            name = 'on-demand'
+            [deploymentTargets.runtime]
+            type = 'InMemory'
+            "#
+        );
+
+        MultiTargetInfrastructure::new(
+            Box::new(Dummy::new()),
+            config
+                .deployment_targets()
+                .iter()
+                .map(|target| {
+                    (
+                        target.clone(),
+                        Box::new(Dummy::new()) as Box<dyn Infrastructure>,
+                    )
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    fn targets_of_apps(services: &MultiMap<AppName, Service>) -> Vec<(String, String)> {
+        let mut targets = services
+            .iter()
+            .map(|(app_name, service)| {
+                (
+                    app_name.to_string(),
+                    service.deployment_target().clone().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        targets.sort();
+        targets
+    }
+
+    #[tokio::test]
+    async fn should_place_apps_by_app_selector() -> Result<(), Error> {
+        let infrastructure = infrastructure();
+
+        infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit("master").await,
+                &ContainerConfig::default(),
+            )
+            .await?;
+        infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit("team-b-login").await,
+                &ContainerConfig::default(),
+            )
+            .await?;
+
+        assert_eq!(
+            targets_of_apps(&infrastructure.get_services().await?),
+            vec![
+                (String::from("master"), String::from("default")),
+                (String::from("team-b-login"), String::from("cluster-b")),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_place_app_on_requested_target() -> Result<(), Error> {
+        let infrastructure = infrastructure();
+        let app_name = AppName::master();
+
+        let target = infrastructure
+            .placement(&app_name, Some("on-demand"))
+            .await?
+            .unwrap();
+        target
+            .deploy_services(
+                "",
+                &deployment_unit("master").await,
+                &ContainerConfig::default(),
+            )
+            .await?;
+
+        assert_eq!(
+            targets_of_apps(&infrastructure.get_services().await?),
+            vec![(String::from("master"), String::from("on-demand"))]
+        );
+
+        let err = infrastructure
+            .placement(&app_name, Some("default"))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast::<DeploymentTargetError>().unwrap(),
+            DeploymentTargetError::AppRunsOnOtherTarget {
+                app_name,
+                target: String::from("on-demand")
+            }
+        );
+
+        Ok(())
+    }
+
+    /// A deployment target whose cluster cannot be reached.
+    struct Unreachable;
+
+    #[async_trait]
+    impl Infrastructure for Unreachable {
+        async fn get_services(&self) -> Result<MultiMap<AppName, Service>, Error> {
+            Err(failure::format_err!("connection refused"))
+        }
+
+        async fn deploy_services(
+            &self,
+            _status_id: &str,
+            _deployment_unit: &DeploymentUnit,
+            _container_config: &ContainerConfig,
+        ) -> Result<Vec<Service>, Error> {
+            Err(failure::format_err!("connection refused"))
+        }
+
+        async fn stop_services(
+            &self,
+            _status_id: &str,
+            _app_name: &AppName,
+        ) -> Result<Vec<Service>, Error> {
+            Err(failure::format_err!("connection refused"))
+        }
+
+        async fn stop_service(
+            &self,
+            _app_name: &AppName,
+            _service_name: &str,
+            _remove_volumes: bool,
+        ) -> Result<Option<Service>, Error> {
+            Err(failure::format_err!("connection refused"))
+        }
+
+        async fn get_logs<'a>(
+            &'a self,
+            _app_name: &'a AppName,
+            _service_name: &'a str,
+            _from: &'a Option<DateTime<FixedOffset>>,
+            _limit: &'a Option<usize>,
+            _streams: LogStreams,
+            _follow: bool,
+        ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), Error>> {
+            Box::pin(stream::empty())
+        }
+
+        async fn change_status(
+            &self,
+            _app_name: &AppName,
+            _service_name: &str,
+            _status: ServiceStatus,
+        ) -> Result<Option<Service>, Error> {
+            Err(failure::format_err!("connection refused"))
+        }
+    }
+
+    #[tokio::test]
+    async fn should_skip_unreachable_targets_only_when_listing_services() -> Result<(), Error> {
+        let config = config_from_str!(
+            r#"
+            [[deploymentTargets]]
+            name = 'cluster-b'
+            appSelector = 'team-b-.*'
+            [deploymentTargets.runtime]
+            type = 'InMemory'
+            "#
+        );
+        let default_infrastructure = Dummy::new();
+        default_infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit("master").await,
+                &ContainerConfig::default(),
+            )
+            .await?;
+        let infrastructure = MultiTargetInfrastructure::new(
+            Box::new(default_infrastructure),
+            vec![(
+                config.deployment_targets()[0].clone(),
+                Box::new(Unreachable) as Box<dyn Infrastructure>,
+            )],
+        )
+        .unwrap();
+
+        assert_eq!(
+            targets_of_apps(&infrastructure.get_services().await?),
+            vec![(String::from("master"), String::from("default"))]
+        );
+        assert!(infrastructure
+            .stop_service(&AppName::master(), "db", false)
+            .await?
+            .is_some());
+
+        let err = infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit("master").await,
+                &ContainerConfig::default(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast::<DeploymentTargetError>().unwrap(),
+            DeploymentTargetError::TargetUnreachable { target, .. } if target == "cluster-b"
+        ));
+
+        // The app might run on the unreachable target.
+        assert!(infrastructure
+            .stop_services("", &AppName::from_str("team-b-login").unwrap())
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    /// A deployment target that counts how often its services are queried.
+    struct Counting {
+        infrastructure: Dummy,
+        queries: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Infrastructure for Counting {
+        async fn get_services(&self) -> Result<MultiMap<AppName, Service>, Error> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            self.infrastructure.get_services().await
+        }
+
+        async fn deploy_services(
+            &self,
+            status_id: &str,
+            deployment_unit: &DeploymentUnit,
+            container_config: &ContainerConfig,
+        ) -> Result<Vec<Service>, Error> {
+            self.infrastructure
+                .deploy_services(status_id, deployment_unit, container_config)
+                .await
+        }
+
+        async fn stop_services(
+            &self,
+            status_id: &str,
+            app_name: &AppName,
+        ) -> Result<Vec<Service>, Error> {
+            self.infrastructure.stop_services(status_id, app_name).await
+        }
+
+        async fn stop_service(
+            &self,
+            app_name: &AppName,
+            service_name: &str,
+            remove_volumes: bool,
+        ) -> Result<Option<Service>, Error> {
+            self.infrastructure
+                .stop_service(app_name, service_name, remove_volumes)
+                .await
+        }
+
+        async fn get_logs<'a>(
+            &'a self,
+            app_name: &'a AppName,
+            service_name: &'a str,
+            from: &'a Option<DateTime<FixedOffset>>,
+            limit: &'a Option<usize>,
+            streams: LogStreams,
+            follow: bool,
+        ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), Error>> {
+            self.infrastructure
+                .get_logs(app_name, service_name, from, limit, streams, follow)
+                .await
+        }
+
+        async fn change_status(
+            &self,
+            app_name: &AppName,
+            service_name: &str,
+            status: ServiceStatus,
+        ) -> Result<Option<Service>, Error> {
+            self.infrastructure
+                .change_status(app_name, service_name, status)
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn should_only_query_cached_target_of_app() -> Result<(), Error> {
+        let config = config_from_str!(
+            r#"
+            [[deploymentTargets]]
+            name = 'cluster-b'
+            appSelector = 'team-b-.*'
+            [deploymentTargets.runtime]
+            type = 'InMemory'
+            "#
+        );
+        let queries = Arc::new(AtomicUsize::new(0));
+        let infrastructure = MultiTargetInfrastructure::new(
+            Box::new(Dummy::new()),
+            vec![(
+                config.deployment_targets()[0].clone(),
+                Box::new(Counting {
+                    infrastructure: Dummy::new(),
+                    queries: queries.clone(),
+                }) as Box<dyn Infrastructure>,
+            )],
+        )
+        .unwrap();
+
+        infrastructure
+            .deploy_services(
+                "",
+                &deployment_unit("master").await,
+                &ContainerConfig::default(),
+            )
+            .await?;
+        infrastructure
+            .change_status(&AppName::master(), "db", ServiceStatus::Paused)
+            .await?;
+        let queries_of_first_lookup = queries.load(Ordering::SeqCst);
+
+        infrastructure
+            .change_status(&AppName::master(), "db", ServiceStatus::Running)
+            .await?;
+        infrastructure
+            .stop_service(&AppName::master(), "db", false)
+            .await?;
+
+        assert_eq!(queries.load(Ordering::SeqCst), queries_of_first_lookup);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_determine_base_route_by_placement() -> Result<(), Error> {
+        let config = config_from_str!(
+            r#"
+            [[deploymentTargets]]
+            name = 'cluster-b'
+            appSelector = 'team-b-.*'
+            [deploymentTargets.runtime]
+            type = 'InMemory'
+            "#
+        );
+        let cluster_b_route = TraefikIngressRoute::with_defaults(&AppName::master(), "prevant");
+        let infrastructure = MultiTargetInfrastructure::new(
+            Box::new(Dummy::new()),
+            vec![(
+                config.deployment_targets()[0].clone(),
+                Box::new(Dummy::with_base_route(cluster_b_route.clone()))
+                    as Box<dyn Infrastructure>,
+            )],
+        )
+        .unwrap();
+
+        assert_eq!(
+            infrastructure
+                .base_traefik_ingress_route(&AppName::master())
+                .await?,
+            None
+        );
+        assert_eq!(
+            infrastructure
+                .base_traefik_ingress_route(&AppName::from_str("team-b-login").unwrap())
+                .await?,
+            Some(cluster_b_route)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_target() {
+        let infrastructure = infrastructure();
+
+        let err = infrastructure
+            .placement(&AppName::master(), Some("unknown"))
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.downcast::<DeploymentTargetError>().unwrap(),
+            DeploymentTargetError::UnknownTarget {
+                target: String::from("unknown")
+            }
+        );
+    }
+}
@@ -0,0 +1,726 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use crate::models::LogStreams;
+use async_stream::stream;
+use base64::{engine::general_purpose::URL_SAFE, Engine};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use hyper::client::Client;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use hyperlocal::UnixConnector;
+use secstr::SecUtf8;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// The version of the libpod API which is provided by Podman 4 and later.
+static API_VERSION: &str = "v4.0.0";
+
+#[derive(Debug, Fail)]
+pub enum PodmanError {
+    #[fail(
+        display = "Podman responded to {} with status {}: {}",
+        path, status, message
+    )]
+    Api {
+        path: String,
+        status: u16,
+        message: String,
+    },
+    #[fail(display = "Unexpected Podman interaction error: {}", internal_message)]
+    UnexpectedError { internal_message: String },
+}
+
+impl PodmanError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, PodmanError::Api { status: 404, .. })
+    }
+}
+
+/// The subset of libpod's container list entries that PREvant relies on.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ContainerInfo {
+    pub id: String,
+    pub image: String,
+    #[serde(rename = "ImageID", default)]
+    pub image_id: String,
+    #[serde(default)]
+    pub labels: Option<HashMap<String, String>>,
+    pub state: String,
+    #[serde(default)]
+    pub started_at: i64,
+}
+
+impl ContainerInfo {
+    pub fn label(&self, key: &str) -> Option<&String> {
+        self.labels.as_ref().and_then(|labels| labels.get(key))
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+/// The subset of libpod's container inspection that PREvant needs to recreate a container.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ContainerDetails {
+    pub id: String,
+    pub image_name: String,
+    pub config: ContainerDetailsConfig,
+    pub host_config: ContainerHostConfig,
+    #[serde(default)]
+    pub mounts: Vec<ContainerMount>,
+    pub state: ContainerState,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ContainerDetailsConfig {
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default)]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ContainerHostConfig {
+    #[serde(default)]
+    pub memory: u64,
+    #[serde(default)]
+    pub memory_swap: u64,
+    #[serde(default)]
+    pub restart_policy: Option<RestartPolicy>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct RestartPolicy {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ContainerMount {
+    #[serde(rename = "Type")]
+    pub kind: String,
+    #[serde(default)]
+    pub name: String,
+    pub destination: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ContainerState {
+    pub status: String,
+}
+
+/// The subset of libpod's pod inspection that PREvant relies on.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct PodInfo {
+    #[serde(default)]
+    pub infra_config: Option<PodInfraConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct PodInfraConfig {
+    #[serde(default)]
+    pub host_add: Option<Vec<String>>,
+}
+
+impl PodInfo {
+    /// The host entries of the pod in the format `host:ip`.
+    pub fn host_entries(&self) -> &[String] {
+        self.infra_config
+            .as_ref()
+            .and_then(|config| config.host_add.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct VolumeInfo {
+    pub name: String,
+    #[serde(default)]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CreateResponse {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ExecDetails {
+    exit_code: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+enum RequestBody {
+    Empty,
+    Json(Value),
+    Tar(Vec<u8>),
+}
+
+/// A minimal client of [Podman's REST API](https://docs.podman.io/en/latest/_static/api.html)
+/// that talks to Podman through its unix socket.
+pub(super) struct PodmanClient {
+    socket: PathBuf,
+    client: Client<UnixConnector>,
+}
+
+impl PodmanClient {
+    pub fn new(socket: PathBuf) -> Self {
+        Self {
+            socket,
+            client: Client::builder().build(UnixConnector),
+        }
+    }
+
+    /// Lists all containers, including the stopped ones, that have all of the given labels. Each
+    /// label filter is either `key` or `key=value`.
+    pub async fn list_containers(
+        &self,
+        labels: &[String],
+    ) -> Result<Vec<ContainerInfo>, PodmanError> {
+        let query = [
+            ("all", String::from("true")),
+            ("filters", json!({ "label": labels }).to_string()),
+        ];
+        self.json(Method::GET, "/containers/json", &query, RequestBody::Empty)
+            .await
+    }
+
+    pub async fn inspect_container(&self, id: &str) -> Result<ContainerDetails, PodmanError> {
+        let path = format!("/containers/{id}/json");
+        self.json(Method::GET, &path, &[], RequestBody::Empty).await
+    }
+
+    pub async fn inspect_pod(&self, name: &str) -> Result<Option<PodInfo>, PodmanError> {
+        let path = format!("/pods/{name}/json");
+        match self.json(Method::GET, &path, &[], RequestBody::Empty).await {
+            Ok(pod) => Ok(Some(pod)),
+            Err(err) if err.is_not_found() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub async fn create_pod(&self, spec: Value) -> Result<String, PodmanError> {
+        let response: CreateResponse = self
+            .json(Method::POST, "/pods/create", &[], RequestBody::Json(spec))
+            .await?;
+        Ok(response.id)
+    }
+
+    pub async fn remove_pod(&self, name: &str) -> Result<(), PodmanError> {
+        let path = format!("/pods/{name}");
+        let query = [("force", String::from("true"))];
+        match self
+            .request(Method::DELETE, &path, &query, RequestBody::Empty, &[])
+            .await
+        {
+            Err(err) if !err.is_not_found() => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn pull_image(
+        &self,
+        reference: &str,
+        credentials: Option<(&str, &SecUtf8)>,
+    ) -> Result<(), PodmanError> {
+        let query = [
+            ("reference", String::from(reference)),
+            ("quiet", String::from("true")),
+        ];
+        let headers = match credentials {
+            None => Vec::new(),
+            Some((username, password)) => vec![(
+                "X-Registry-Auth",
+                URL_SAFE.encode(
+                    json!({ "username": username, "password": password.unsecure() }).to_string(),
+                ),
+            )],
+        };
+
+        let response = self
+            .request(
+                Method::POST,
+                "/images/pull",
+                &query,
+                RequestBody::Empty,
+                &headers,
+            )
+            .await?;
+
+        // The pull reports failures in its progress stream while the status code is still 200.
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        for report in serde_json::Deserializer::from_slice(&body).into_iter::<Value>() {
+            if let Some(error) = report?.get("error").and_then(Value::as_str) {
+                return Err(PodmanError::UnexpectedError {
+                    internal_message: format!("Cannot pull {reference}: {error}"),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn create_container(&self, spec: Value) -> Result<String, PodmanError> {
+        let response: CreateResponse = self
+            .json(
+                Method::POST,
+                "/containers/create",
+                &[],
+                RequestBody::Json(spec),
+            )
+            .await?;
+        Ok(response.id)
+    }
+
+    pub async fn start_container(&self, id: &str) -> Result<(), PodmanError> {
+        self.container_action(id, "start").await
+    }
+
+    pub async fn stop_container(&self, id: &str) -> Result<(), PodmanError> {
+        self.container_action(id, "stop").await
+    }
+
+    pub async fn pause_container(&self, id: &str) -> Result<(), PodmanError> {
+        self.container_action(id, "pause").await
+    }
+
+    pub async fn unpause_container(&self, id: &str) -> Result<(), PodmanError> {
+        self.container_action(id, "unpause").await
+    }
+
+    pub async fn remove_container(&self, id: &str) -> Result<(), PodmanError> {
+        let path = format!("/containers/{id}");
+        let query = [("force", String::from("true"))];
+        self.request(Method::DELETE, &path, &query, RequestBody::Empty, &[])
+            .await?;
+        Ok(())
+    }
+
+    /// Waits until the container stopped and returns its exit code.
+    pub async fn wait_container(&self, id: &str) -> Result<i64, PodmanError> {
+        let path = format!("/containers/{id}/wait");
+        let query = [("condition", String::from("stopped"))];
+        self.json(Method::POST, &path, &query, RequestBody::Empty)
+            .await
+    }
+
+    /// Extracts the tar `archive` into the container's file system at `path`.
+    pub async fn put_archive(
+        &self,
+        id: &str,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> Result<(), PodmanError> {
+        let query = [("path", String::from(path))];
+        self.request(
+            Method::PUT,
+            &format!("/containers/{id}/archive"),
+            &query,
+            RequestBody::Tar(archive),
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Streams a tar archive of the file or directory at `path` in the container's file system.
+    pub async fn get_archive(
+        &self,
+        id: &str,
+        path: &str,
+    ) -> Result<BoxStream<'static, Result<Vec<u8>, PodmanError>>, PodmanError> {
+        let query = [("path", String::from(path))];
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/containers/{id}/archive"),
+                &query,
+                RequestBody::Empty,
+                &[],
+            )
+            .await?;
+        Ok(response
+            .into_body()
+            .map(|chunk| chunk.map(|chunk| chunk.to_vec()).map_err(PodmanError::from))
+            .boxed())
+    }
+
+    /// Streams the log frames of the container which start with their timestamp.
+    pub async fn logs(
+        &self,
+        id: &str,
+        since: Option<i64>,
+        streams: LogStreams,
+        follow: bool,
+    ) -> Result<BoxStream<'static, Result<String, PodmanError>>, PodmanError> {
+        let mut query = vec![
+            ("stdout", streams.stdout().to_string()),
+            ("stderr", streams.stderr().to_string()),
+            ("timestamps", String::from("true")),
+            ("follow", follow.to_string()),
+        ];
+        if let Some(since) = since {
+            query.push(("since", since.to_string()));
+        }
+
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/containers/{id}/logs"),
+                &query,
+                RequestBody::Empty,
+                &[],
+            )
+            .await?;
+        Ok(demultiplex(response.into_body()))
+    }
+
+    /// Runs the command in the container until it exits and returns its exit code, stdout, and
+    /// stderr.
+    pub async fn exec(
+        &self,
+        id: &str,
+        command: &[String],
+    ) -> Result<(Option<i64>, String, String), PodmanError> {
+        let exec: CreateResponse = self
+            .json(
+                Method::POST,
+                &format!("/containers/{id}/exec"),
+                &[],
+                RequestBody::Json(json!({
+                    "AttachStdout": true,
+                    "AttachStderr": true,
+                    "Cmd": command,
+                })),
+            )
+            .await?;
+
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/exec/{}/start", exec.id),
+                &[],
+                RequestBody::Json(json!({ "Detach": false })),
+                &[],
+            )
+            .await?;
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut frames = frames(response.into_body());
+        while let Some(frame) = frames.next().await {
+            match frame? {
+                (2, frame) => stderr.extend(frame),
+                (_, frame) => stdout.extend(frame),
+            }
+        }
+
+        let details: ExecDetails = self
+            .json(
+                Method::GET,
+                &format!("/exec/{}/json", exec.id),
+                &[],
+                RequestBody::Empty,
+            )
+            .await?;
+        Ok((
+            details.exit_code,
+            String::from_utf8_lossy(&stdout).into_owned(),
+            String::from_utf8_lossy(&stderr).into_owned(),
+        ))
+    }
+
+    pub async fn create_volume(
+        &self,
+        labels: &BTreeMap<&str, &str>,
+    ) -> Result<String, PodmanError> {
+        let volume: VolumeInfo = self
+            .json(
+                Method::POST,
+                "/volumes/create",
+                &[],
+                RequestBody::Json(json!({ "Label": labels })),
+            )
+            .await?;
+        Ok(volume.name)
+    }
+
+    pub async fn list_volumes(&self, labels: &[String]) -> Result<Vec<VolumeInfo>, PodmanError> {
+        let query = [("filters", json!({ "label": labels }).to_string())];
+        self.json(Method::GET, "/volumes/json", &query, RequestBody::Empty)
+            .await
+    }
+
+    pub async fn remove_volume(&self, name: &str) -> Result<(), PodmanError> {
+        let path = format!("/volumes/{name}");
+        let query = [("force", String::from("true"))];
+        self.request(Method::DELETE, &path, &query, RequestBody::Empty, &[])
+            .await?;
+        Ok(())
+    }
+
+    async fn container_action(&self, id: &str, action: &str) -> Result<(), PodmanError> {
+        let path = format!("/containers/{id}/{action}");
+        self.request(Method::POST, &path, &[], RequestBody::Empty, &[])
+            .await?;
+        Ok(())
+    }
+
+    async fn json<T>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: RequestBody,
+    ) -> Result<T, PodmanError>
+    where
+        T: DeserializeOwned,
+    {
+        let response = self.request(method, path, query, body, &[]).await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: RequestBody,
+        headers: &[(&str, String)],
+    ) -> Result<Response<Body>, PodmanError> {
+        let mut path_and_query = format!("/{API_VERSION}/libpod{path}");
+        if !query.is_empty() {
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(query.iter().map(|(key, value)| (*key, value.as_str())))
+                .finish();
+            path_and_query.push('?');
+            path_and_query.push_str(&query);
+        }
+
+        let mut builder =
+            Request::builder()
+                .method(method)
+                .uri(hyper::Uri::from(hyperlocal::Uri::new(
+                    &self.socket,
+                    &path_and_query,
+                )));
+        for (name, value) in headers {
+            builder = builder.header(*name, value.as_str());
+        }
+        let request = match body {
+            RequestBody::Empty => builder.body(Body::empty()),
+            RequestBody::Json(value) => builder
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(value.to_string())),
+            RequestBody::Tar(archive) => builder
+                .header(CONTENT_TYPE, "application/x-tar")
+                .body(Body::from(archive)),
+        }?;
+
+        trace!("Requesting {} from Podman", path_and_query);
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        if status.is_success() || status == StatusCode::NOT_MODIFIED {
+            return Ok(response);
+        }
+
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let message = serde_json::from_slice::<ErrorResponse>(&body)
+            .map(|response| response.message)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned());
+        Err(PodmanError::Api {
+            path: String::from(path),
+            status: status.as_u16(),
+            message,
+        })
+    }
+}
+
+/// Splits the multiplexed stdout and stderr stream into its frames. Each frame starts with an
+/// eight byte header whose last four bytes contain the length of the frame (big endian).
+fn demultiplex(body: Body) -> BoxStream<'static, Result<String, PodmanError>> {
+    frames(body)
+        .map(|frame| frame.map(|(_, frame)| String::from_utf8_lossy(&frame).into_owned()))
+        .boxed()
+}
+
+/// Like [`demultiplex`] but keeps the stream type of the frames, i.e. the first byte of the
+/// header which is `1` for stdout and `2` for stderr.
+fn frames(mut body: Body) -> BoxStream<'static, Result<(u8, Vec<u8>), PodmanError>> {
+    stream! {
+        let mut buffer = Vec::new();
+        while let Some(chunk) = body.next().await {
+            match chunk {
+                Ok(chunk) => buffer.extend_from_slice(&chunk),
+                Err(err) => {
+                    yield Err(PodmanError::from(err));
+                    break;
+                }
+            }
+
+            while buffer.len() >= 8 {
+                let length =
+                    u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+                if buffer.len() < 8 + length {
+                    break;
+                }
+
+                let stream_type = buffer[0];
+                let frame = buffer.drain(..8 + length).skip(8).collect::<Vec<u8>>();
+                yield Ok((stream_type, frame));
+            }
+        }
+    }
+    .boxed()
+}
+
+impl From<hyper::Error> for PodmanError {
+    fn from(err: hyper::Error) -> Self {
+        PodmanError::UnexpectedError {
+            internal_message: err.to_string(),
+        }
+    }
+}
+
+impl From<hyper::http::Error> for PodmanError {
+    fn from(err: hyper::http::Error) -> Self {
+        PodmanError::UnexpectedError {
+            internal_message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PodmanError {
+    fn from(err: serde_json::Error) -> Self {
+        PodmanError::UnexpectedError {
+            internal_message: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::podman::fake_socket::FakeSocket;
+
+    #[tokio::test]
+    async fn should_report_api_errors() {
+        let socket = FakeSocket::start(|_, _, _| {
+            (
+                StatusCode::NOT_FOUND,
+                json!({ "cause": "no such container", "message": "no container with name or ID \"db\" found", "response": 404 }),
+            )
+        });
+        let client = PodmanClient::new(socket.socket());
+
+        let err = client.start_container("db").await.unwrap_err();
+
+        assert!(err.is_not_found());
+        assert_eq!(
+            err.to_string(),
+            "Podman responded to /containers/db/start with status 404: no container with name or ID \"db\" found"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_fail_pull_with_error_in_progress_stream() {
+        let socket = FakeSocket::start(|_, _, _| {
+            (
+                StatusCode::OK,
+                json!({ "error": "initializing source docker://example.com/unknown:latest: manifest unknown" }),
+            )
+        });
+        let client = PodmanClient::new(socket.socket());
+
+        let err = client
+            .pull_image("example.com/unknown:latest", None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Unexpected Podman interaction error: Cannot pull example.com/unknown:latest: initializing source docker://example.com/unknown:latest: manifest unknown"
+        );
+        assert_eq!(
+            socket.requests()[0].query,
+            "reference=example.com%2Funknown%3Alatest&quiet=true"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_demultiplex_log_frames() {
+        let mut frames = Vec::new();
+        for (stream, line) in [
+            (1u8, "2024-01-01T12:00:00.000000000Z Starting\n"),
+            (2u8, "2024-01-01T12:00:01.000000000Z Listening on port 80\n"),
+        ] {
+            frames.extend_from_slice(&[stream, 0, 0, 0]);
+            frames.extend_from_slice(&(line.len() as u32).to_be_bytes());
+            frames.extend_from_slice(line.as_bytes());
+        }
+        let socket = FakeSocket::start_with_bytes(move |_, _, _| (StatusCode::OK, frames.clone()));
+        let client = PodmanClient::new(socket.socket());
+
+        let lines = client
+            .logs("db", None, false)
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            lines,
+            vec![
+                "2024-01-01T12:00:00.000000000Z Starting\n",
+                "2024-01-01T12:00:01.000000000Z Listening on port 80\n"
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyperlocal::UnixServerExt;
+use serde_json::Value;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// A request received by [`FakeSocket`] whose path is relative to the libpod API, e.g.
+/// `/containers/json`.
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: String,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+/// Serves a fake Podman REST API on a unix socket in a temporary directory. Each request is
+/// answered by the handler and recorded for later assertions.
+pub struct FakeSocket {
+    directory: TempDir,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl FakeSocket {
+    pub fn start<H>(handler: H) -> Self
+    where
+        H: Fn(&Method, &str, &str) -> (StatusCode, Value) + Send + Sync + 'static,
+    {
+        Self::start_with_bytes(move |method, path, query| {
+            let (status, body) = handler(method, path, query);
+            match status {
+                StatusCode::NO_CONTENT => (status, Vec::new()),
+                status => (status, body.to_string().into_bytes()),
+            }
+        })
+    }
+
+    pub fn start_with_bytes<H>(handler: H) -> Self
+    where
+        H: Fn(&Method, &str, &str) -> (StatusCode, Vec<u8>) + Send + Sync + 'static,
+    {
+        let directory = tempfile::tempdir().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+
+        let recorded_requests = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let handler = handler.clone();
+            let recorded_requests = recorded_requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let handler = handler.clone();
+                    let recorded_requests = recorded_requests.clone();
+                    async move {
+                        let method = request.method().clone();
+                        let path = request
+                            .uri()
+                            .path()
+                            .trim_start_matches("/v4.0.0/libpod")
+                            .to_string();
+                        let query = request.uri().query().unwrap_or_default().to_string();
+                        let body = hyper::body::to_bytes(request.into_body())
+                            .await
+                            .unwrap()
+                            .to_vec();
+
+                        let (status, response) = handler(&method, &path, &query);
+                        recorded_requests.lock().unwrap().push(RecordedRequest {
+                            method,
+                            path,
+                            query,
+                            body,
+                        });
+
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .status(status)
+                                .body(Body::from(response))
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind_unix(directory.path().join("podman.sock"))
+            .unwrap()
+            .serve(make_service);
+        tokio::spawn(server);
+
+        Self {
+            directory,
+            requests,
+        }
+    }
+
+    pub fn socket(&self) -> PathBuf {
+        self.directory.path().join("podman.sock")
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns the requests that match the method and path.
+    pub fn requests_to(&self, method: Method, path: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method == method && request.path == path)
+            .collect()
+    }
+}
@@ -0,0 +1,1674 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use super::client::{ContainerDetails, ContainerInfo, PodmanClient, PodmanError, VolumeInfo};
+use crate::config::{Config, ContainerConfig, PodmanRuntimeConfig, Runtime, Tls};
+use crate::deployment::deployment_unit::{DeployableService, DeploymentStrategy};
+use crate::deployment::DeploymentUnit;
+use crate::infrastructure::self_signed_certificates::{
+    issue_app_certificate, remove_app_certificate,
+};
+use crate::infrastructure::{
+    replicated_environment_variable_to_json, Docker, Infrastructure, ADDITIONAL_CONTAINER_LABEL,
+    APP_NAME_LABEL, CONTAINER_TYPE_LABEL, FILES_LABEL, IMAGE_LABEL, PARENT_SERVICE_LABEL,
+    REPLICATED_ENV_LABEL, SERVICE_NAME_LABEL, SIDECAR_LABEL,
+};
+use crate::models::service::{ContainerType, Service, ServiceError, ServiceStatus};
+use crate::models::{
+    AdditionalContainer, AppName, Environment, ExecOutput, Image, LogStreams, PortProtocol,
+    ServiceBuilder, ServiceBuilderError, ServiceConfig,
+};
+use async_stream::stream;
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use failure::{format_err, Error};
+use futures::future::join_all;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use multimap::MultiMap;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub struct PodmanInfrastructure {
+    config: Config,
+    client: PodmanClient,
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum PodmanInfrastructureError {
+    #[fail(
+        display = "The container {} does not provide a label for service name.",
+        container_id
+    )]
+    MissingServiceNameLabel { container_id: String },
+    #[fail(
+        display = "The container {} does not provide a label for app name.",
+        container_id
+    )]
+    MissingAppNameLabel { container_id: String },
+    #[fail(display = "Unexpected image format for image “{}” ({}).", img, err)]
+    UnexpectedImageFormat { img: String, err: String },
+    #[fail(display = "Unexpected podman interaction error: {}", internal_message)]
+    UnexpectedError { internal_message: String },
+    #[fail(
+        display = "The init container {} of service {} exited with status code {}.",
+        container_name, service_name, status_code
+    )]
+    InitContainerFailed {
+        container_name: String,
+        service_name: String,
+        status_code: i64,
+    },
+}
+
+impl PodmanInfrastructure {
+    pub fn new(config: Config) -> Self {
+        let socket = match config.runtime_config() {
+            Runtime::Podman(podman) => podman.socket(),
+            _ => PodmanRuntimeConfig::default().socket(),
+        };
+
+        Self {
+            config,
+            client: PodmanClient::new(socket),
+        }
+    }
+
+    /// Creates the pod that contains all containers of the app. The containers of a pod share
+    /// their network namespace and, thus, the service names are resolved to the loopback
+    /// interface. Podman only applies the host entries when it creates the pod. Therefore, the pod
+    /// is recreated together with the containers of the other services if the deployment adds
+    /// services whose names cannot be resolved yet.
+    async fn create_or_update_pod(&self, deployment_unit: &DeploymentUnit) -> Result<(), Error> {
+        let app_name = deployment_unit.app_name();
+        let pod_name = pod_name(app_name);
+
+        let existing_entries = self
+            .client
+            .inspect_pod(&pod_name)
+            .await?
+            .map(|pod| pod.host_entries().to_vec());
+
+        let mut host_entries = existing_entries.clone().unwrap_or_default();
+        for service in deployment_unit.services() {
+            let entry = host_entry(service.service_name());
+            if !host_entries.contains(&entry) {
+                host_entries.push(entry);
+            }
+        }
+
+        let kept_containers = match existing_entries {
+            Some(existing_entries) if existing_entries.len() == host_entries.len() => return Ok(()),
+            Some(_) => {
+                debug!(
+                    "Recreating pod {} of app {} because of the host entries of new services.",
+                    pod_name, app_name
+                );
+                let kept_containers = self.snapshot_other_containers(deployment_unit).await?;
+                self.client.remove_pod(&pod_name).await?;
+                kept_containers
+            }
+            None => Vec::new(),
+        };
+
+        debug!("Creating pod {} for app {}.", pod_name, app_name);
+        let pod_id = self
+            .client
+            .create_pod(json!({
+                "name": pod_name,
+                "labels": { APP_NAME_LABEL: app_name.as_str() },
+                "hostadd": host_entries,
+            }))
+            .await?;
+        debug!("Created pod for app {} with id {}", app_name, pod_id);
+
+        for container in kept_containers {
+            self.recreate_container(app_name, container).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Captures the containers of the app that the deployment does not replace, i.e. the
+    /// containers of other services and their sidecars, so that they can be recreated in a new
+    /// pod. Finished init containers are not captured.
+    async fn snapshot_other_containers(
+        &self,
+        deployment_unit: &DeploymentUnit,
+    ) -> Result<Vec<ContainerSnapshot>, Error> {
+        let deployed_services = deployment_unit
+            .services()
+            .iter()
+            .map(|service| service.service_name())
+            .collect::<Vec<_>>();
+
+        let containers = self
+            .client
+            .list_containers(&[label_filter(APP_NAME_LABEL, deployment_unit.app_name())])
+            .await?;
+
+        let mut snapshots = Vec::new();
+        for container in containers {
+            let service_name = container
+                .label(SERVICE_NAME_LABEL)
+                .or_else(|| container.label(PARENT_SERVICE_LABEL));
+            if service_name.map_or(true, |name| deployed_services.contains(&name)) {
+                continue;
+            }
+            if container.label(ADDITIONAL_CONTAINER_LABEL).is_some()
+                && container.label(SIDECAR_LABEL).is_none()
+            {
+                continue;
+            }
+
+            let details = self.client.inspect_container(&container.id).await?;
+            let files = self.read_copied_files(&details).await?;
+            snapshots.push(ContainerSnapshot { details, files });
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Reads the archives of the files that PREvant copied into the container so that they can be
+    /// copied into a recreated container.
+    async fn read_copied_files(
+        &self,
+        details: &ContainerDetails,
+    ) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+        let Some(paths) = details
+            .config
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(FILES_LABEL))
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut files = Vec::new();
+        for path in serde_json::from_str::<Vec<PathBuf>>(paths)? {
+            let archive = self
+                .client
+                .get_archive(&details.id, &path.to_string_lossy())
+                .await?
+                .try_concat()
+                .await?;
+            files.push((path, archive));
+        }
+        Ok(files)
+    }
+
+    async fn recreate_container(
+        &self,
+        app_name: &AppName,
+        container: ContainerSnapshot,
+    ) -> Result<(), Error> {
+        let ContainerSnapshot { details, files } = container;
+        info!(
+            "Recreating container {} of app {} in the new pod",
+            details.id, app_name
+        );
+
+        let id = self
+            .client
+            .create_container(recreated_container_spec(app_name, &details))
+            .await?;
+        for (path, archive) in files {
+            let directory = path.parent().unwrap_or_else(|| Path::new("/"));
+            self.client
+                .put_archive(&id, &directory.to_string_lossy(), archive)
+                .await?;
+        }
+
+        match details.state.status.as_str() {
+            "running" => self.client.start_container(&id).await?,
+            "paused" => {
+                self.client.start_container(&id).await?;
+                self.client.pause_container(&id).await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Warns about services of the app that use the same port because the containers of a pod
+    /// share their network namespace and, thus, only one of them can listen on it.
+    async fn warn_about_port_clashes(
+        &self,
+        deployment_unit: &DeploymentUnit,
+    ) -> Result<(), PodmanError> {
+        let app_name = deployment_unit.app_name();
+        let mut services_by_port = HashMap::<(u16, PortProtocol), Vec<String>>::new();
+        for service in deployment_unit.services() {
+            for port in service_ports(service) {
+                services_by_port
+                    .entry(port)
+                    .or_default()
+                    .push(service.service_name().clone());
+            }
+        }
+
+        let containers = self
+            .client
+            .list_containers(&[label_filter(APP_NAME_LABEL, app_name)])
+            .await?;
+        for container in containers.iter().filter(|c| is_service_container(c)) {
+            let Some(service_name) = container.label(SERVICE_NAME_LABEL) else {
+                continue;
+            };
+            if deployment_unit
+                .services()
+                .iter()
+                .any(|service| service.service_name() == service_name)
+            {
+                continue;
+            }
+            for port in container_ports(container) {
+                services_by_port
+                    .entry(port)
+                    .or_default()
+                    .push(service_name.clone());
+            }
+        }
+
+        for ((port, _), service_names) in services_by_port {
+            if service_names.len() > 1 {
+                warn!(
+                    "The services {} of app {} use the port {} but only one of them can listen on it because they share the network of the pod.",
+                    service_names.join(", "),
+                    app_name,
+                    port
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deploy_services_impl(
+        &self,
+        deployment_unit: &DeploymentUnit,
+        container_config: &ContainerConfig,
+    ) -> Result<Vec<Service>, Error> {
+        let app_name = deployment_unit.app_name();
+        self.warn_about_port_clashes(deployment_unit).await?;
+        self.create_or_update_pod(deployment_unit).await?;
+
+        if let Some(Tls::SelfSigned { directory }) = self.config.tls() {
+            let mut dns_names = deployment_unit.app_base_route().certificate_dns_names();
+            if dns_names.is_empty() {
+                dns_names.push(String::from("localhost"));
+            }
+            issue_app_certificate(directory, app_name, dns_names).await?;
+        }
+
+        let existing_volumes = self
+            .client
+            .list_volumes(&[label_filter(APP_NAME_LABEL, app_name)])
+            .await?;
+
+        // The services of the next stage reach their dependencies through the pod's loopback
+        // interface which PREvant cannot probe. Therefore, the stages only define the start order.
+        let mut services = Vec::with_capacity(deployment_unit.services().len());
+        for stage in deployment_unit.startup_stages() {
+            let futures = stage
+                .iter()
+                .map(|service| {
+                    self.start_container(app_name, service, container_config, &existing_volumes)
+                })
+                .collect::<Vec<_>>();
+
+            for service in join_all(futures).await {
+                services.push(service?);
+            }
+        }
+
+        Ok(services)
+    }
+
+    async fn start_container(
+        &self,
+        app_name: &AppName,
+        service: &DeployableService,
+        container_config: &ContainerConfig,
+        existing_volumes: &[VolumeInfo],
+    ) -> Result<Service, Error> {
+        if let Image::Named { .. } = service.image() {
+            info!(
+                "Pulling {:?} for {:?} of app {:?}",
+                service.image(),
+                service.service_name(),
+                app_name
+            );
+            self.pull(service.image()).await?;
+        }
+
+        let service_filters = [
+            label_filter(APP_NAME_LABEL, app_name),
+            label_filter(SERVICE_NAME_LABEL, service.service_name()),
+        ];
+        let existing_containers = self.client.list_containers(&service_filters).await?;
+        if let Some(container) = existing_containers.first() {
+            match service.strategy() {
+                DeploymentStrategy::RedeployOnImageUpdate(image_id)
+                    if image_id.trim_start_matches("sha256:") == container.image_id =>
+                {
+                    debug!(
+                        "Container {} of review app {} is still running with the desired image id {}",
+                        container.id, app_name, image_id
+                    );
+                    return Ok(Service::try_from(container)?);
+                }
+                DeploymentStrategy::RedeployNever => {
+                    debug!(
+                        "Container {} of review app {} already deployed.",
+                        container.id, app_name
+                    );
+                    return Ok(Service::try_from(container)?);
+                }
+                DeploymentStrategy::RedeployAlways
+                | DeploymentStrategy::RedeployOnImageUpdate(_) => {}
+            }
+        }
+
+        let additional_containers = self
+            .client
+            .list_containers(&[
+                label_filter(APP_NAME_LABEL, app_name),
+                label_filter(PARENT_SERVICE_LABEL, service.service_name()),
+            ])
+            .await?;
+        for container in additional_containers
+            .iter()
+            .chain(existing_containers.iter())
+        {
+            info!(
+                "Removing container {} of review app {}",
+                container.id, app_name
+            );
+            self.client.remove_container(&container.id).await?;
+        }
+
+        self.run_init_containers(app_name, service, container_config)
+            .await?;
+
+        if service.initial_replicas() > 1 {
+            warn!(
+                "Podman starts a single container for {} of {} because the containers of a pod share their ports.",
+                service.service_name(),
+                app_name
+            );
+        }
+
+        info!(
+            "Creating new review app container for {:?}: service={:?} with image={:?} ({:?})",
+            app_name,
+            service.service_name(),
+            service.image(),
+            service.container_type(),
+        );
+
+        let volume = self
+            .service_volume(app_name, service, existing_volumes)
+            .await?;
+        let spec = PodmanInfrastructure::create_container_spec(
+            app_name,
+            service,
+            container_config,
+            volume.as_deref(),
+        );
+        let id = self.client.create_container(spec).await?;
+        debug!("Created container: {}", id);
+
+        self.copy_file_data(&id, service).await?;
+
+        self.client.start_container(&id).await?;
+        debug!("Started container: {}", id);
+
+        for sidecar in service.sidecars() {
+            debug!("Starting sidecar {:?} for container {}", sidecar.name(), id);
+
+            self.pull(sidecar.image()).await?;
+            let spec = PodmanInfrastructure::create_additional_container_spec(
+                app_name,
+                service,
+                sidecar,
+                container_config,
+                true,
+            );
+            let sidecar_id = self.client.create_container(spec).await?;
+            self.client.start_container(&sidecar_id).await?;
+        }
+
+        let container = self
+            .client
+            .list_containers(&service_filters)
+            .await?
+            .into_iter()
+            .find(|container| container.id == id)
+            .ok_or_else(|| {
+                format_err!(
+                    "No container has been started for {} of {}",
+                    service.service_name(),
+                    app_name
+                )
+            })?;
+        Ok(Service::try_from(&container)?)
+    }
+
+    async fn run_init_containers(
+        &self,
+        app_name: &AppName,
+        service: &DeployableService,
+        container_config: &ContainerConfig,
+    ) -> Result<(), Error> {
+        for init_container in service.init_containers() {
+            info!(
+                "Running init container {:?} of service {:?} in app {:?}",
+                init_container.name(),
+                service.service_name(),
+                app_name
+            );
+
+            self.pull(init_container.image()).await?;
+
+            let spec = PodmanInfrastructure::create_additional_container_spec(
+                app_name,
+                service,
+                init_container,
+                container_config,
+                false,
+            );
+            let id = self.client.create_container(spec).await?;
+            self.client.start_container(&id).await?;
+
+            let status_code = self.client.wait_container(&id).await?;
+            if status_code != 0 {
+                // The failed container is kept for inspecting its logs and it will be removed
+                // with the next deployment.
+                return Err(PodmanInfrastructureError::InitContainerFailed {
+                    container_name: init_container.name().clone(),
+                    service_name: service.service_name().clone(),
+                    status_code,
+                }
+                .into());
+            }
+
+            self.client.remove_container(&id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the volume that persists the declared volumes of the service and creates the
+    /// volume if it does not exist yet.
+    async fn service_volume(
+        &self,
+        app_name: &AppName,
+        service: &DeployableService,
+        existing_volumes: &[VolumeInfo],
+    ) -> Result<Option<String>, PodmanError> {
+        if service.declared_volumes().is_empty() {
+            return Ok(None);
+        }
+
+        let existing_volume = existing_volumes.iter().find(|volume| {
+            volume
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get(SERVICE_NAME_LABEL))
+                == Some(service.service_name())
+        });
+        if let Some(volume) = existing_volume {
+            return Ok(Some(volume.name.clone()));
+        }
+
+        let mut labels: BTreeMap<&str, &str> = BTreeMap::new();
+        labels.insert(APP_NAME_LABEL, app_name);
+        labels.insert(SERVICE_NAME_LABEL, service.service_name());
+        Ok(Some(self.client.create_volume(&labels).await?))
+    }
+
+    async fn copy_file_data(&self, id: &str, service_config: &ServiceConfig) -> Result<(), Error> {
+        let files = match service_config.files() {
+            None => return Ok(()),
+            Some(files) => files,
+        };
+
+        debug!(
+            "Copy data to container: {} (service = {})",
+            id,
+            service_config.service_name()
+        );
+
+        let mut archive = tar::Builder::new(Vec::new());
+        for (path, data) in files {
+            let data = data.unsecure().as_bytes();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            archive.append_data(&mut header, path.strip_prefix("/").unwrap_or(path), data)?;
+        }
+
+        self.client
+            .put_archive(id, "/", archive.into_inner()?)
+            .await?;
+        Ok(())
+    }
+
+    async fn service_container(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+    ) -> Result<Option<ContainerInfo>, PodmanError> {
+        let filters = [
+            label_filter(APP_NAME_LABEL, app_name),
+            label_filter(SERVICE_NAME_LABEL, service_name),
+        ];
+        Ok(self
+            .client
+            .list_containers(&filters)
+            .await?
+            .into_iter()
+            .find(is_service_container))
+    }
+
+    async fn pull(&self, image: &Image) -> Result<(), PodmanError> {
+        let credentials = image
+            .registry()
+            .and_then(|registry| self.config.registry_credentials(&registry));
+        self.client
+            .pull_image(&image.to_string(), credentials)
+            .await
+    }
+
+    /// Creates the [libpod container
+    /// specification](https://docs.podman.io/en/latest/_static/api.html#tag/containers/operation/ContainerCreateLibpod)
+    /// of a service. The Traefik labels are the same as those of Docker containers so that Traefik
+    /// can discover the containers through the Docker provider that connects to Podman's socket.
+    fn create_container_spec(
+        app_name: &AppName,
+        service: &DeployableService,
+        container_config: &ContainerConfig,
+        volume: Option<&str>,
+    ) -> Value {
+        let mut labels: BTreeMap<String, String> = BTreeMap::new();
+        labels.extend(Docker::traefik_labels(
+            app_name,
+            service,
+            service.ingress_route(),
+        ));
+        labels.extend(Docker::port_router_labels(app_name, service));
+        if let Some(config_labels) = service.labels() {
+            labels.extend(config_labels.clone());
+        }
+
+        labels.insert(String::from(APP_NAME_LABEL), app_name.to_string());
+        labels.insert(
+            String::from(SERVICE_NAME_LABEL),
+            service.service_name().clone(),
+        );
+        labels.insert(
+            String::from(CONTAINER_TYPE_LABEL),
+            service.container_type().to_string(),
+        );
+        labels.insert(String::from(IMAGE_LABEL), service.image().to_string());
+        // The paths of the files let a recreation of the pod copy them into the new container.
+        if let Some(files) = service.files() {
+            labels.insert(
+                String::from(FILES_LABEL),
+                json!(files.keys().collect::<Vec<_>>()).to_string(),
+            );
+        }
+        if let Some(replicated_env) = service
+            .env()
+            .and_then(replicated_environment_variable_to_json)
+        {
+            labels.insert(
+                String::from(REPLICATED_ENV_LABEL),
+                replicated_env.to_string(),
+            );
+        }
+
+        let mut spec = json!({
+            "image": service.image().to_string(),
+            "pod": pod_name(app_name),
+            "labels": labels,
+            "env": environment(service.env()),
+            "restart_policy": "always",
+        });
+
+        if let Some(volume) = volume {
+            spec["volumes"] = service
+                .declared_volumes()
+                .iter()
+                .map(|path| json!({ "Name": volume, "Dest": path }))
+                .collect();
+        }
+
+        apply_memory_limit(&mut spec, container_config);
+        spec
+    }
+
+    /// Creates the container specification for init containers and sidecars. Both are placed into
+    /// the app's pod but only sidecars are restarted.
+    fn create_additional_container_spec(
+        app_name: &AppName,
+        service_config: &ServiceConfig,
+        container: &AdditionalContainer,
+        container_config: &ContainerConfig,
+        sidecar: bool,
+    ) -> Value {
+        let mut labels: BTreeMap<&str, &str> = BTreeMap::new();
+        labels.insert(APP_NAME_LABEL, app_name);
+        labels.insert(PARENT_SERVICE_LABEL, service_config.service_name());
+        labels.insert(ADDITIONAL_CONTAINER_LABEL, container.name());
+        if sidecar {
+            labels.insert(SIDECAR_LABEL, "true");
+        }
+
+        let mut spec = json!({
+            "image": container.image().to_string(),
+            "pod": pod_name(app_name),
+            "labels": labels,
+            "env": environment(container.env()),
+        });
+
+        if let Some(args) = container.args() {
+            spec["command"] = json!(args);
+        }
+        if sidecar {
+            spec["restart_policy"] = json!("always");
+        }
+
+        apply_memory_limit(&mut spec, container_config);
+        spec
+    }
+
+    async fn stop_services_impl(&self, app_name: &AppName) -> Result<Vec<Service>, Error> {
+        let containers = self
+            .client
+            .list_containers(&[label_filter(APP_NAME_LABEL, app_name)])
+            .await?;
+        if containers.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let futures = containers
+            .iter()
+            .map(|container| self.client.remove_container(&container.id));
+        for result in join_all(futures).await {
+            result?;
+        }
+
+        let mut services = Vec::with_capacity(containers.len());
+        for container in containers
+            .iter()
+            .filter(|container| is_service_container(container))
+        {
+            trace!("Deleted container {:?}", container);
+            services.push(Service::try_from(container)?);
+        }
+
+        self.client.remove_pod(&pod_name(app_name)).await?;
+        for volume in self
+            .client
+            .list_volumes(&[label_filter(APP_NAME_LABEL, app_name)])
+            .await?
+        {
+            self.client.remove_volume(&volume.name).await?;
+        }
+        if let Some(Tls::SelfSigned { directory }) = self.config.tls() {
+            remove_app_certificate(directory, app_name).await?;
+        }
+
+        Ok(services)
+    }
+}
+
+#[async_trait]
+impl Infrastructure for PodmanInfrastructure {
+    async fn get_services(&self) -> Result<MultiMap<AppName, Service>, Error> {
+        let containers = self
+            .client
+            .list_containers(&[String::from(APP_NAME_LABEL)])
+            .await?;
+
+        let mut apps = MultiMap::new();
+        for container in containers
+            .iter()
+            .filter(|container| is_service_container(container))
+        {
+            let service = match Service::try_from(container) {
+                Ok(service) => service,
+                Err(e) => {
+                    debug!("Container does not provide required data: {:?}", e);
+                    continue;
+                }
+            };
+
+            if let Ok(app_name) = AppName::from_str(service.app_name()) {
+                apps.insert(app_name, service);
+            }
+        }
+
+        Ok(apps)
+    }
+
+    async fn deploy_services(
+        &self,
+        _status_id: &str,
+        deployment_unit: &DeploymentUnit,
+        container_config: &ContainerConfig,
+    ) -> Result<Vec<Service>, Error> {
+        self.deploy_services_impl(deployment_unit, container_config)
+            .await
+    }
+
+    /// Deletes all services and the pod for the given `app_name`.
+    async fn stop_services(
+        &self,
+        _status_id: &str,
+        app_name: &AppName,
+    ) -> Result<Vec<Service>, Error> {
+        self.stop_services_impl(app_name).await
+    }
+
+    /// Deletes the containers of the service, including its init containers and sidecars, while
+    /// the pod of the app keeps running.
+    async fn stop_service(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        remove_volumes: bool,
+    ) -> Result<Option<Service>, Error> {
+        let service_containers = self
+            .client
+            .list_containers(&[
+                label_filter(APP_NAME_LABEL, app_name),
+                label_filter(SERVICE_NAME_LABEL, service_name),
+            ])
+            .await?;
+        if service_containers.is_empty() {
+            return Ok(None);
+        }
+
+        let additional_containers = self
+            .client
+            .list_containers(&[
+                label_filter(APP_NAME_LABEL, app_name),
+                label_filter(PARENT_SERVICE_LABEL, service_name),
+            ])
+            .await?;
+
+        let futures = service_containers
+            .iter()
+            .chain(additional_containers.iter())
+            .map(|container| self.client.remove_container(&container.id));
+        for result in join_all(futures).await {
+            result?;
+        }
+
+        if remove_volumes {
+            for volume in self
+                .client
+                .list_volumes(&[
+                    label_filter(APP_NAME_LABEL, app_name),
+                    label_filter(SERVICE_NAME_LABEL, service_name),
+                ])
+                .await?
+            {
+                self.client.remove_volume(&volume.name).await?;
+            }
+        }
+
+        let service = service_containers
+            .iter()
+            .find(|container| is_service_container(container))
+            .map(Service::try_from)
+            .transpose()?;
+        Ok(service)
+    }
+
+    async fn get_logs<'a>(
+        &'a self,
+        app_name: &'a AppName,
+        service_name: &'a str,
+        from: &'a Option<DateTime<FixedOffset>>,
+        limit: &'a Option<usize>,
+        streams: LogStreams,
+        follow: bool,
+    ) -> BoxStream<'a, Result<(DateTime<FixedOffset>, String), failure::Error>> {
+        stream! {
+            let filters = [
+                label_filter(APP_NAME_LABEL, app_name),
+                label_filter(SERVICE_NAME_LABEL, service_name),
+            ];
+            let container = self
+                .client
+                .list_containers(&filters)
+                .await
+                .map(|containers| containers.into_iter().next());
+
+            match container {
+                Ok(None) => {}
+                Ok(Some(container)) => {
+                    trace!("Acquiring logs of container {} since {:?}", container.id, from);
+
+                    let since = from.as_ref().map(DateTime::timestamp);
+                    match self.client.logs(&container.id, since, streams, follow).await {
+                        Ok(logs) => {
+                            let mut logs = match limit {
+                                Some(log_limit) => logs.take(*log_limit).boxed(),
+                                None => logs,
+                            };
+
+                            while let Some(result) = logs.next().await {
+                                match result {
+                                    Ok(line) => {
+                                        let mut iter = line.splitn(2, ' ');
+                                        let timestamp = iter.next().unwrap_or_default();
+                                        match DateTime::parse_from_rfc3339(timestamp) {
+                                            Ok(datetime) => {
+                                                let log_line = iter.collect::<Vec<&str>>().join(" ");
+                                                yield Ok((datetime, log_line))
+                                            }
+                                            Err(err) => yield Err(format_err!(
+                                                "Podman returned a log line without valid timestamp: {}",
+                                                err
+                                            )),
+                                        }
+                                    }
+                                    Err(e) => yield Err(e.into()),
+                                }
+                            }
+                        }
+                        Err(e) => yield Err(e.into()),
+                    }
+                }
+                Err(e) => yield Err(e.into()),
+            }
+        }
+        .boxed()
+    }
+
+    /// Pauses or resumes the containers of the service, including its sidecars. Stopped
+    /// containers, e.g. after a restart of the host, are started again.
+    async fn change_status(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        status: ServiceStatus,
+    ) -> Result<Option<Service>, Error> {
+        let service_filters = [
+            label_filter(APP_NAME_LABEL, app_name),
+            label_filter(SERVICE_NAME_LABEL, service_name),
+        ];
+        let service_containers = self.client.list_containers(&service_filters).await?;
+        if service_containers.is_empty() {
+            return Ok(None);
+        }
+
+        let sidecars = self
+            .client
+            .list_containers(&[
+                label_filter(APP_NAME_LABEL, app_name),
+                label_filter(PARENT_SERVICE_LABEL, service_name),
+                String::from(SIDECAR_LABEL),
+            ])
+            .await?;
+
+        for container in service_containers.iter().chain(sidecars.iter()) {
+            let result = match (&status, container.state.as_str()) {
+                (ServiceStatus::Running, "running") | (ServiceStatus::Paused, "paused") => {
+                    trace!("Container {} already in desired state", container.id);
+                    Ok(())
+                }
+                (ServiceStatus::Running, "paused") => {
+                    self.client.unpause_container(&container.id).await
+                }
+                (ServiceStatus::Running, _) => self.client.start_container(&container.id).await,
+                (ServiceStatus::Paused, "running") => {
+                    self.client.pause_container(&container.id).await
+                }
+                (ServiceStatus::Paused, _) => Ok(()),
+            };
+
+            if let Err(err) = result {
+                error!(
+                    "Could not change status of container {}: {}",
+                    container.id, err
+                );
+                return Err(err.into());
+            }
+        }
+
+        let service = self
+            .client
+            .list_containers(&service_filters)
+            .await?
+            .iter()
+            .find(|container| is_service_container(container))
+            .map(Service::try_from)
+            .transpose()?;
+        Ok(service)
+    }
+
+    async fn exec(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<Option<ExecOutput>, Error> {
+        let Some(container) = self.service_container(app_name, service_name).await? else {
+            return Ok(None);
+        };
+
+        let (exit_code, stdout, stderr) = self.client.exec(&container.id, command).await?;
+        Ok(Some(ExecOutput::new(exit_code, stdout, stderr)))
+    }
+
+    async fn download_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+    ) -> Result<Option<BoxStream<'static, Result<Vec<u8>, Error>>>, Error> {
+        let Some(container) = self.service_container(app_name, service_name).await? else {
+            return Ok(None);
+        };
+
+        let archive = self.client.get_archive(&container.id, path).await?;
+        Ok(Some(
+            archive.map(|chunk| chunk.map_err(Error::from)).boxed(),
+        ))
+    }
+
+    async fn upload_files(
+        &self,
+        app_name: &AppName,
+        service_name: &str,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> Result<bool, Error> {
+        let Some(container) = self.service_container(app_name, service_name).await? else {
+            return Ok(false);
+        };
+
+        self.client
+            .put_archive(&container.id, path, archive)
+            .await?;
+        Ok(true)
+    }
+}
+
+fn pod_name(app_name: &AppName) -> String {
+    format!("{}-pod", app_name)
+}
+
+/// A container that is recreated when the pod of its app is recreated.
+struct ContainerSnapshot {
+    details: ContainerDetails,
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+fn host_entry(service_name: &str) -> String {
+    format!("{}:127.0.0.1", service_name)
+}
+
+/// Creates the container specification of a container that is recreated from its inspection.
+fn recreated_container_spec(app_name: &AppName, details: &ContainerDetails) -> Value {
+    let env = details
+        .config
+        .env
+        .iter()
+        .filter_map(|variable| variable.split_once('='))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut spec = json!({
+        "image": details.image_name,
+        "pod": pod_name(app_name),
+        "labels": details.config.labels.clone().unwrap_or_default(),
+        "env": env,
+    });
+
+    if let Some(cmd) = &details.config.cmd {
+        spec["command"] = json!(cmd);
+    }
+    if let Some(restart_policy) = details
+        .host_config
+        .restart_policy
+        .as_ref()
+        .filter(|policy| !policy.name.is_empty() && policy.name != "no")
+    {
+        spec["restart_policy"] = json!(restart_policy.name);
+    }
+
+    let volumes = details
+        .mounts
+        .iter()
+        .filter(|mount| mount.kind == "volume")
+        .map(|mount| json!({ "Name": mount.name, "Dest": mount.destination }))
+        .collect::<Vec<_>>();
+    if !volumes.is_empty() {
+        spec["volumes"] = json!(volumes);
+    }
+
+    if details.host_config.memory > 0 {
+        spec["resource_limits"] = json!({
+            "memory": {
+                "limit": details.host_config.memory,
+                "swap": details.host_config.memory_swap,
+            }
+        });
+    }
+
+    spec
+}
+
+/// The ports a service listens on: the HTTP port and its additional ports.
+fn service_ports(service: &ServiceConfig) -> Vec<(u16, PortProtocol)> {
+    std::iter::once((service.port(), PortProtocol::Tcp))
+        .chain(
+            service
+                .ports()
+                .iter()
+                .map(|port| (port.port(), port.protocol())),
+        )
+        .collect()
+}
+
+/// The ports of a running service as declared by its Traefik labels, e.g.
+/// `traefik.http.services.master-db.loadbalancer.server.port`.
+fn container_ports(container: &ContainerInfo) -> Vec<(u16, PortProtocol)> {
+    container
+        .labels
+        .iter()
+        .flatten()
+        .filter(|(key, _)| key.ends_with(".loadbalancer.server.port"))
+        .filter_map(|(key, value)| {
+            let protocol = match key.split('.').nth(1) {
+                Some("udp") => PortProtocol::Udp,
+                _ => PortProtocol::Tcp,
+            };
+            value.parse::<u16>().ok().map(|port| (port, protocol))
+        })
+        .collect()
+}
+
+fn label_filter(key: &str, value: &str) -> String {
+    format!("{key}={value}")
+}
+
+/// Returns `false` for init containers and sidecars so that each service is reported only once.
+fn is_service_container(container: &ContainerInfo) -> bool {
+    container.label(PARENT_SERVICE_LABEL).is_none()
+}
+
+fn environment(env: Option<&Environment>) -> BTreeMap<String, String> {
+    env.map(|env| {
+        env.iter()
+            .map(|variable| {
+                (
+                    variable.key().clone(),
+                    variable.value().unsecure().to_string(),
+                )
+            })
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn apply_memory_limit(spec: &mut Value, container_config: &ContainerConfig) {
+    if let Some(memory_limit) = container_config.memory_limit() {
+        spec["resource_limits"] = json!({
+            "memory": {
+                "limit": memory_limit.as_u64(),
+                "swap": memory_limit.as_u64(),
+            }
+        });
+    }
+}
+
+impl TryFrom<&ContainerInfo> for Service {
+    type Error = PodmanInfrastructureError;
+
+    fn try_from(container: &ContainerInfo) -> Result<Service, PodmanInfrastructureError> {
+        let app_name = container.label(APP_NAME_LABEL).ok_or_else(|| {
+            PodmanInfrastructureError::MissingAppNameLabel {
+                container_id: container.id.clone(),
+            }
+        })?;
+
+        let status = if container.is_running() {
+            ServiceStatus::Running
+        } else {
+            ServiceStatus::Paused
+        };
+        let started_at = Utc
+            .timestamp_opt(container.started_at, 0)
+            .single()
+            .unwrap_or_else(Utc::now);
+
+        Ok(ServiceBuilder::new()
+            .id(container.id.clone())
+            .app_name(app_name.clone())
+            .config(ServiceConfig::try_from(container)?)
+            .service_status(status)
+            .started_at(started_at)
+            .build()?)
+    }
+}
+
+impl TryFrom<&ContainerInfo> for ServiceConfig {
+    type Error = PodmanInfrastructureError;
+
+    fn try_from(container: &ContainerInfo) -> Result<Self, Self::Error> {
+        let service_name = container.label(SERVICE_NAME_LABEL).ok_or_else(|| {
+            PodmanInfrastructureError::MissingServiceNameLabel {
+                container_id: container.id.clone(),
+            }
+        })?;
+
+        let image = container.label(IMAGE_LABEL).unwrap_or(&container.image);
+        let image = Image::from_str(image).map_err(|err| {
+            PodmanInfrastructureError::UnexpectedImageFormat {
+                img: image.clone(),
+                err: err.to_string(),
+            }
+        })?;
+        let mut config = ServiceConfig::new(service_name.clone(), image);
+
+        if let Some(container_type) = container.label(CONTAINER_TYPE_LABEL) {
+            config.set_container_type(container_type.parse::<ContainerType>()?);
+        }
+
+        if let Some(replicated_env) = container.label(REPLICATED_ENV_LABEL) {
+            let env = serde_json::from_str::<Environment>(replicated_env).map_err(|err| {
+                PodmanInfrastructureError::UnexpectedError {
+                    internal_message: err.to_string(),
+                }
+            })?;
+            config.set_env(Some(env));
+        }
+
+        Ok(config)
+    }
+}
+
+impl From<ServiceError> for PodmanInfrastructureError {
+    fn from(err: ServiceError) -> Self {
+        PodmanInfrastructureError::UnexpectedError {
+            internal_message: err.to_string(),
+        }
+    }
+}
+
+impl From<ServiceBuilderError> for PodmanInfrastructureError {
+    fn from(err: ServiceBuilderError) -> Self {
+        PodmanInfrastructureError::UnexpectedError {
+            internal_message: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deployment::deployment_unit::DeploymentUnitBuilder;
+    use crate::infrastructure::podman::fake_socket::FakeSocket;
+    use crate::infrastructure::TraefikIngressRoute;
+    use crate::sc;
+    use hyper::{Method, StatusCode};
+    use std::collections::HashMap;
+
+    fn infrastructure(socket: &FakeSocket) -> PodmanInfrastructure {
+        let config = crate::config_from_str!(&format!(
+            r#"
+            [runtime]
+            type = 'Podman'
+            socket = '{}'
+            "#,
+            socket.socket().display()
+        ));
+        PodmanInfrastructure::new(config)
+    }
+
+    fn container(id: &str, app_name: &str, service_name: &str, state: &str) -> Value {
+        json!({
+            "Id": id,
+            "Image": "docker.io/library/mariadb:10.3.17",
+            "ImageID": "4711",
+            "State": state,
+            "StartedAt": 1700000000,
+            "Labels": {
+                APP_NAME_LABEL: app_name,
+                SERVICE_NAME_LABEL: service_name,
+                CONTAINER_TYPE_LABEL: "instance",
+                IMAGE_LABEL: "docker.io/library/mariadb:10.3.17",
+            }
+        })
+    }
+
+    fn sidecar(id: &str, app_name: &str, service_name: &str) -> Value {
+        json!({
+            "Id": id,
+            "Image": "docker.io/prom/mysqld-exporter:latest",
+            "State": "running",
+            "Labels": {
+                APP_NAME_LABEL: app_name,
+                PARENT_SERVICE_LABEL: service_name,
+                ADDITIONAL_CONTAINER_LABEL: "exporter",
+                SIDECAR_LABEL: "true",
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn should_collect_services_of_apps() -> Result<(), Error> {
+        let socket = FakeSocket::start(|method, path, _| match (method, path) {
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([
+                    container("db-master", "master", "db", "running"),
+                    sidecar("exporter-master", "master", "db"),
+                    container("db-feature", "feature", "db", "paused"),
+                ]),
+            ),
+            _ => (StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        });
+
+        let apps = infrastructure(&socket).get_services().await?;
+
+        let services = apps.get_vec(&AppName::master()).unwrap();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), "db-master");
+        assert_eq!(services[0].service_name(), "db");
+        assert_eq!(services[0].status(), &ServiceStatus::Running);
+
+        let services = apps.get_vec(&AppName::from_str("feature")?).unwrap();
+        assert_eq!(services[0].status(), &ServiceStatus::Paused);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_deploy_services_into_pod_of_app() -> Result<(), Error> {
+        let socket = FakeSocket::start(|method, path, query| match (method, path) {
+            (&Method::GET, "/pods/master-pod/json") => {
+                (StatusCode::NOT_FOUND, json!({ "message": "no such pod" }))
+            }
+            (&Method::POST, "/pods/create") => (StatusCode::CREATED, json!({ "Id": "pod-id" })),
+            (&Method::POST, "/images/pull") => (StatusCode::OK, json!({ "id": "4711" })),
+            (&Method::GET, "/volumes/json") => (StatusCode::OK, json!([])),
+            (&Method::GET, "/containers/json") if query.contains("parent-service") => {
+                (StatusCode::OK, json!([]))
+            }
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([container("db-id", "master", "db", "running")]),
+            ),
+            (&Method::DELETE, "/containers/db-id") => (StatusCode::OK, json!([])),
+            (&Method::POST, "/containers/create") => (
+                StatusCode::CREATED,
+                json!({ "Id": "db-id", "Warnings": [] }),
+            ),
+            (&Method::POST, "/containers/db-id/start") => (StatusCode::NO_CONTENT, Value::Null),
+            _ => (StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        });
+
+        let config = Config::default();
+        let unit =
+            DeploymentUnitBuilder::init(AppName::master(), vec![sc!("db", "mariadb:10.3.17")])
+                .extend_with_config(&config)
+                .await?
+                .extend_with_templating_only_service_configs(Vec::new())
+                .extend_with_image_infos(HashMap::new())
+                .apply_templating()?
+                .apply_hooks(&config)
+                .await?
+                .apply_base_traefik_ingress_route(TraefikIngressRoute::empty())
+                .build();
+
+        let services = infrastructure(&socket)
+            .deploy_services("status-id", &unit, &ContainerConfig::default())
+            .await?;
+
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), "db-id");
+
+        let pod = socket.requests_to(Method::POST, "/pods/create")[0].json();
+        assert_eq!(pod["name"], "master-pod");
+        assert_eq!(pod["hostadd"], json!(["db:127.0.0.1"]));
+
+        assert_eq!(
+            socket
+                .requests_to(Method::DELETE, "/containers/db-id")
+                .len(),
+            1
+        );
+
+        let spec = socket.requests_to(Method::POST, "/containers/create")[0].json();
+        assert_eq!(spec["pod"], "master-pod");
+        assert_eq!(spec["image"], "docker.io/library/mariadb:10.3.17");
+        assert_eq!(spec["labels"][APP_NAME_LABEL], "master");
+        assert_eq!(
+            spec["labels"]["traefik.http.routers.master-db.rule"],
+            "PathPrefix(`/master/db/`)"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_recreate_pod_with_host_entries_of_new_services() -> Result<(), Error> {
+        let socket = FakeSocket::start(|method, path, query| match (method, path) {
+            (&Method::GET, "/pods/master-pod/json") => (
+                StatusCode::OK,
+                json!({ "Name": "master-pod", "InfraConfig": { "HostAdd": ["db:127.0.0.1"] } }),
+            ),
+            (&Method::DELETE, "/pods/master-pod") => (StatusCode::OK, json!([])),
+            (&Method::POST, "/pods/create") => (StatusCode::CREATED, json!({ "Id": "pod-id" })),
+            (&Method::POST, "/images/pull") => (StatusCode::OK, json!({ "id": "4711" })),
+            (&Method::GET, "/volumes/json") => (StatusCode::OK, json!([])),
+            (&Method::GET, "/containers/json")
+                if query.contains("service-name") || query.contains("parent-service") =>
+            {
+                (StatusCode::OK, json!([]))
+            }
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([container("db-id", "master", "db", "running")]),
+            ),
+            (&Method::GET, "/containers/db-id/json") => (
+                StatusCode::OK,
+                json!({
+                    "Id": "db-id",
+                    "ImageName": "docker.io/library/mariadb:10.3.17",
+                    "Config": {
+                        "Env": ["MYSQL_DATABASE=app"],
+                        "Labels": { APP_NAME_LABEL: "master", SERVICE_NAME_LABEL: "db" }
+                    },
+                    "HostConfig": { "RestartPolicy": { "Name": "always" } },
+                    "Mounts": [{ "Type": "volume", "Name": "db-volume", "Destination": "/var/lib/mysql" }],
+                    "State": { "Status": "running" }
+                }),
+            ),
+            (&Method::POST, "/containers/create") => (
+                StatusCode::CREATED,
+                json!({ "Id": "new-id", "Warnings": [] }),
+            ),
+            (&Method::POST, "/containers/new-id/start") => (StatusCode::NO_CONTENT, Value::Null),
+            _ => (StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        });
+
+        let config = Config::default();
+        let unit = DeploymentUnitBuilder::init(AppName::master(), vec![sc!("api", "nginx:1.25")])
+            .extend_with_config(&config)
+            .await?
+            .extend_with_templating_only_service_configs(Vec::new())
+            .extend_with_image_infos(HashMap::new())
+            .apply_templating()?
+            .apply_hooks(&config)
+            .await?
+            .apply_base_traefik_ingress_route(TraefikIngressRoute::empty())
+            .build();
+
+        infrastructure(&socket)
+            .deploy_services("status-id", &unit, &ContainerConfig::default())
+            .await?;
+
+        assert_eq!(
+            socket.requests_to(Method::DELETE, "/pods/master-pod").len(),
+            1
+        );
+        let pod = socket.requests_to(Method::POST, "/pods/create")[0].json();
+        assert_eq!(pod["hostadd"], json!(["db:127.0.0.1", "api:127.0.0.1"]));
+
+        let specs = socket.requests_to(Method::POST, "/containers/create");
+        assert_eq!(specs.len(), 2);
+        let db = specs[0].json();
+        assert_eq!(db["pod"], "master-pod");
+        assert_eq!(db["image"], "docker.io/library/mariadb:10.3.17");
+        assert_eq!(db["labels"][SERVICE_NAME_LABEL], "db");
+        assert_eq!(db["env"]["MYSQL_DATABASE"], "app");
+        assert_eq!(db["restart_policy"], "always");
+        assert_eq!(
+            db["volumes"],
+            json!([{ "Name": "db-volume", "Dest": "/var/lib/mysql" }])
+        );
+        assert_eq!(specs[1].json()["image"], "docker.io/library/nginx:1.25");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_keep_pod_if_services_are_resolvable() -> Result<(), Error> {
+        let socket = FakeSocket::start(|method, path, query| match (method, path) {
+            (&Method::GET, "/pods/master-pod/json") => (
+                StatusCode::OK,
+                json!({ "Name": "master-pod", "InfraConfig": { "HostAdd": ["db:127.0.0.1"] } }),
+            ),
+            (&Method::POST, "/images/pull") => (StatusCode::OK, json!({ "id": "4711" })),
+            (&Method::GET, "/volumes/json") => (StatusCode::OK, json!([])),
+            (&Method::GET, "/containers/json") if query.contains("parent-service") => {
+                (StatusCode::OK, json!([]))
+            }
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([container("db-id", "master", "db", "running")]),
+            ),
+            (&Method::DELETE, "/containers/db-id") => (StatusCode::OK, json!([])),
+            (&Method::POST, "/containers/create") => (
+                StatusCode::CREATED,
+                json!({ "Id": "db-id", "Warnings": [] }),
+            ),
+            (&Method::POST, "/containers/db-id/start") => (StatusCode::NO_CONTENT, Value::Null),
+            _ => (StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        });
+
+        let config = Config::default();
+        let unit =
+            DeploymentUnitBuilder::init(AppName::master(), vec![sc!("db", "mariadb:10.3.17")])
+                .extend_with_config(&config)
+                .await?
+                .extend_with_templating_only_service_configs(Vec::new())
+                .extend_with_image_infos(HashMap::new())
+                .apply_templating()?
+                .apply_hooks(&config)
+                .await?
+                .apply_base_traefik_ingress_route(TraefikIngressRoute::empty())
+                .build();
+
+        infrastructure(&socket)
+            .deploy_services("status-id", &unit, &ContainerConfig::default())
+            .await?;
+
+        assert!(socket.requests_to(Method::POST, "/pods/create").is_empty());
+        assert!(socket
+            .requests_to(Method::DELETE, "/pods/master-pod")
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_pause_service_and_its_sidecars() -> Result<(), Error> {
+        let socket = FakeSocket::start(|method, path, query| match (method, path) {
+            (&Method::GET, "/containers/json") if query.contains("parent-service") => (
+                StatusCode::OK,
+                json!([sidecar("exporter-id", "master", "db")]),
+            ),
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([container("db-id", "master", "db", "running")]),
+            ),
+            (&Method::POST, "/containers/db-id/pause")
+            | (&Method::POST, "/containers/exporter-id/pause") => {
+                (StatusCode::NO_CONTENT, Value::Null)
+            }
+            _ => (StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        });
+
+        let service = infrastructure(&socket)
+            .change_status(&AppName::master(), "db", ServiceStatus::Paused)
+            .await?;
+
+        assert!(service.is_some());
+        assert_eq!(
+            socket
+                .requests_to(Method::POST, "/containers/db-id/pause")
+                .len(),
+            1
+        );
+        assert_eq!(
+            socket
+                .requests_to(Method::POST, "/containers/exporter-id/pause")
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_stop_services_and_remove_pod_and_volumes() -> Result<(), Error> {
+        let socket = FakeSocket::start(|method, path, _| match (method, path) {
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([container("db-id", "master", "db", "running")]),
+            ),
+            (&Method::GET, "/volumes/json") => (
+                StatusCode::OK,
+                json!([{ "Name": "db-volume", "Labels": { APP_NAME_LABEL: "master" } }]),
+            ),
+            (&Method::DELETE, "/containers/db-id") | (&Method::DELETE, "/pods/master-pod") => {
+                (StatusCode::OK, json!({}))
+            }
+            (&Method::DELETE, "/volumes/db-volume") => (StatusCode::NO_CONTENT, Value::Null),
+            _ => (StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        });
+
+        let services = infrastructure(&socket)
+            .stop_services("status-id", &AppName::master())
+            .await?;
+
+        assert_eq!(services.len(), 1);
+        assert_eq!(
+            socket.requests_to(Method::DELETE, "/pods/master-pod").len(),
+            1
+        );
+        assert_eq!(
+            socket
+                .requests_to(Method::DELETE, "/volumes/db-volume")
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_stop_single_service_with_its_sidecars() -> Result<(), Error> {
+        let socket = FakeSocket::start(|method, path, query| match (method, path) {
+            (&Method::GET, "/containers/json") if query.contains("parent-service") => (
+                StatusCode::OK,
+                json!([sidecar("exporter-id", "master", "db")]),
+            ),
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([container("db-id", "master", "db", "running")]),
+            ),
+            (&Method::DELETE, "/containers/db-id")
+            | (&Method::DELETE, "/containers/exporter-id") => (StatusCode::OK, json!({})),
+            _ => (StatusCode::NOT_FOUND, json!({ "message": "not found" })),
+        });
+
+        let service = infrastructure(&socket)
+            .stop_service(&AppName::master(), "db", false)
+            .await?;
+
+        assert_eq!(
+            service.map(|s| s.service_name().clone()),
+            Some(String::from("db"))
+        );
+        assert_eq!(
+            socket
+                .requests_to(Method::DELETE, "/containers/exporter-id")
+                .len(),
+            1
+        );
+        assert!(socket
+            .requests_to(Method::DELETE, "/pods/master-pod")
+            .is_empty());
+        assert!(socket.requests_to(Method::GET, "/volumes/json").is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_stream_logs_with_timestamps() -> Result<(), Error> {
+        let line = "2024-01-01T12:00:00Z Listening on port 3306\n";
+        let mut frame = vec![1u8, 0, 0, 0];
+        frame.extend_from_slice(&(line.len() as u32).to_be_bytes());
+        frame.extend_from_slice(line.as_bytes());
+
+        let socket = FakeSocket::start_with_bytes(move |method, path, _| match (method, path) {
+            (&Method::GET, "/containers/json") => (
+                StatusCode::OK,
+                json!([container("db-id", "master", "db", "running")])
+                    .to_string()
+                    .into_bytes(),
+            ),
+            (&Method::GET, "/containers/db-id/logs") => (StatusCode::OK, frame.clone()),
+            _ => (StatusCode::NOT_FOUND, Vec::new()),
+        });
+
+        let infrastructure = infrastructure(&socket);
+        let app_name = AppName::master();
+        let from = Some(DateTime::parse_from_rfc3339("2024-01-01T11:00:00Z")?);
+        let logs = infrastructure
+            .get_logs(&app_name, "db", &from, &None, LogStreams::Both, false)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(logs.len(), 1);
+        let (timestamp, log_line) = logs.into_iter().next().unwrap()?;
+        assert_eq!(
+            timestamp,
+            DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z")?
+        );
+        assert_eq!(log_line, "Listening on port 3306\n");
+        assert!(socket.requests_to(Method::GET, "/containers/db-id/logs")[0]
+            .query
+            .contains("since=1704106800"));
+
+        Ok(())
+    }
+}
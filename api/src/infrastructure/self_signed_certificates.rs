@@ -0,0 +1,255 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+
+//! Issues certificates for review apps that are signed by a self-signed CA. The certificates are
+//! stored next to a [Traefik file provider](https://doc.traefik.io/traefik/v1.7/configuration/backends/file/)
+//! configuration so that Traefik picks them up. Browsers trust the certificates once `ca.crt` has
+//! been imported.
+
+use crate::models::AppName;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair, RcgenError};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+const CA_CERT_FILE: &str = "ca.crt";
+const CA_KEY_FILE: &str = "ca.key";
+
+#[derive(Debug, Fail)]
+pub enum SelfSignedCertificateError {
+    #[fail(display = "Cannot issue self-signed certificate: {}", err)]
+    Issuing { err: String },
+    #[fail(display = "Cannot store self-signed certificate: {}", err)]
+    Storage { err: String },
+}
+
+impl From<RcgenError> for SelfSignedCertificateError {
+    fn from(err: RcgenError) -> Self {
+        Self::Issuing {
+            err: err.to_string(),
+        }
+    }
+}
+
+impl From<std::io::Error> for SelfSignedCertificateError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Storage {
+            err: err.to_string(),
+        }
+    }
+}
+
+/// Issues a certificate for `dns_names` and stores it together with the Traefik configuration
+/// that refers to it in `directory`. The CA is created on first use and reused afterwards.
+pub async fn issue_app_certificate(
+    directory: &Path,
+    app_name: &AppName,
+    dns_names: Vec<String>,
+) -> Result<(), SelfSignedCertificateError> {
+    tokio::fs::create_dir_all(directory).await?;
+    let ca = load_or_create_ca(directory).await?;
+
+    let mut params = CertificateParams::new(dns_names);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, app_name.as_str());
+    let certificate = Certificate::from_params(params)?;
+
+    let (cert_file, key_file) = app_certificate_files(directory, app_name);
+    tokio::fs::write(&cert_file, certificate.serialize_pem_with_signer(&ca)?).await?;
+    write_private_key(&key_file, certificate.serialize_private_key_pem()).await?;
+    tokio::fs::write(
+        app_configuration_file(directory, app_name),
+        traefik_configuration(&cert_file, &key_file),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Removes the certificate of the app so that Traefik stops serving it.
+pub async fn remove_app_certificate(
+    directory: &Path,
+    app_name: &AppName,
+) -> Result<(), SelfSignedCertificateError> {
+    let (cert_file, key_file) = app_certificate_files(directory, app_name);
+    for file in [
+        app_configuration_file(directory, app_name),
+        cert_file,
+        key_file,
+    ] {
+        match tokio::fs::remove_file(&file).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+async fn load_or_create_ca(directory: &Path) -> Result<Certificate, SelfSignedCertificateError> {
+    let cert_file = directory.join(CA_CERT_FILE);
+    let key_file = directory.join(CA_KEY_FILE);
+
+    if let (Ok(cert), Ok(key)) = (
+        tokio::fs::read_to_string(&cert_file).await,
+        tokio::fs::read_to_string(&key_file).await,
+    ) {
+        let params = CertificateParams::from_ca_cert_pem(&cert, KeyPair::from_pem(&key)?)?;
+        return Ok(Certificate::from_params(params)?);
+    }
+
+    info!("Creating self-signed CA in {}", directory.to_string_lossy());
+    let mut params = CertificateParams::default();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "PREvant Review App CA");
+    let ca = Certificate::from_params(params)?;
+
+    tokio::fs::write(&cert_file, ca.serialize_pem()?).await?;
+    write_private_key(&key_file, ca.serialize_private_key_pem()).await?;
+
+    Ok(ca)
+}
+
+/// Writes a private key that only the owner may read. The permissions are also restricted if the
+/// file already existed because the mode is only applied to newly created files.
+async fn write_private_key(key_file: &Path, pem: String) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(key_file)
+        .await?;
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))
+        .await?;
+    file.write_all(pem.as_bytes()).await?;
+    file.flush().await
+}
+
+fn app_certificate_files(directory: &Path, app_name: &AppName) -> (PathBuf, PathBuf) {
+    (
+        directory.join(format!("{app_name}.crt")),
+        directory.join(format!("{app_name}.key")),
+    )
+}
+
+fn app_configuration_file(directory: &Path, app_name: &AppName) -> PathBuf {
+    directory.join(format!("{app_name}.toml"))
+}
+
+fn traefik_configuration(cert_file: &Path, key_file: &Path) -> String {
+    format!(
+        r#"[[tls.certificates]]
+  certFile = "{}"
+  keyFile = "{}"
+"#,
+        cert_file.to_string_lossy(),
+        key_file.to_string_lossy()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_issue_certificate_signed_by_reused_ca() -> Result<(), SelfSignedCertificateError>
+    {
+        let directory = tempfile::tempdir()?;
+
+        issue_app_certificate(
+            directory.path(),
+            &AppName::master(),
+            vec![String::from("master.review.example.com")],
+        )
+        .await?;
+        let ca = tokio::fs::read_to_string(directory.path().join(CA_CERT_FILE)).await?;
+
+        issue_app_certificate(
+            directory.path(),
+            &AppName::master(),
+            vec![String::from("master.review.example.com")],
+        )
+        .await?;
+
+        assert_eq!(
+            tokio::fs::read_to_string(directory.path().join(CA_CERT_FILE)).await?,
+            ca
+        );
+        assert!(directory.path().join("master.crt").exists());
+        assert!(directory.path().join("master.key").exists());
+        assert_eq!(
+            tokio::fs::read_to_string(directory.path().join("master.toml")).await?,
+            traefik_configuration(
+                &directory.path().join("master.crt"),
+                &directory.path().join("master.key")
+            )
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_restrict_permissions_of_private_keys() -> Result<(), SelfSignedCertificateError>
+    {
+        let directory = tempfile::tempdir()?;
+
+        issue_app_certificate(
+            directory.path(),
+            &AppName::master(),
+            vec![String::from("master.review.example.com")],
+        )
+        .await?;
+
+        for key_file in [CA_KEY_FILE, "master.key"] {
+            let metadata = tokio::fs::metadata(directory.path().join(key_file)).await?;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_remove_certificate() -> Result<(), SelfSignedCertificateError> {
+        let directory = tempfile::tempdir()?;
+        issue_app_certificate(
+            directory.path(),
+            &AppName::master(),
+            vec![String::from("localhost")],
+        )
+        .await?;
+
+        remove_app_certificate(directory.path(), &AppName::master()).await?;
+
+        assert!(!directory.path().join("master.toml").exists());
+        assert!(!directory.path().join("master.crt").exists());
+        assert!(directory.path().join(CA_CERT_FILE).exists());
+
+        Ok(())
+    }
+}
@@ -108,7 +108,87 @@ impl TraefikIngressRoute {
         Self {
             entry_points,
             routes: vec![TraefikRoute { rule, middlewares }],
-            tls: cert_resolver.map(|cert_resolver| TraefikTLS { cert_resolver }),
+            tls: cert_resolver.map(|cert_resolver| TraefikTLS {
+                cert_resolver: Some(cert_resolver),
+                secret_name: None,
+                domains: Vec::new(),
+            }),
+        }
+    }
+
+    /// Constructs a new [`TraefikIngressRoute`] that routes all requests for `host` without any
+    /// middleware, e.g. for serving a service on its own subdomain.
+    pub fn with_host_defaults(host: String) -> Self {
+        Self {
+            entry_points: Vec::new(),
+            routes: vec![TraefikRoute {
+                rule: TraefikRouterRule::host_rule(vec![host]),
+                middlewares: Vec::new(),
+            }],
+            tls: None,
+        }
+    }
+
+    /// Adds another route without middlewares, e.g. for testing the payloads of routes that are
+    /// served on different hosts.
+    #[cfg(test)]
+    pub fn add_route(&mut self, rule: TraefikRouterRule) {
+        self.routes.push(TraefikRoute {
+            rule,
+            middlewares: Vec::new(),
+        });
+    }
+
+    pub fn set_tls(&mut self, tls: TraefikTLS) {
+        self.tls = Some(tls);
+    }
+
+    /// Puts the middlewares in front of the existing middlewares of all routes so that, for
+    /// example, requests are authenticated before any other middleware handles them.
+    pub fn prepend_middlewares(&mut self, middlewares: &[TraefikMiddleware]) {
+        for route in &mut self.routes {
+            route.middlewares.splice(0..0, middlewares.iter().cloned());
+        }
+    }
+
+    /// Puts the middlewares behind the existing middlewares of all routes.
+    pub fn append_middlewares(&mut self, middlewares: &[TraefikMiddleware]) {
+        for route in &mut self.routes {
+            route.middlewares.extend(middlewares.iter().cloned());
+        }
+    }
+
+    /// Returns the domain names that a certificate for this route must be valid for: the TLS
+    /// domains if present and, otherwise, the domains of all host matchers.
+    pub fn certificate_dns_names(&self) -> Vec<String> {
+        match &self.tls {
+            Some(tls) if !tls.domains.is_empty() => tls
+                .domains
+                .iter()
+                .flat_map(|domain| std::iter::once(&domain.main).chain(domain.sans.iter()))
+                .cloned()
+                .collect(),
+            _ => self
+                .routes
+                .iter()
+                .flat_map(|route| route.rule.matches.iter())
+                .filter_map(|m| match m {
+                    Matcher::Host { domains } => Some(domains),
+                    _ => None,
+                })
+                .flatten()
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Returns a copy of this route that keeps the entry points and the TLS configuration but
+    /// drops the routing rules and their middlewares.
+    pub fn without_routes(&self) -> Self {
+        Self {
+            entry_points: self.entry_points.clone(),
+            routes: Vec::new(),
+            tls: self.tls.clone(),
         }
     }
 
@@ -204,6 +284,10 @@ pub struct TraefikRouterRule {
 }
 
 impl TraefikRouterRule {
+    pub fn matches(&self) -> &Vec<Matcher> {
+        &self.matches
+    }
+
     fn path_prefix_from_segments<S>(segments: S) -> String
     where
         S: IntoIterator,
@@ -232,7 +316,6 @@ impl TraefikRouterRule {
         format!("/{}/", base.path().trim_matches('/'))
     }
 
-    #[cfg(test)]
     pub fn host_rule(domains: Vec<String>) -> Self {
         Self {
             matches: vec![Matcher::Host { domains }],
@@ -432,7 +515,18 @@ impl Display for TraefikRouterRule {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TraefikTLS {
-    pub cert_resolver: String,
+    pub cert_resolver: Option<String>,
+    /// The Kubernetes secret that contains the certificate, e.g. issued by cert-manager.
+    pub secret_name: Option<String>,
+    /// The [domains](https://doc.traefik.io/traefik/routing/routers/#domains) that the
+    /// certificate is issued for. If empty, the domains are derived from the rule.
+    pub domains: Vec<TraefikTLSDomain>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraefikTLSDomain {
+    pub main: String,
+    pub sans: Vec<String>,
 }
 
 #[cfg(test)]
@@ -720,7 +814,9 @@ mod test {
                 middlewares: vec![TraefikMiddleware::Ref(String::from("traefik-forward-auth"))],
             }],
             tls: Some(TraefikTLS {
-                cert_resolver: String::from("letsencrypt"),
+                cert_resolver: Some(String::from("letsencrypt")),
+                secret_name: None,
+                domains: Vec::new(),
             }),
         };
         let route2 = TraefikIngressRoute::with_defaults(&AppName::master(), "whoami");
@@ -753,7 +849,9 @@ mod test {
                     ],
                 }],
                 tls: Some(TraefikTLS {
-                    cert_resolver: String::from("letsencrypt"),
+                    cert_resolver: Some(String::from("letsencrypt")),
+                    secret_name: None,
+                    domains: Vec::new(),
                 }),
             }
         );
@@ -768,7 +866,9 @@ mod test {
                 middlewares: vec![TraefikMiddleware::Ref(String::from("traefik-forward-auth"))],
             }],
             tls: Some(TraefikTLS {
-                cert_resolver: String::from("letsencrypt"),
+                cert_resolver: Some(String::from("letsencrypt")),
+                secret_name: None,
+                domains: Vec::new(),
             }),
         };
         let mut route2 = TraefikIngressRoute::with_defaults(&AppName::master(), "whoami");
@@ -801,7 +901,9 @@ mod test {
                     ],
                 }],
                 tls: Some(TraefikTLS {
-                    cert_resolver: String::from("letsencrypt"),
+                    cert_resolver: Some(String::from("letsencrypt")),
+                    secret_name: None,
+                    domains: Vec::new(),
                 }),
             }
         );
@@ -836,11 +938,15 @@ mod test {
     fn merge_two_existing_tls_configs() {
         let mut route1 = TraefikIngressRoute::empty();
         route1.tls = Some(TraefikTLS {
-            cert_resolver: String::from("first"),
+            cert_resolver: Some(String::from("first")),
+            secret_name: None,
+            domains: Vec::new(),
         });
         let mut route2 = TraefikIngressRoute::empty();
         route2.tls = Some(TraefikTLS {
-            cert_resolver: String::from("second"),
+            cert_resolver: Some(String::from("second")),
+            secret_name: None,
+            domains: Vec::new(),
         });
 
         route1.merge_with(route2);
@@ -851,12 +957,34 @@ mod test {
                 entry_points: Vec::new(),
                 routes: Vec::new(),
                 tls: Some(TraefikTLS {
-                    cert_resolver: String::from("second")
+                    cert_resolver: Some(String::from("second")),
+                    secret_name: None,
+                    domains: Vec::new(),
                 })
             }
         );
     }
 
+    #[test]
+    fn prepend_middlewares_to_all_routes() {
+        let mut route = TraefikIngressRoute::with_existing_routing_rules(
+            Vec::new(),
+            TraefikRouterRule::path_prefix_rule(["master"]),
+            vec![String::from("strip-prefix")],
+            None,
+        );
+
+        route.prepend_middlewares(&[TraefikMiddleware::Ref(String::from("basic-auth"))]);
+
+        assert_eq!(
+            route.routes()[0].middlewares(),
+            &vec![
+                TraefikMiddleware::Ref(String::from("basic-auth")),
+                TraefikMiddleware::Ref(String::from("strip-prefix")),
+            ]
+        );
+    }
+
     mod to_url {
         use super::*;
 
@@ -899,7 +1027,9 @@ mod test {
                     "example.com",
                 )]));
             route.tls = Some(TraefikTLS {
-                cert_resolver: String::from("first"),
+                cert_resolver: Some(String::from("first")),
+                secret_name: None,
+                domains: Vec::new(),
             });
             let url = route.to_url();
 
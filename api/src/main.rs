@@ -34,9 +34,9 @@ extern crate rocket;
 extern crate serde_derive;
 
 use crate::apps::host_meta_crawling;
-use crate::apps::Apps;
+use crate::apps::{Apps, DigestWatcher, LogRetainer, OidcSessionValidator};
 use crate::config::{Config, Runtime};
-use crate::infrastructure::{Docker, Infrastructure, Kubernetes};
+use crate::infrastructure::{Docker, Dummy, Infrastructure, Kubernetes, MultiTarget, Podman};
 use crate::models::request_info::RequestInfo;
 use clap::Parser;
 use rocket::fs::{FileServer, Options};
@@ -51,6 +51,7 @@ mod config;
 mod deployment;
 mod http_result;
 mod infrastructure;
+mod metrics;
 mod models;
 mod registry;
 mod tickets;
@@ -76,17 +77,63 @@ fn openapi(request_info: RequestInfo) -> Option<String> {
     Some(to_string(&v).unwrap())
 }
 
-fn create_infrastructure(config: &Config) -> Box<dyn Infrastructure> {
-    match config.runtime_config() {
-        Runtime::Docker => {
-            log::info!("Using Docker backend");
-            Box::new(Docker::new(config.clone()))
+fn create_infrastructure(config: &Config) -> Result<Box<dyn Infrastructure>, StartUpError> {
+    let infrastructure = create_runtime_infrastructure(config)?;
+    if config.deployment_targets().is_empty() {
+        return Ok(infrastructure);
+    }
+
+    let deployment_targets = config
+        .deployment_targets()
+        .iter()
+        .map(|target| {
+            log::info!("Adding deployment target {}", target.name());
+            let infrastructure =
+                create_runtime_infrastructure(&config.with_runtime(target.runtime().clone()))?;
+            Ok((target.clone(), infrastructure))
+        })
+        .collect::<Result<Vec<_>, StartUpError>>()?;
+
+    let infrastructure = MultiTarget::new(infrastructure, deployment_targets).map_err(|err| {
+        StartUpError::InvalidConfiguration {
+            err: err.to_string(),
+        }
+    })?;
+    Ok(Box::new(infrastructure))
+}
+
+fn create_runtime_infrastructure(config: &Config) -> Result<Box<dyn Infrastructure>, StartUpError> {
+    let infrastructure: Box<dyn Infrastructure> = match config.runtime_config() {
+        Runtime::Docker(docker) => {
+            log::info!(
+                "Using Docker backend with host {}",
+                docker
+                    .host()
+                    .map_or_else(|| String::from("from environment"), |host| host.to_string())
+            );
+            let docker =
+                Docker::new(config.clone()).map_err(|err| StartUpError::InvalidConfiguration {
+                    err: err.to_string(),
+                })?;
+            Box::new(docker)
         }
         Runtime::Kubernetes(_config) => {
             log::info!("Using Kubernetes backend");
             Box::new(Kubernetes::new(config.clone()))
         }
-    }
+        Runtime::Podman(podman) => {
+            log::info!(
+                "Using Podman backend with socket {}",
+                podman.socket().display()
+            );
+            Box::new(Podman::new(config.clone()))
+        }
+        Runtime::InMemory(in_memory) => {
+            log::warn!("Using in-memory backend: no container will be started");
+            Box::new(Dummy::from_config(in_memory.clone()))
+        }
+    };
+    Ok(infrastructure)
 }
 
 #[rocket::main]
@@ -99,7 +146,7 @@ async fn main() -> Result<(), StartUpError> {
         err: err.to_string(),
     })?;
 
-    let infrastructure = create_infrastructure(&config);
+    let infrastructure = create_infrastructure(&config)?;
     let apps = match Apps::new(config.clone(), infrastructure) {
         Ok(apps_service) => apps_service,
         Err(e) => {
@@ -108,22 +155,27 @@ async fn main() -> Result<(), StartUpError> {
         }
     };
 
-    let (host_meta_cache, host_meta_crawler) = host_meta_crawling();
+    let (host_meta_cache, host_meta_crawler) = host_meta_crawling(config.routing().clone());
     let apps = Arc::new(apps);
     host_meta_crawler.spawn(apps.clone());
+    DigestWatcher::new(config.clone()).spawn(apps.clone());
+    LogRetainer::new(&config).spawn(apps.clone());
 
     let _rocket = rocket::build()
         .manage(config)
         .manage(apps)
         .manage(host_meta_cache)
+        .manage(OidcSessionValidator::default())
         .mount(
             "/",
             FileServer::new(Path::new("frontend"), Options::Index | Options::Missing),
         )
         .mount("/openapi.yaml", routes![openapi])
         .mount("/api/apps", crate::apps::apps_routes())
+        .mount("/api", crate::config::config_routes())
         .mount("/api", routes![tickets::tickets])
         .mount("/api", routes![webhooks::webhooks])
+        .mount("/", routes![metrics::metrics])
         .launch()
         .await?;
 
@@ -0,0 +1,338 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+
+//! Exposes metrics about PREvant itself in the [Prometheus text
+//! format](https://prometheus.io/docs/instrumenting/exposition_formats/) at `GET /metrics`, e.g.
+//! to alert on failing deployments.
+
+use crate::apps::{Apps, AppsError};
+use crate::models::service::{Service, ServiceStatus};
+use crate::models::web_hook_info::EventKey;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, Histogram, HistogramTimer, HistogramVec, IntCounterVec,
+    IntGauge, IntGaugeVec, TextEncoder,
+};
+use rocket::http::ContentType;
+use rocket::State;
+use std::sync::Arc;
+
+/// Deployments can take minutes, which exceeds the default buckets of Prometheus.
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+lazy_static! {
+    static ref DEPLOYMENTS: IntCounterVec = register_int_counter_vec!(
+        "prevant_deployments_total",
+        "Number of processed deployments, redeployments and restarts by operation and result.",
+        &["operation", "result"]
+    )
+    .unwrap();
+    static ref DELETIONS: IntCounterVec = register_int_counter_vec!(
+        "prevant_deletions_total",
+        "Number of processed deletions of apps and services by operation and result.",
+        &["operation", "result"]
+    )
+    .unwrap();
+    static ref FAILURES: IntCounterVec = register_int_counter_vec!(
+        "prevant_failures_total",
+        "Number of failed operations by operation and error.",
+        &["operation", "error"]
+    )
+    .unwrap();
+    static ref DEPLOYMENT_STAGE_DURATION: HistogramVec = register_histogram_vec!(
+        "prevant_deployment_stage_duration_seconds",
+        "Duration of the stages of deployments.",
+        &["stage"],
+        DURATION_BUCKETS.to_vec()
+    )
+    .unwrap();
+    static ref REGISTRY_RESOLUTION_DURATION: Histogram = register_histogram!(
+        "prevant_registry_resolution_duration_seconds",
+        "Duration of resolving image information from registries.",
+        DURATION_BUCKETS.to_vec()
+    )
+    .unwrap();
+    static ref APPS: IntGauge =
+        register_int_gauge!("prevant_apps", "Number of deployed apps.").unwrap();
+    static ref SERVICES: IntGaugeVec = register_int_gauge_vec!(
+        "prevant_services",
+        "Number of deployed services by status.",
+        &["status"]
+    )
+    .unwrap();
+    static ref HOST_META_CRAWLS: IntCounterVec = register_int_counter_vec!(
+        "prevant_host_meta_crawls_total",
+        "Number of requests for the host meta of services by result.",
+        &["result"]
+    )
+    .unwrap();
+    static ref WEBHOOK_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "prevant_webhook_events_total",
+        "Number of received webhook events by event.",
+        &["event"]
+    )
+    .unwrap();
+}
+
+/// The stages of a deployment whose durations are observed separately.
+#[derive(Clone, Copy, Debug)]
+pub enum DeploymentStage {
+    Configuration,
+    ImageResolution,
+    Templating,
+    Hooks,
+    Deployment,
+}
+
+impl DeploymentStage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentStage::Configuration => "configuration",
+            DeploymentStage::ImageResolution => "image_resolution",
+            DeploymentStage::Templating => "templating",
+            DeploymentStage::Hooks => "hooks",
+            DeploymentStage::Deployment => "deployment",
+        }
+    }
+}
+
+/// The operations that change the services of an app, whose outcomes are counted.
+#[derive(Clone, Copy, Debug)]
+pub enum Operation {
+    /// The deployment of services of an app.
+    Deployment,
+    /// The redeployment of a single service, e.g. by the digest watcher.
+    Redeployment,
+    Restart,
+    /// The deletion of an app.
+    Deletion,
+    ServiceDeletion,
+}
+
+impl Operation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Deployment => "deployment",
+            Operation::Redeployment => "redeployment",
+            Operation::Restart => "restart",
+            Operation::Deletion => "deletion",
+            Operation::ServiceDeletion => "service_deletion",
+        }
+    }
+}
+
+/// The result of a request for the host meta of a service.
+#[derive(Clone, Copy, Debug)]
+pub enum HostMetaCrawlResult {
+    Resolved,
+    Unparsable,
+    Unreachable,
+}
+
+/// Starts a timer that observes the duration of the stage when it is dropped.
+pub fn time_deployment_stage(stage: DeploymentStage) -> HistogramTimer {
+    DEPLOYMENT_STAGE_DURATION
+        .with_label_values(&[stage.as_str()])
+        .start_timer()
+}
+
+/// Starts a timer that observes the duration of resolving images when it is dropped.
+pub fn time_registry_resolution() -> HistogramTimer {
+    REGISTRY_RESOLUTION_DURATION.start_timer()
+}
+
+/// Counts the outcome of an operation as deployment or deletion, and the error if it failed.
+pub fn observe_operation<T>(operation: Operation, result: &Result<T, AppsError>) {
+    let counter = match operation {
+        Operation::Deployment | Operation::Redeployment | Operation::Restart => &DEPLOYMENTS,
+        Operation::Deletion | Operation::ServiceDeletion => &DELETIONS,
+    };
+
+    match result {
+        Ok(_) => counter
+            .with_label_values(&[operation.as_str(), "success"])
+            .inc(),
+        Err(err) => {
+            counter
+                .with_label_values(&[operation.as_str(), "failure"])
+                .inc();
+            FAILURES
+                .with_label_values(&[operation.as_str(), error_name(err)])
+                .inc();
+        }
+    }
+}
+
+pub fn observe_host_meta_crawl(result: HostMetaCrawlResult) {
+    let result = match result {
+        HostMetaCrawlResult::Resolved => "resolved",
+        HostMetaCrawlResult::Unparsable => "unparsable",
+        HostMetaCrawlResult::Unreachable => "unreachable",
+    };
+    HOST_META_CRAWLS.with_label_values(&[result]).inc();
+}
+
+pub fn observe_webhook_event(event_key: &EventKey) {
+    let event = match event_key {
+        EventKey::MergedPullRequest => "pr:merged",
+        EventKey::DeclinedPullRequest => "pr:declined",
+        EventKey::DeletedPullRequest => "pr:deleted",
+    };
+    WEBHOOK_EVENTS.with_label_values(&[event]).inc();
+}
+
+fn error_name(error: &AppsError) -> &'static str {
+    match error {
+        AppsError::AppNotFound { .. } => "AppNotFound",
+        AppsError::ServiceNotFound { .. } => "ServiceNotFound",
+        AppsError::AppIsInDeployment { .. } => "AppIsInDeployment",
+        AppsError::AppIsInDeletion { .. } => "AppIsInDeletion",
+        AppsError::DeploymentCancelled { .. } => "DeploymentCancelled",
+        AppsError::InfrastructureError { .. } => "InfrastructureError",
+        AppsError::InvalidServerConfiguration { .. } => "InvalidServerConfiguration",
+        AppsError::InvalidTemplateFormat { .. } => "InvalidTemplateFormat",
+        AppsError::UnableToResolveImage { .. } => "UnableToResolveImage",
+        AppsError::InvalidDeploymentHook => "InvalidDeploymentHook",
+        AppsError::CyclicServiceDependencies { .. } => "CyclicServiceDependencies",
+        AppsError::InvalidDeploymentTarget { .. } => "InvalidDeploymentTarget",
+        AppsError::UnsupportedLogStreams { .. } => "UnsupportedLogStreams",
+        AppsError::InaccessibleLogArchive { .. } => "InaccessibleLogArchive",
+        AppsError::InvalidHostName { .. } => "InvalidHostName",
+    }
+}
+
+/// Counts the deployed apps and services when Prometheus scrapes the metrics because the
+/// infrastructure is the only source of truth for them.
+async fn count_apps_and_services(apps: &Apps) {
+    let services = match apps.get_apps().await {
+        Ok(services) => services,
+        Err(err) => {
+            error!("Cannot count the apps for the metrics: {}", err);
+            return;
+        }
+    };
+
+    APPS.set(services.len() as i64);
+    SERVICES.reset();
+    for service in services.iter_all().flat_map(|(_, services)| services) {
+        let status = match service.status() {
+            ServiceStatus::Running => "running",
+            ServiceStatus::Paused => "paused",
+        };
+        SERVICES.with_label_values(&[status]).inc();
+    }
+}
+
+#[get("/metrics")]
+pub async fn metrics(apps: &State<Arc<Apps>>) -> Option<(ContentType, String)> {
+    count_apps_and_services(apps).await;
+
+    let mut buffer = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Cannot encode the metrics: {}", err);
+        return None;
+    }
+
+    Some((
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        String::from_utf8_lossy(&buffer).into_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::Dummy;
+    use crate::models::{AppName, AppStatusChangeId};
+    use crate::sc;
+    use rocket::{http::Status, local::asynchronous::Client};
+
+    #[tokio::test]
+    async fn should_expose_metrics_of_apps() -> Result<(), AppsError> {
+        let apps = Arc::new(Apps::new(Default::default(), Box::new(Dummy::new()))?);
+        let result = apps
+            .create_or_update(
+                &AppName::master(),
+                &AppStatusChangeId::new(),
+                None,
+                &vec![sc!("service-a"), sc!("service-b")],
+                None,
+            )
+            .await;
+        assert!(result.is_ok());
+        observe_webhook_event(&EventKey::MergedPullRequest);
+
+        let rocket = rocket::build().manage(apps).mount("/", routes![metrics]);
+        let client = Client::tracked(rocket).await.expect("valid rocket");
+
+        let response = client.get("/metrics").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains("prevant_apps 1"), "{body}");
+        assert!(
+            body.contains("prevant_services{status=\"running\"} 2"),
+            "{body}"
+        );
+        assert!(
+            body.contains("prevant_deployments_total{operation=\"deployment\",result=\"success\"}")
+        );
+        assert!(
+            body.contains("prevant_deployment_stage_duration_seconds_bucket{stage=\"deployment\"")
+        );
+        assert!(body.contains("prevant_webhook_events_total{event=\"pr:merged\"}"));
+        Ok(())
+    }
+    #[tokio::test]
+    async fn should_expose_metrics_of_failed_redeployments() -> Result<(), AppsError> {
+        let apps = Arc::new(Apps::new(Default::default(), Box::new(Dummy::new()))?);
+        let result = apps
+            .redeploy_service(&AppName::master(), "unknown-service")
+            .await;
+        assert!(result.is_err());
+
+        let rocket = rocket::build().manage(apps).mount("/", routes![metrics]);
+        let client = Client::tracked(rocket).await.expect("valid rocket");
+
+        let response = client.get("/metrics").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let body = response.into_string().await.unwrap();
+        assert!(
+            body.contains(
+                "prevant_deployments_total{operation=\"redeployment\",result=\"failure\"}"
+            ),
+            "{body}"
+        );
+        assert!(
+            body.contains(
+                "prevant_failures_total{error=\"ServiceNotFound\",operation=\"redeployment\"}"
+            ),
+            "{body}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+/*-
+ * ========================LICENSE_START=================================
+ * PREvant REST API
+ * %%
+ * Copyright (C) 2018 - 2019 aixigo AG
+ * %%
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ * =========================LICENSE_END==================================
+ */
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// The output of a command that has been executed in the container of a service.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecOutput {
+    /// `None` if the runtime does not report the exit code.
+    exit_code: Option<i64>,
+    stdout: String,
+    stderr: String,
+}
+
+impl ExecOutput {
+    pub fn new(exit_code: Option<i64>, stdout: String, stderr: String) -> Self {
+        Self {
+            exit_code,
+            stdout,
+            stderr,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    #[cfg(test)]
+    pub fn stdout(&self) -> &str {
+        &self.stdout
+    }
+}
+
+/// A command that runs with a TTY in the container of a service. The input is written to the TTY
+/// and the output contains everything that the TTY prints, i.e. stdout and stderr.
+pub struct ExecSession {
+    input: Pin<Box<dyn AsyncWrite + Send>>,
+    output: Pin<Box<dyn AsyncRead + Send>>,
+}
+
+impl ExecSession {
+    pub fn new<W, R>(input: W, output: R) -> Self
+    where
+        W: AsyncWrite + Send + 'static,
+        R: AsyncRead + Send + 'static,
+    {
+        Self {
+            input: Box::pin(input),
+            output: Box::pin(output),
+        }
+    }
+
+    pub fn into_parts(
+        self,
+    ) -> (
+        Pin<Box<dyn AsyncWrite + Send>>,
+        Pin<Box<dyn AsyncRead + Send>>,
+    ) {
+        (self.input, self.output)
+    }
+}
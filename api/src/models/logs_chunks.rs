@@ -24,6 +24,7 @@
  * =========================LICENSE_END==================================
  */
 use chrono::{DateTime, FixedOffset, Utc};
+use regex::Regex;
 use std::convert::From;
 
 pub struct LogChunk {
@@ -76,3 +77,100 @@ impl From<Vec<(DateTime<FixedOffset>, String)>> for LogChunk {
         })
     }
 }
+
+/// Selects the output streams of a service whose log lines should be returned.
+#[derive(Clone, Copy, Debug, Default, FromFormField, PartialEq)]
+pub enum LogStreams {
+    #[default]
+    #[field(value = "both")]
+    Both,
+    #[field(value = "stdout")]
+    Stdout,
+    #[field(value = "stderr")]
+    Stderr,
+}
+
+impl LogStreams {
+    pub fn stdout(&self) -> bool {
+        *self != LogStreams::Stderr
+    }
+
+    pub fn stderr(&self) -> bool {
+        *self != LogStreams::Stdout
+    }
+}
+
+impl std::fmt::Display for LogStreams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogStreams::Both => write!(f, "both"),
+            LogStreams::Stdout => write!(f, "stdout"),
+            LogStreams::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+/// Restricts the log lines of services to the lines that have been written until a point in time,
+/// that match a pattern, and that have been written to the selected output streams.
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    until: Option<DateTime<FixedOffset>>,
+    pattern: Option<Regex>,
+    streams: LogStreams,
+}
+
+impl LogFilter {
+    pub fn new(
+        until: Option<DateTime<FixedOffset>>,
+        pattern: Option<Regex>,
+        streams: LogStreams,
+    ) -> Self {
+        Self {
+            until,
+            pattern,
+            streams,
+        }
+    }
+
+    pub fn streams(&self) -> LogStreams {
+        self.streams
+    }
+
+    pub fn has_pattern(&self) -> bool {
+        self.pattern.is_some()
+    }
+
+    pub fn is_past_until(&self, timestamp: &DateTime<FixedOffset>) -> bool {
+        self.until.map_or(false, |until| *timestamp > until)
+    }
+
+    pub fn matches(&self, log_line: &str) -> bool {
+        self.pattern
+            .as_ref()
+            .map_or(true, |pattern| pattern.is_match(log_line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_filter_log_lines_by_until_and_pattern() {
+        let filter = LogFilter::new(
+            Some(DateTime::parse_from_rfc3339("2019-07-18T07:30:00Z").unwrap()),
+            Some(Regex::new("ERROR|WARN").unwrap()),
+            LogStreams::Stderr,
+        );
+
+        assert!(
+            !filter.is_past_until(&DateTime::parse_from_rfc3339("2019-07-18T07:30:00Z").unwrap())
+        );
+        assert!(filter
+            .is_past_until(&DateTime::parse_from_rfc3339("2019-07-18T07:30:00.001Z").unwrap()));
+        assert!(filter.matches("WARN disk almost full\n"));
+        assert!(!filter.matches("INFO started\n"));
+        assert!(!filter.streams().stdout());
+        assert!(filter.streams().stderr());
+    }
+}
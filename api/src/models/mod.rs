@@ -26,15 +26,20 @@
 
 pub use app_name::{AppName, AppNameError};
 pub use app_status_change_id::{AppStatusChangeId, AppStatusChangeIdError};
+pub use exec::{ExecOutput, ExecSession};
 pub use image::Image;
-pub use logs_chunks::LogChunk;
+pub use logs_chunks::{LogChunk, LogFilter, LogStreams};
 pub use request_info::RequestInfo;
 pub use service::{ContainerType, ServiceBuilder, ServiceBuilderError};
-pub use service_config::{Environment, EnvironmentVariable, Router, ServiceConfig};
+pub use service_config::{
+    AdditionalContainer, Autoscaling, Environment, EnvironmentVariable, PortProtocol, Router,
+    ServiceConfig, ServicePort,
+};
 pub use web_host_meta::WebHostMeta;
 
 mod app_name;
 mod app_status_change_id;
+mod exec;
 mod image;
 mod logs_chunks;
 pub mod request_info;
@@ -24,6 +24,7 @@
  * =========================LICENSE_END==================================
  */
 
+use crate::config::Routing;
 use crate::models::{web_host_meta::WebHostMeta, ServiceConfig};
 use chrono::{DateTime, Utc};
 use serde::ser::{Serialize, Serializer};
@@ -39,10 +40,16 @@ pub struct Service {
     id: String,
     app_name: String,
     base_url: Option<Url>,
+    routing: Routing,
     endpoint: Option<ServiceEndpoint>,
     web_host_meta: Option<WebHostMeta>,
     state: State,
     config: ServiceConfig,
+    /// The name of the deployment target the service runs on if PREvant manages multiple ones
+    deployment_target: Option<String>,
+    /// The digest of the image that the service runs, if the runtime reports it. Docker reports
+    /// the digest of the image config, Kubernetes the digest of the image manifest.
+    image_digest: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -82,9 +89,9 @@ impl Service {
     }
 
     fn service_url(&self) -> Option<Url> {
-        self.base_url.clone().map(|url| {
-            url.join(&format!("/{}/{}/", &self.app_name, self.service_name()))
-                .unwrap()
+        self.base_url.as_ref().and_then(|url| {
+            self.routing
+                .service_url(url, &self.app_name, self.service_name())
         })
     }
 
@@ -115,6 +122,14 @@ impl Service {
     pub fn status(&self) -> &ServiceStatus {
         &self.state.status
     }
+
+    pub fn deployment_target(&self) -> &Option<String> {
+        &self.deployment_target
+    }
+
+    pub fn image_digest(&self) -> Option<&str> {
+        self.image_digest.as_deref()
+    }
 }
 
 impl Serialize for Service {
@@ -135,6 +150,8 @@ impl Serialize for Service {
             #[serde(skip_serializing_if = "Option::is_none")]
             open_api_url: Option<Url>,
             state: &'a State,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            deployment_target: &'a Option<String>,
         }
 
         #[derive(Serialize)]
@@ -168,6 +185,7 @@ impl Serialize for Service {
             version,
             open_api_url,
             state: &self.state,
+            deployment_target: &self.deployment_target,
         };
 
         s.serialize(serializer)
@@ -182,8 +200,11 @@ pub struct ServiceBuilder {
     status: Option<ServiceStatus>,
     started_at: Option<DateTime<Utc>>,
     base_url: Option<Url>,
+    routing: Option<Routing>,
     web_host_meta: Option<WebHostMeta>,
     endpoint: Option<ServiceEndpoint>,
+    deployment_target: Option<String>,
+    image_digest: Option<String>,
 }
 
 impl ServiceBuilder {
@@ -194,9 +215,12 @@ impl ServiceBuilder {
             status: None,
             started_at: None,
             base_url: None,
+            routing: None,
             web_host_meta: None,
             endpoint: None,
             config: None,
+            deployment_target: None,
+            image_digest: None,
         }
     }
 
@@ -213,12 +237,15 @@ impl ServiceBuilder {
             app_name,
             config,
             base_url: self.base_url,
+            routing: self.routing.unwrap_or_default(),
             endpoint: self.endpoint,
             web_host_meta: self.web_host_meta,
             state: State {
                 started_at,
                 status: self.status.unwrap_or(ServiceStatus::Running),
             },
+            deployment_target: self.deployment_target,
+            image_digest: self.image_digest,
         })
     }
 
@@ -247,6 +274,11 @@ impl ServiceBuilder {
         self
     }
 
+    pub fn routing(mut self, routing: Routing) -> Self {
+        self.routing = Some(routing);
+        self
+    }
+
     pub fn web_host_meta(mut self, web_host_meta: WebHostMeta) -> Self {
         self.web_host_meta = Some(web_host_meta);
         self
@@ -264,6 +296,16 @@ impl ServiceBuilder {
         });
         self
     }
+
+    pub fn deployment_target(mut self, deployment_target: String) -> Self {
+        self.deployment_target = Some(deployment_target);
+        self
+    }
+
+    pub fn image_digest(mut self, image_digest: String) -> Self {
+        self.image_digest = Some(image_digest);
+        self
+    }
 }
 
 #[derive(Debug, Fail, PartialEq)]
@@ -285,8 +327,11 @@ impl From<Service> for ServiceBuilder {
             status: Some(service.state.status),
             started_at: Some(service.state.started_at),
             base_url: service.base_url,
+            routing: Some(service.routing),
             web_host_meta: service.web_host_meta,
             endpoint: service.endpoint,
+            deployment_target: service.deployment_target,
+            image_digest: service.image_digest,
         }
     }
 }
@@ -396,6 +441,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_build_service_with_host_based_routing() {
+        let routing = crate::config_from_str!(
+            r#"
+            [routing]
+            mode = 'host'
+            domain = 'review.example.com'
+            "#
+        )
+        .routing()
+        .clone();
+
+        let service = ServiceBuilder::new()
+            .id("some-random-id".to_string())
+            .app_name("master".to_string())
+            .config(sc!("nginx", "nginx"))
+            .started_at(Utc::now())
+            .base_url(Url::parse("http://example.com").unwrap())
+            .routing(routing)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            service.service_url(),
+            Url::parse("http://nginx.master.review.example.com/").ok()
+        );
+    }
+
     #[test]
     fn should_build_service_with_web_host_meta() {
         let meta = WebHostMeta::empty();
@@ -124,6 +124,7 @@ pub struct EnvironmentVariable {
     original_value: Option<SecUtf8>,
     templated: bool,
     replicate: bool,
+    secret_key: Option<String>,
 }
 
 impl EnvironmentVariable {
@@ -134,6 +135,22 @@ impl EnvironmentVariable {
             original_value: None,
             templated: false,
             replicate: false,
+            secret_key: None,
+        }
+    }
+
+    /// Creates a variable whose value originates from a secret of PREvant's configuration.
+    /// Infrastructures that support secret objects, e.g. Kubernetes, refer to the secret by
+    /// `secret_key` instead of providing the value in plain text. The key is unique within the app,
+    /// i.e. `<service>-<secret name>`.
+    pub fn with_secret(key: String, value: SecUtf8, secret_key: String) -> Self {
+        EnvironmentVariable {
+            key,
+            value,
+            original_value: None,
+            templated: false,
+            replicate: false,
+            secret_key: Some(secret_key),
         }
     }
 
@@ -144,6 +161,7 @@ impl EnvironmentVariable {
             original_value: Some(original.value),
             templated: original.templated,
             replicate: original.replicate,
+            secret_key: original.secret_key,
         }
     }
 
@@ -155,6 +173,7 @@ impl EnvironmentVariable {
             original_value: None,
             templated: true,
             replicate: false,
+            secret_key: None,
         }
     }
 
@@ -166,6 +185,7 @@ impl EnvironmentVariable {
             original_value: None,
             templated: false,
             replicate: true,
+            secret_key: None,
         }
     }
 
@@ -195,6 +215,10 @@ impl EnvironmentVariable {
         self.replicate
     }
 
+    pub fn secret_key(&self) -> Option<&String> {
+        self.secret_key.as_ref()
+    }
+
     pub fn original(&self) -> Self {
         match &self.original_value {
             Some(original_value) => EnvironmentVariable {
@@ -203,6 +227,7 @@ impl EnvironmentVariable {
                 templated: self.templated,
                 replicate: self.replicate,
                 original_value: None,
+                secret_key: self.secret_key.clone(),
             },
             None => self.clone(),
         }
@@ -255,6 +280,7 @@ impl TryFrom<(String, Value)> for EnvironmentVariable {
             original_value: None,
             templated,
             replicate,
+            secret_key: None,
         })
     }
 }
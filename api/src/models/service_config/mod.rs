@@ -27,9 +27,10 @@ use crate::models::service::ContainerType;
 use crate::models::Image;
 pub use environment::{Environment, EnvironmentVariable};
 use secstr::SecUtf8;
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer};
 use serde_value::Value;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::hash::Hash;
 use std::path::PathBuf;
 
@@ -44,6 +45,13 @@ pub struct ServiceConfig {
     env: Option<Environment>,
     #[serde(alias = "volumes", alias = "files", default)]
     files: Option<BTreeMap<PathBuf, SecUtf8>>,
+    #[serde(default, deserialize_with = "ServiceConfig::parse_replicas")]
+    replicas: Option<u32>,
+    autoscaling: Option<Autoscaling>,
+    init_containers: Option<Vec<AdditionalContainer>>,
+    sidecars: Option<Vec<AdditionalContainer>>,
+    depends_on: Option<Vec<String>>,
+    ports: Option<Vec<ServicePort>>,
     #[serde(skip)]
     labels: Option<BTreeMap<String, String>>,
     #[serde(skip, default = "ContainerType::default")]
@@ -63,6 +71,12 @@ impl ServiceConfig {
             image,
             env: None,
             files: None,
+            replicas: None,
+            autoscaling: None,
+            init_containers: None,
+            sidecars: None,
+            depends_on: None,
+            ports: None,
             labels: None,
             container_type: ContainerType::Instance,
             port: 80,
@@ -126,6 +140,25 @@ impl ServiceConfig {
         }
     }
 
+    /// Adds or replaces the environment variable `key` whose value is provided by the secret
+    /// `secret_key` of the app.
+    pub fn add_secret_env(&mut self, key: String, value: SecUtf8, secret_key: String) {
+        let variable = EnvironmentVariable::with_secret(key, value, secret_key);
+
+        let mut env = self
+            .env
+            .take()
+            .map(|env| {
+                env.into_iter()
+                    .filter(|v| v.key() != variable.key())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        env.push(variable);
+
+        self.env = Some(Environment::new(env));
+    }
+
     pub fn set_files(&mut self, files: Option<BTreeMap<PathBuf, SecUtf8>>) {
         self.files = files
     }
@@ -137,14 +170,92 @@ impl ServiceConfig {
         }
     }
 
+    /// Parses the number of replicas which must be at least one because a service without any
+    /// running container cannot be distinguished from a service that does not exist.
+    pub fn parse_replicas<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<u32>::deserialize(deserializer)? {
+            Some(0) => Err(de::Error::custom("replicas must be at least 1")),
+            replicas => Ok(replicas),
+        }
+    }
+
+    pub fn set_replicas(&mut self, replicas: Option<u32>) {
+        self.replicas = replicas;
+    }
+
+    /// The number of instances that should be running for this service, if not configured
+    /// otherwise.
+    pub fn replicas(&self) -> Option<u32> {
+        self.replicas
+    }
+
+    pub fn set_autoscaling(&mut self, autoscaling: Option<Autoscaling>) {
+        self.autoscaling = autoscaling;
+    }
+
+    pub fn autoscaling(&self) -> Option<&Autoscaling> {
+        self.autoscaling.as_ref()
+    }
+
+    /// The number of instances that have to be started initially: the configured replicas or
+    /// the lower bound of the autoscaling.
+    pub fn initial_replicas(&self) -> u32 {
+        match (&self.autoscaling, self.replicas) {
+            (Some(autoscaling), _) => autoscaling.min_replicas(),
+            (None, Some(replicas)) => replicas,
+            (None, None) => 1,
+        }
+    }
+
+    pub fn set_init_containers(&mut self, init_containers: Option<Vec<AdditionalContainer>>) {
+        self.init_containers = init_containers;
+    }
+
+    /// Containers that have to run to completion, in the given order, before the service will be
+    /// started.
+    pub fn init_containers(&self) -> &[AdditionalContainer] {
+        self.init_containers.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_sidecars(&mut self, sidecars: Option<Vec<AdditionalContainer>>) {
+        self.sidecars = sidecars;
+    }
+
+    /// Containers that run alongside the service and share its network namespace.
+    pub fn sidecars(&self) -> &[AdditionalContainer] {
+        self.sidecars.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_depends_on(&mut self, depends_on: Option<Vec<String>>) {
+        self.depends_on = depends_on;
+    }
+
+    /// Names of the services that have to be ready before this service will be started.
+    pub fn depends_on(&self) -> &[String] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+
     pub fn set_port(&mut self, port: u16) {
         self.port = port;
     }
 
+    /// The port that serves the HTTP requests routed to this service.
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    pub fn set_ports(&mut self, ports: Option<Vec<ServicePort>>) {
+        self.ports = ports;
+    }
+
+    /// Additional ports of the service besides the HTTP [port](Self::port).
+    pub fn ports(&self) -> &[ServicePort] {
+        self.ports.as_deref().unwrap_or_default()
+    }
+
     pub fn set_router(&mut self, router: Router) {
         self.router = Some(router);
     }
@@ -192,12 +303,226 @@ impl ServiceConfig {
         let mut labels = other.labels.as_ref().cloned().unwrap_or_default();
         labels.extend(self.labels.as_ref().cloned().unwrap_or_default());
         self.labels = Some(labels);
+
+        if self.replicas.is_none() {
+            self.replicas = other.replicas;
+        }
+        if self.autoscaling.is_none() {
+            self.autoscaling = other.autoscaling.clone();
+        }
+        if self.init_containers.is_none() {
+            self.init_containers = other.init_containers.clone();
+        }
+        if self.sidecars.is_none() {
+            self.sidecars = other.sidecars.clone();
+        }
+        if self.depends_on.is_none() {
+            self.depends_on = other.depends_on.clone();
+        }
+        if self.ports.is_none() {
+            self.ports = other.ports.clone();
+        }
+    }
+}
+
+/// A named port of a service. Ports with an `entry_point` are exposed through the Traefik entry
+/// point of the same name so that they can be reached from outside of the review app, e.g. to
+/// connect to a database.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServicePort {
+    name: String,
+    port: u16,
+    #[serde(default)]
+    protocol: PortProtocol,
+    entry_point: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl ServicePort {
+    #[cfg(test)]
+    pub fn new(
+        name: String,
+        port: u16,
+        protocol: PortProtocol,
+        entry_point: Option<String>,
+    ) -> Self {
+        ServicePort {
+            name,
+            port,
+            protocol,
+            entry_point,
+        }
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn protocol(&self) -> PortProtocol {
+        self.protocol
+    }
+
+    pub fn entry_point(&self) -> Option<&String> {
+        self.entry_point.as_ref()
+    }
+}
+
+/// A container that belongs to a service but is not the service itself, e.g. an init container
+/// that migrates a database or a sidecar that ships logs.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdditionalContainer {
+    #[serde(deserialize_with = "AdditionalContainer::parse_name")]
+    name: String,
+    image: Image,
+    env: Option<Environment>,
+    args: Option<Vec<String>>,
+}
+
+impl AdditionalContainer {
+    #[cfg(test)]
+    pub fn new(name: String, image: Image) -> Self {
+        AdditionalContainer {
+            name,
+            image,
+            env: None,
+            args: None,
+        }
+    }
+
+    /// Parses the name of the container which must be a valid
+    /// [RFC 1123 label](https://kubernetes.io/docs/concepts/overview/working-with-objects/names/#dns-label-names)
+    /// because Kubernetes uses it as container name.
+    fn parse_name<'de, D>(deserializer: D) -> Result<String, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+
+        let is_rfc1123_label = !name.is_empty()
+            && name.len() <= 63
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && !name.starts_with('-')
+            && !name.ends_with('-');
+        if !is_rfc1123_label {
+            return Err(de::Error::custom(format!(
+                "The container name {name} is not a valid RFC 1123 label"
+            )));
+        }
+
+        Ok(name)
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn env(&self) -> Option<&Environment> {
+        self.env.as_ref()
+    }
+
+    /// Overrides the default command (`CMD`) of the image, keeping its entrypoint.
+    pub fn args(&self) -> Option<&Vec<String>> {
+        self.args.as_ref()
+    }
+}
+
+/// Scales the service horizontally between `min_replicas` and `max_replicas` depending on the
+/// CPU utilization (see
+/// [here](https://kubernetes.io/docs/tasks/run-application/horizontal-pod-autoscale/)).
+#[derive(Clone, Debug, Hash, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", try_from = "RawAutoscaling")]
+pub struct Autoscaling {
+    min_replicas: u32,
+    max_replicas: u32,
+    target_cpu_utilization: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawAutoscaling {
+    #[serde(default = "Autoscaling::default_min_replicas")]
+    min_replicas: u32,
+    max_replicas: u32,
+    #[serde(default = "Autoscaling::default_target_cpu_utilization")]
+    target_cpu_utilization: u32,
+}
+
+impl TryFrom<RawAutoscaling> for Autoscaling {
+    type Error = String;
+
+    fn try_from(raw: RawAutoscaling) -> Result<Self, Self::Error> {
+        if raw.min_replicas == 0 {
+            return Err(String::from("minReplicas must be at least 1"));
+        }
+        if raw.max_replicas < raw.min_replicas {
+            return Err(format!(
+                "maxReplicas ({}) must not be less than minReplicas ({})",
+                raw.max_replicas, raw.min_replicas
+            ));
+        }
+
+        Ok(Autoscaling {
+            min_replicas: raw.min_replicas,
+            max_replicas: raw.max_replicas,
+            target_cpu_utilization: raw.target_cpu_utilization,
+        })
+    }
+}
+
+impl Autoscaling {
+    #[cfg(test)]
+    pub fn new(min_replicas: u32, max_replicas: u32, target_cpu_utilization: u32) -> Self {
+        Autoscaling {
+            min_replicas,
+            max_replicas,
+            target_cpu_utilization,
+        }
+    }
+
+    pub fn min_replicas(&self) -> u32 {
+        self.min_replicas
+    }
+
+    pub fn max_replicas(&self) -> u32 {
+        self.max_replicas
+    }
+
+    /// The average CPU utilization in percent of the requested CPU resources.
+    pub fn target_cpu_utilization(&self) -> u32 {
+        self.target_cpu_utilization
+    }
+
+    fn default_min_replicas() -> u32 {
+        1
+    }
+
+    fn default_target_cpu_utilization() -> u32 {
+        80
     }
 }
 
 /// Helper that configures the service routing for Traefik (see
 /// [here](https://docs.traefik.io/routing/routers/)).
-#[derive(Clone, Debug, Hash, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Router {
     rule: String,
     priority: Option<i32>,
@@ -308,6 +633,7 @@ macro_rules! sc {
 mod tests {
     use super::*;
     use serde_json::from_value;
+    use std::str::FromStr;
 
     #[test]
     fn should_parse_service_config_json() {
@@ -332,6 +658,167 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_parse_service_config_json_with_replicas_and_autoscaling() {
+        let config = from_value::<ServiceConfig>(serde_json::json!({
+            "serviceName": "mariadb",
+            "image": "mariadb:10.3",
+            "replicas": 2,
+            "autoscaling": {
+                "maxReplicas": 5
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(config.replicas(), Some(2));
+        assert_eq!(config.autoscaling(), Some(&Autoscaling::new(1, 5, 80)));
+        assert_eq!(config.initial_replicas(), 1);
+    }
+
+    #[test]
+    fn should_not_parse_service_config_json_without_replicas() {
+        let result = from_value::<ServiceConfig>(serde_json::json!({
+            "serviceName": "mariadb",
+            "image": "mariadb:10.3",
+            "replicas": 0
+        }));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_not_parse_service_config_json_with_autoscaling_without_replicas() {
+        let result = from_value::<ServiceConfig>(serde_json::json!({
+            "serviceName": "mariadb",
+            "image": "mariadb:10.3",
+            "autoscaling": {
+                "minReplicas": 0,
+                "maxReplicas": 5
+            }
+        }));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_not_parse_service_config_json_with_max_replicas_less_than_min_replicas() {
+        let result = from_value::<ServiceConfig>(serde_json::json!({
+            "serviceName": "mariadb",
+            "image": "mariadb:10.3",
+            "autoscaling": {
+                "minReplicas": 3,
+                "maxReplicas": 2
+            }
+        }));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_parse_service_config_json_with_init_containers_and_sidecars() {
+        let config = from_value::<ServiceConfig>(serde_json::json!({
+            "serviceName": "mariadb",
+            "image": "mariadb:10.3",
+            "initContainers": [{
+                "name": "migration",
+                "image": "flyway/flyway:9",
+                "env": [ "FLYWAY_URL=jdbc:mariadb://localhost/db" ],
+                "args": [ "migrate" ]
+            }],
+            "sidecars": [{
+                "name": "exporter",
+                "image": "prom/mysqld-exporter"
+            }]
+        }))
+        .unwrap();
+
+        let init_container = &config.init_containers()[0];
+        assert_eq!(init_container.name(), "migration");
+        assert_eq!(
+            init_container.image(),
+            &Image::from_str("flyway/flyway:9").unwrap()
+        );
+        assert_eq!(
+            init_container.env(),
+            Some(&Environment::new(vec![EnvironmentVariable::new(
+                String::from("FLYWAY_URL"),
+                SecUtf8::from("jdbc:mariadb://localhost/db")
+            )]))
+        );
+        assert_eq!(init_container.args(), Some(&vec![String::from("migrate")]));
+        assert_eq!(
+            config.sidecars(),
+            &[AdditionalContainer::new(
+                String::from("exporter"),
+                Image::from_str("prom/mysqld-exporter").unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn should_not_parse_service_config_json_with_invalid_container_name() {
+        let result = from_value::<ServiceConfig>(serde_json::json!({
+            "serviceName": "mariadb",
+            "image": "mariadb:10.3",
+            "sidecars": [{
+                "name": "Log_Shipper",
+                "image": "fluent/fluent-bit"
+            }]
+        }));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_parse_service_config_json_with_ports() {
+        let config = from_value::<ServiceConfig>(serde_json::json!({
+            "serviceName": "postgres",
+            "image": "postgres:16",
+            "ports": [{
+                "name": "sql",
+                "port": 5432,
+                "entryPoint": "postgres"
+            }, {
+                "name": "metrics",
+                "port": 9187,
+                "protocol": "udp"
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            config.ports(),
+            &[
+                ServicePort::new(
+                    String::from("sql"),
+                    5432,
+                    PortProtocol::Tcp,
+                    Some(String::from("postgres"))
+                ),
+                ServicePort::new(String::from("metrics"), 9187, PortProtocol::Udp, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_start_one_replica_by_default() {
+        let config = sc!("mariadb", "mariadb:10.3");
+
+        assert_eq!(config.replicas(), None);
+        assert_eq!(config.initial_replicas(), 1);
+    }
+
+    #[test]
+    fn should_merge_replicas_if_not_set() {
+        let mut config = sc!("proxy", "nginx");
+        let mut companion = sc!("proxy", "nginx");
+        companion.set_replicas(Some(3));
+
+        config.merge_with(&companion);
+
+        assert_eq!(config.replicas(), Some(3));
+    }
+
     #[test]
     fn should_merge_service_configs_labels() {
         let mut config = sc!(
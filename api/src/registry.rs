@@ -25,6 +25,7 @@
  */
 
 use crate::config::Config;
+use crate::metrics;
 use crate::models::Image;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
@@ -52,6 +53,7 @@ impl<'a> Registry<'a> {
         &self,
         images: &HashSet<Image>,
     ) -> Result<HashMap<Image, ImageInfo>, RegistryError> {
+        let _timer = metrics::time_registry_resolution();
         let mut resolve_image_info_futures = images
             .iter()
             .filter_map(|image| match image {
@@ -121,19 +123,25 @@ impl<'a> Registry<'a> {
         let reference = Reference::from_str(&image.to_string())
             .expect("Image should be convertable if it is the Named variant");
 
-        let (_manifest, digest, config) = client
+        let (manifest, digest, config) = client
             .pull_manifest_and_config(&reference, &Self::registry_auth(config, &reference))
             .await
             .map_err(|err| (image, dbg!(err)))?;
 
+        let config_digest = manifest.config.digest;
         let blob = match serde_json::from_str::<ImageBlob>(&config) {
             Ok(blob) => ImageInfo {
                 blob: Some(blob),
                 digest,
+                config_digest,
             },
             Err(err) => {
                 warn!("Cannot parse manifest blob for {image}: {err}");
-                ImageInfo { blob: None, digest }
+                ImageInfo {
+                    blob: None,
+                    digest,
+                    config_digest,
+                }
             }
         };
 
@@ -154,6 +162,7 @@ impl<'a> Registry<'a> {
 pub struct ImageInfo {
     blob: Option<ImageBlob>,
     digest: String,
+    config_digest: String,
 }
 
 impl ImageInfo {
@@ -165,6 +174,11 @@ impl ImageInfo {
         &self.digest
     }
 
+    /// The digest of the image config that Docker uses as id of the image.
+    pub fn config_digest(&self) -> &String {
+        &self.config_digest
+    }
+
     pub fn declared_volumes(&self) -> Vec<&String> {
         match self.blob.as_ref() {
             Some(info) => info.declared_volumes(),
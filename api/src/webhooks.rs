@@ -27,6 +27,7 @@
 use crate::apps::delete_app_sync;
 use crate::apps::Apps;
 use crate::http_result::HttpResult;
+use crate::metrics;
 use crate::models::service::Service;
 use crate::models::web_hook_info::WebHookInfo;
 use crate::models::AppName;
@@ -47,6 +48,8 @@ pub async fn webhooks(
         web_hook_info.get_event_key()
     );
 
+    metrics::observe_webhook_event(web_hook_info.get_event_key());
+
     let app_name = AppName::from_str(&web_hook_info.get_app_name());
     delete_app_sync(app_name, apps).await
 }